@@ -1,29 +1,199 @@
+use crate::config;
+use crate::docgen::DocFormat;
+use crate::highlight::HighlightFormat;
+use crate::serve;
+use crate::input::{Backend, Keys};
+use crate::numbers::NumberFormat;
 use crate::output::*;
+use crate::tty::Theme;
+use pesc::pesc::{DivZeroMode, NanMode, ParseMode, Precision, PescOptions};
 use getopts::Options as g_Options;
 use std::env;
+use std::time::Duration;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    // run FILE, or start the REPL
+    Run,
+
+    // run some code given on the command line
+    Eval(String),
+
+    // format the given files
+    Fmt(Vec<String>),
+
+    // check the given files without running them
+    Lint(Vec<String>),
+
+    // check the given files for functions that will run out of arguments
+    Check(Vec<String>),
+
+    // run the tests in the given files and directories
+    Test(Vec<String>),
+
+    // talk to an editor over stdin and stdout
+    Lsp,
+
+    // the full-screen REPL
+    Tui,
+
+    // print a reference for every function
+    Doc(DocFormat),
+
+    // print the given files highlighted
+    Highlight(HighlightFormat, Vec<String>),
+
+    // run some code on each line of stdin
+    Map(String),
+}
 
 #[derive(Clone, Debug)]
 pub struct Options {
+    pub command: Command,
     pub file: Option<String>,
     pub load_lua: bool,
     pub load_extra: Option<String>,
     pub output: OutputMode,
     pub verbose: bool,
+    pub legacy_bools: bool,
+    pub parse_mode: ParseMode,
+    pub div_zero: DivZeroMode,
+    pub nan_mode: NanMode,
+    pub precision: Precision,
+    pub check: bool,
+
+    // start the REPL even when stdin isn't a terminal
+    pub interactive: bool,
+
+    // exit with a status that depends on the value on top of the stack
+    pub exit_top: bool,
+
+    // print errors as JSON objects (as --output json does too)
+    pub json_errors: bool,
+    pub ignore_case: bool,
+    pub checked_ints: bool,
+    pub profile: bool,
+    pub trace: bool,
+    pub dump_ast: bool,
+    pub coverage: bool,
+    pub lcov: Option<String>,
+    pub trace_file: Option<String>,
+    pub infix: bool,
+    pub dc: bool,
+    pub hp: bool,
+    pub forth: bool,
+    pub serve: Option<String>,
+    pub jupyter: Option<String>,
+    pub max_depth: usize,
+    pub max_loops: usize,
+    pub max_steps: Option<u64>,
+    pub timeout: Option<Duration>,
+    pub max_stack: Option<usize>,
+    pub keep_on_error: bool,
+
+    // a units(1) definitions file, or "" for wherever it usually is
+    pub units: Option<String>,
+
+    // a file to [include] before anything else, instead of the
+    // usual one (unless no_prelude)
+    pub prelude: Option<String>,
+    pub no_prelude: bool,
+
+    pub rates: Option<String>,
+    pub fetch_rates: Option<String>,
+
+    // leave out what reads files or runs programs ([include], the pager
+    // and --fetch-rates), as --serve always does
+    pub sandbox: bool,
+    pub editor: Backend,
+    pub keys: Keys,
+    pub history_size: usize,
+
+    // where to keep the history instead of the usual place, or ""
+    // to not keep it
+    pub history_file: Option<String>,
+    pub theme: Theme,
+
+    // the style of each kind of value (see output::KINDS)
+    pub colors: Colors,
+
+    // how many digits after the point to show, at most
+    pub digits: Option<usize>,
+
+    // how many characters of each value to show on the stack (0 for
+    // all of them)
+    pub cell_width: usize,
+
+    // how to show numbers (see numbers.rs)
+    pub numbers: NumberFormat,
+
+    // save the REPL's stack on exit, and bring it back next time
+    pub persist: bool,
+    pub state_file: Option<String>,
 }
 
 impl Options {
     // set default values of options
     pub fn new() -> Self {
         Self {
+            command: Command::Run,
             file: None,
             load_lua: false,
             load_extra: None,
             output: OutputMode::auto(),
             verbose: false,
+            legacy_bools: false,
+            parse_mode: ParseMode::Normal,
+            div_zero: DivZeroMode::Error,
+            nan_mode: NanMode::Propagate,
+            precision: Precision::Float,
+            check: false,
+            interactive: false,
+            exit_top: false,
+            json_errors: false,
+            ignore_case: true,
+            checked_ints: false,
+            profile: false,
+            trace: false,
+            dump_ast: false,
+            coverage: false,
+            lcov: None,
+            trace_file: None,
+            infix: false,
+            dc: false,
+            hp: false,
+            forth: false,
+            serve: None,
+            jupyter: None,
+            max_depth: PescOptions::default().max_depth,
+            max_loops: PescOptions::default().max_loops,
+            max_steps: None,
+            timeout: None,
+            max_stack: None,
+            keep_on_error: false,
+            units: None,
+            prelude: None,
+            no_prelude: false,
+            rates: None,
+            fetch_rates: None,
+            sandbox: false,
+            editor: Backend::Rustyline,
+            keys: Keys::Vi,
+            history_size: 1000,
+            history_file: None,
+            theme: Theme::auto(),
+            colors: DEFAULT_COLORS,
+            digits: None,
+            cell_width: CELL_WIDTH,
+            numbers: NumberFormat::default(),
+            persist: false,
+            state_file: None,
         }
     }
 
-    pub fn parse(mut self) -> Result<Self, ()> {
+    // on Err, what to exit with: 2 for a usage error, or 0 after
+    // --help or --version
+    pub fn parse(mut self) -> Result<Self, i32> {
         let args: Vec<String> = env::args().collect();
         let argv0 = args[0].clone();
 
@@ -32,43 +202,417 @@ impl Options {
         opts.optflag("h", "help", "print this help message.");
         opts.optflag("V", "version", "print the version.");
         opts.optflag("q", "quiet", "reduce output.");
+        opts.optflag("m", "machine", "print tab-separated types and values, for scripts.");
+        opts.optopt("o", "output", "print the stack as human, simple, quiet, machine or json.",
+            "MODE");
+        opts.optflag("", "no-color", "don't color anything.");
+        opts.optopt("", "color", "color things always, never, or on a terminal (auto).",
+            "WHEN");
+        opts.optopt("", "errors", "print errors as text or json.",
+            "FORMAT");
         opts.optflag("i", "", "force interactive mode.");
         opts.optflag("l", "load", "load extended stdlib from $PESCLIBS.");
         opts.optflag("v", "verbose", "show elapsed time.");
+        opts.optflag("", "legacy-bools", "treat every T/F as a boolean.");
+        opts.optflag("c", "check", "run FILE, only reporting problems.");
+        opts.optflag("", "exit-top", "exit with a status that depends on the top of the stack.");
+        opts.optflag("", "checked", "fail when integer results aren't exact.");
+        opts.optflag("", "case-sensitive", "don't treat [DUP] as [dup].");
+        opts.optflag("", "strict", "reject unknown functions before running.");
+        opts.optflag("", "lenient", "only warn about unknown operators.");
+        opts.optflag("", "profile", "report how long each function took.");
+        opts.optflag("", "trace", "print every function called, and the stack around it.");
+        opts.optflag("", "infix", "read expressions like 3*(4+5) instead.");
+        opts.optflag("", "dc", "read dc(1) code instead.");
+        opts.optflag("", "forth", "read (a subset of) Forth code instead.");
+        opts.optflag("", "hp", "keep a four-level stack, like an HP calculator.");
+        opts.optflag("", "dump-ast", "print the tokens in FILE instead of running it.");
+        opts.optflag("", "no-readline", "read plain lines, without editing or hints.");
+        opts.optflag("", "dump-config", "print the settings in effect, and exit.");
 
+        opts.optflagopt("", "coverage", "report which code was never run.",
+            "FILE");
+        opts.optflagopt("", "units", "define [convert] with the units(1) definitions in FILE.",
+            "FILE");
+        opts.optopt("", "prelude", "include the pesc code in FILE before anything else.",
+            "FILE");
+        opts.optflag("", "no-prelude", "don't include a prelude.");
+        opts.optopt("", "rates", "read currency exchange rates from FILE (JSON or CSV).",
+            "FILE");
+        opts.optopt("", "fetch-rates", "download the exchange rates from URL first.",
+            "URL");
+        opts.optflag("", "sandbox", "leave out what reads files or runs programs.");
+        opts.optopt("", "trace-file", "write a JSON lines trace of evaluation to FILE.",
+            "FILE");
+        opts.optopt("", "serve", "evaluate lines sent to ADDR (unix:PATH or tcp:HOST:PORT).",
+            "ADDR");
+        opts.optopt("", "jupyter", "run as a Jupyter kernel, with the connection FILE.",
+            "FILE");
+        opts.optopt("", "editor", "read lines with EDITOR (rustyline, reedline, plain).",
+            "EDITOR");
+        opts.optopt("", "edit-mode", "edit lines with vi or emacs keys.",
+            "MODE");
+        opts.optopt("", "max-stack", "fail when the stack holds more than N values.",
+            "N");
+        opts.optopt("", "history-size", "remember the last N lines typed.",
+            "N");
+        opts.optopt("", "history-file", "keep the history in FILE.",
+            "FILE");
+        opts.optopt("", "theme", "color things for a dark or light terminal, or none.",
+            "THEME");
+        opts.optopt("", "digits", "show at most N digits after the point.",
+            "N");
+        opts.optopt("", "config", "read settings from FILE (TOML, or JSON).",
+            "FILE");
         opts.optopt("L", "lua", "load the Lua file(s) in <PATH>.",
             "PATH");
+        opts.optopt("", "div-zero", "what dividing by zero does (inf, error, nil).",
+            "MODE");
+        opts.optopt("", "nan", "how comparisons treat NaN (error, propagate, total).",
+            "MODE");
+        opts.optopt("", "precision", "how numbers are kept (float, exact, decimal).",
+            "MODE");
 
         let matches = match opts.parse(&args[1..]) {
             Ok(ma) => ma,
             Err(e) => {
                 println!("pesc: error: {}", e);
-                return Err(());
+                return Err(2);
             },
         };
 
         if matches.opt_present("h") {
             Options::usage(&argv0);
-            return Err(());
+            return Err(0);
         } else if matches.opt_present("V") {
-            // TODO
-            todo!();
+            println!("pesc {}", env!("CARGO_PKG_VERSION"));
+            return Err(0);
         }
 
-        self.file = if !matches.free.is_empty() {
-            Some(matches.free[0].clone())
-        } else {
-            None
-        };
+        match matches.free.first().map(|s| s.as_str()) {
+            Some("test") => {
+                let mut paths = matches.free[1..].to_vec();
+                if paths.is_empty() {
+                    paths.push(String::from("."));
+                }
+
+                self.command = Command::Test(paths);
+            },
+            Some("lsp") => self.command = Command::Lsp,
+            Some("doc") => self.command = match matches.free.get(1).map(|s| s.as_str()) {
+                None | Some("markdown") => Command::Doc(DocFormat::Markdown),
+                Some("man") => Command::Doc(DocFormat::Man),
+                Some(f) => {
+                    println!("pesc: error: doc can be markdown or man, not {}", f);
+                    return Err(2);
+                },
+            },
+            Some("tui") if cfg!(feature = "tui") => self.command = Command::Tui,
+            Some("tui") => {
+                println!("pesc: error: this pesc was built without `--features tui`");
+                return Err(2);
+            },
+            Some(c @ "fmt") | Some(c @ "lint") | Some(c @ "check") => {
+                if matches.free.len() < 2 {
+                    println!("pesc: error: {} needs a FILE", c);
+                    return Err(2);
+                }
+
+                let files = matches.free[1..].to_vec();
+                self.command = match c {
+                    "fmt" => Command::Fmt(files),
+                    "check" => Command::Check(files),
+                    _ => Command::Lint(files),
+                };
+            },
+            Some("highlight") => {
+                let (format, files) = match matches.free.get(1).map(|s| s.as_str()) {
+                    Some("html") => (HighlightFormat::Html, &matches.free[2..]),
+                    Some("ansi") => (HighlightFormat::Ansi, &matches.free[2..]),
+                    _ => (HighlightFormat::Html, &matches.free[1..]),
+                };
+
+                if files.is_empty() {
+                    println!("pesc: error: highlight needs a FILE");
+                    return Err(2);
+                }
+
+                self.command = Command::Highlight(format, files.to_vec());
+            },
+            Some("map") => {
+                if matches.free.len() < 2 {
+                    println!("pesc: error: map needs some code");
+                    return Err(2);
+                }
+
+                self.command = Command::Map(matches.free[1..].join(" "));
+            },
+            Some("serve") => {
+                let addr = matches.free.get(1).map_or(serve::DEFAULT_ADDR, |a| a.as_str());
+                self.serve = Some(addr.to_string());
+            },
+            Some("eval") => {
+                if matches.free.len() < 2 {
+                    println!("pesc: error: eval needs some code");
+                    return Err(2);
+                }
+
+                self.command = Command::Eval(matches.free[1..].join(" "));
+            },
+            Some("run") => match matches.free.get(1) {
+                Some(f) => self.file = Some(f.clone()),
+                None => {
+                    println!("pesc: error: run needs a FILE");
+                    return Err(2);
+                },
+            },
+            Some("repl") => self.interactive = true,
+
+            // pesc FILE, or (when it can't be one) pesc '2 3+'
+            Some(f) if is_file(f) => self.file = Some(String::from(f)),
+            Some(_) => self.command = Command::Eval(matches.free.join(" ")),
+            None => (),
+        }
+
+        // the config file comes first, so that options can override it
+        let config = matches.opt_str("config").or_else(|| Some(config::default_file())
+            .filter(|p| p.exists())
+            .map(|p| p.display().to_string()));
+
+        if let Some(path) = config {
+            if let Err(e) = config::load(&path, &mut self) {
+                println!("pesc: error: {}", e);
+                return Err(2);
+            }
+        }
 
         self.load_lua = matches.opt_present("l");
         self.load_extra = matches.opt_str("L");
-        self.verbose = matches.opt_present("v");
+        self.verbose |= matches.opt_present("v");
+        self.legacy_bools |= matches.opt_present("legacy-bools");
+        self.check = matches.opt_present("c");
+        self.interactive |= matches.opt_present("i");
+        self.exit_top = matches.opt_present("exit-top");
+        self.checked_ints |= matches.opt_present("checked");
+        self.profile = matches.opt_present("profile");
+        self.trace = matches.opt_present("trace");
+        self.dump_ast = matches.opt_present("dump-ast");
+        self.coverage = matches.opt_present("coverage");
+        self.lcov = matches.opt_str("coverage");
+        self.trace_file = matches.opt_str("trace-file");
+
+        if let Some(e) = matches.opt_str("editor") {
+            self.editor = match Backend::from_name(&e) {
+                Ok(b) => b,
+                Err(e) => {
+                    println!("pesc: error: {}", e);
+                    return Err(2);
+                },
+            };
+        }
+
+        if matches.opt_present("no-readline") {
+            self.editor = Backend::Plain;
+        }
+
+        if let Some(m) = matches.opt_str("edit-mode") {
+            self.keys = match Keys::from_name(&m) {
+                Ok(k) => k,
+                Err(e) => {
+                    println!("pesc: error: {}", e);
+                    return Err(2);
+                },
+            };
+        }
+
+        if let Some(f) = matches.opt_str("history-file") {
+            self.history_file = Some(f);
+        }
+
+        if let Some(t) = matches.opt_str("theme") {
+            self.theme = match Theme::from_name(&t) {
+                Ok(t) => t,
+                Err(e) => {
+                    println!("pesc: error: {}", e);
+                    return Err(2);
+                },
+            };
+        }
+
+        if let Some(n) = matches.opt_str("max-stack") {
+            self.max_stack = match n.parse() {
+                Ok(n) => Some(n),
+                Err(_) => {
+                    println!("pesc: error: --max-stack wants a number, not '{}'", n);
+                    return Err(2);
+                },
+            };
+        }
+
+        if let Some(n) = matches.opt_str("history-size") {
+            self.history_size = match n.parse() {
+                Ok(n) => n,
+                Err(_) => {
+                    println!("pesc: error: --history-size wants a number, not '{}'", n);
+                    return Err(2);
+                },
+            };
+        }
+
+        if let Some(n) = matches.opt_str("digits") {
+            self.digits = match n.parse() {
+                Ok(n) => Some(n),
+                Err(_) => {
+                    println!("pesc: error: --digits wants a number, not '{}'", n);
+                    return Err(2);
+                },
+            };
+        }
+
+        if let Some(p) = matches.opt_str("prelude") {
+            self.prelude = Some(p);
+        }
+
+        if matches.opt_present("no-prelude") {
+            self.no_prelude = true;
+        }
+
+        if let Some(r) = matches.opt_str("rates") {
+            self.rates = Some(r);
+        }
+        self.fetch_rates = matches.opt_str("fetch-rates");
+
+        if matches.opt_present("units") {
+            self.units = Some(matches.opt_str("units").unwrap_or_default());
+        }
+
+        if matches.opt_present("case-sensitive") {
+            self.ignore_case = false;
+        }
+
+        if ["infix", "dc", "forth", "hp"].iter().any(|m| matches.opt_present(m)) {
+            self.infix = matches.opt_present("infix");
+            self.dc = matches.opt_present("dc");
+            self.forth = matches.opt_present("forth");
+            self.hp = matches.opt_present("hp");
+        }
+
+        if let Some(addr) = matches.opt_str("serve") {
+            self.serve = Some(addr);
+        }
+
+        // (what's sent to a server can't be trusted with files)
+        if matches.opt_present("sandbox") || self.serve.is_some() {
+            self.sandbox = true;
+        }
+        self.jupyter = matches.opt_str("jupyter");
+
+        if let Some(m) = matches.opt_str("div-zero") {
+            self.div_zero = match m.as_str() {
+                "inf" => DivZeroMode::Ieee,
+                "error" => DivZeroMode::Error,
+                "nil" => DivZeroMode::Nil,
+                _ => {
+                    println!("pesc: error: unknown --div-zero mode '{}'", m);
+                    return Err(2);
+                },
+            };
+        }
+
+        if let Some(m) = matches.opt_str("nan") {
+            self.nan_mode = match m.as_str() {
+                "error" => NanMode::Error,
+                "propagate" => NanMode::Propagate,
+                "total" => NanMode::Total,
+                _ => {
+                    println!("pesc: error: unknown --nan mode '{}'", m);
+                    return Err(2);
+                },
+            };
+        }
+
+        if let Some(m) = matches.opt_str("precision") {
+            self.precision = match m.as_str() {
+                "float" => Precision::Float,
+                "exact" => Precision::Exact,
+                "decimal" => Precision::Decimal,
+                _ => {
+                    println!("pesc: error: unknown --precision mode '{}'", m);
+                    return Err(2);
+                },
+            };
+        }
+
+        if self.dc && self.hp {
+            println!("pesc: error: --dc and --hp can't be used together");
+            return Err(2);
+        }
+
+        if [self.infix, self.dc, self.forth].iter().filter(|f| **f).count() > 1 {
+            println!("pesc: error: only one of --infix, --dc and --forth can be used");
+            return Err(2);
+        }
+
+        if self.dump_ast && self.file.is_none() {
+            println!("pesc: error: --dump-ast needs a FILE");
+            return Err(2);
+        }
+
+        if matches.opt_present("strict") {
+            self.parse_mode = ParseMode::Strict;
+        } else if matches.opt_present("lenient") {
+            self.parse_mode = ParseMode::Lenient;
+        }
+
+        if let Some(f) = matches.opt_str("errors") {
+            self.json_errors = match f.as_str() {
+                "text" => false,
+                "json" => true,
+                _ => {
+                    println!("pesc: error: unknown --errors format '{}'", f);
+                    return Err(2);
+                },
+            };
+        }
+
+        if matches.opt_present("no-color") {
+            self.theme = Theme::Plain;
+        }
+
+        let always = match matches.opt_str("color").as_deref() {
+            None | Some("auto") => false,
+            Some("always") => true,
+            Some("never") => {
+                self.theme = Theme::Plain;
+                false
+            },
+            Some(w) => {
+                println!("pesc: error: --color can be always, never or auto, not '{}'", w);
+                return Err(2);
+            },
+        };
+
+        if always && self.theme == Theme::Plain {
+            self.theme = Theme::Dark;
+        }
 
         self.output = {
-            // if -q is set, force quiet mode
-            if matches.opt_present("q") {
+            // --output, -q or -m say what to use
+            if let Some(m) = matches.opt_str("output") {
+                match OutputMode::from_name(&m) {
+                    Some(m) => m,
+                    None => {
+                        println!("pesc: error: unknown --output mode '{}'", m);
+                        return Err(2);
+                    },
+                }
+            } else if matches.opt_present("q") {
                 OutputMode::Quiet
+            } else if matches.opt_present("m") {
+                OutputMode::Machine
+            } else if always {
+                // as if stdout were a terminal, e.g. for less -R
+                OutputMode::Human
             } else {
                 // default to the previous value,
                 // which is set automatically based on
@@ -77,18 +621,163 @@ impl Options {
             }
         };
 
+        if matches.opt_present("dump-config") {
+            print!("{}", config::dump(&self));
+            return Err(2);
+        }
+
         Ok(self)
     }
 
     fn usage(argv0: &str) {
-        println!("Usage: {} [OPTION]... [FILE]
+        println!("Usage: {0} [OPTION]... [FILE | CODE]
+       {0} [OPTION]... run FILE
+       {0} [OPTION]... eval CODE
+       {0} [OPTION]... repl
+       {0} [OPTION]... fmt FILE...
+       {0} [OPTION]... lint FILE...
+       {0} [OPTION]... check FILE...
+       {0} [OPTION]... test [PATH]...
+       {0} [OPTION]... lsp
+       {0} [OPTION]... tui
+       {0} [OPTION]... doc [markdown | man]
+       {0} [OPTION]... highlight [html | ansi] FILE...
+       {0} [OPTION]... serve [ADDR]
+       {0} [OPTION]... map CODE
 
 Options:
     -h, --help             print this help message.
     -V, --version          print the version.
     -q, --quiet            reduce output.
+    -m, --machine          print a line for each value, top first, with its
+                           type and value separated by a tab, and an empty
+                           line after the last.
+    -o, --output [MODE]    print the stack for a human (with colors), simply
+                           (a value on each line), quietly, for a machine
+                           (as -m does), or as json: an array, bottom first,
+                           where macros are {{\"macro\": CODE}}, fractions
+                           {{\"fraction\": \"N/D\"}} and functions {{\"func\": F}}.
+        --no-color         don't color anything (--theme none, which is also
+                           the default when $NO_COLOR is set).
+        --color [WHEN]     color things always (even when printing to a
+                           pipe, e.g. for less -R), never (--no-color) or
+                           only on a terminal (auto, the default).
+        --errors [FORMAT]  print errors as text (the default), or as json
+                           (as --output json does): an object on one line
+                           with the error's kind, message, file, line,
+                           column and the token that failed.
+    -i                     start the REPL, even if stdin isn't a terminal
+                           (as the repl command does).
     -l, --load             load extended stdlib from $PESCLIBS.
     -L, --lua     [PATH]   load the Lua file(s) in <PATH>.
+    -c, --check            run FILE, only reporting problems (or, with
+                           fmt, only report files that aren't formatted).
+        --exit-top         after running FILE or CODE, exit with 0 if what's
+                           on top of the stack is true (or 1 if it's false),
+                           or with a number from 0 to 255 if it's one (or 1
+                           for any other number), e.g. for $X > 10,
+                           pesc -q --exit-top \"10 $X [gt?]\". with nothing
+                           there, exit with 2.
+        --legacy-bools     treat every T/F as a boolean.
+        --checked          fail when integer results aren't exact.
+        --case-sensitive   don't treat [DUP] as [dup].
+        --strict           reject unknown functions before running.
+        --lenient          only warn about unknown operators.
+        --profile          report how long each function took.
+        --max-stack [N]    fail when the stack holds more than N values,
+                           rather than filling up memory.
+        --trace            print every function that's called, with the
+                           stack before and after it, to stderr.
+        --editor [EDITOR]  read lines with rustyline (the default), reedline
+                           (if pesc was built with `--features reedline`),
+                           or plain, which is what --no-readline means.
+        --no-readline      read plain lines, without editing, hints or colors
+                           (as happens anyway when TERM is dumb).
+        --edit-mode [MODE] edit lines with vi (the default) or emacs keys.
+        --history-size [N] remember the last N lines typed (1000), or none.
+        --history-file [FILE]
+                           keep them in FILE instead of $PESC_HISTORY or
+                           ~/.local/share/pesc/history (or nowhere, if
+                           FILE is \"\").
+        --theme [THEME]    color things for a dark terminal (the default),
+                           a light one, or not at all (none). the colors
+                           of values can be changed in the config, e.g.
+                           color.string = \"green\".
+        --digits [N]       show at most N digits after the point.
+        --dump-ast         print the tokens in FILE instead of running it.
+        --config [FILE]    read settings from FILE (TOML, or JSON if it ends
+                           with .json) instead of $PESC_CONFIG or
+                           ~/.config/pesc/config.toml; options given here
+                           win over it.
+        --dump-config      print the settings in effect, and exit.
+        --infix            read expressions like 3*(4+5) instead.
+        --dc               read dc(1) code instead (from stdin, if it
+                           isn't a terminal and there's no FILE).
+        --forth            read (a subset of) Forth code instead: definitions,
+                           IF/ELSE/THEN, stack words, arithmetic, . .\" CR.
+        --hp               keep a four-level X/Y/Z/T stack, like an HP
+                           calculator ([enter], [lastx], [clx], [rdn]).
+        --coverage[=FILE]  report which code was never run, or write lcov
+                           data about what was to FILE.
+        --units[=FILE]     define [convert] with the units(1) definitions in
+                           FILE (by default, $PESC_UNITS or GNU units' own).
+        --prelude [FILE]   [include] the pesc code in FILE first, so that its
+                           definitions are always there (by default,
+                           $PESC_PRELUDE or ~/.config/pesc/prelude.pesc,
+                           if it exists).
+        --no-prelude       don't include a prelude.
+        --rates [FILE]     read currency exchange rates for [cur-convert]
+                           from FILE (JSON, or CSV if it ends with .csv).
+        --fetch-rates [URL]
+                           download the exchange rates from URL first.
+        --sandbox          leave out what reads files or runs programs:
+                           [include], the pager and --fetch-rates (--serve
+                           always does).
+        --trace-file [FILE]
+                           write a JSON lines trace of evaluation to FILE.
+        --serve [ADDR]     keep running, and evaluate the lines (or the bodies
+                           of the HTTP POSTs) sent to ADDR (unix:PATH,
+                           tcp:HOST:PORT or a PORT). set $PESC_SERVE_TOKEN
+                           to require `auth TOKEN` (or a bearer token) first.
+                           each line or POST runs for 5s at most, unless
+                           timeout or max-steps in the config say otherwise.
+        --jupyter [FILE]   run as a Jupyter kernel, with the connection FILE.
+        --div-zero [MODE]  what dividing by zero does (inf, error, nil).
+        --nan      [MODE]  how comparisons treat NaN (error, propagate, total).
+        --precision [MODE] how numbers are kept: as floats, or (exact) with
+                           whole numbers as integers of any size, so that
+                           2 64^ is 18446744073709551616 and 1 3/ is (1/3),
+                           or (decimal) with those and exact decimals, so
+                           that 0.1 0.2+ is 0.3.
+
+without a command, the first argument is run as a FILE if there's a file
+by that name (or it ends with .pesc), and as CODE otherwise, e.g. pesc '2 3+'.
+eval runs CODE and prints the stack, and repl starts the REPL. without
+either, when stdin isn't a terminal, what's piped in is run as a FILE.
+lint exits with 1 if it found problems, and with 2 if a file couldn't be checked.
+check does the same, but only looks for functions that will run out of arguments
+(the REPL warns about those, too, and doesn't run the line).
+test runs every *_test.pesc file in PATH (by default, the current directory),
+with [assert] and [assert-eq], and exits with 1 if any of them failed.
+lsp runs a language server, for editors, on stdin and stdout.
+tui runs the REPL full-screen, with the stack, what's been run and the
+functions that fit what's being typed each in a pane of its own (if pesc
+was built with `--features tui`).
+doc prints what every function does, with its operators and examples, as
+markdown or as a man page.
+map runs CODE on each line of stdin, alone on the stack (as a number if it
+is one, or else as a string), and prints what's left on a line, e.g.
+seq 10 | pesc map '2*'. lines that leave nothing aren't printed.
+serve does what --serve does, at ADDR or else at 127.0.0.1:7878. each HTTP
+POST is run in an interpreter of its own, unless it has an X-Pesc-Session
+header, in which case it's run in the one kept for that session.
+highlight prints files colored the way the REPL colors what's typed, as HTML
+(a <pre>, for blog posts and docs) or for a terminal.
 ", argv0);
     }
 }
+
+// whether a command-line argument is a file to run, rather than code
+fn is_file(arg: &str) -> bool {
+    arg.ends_with(".pesc") || std::path::Path::new(arg).exists()
+}
@@ -4,65 +4,172 @@
 // TODO: choose a more accurate name for this module
 
 use pesc::pesc::Pesc;
-use pesc::errors::*;
 
+use crate::highlight;
 use crate::tty::*;
 
 use rustyline::error::ReadlineError;
 use rustyline::{
+    completion::{Candidate, Completer},
     hint::{
         Hinter,
         HistoryHinter
     }, Context,
-    validate::{
-        ValidationContext, Validator,
-        ValidationResult::{
-            self, Incomplete, Valid
-        },
-    },
+    validate::Validator,
     highlight::Highlighter,
+    CompletionType,
 };
-use rustyline_derive::{
-    Completer, Helper,
-};
+use rustyline_derive::Helper;
 
 use std::borrow::{Cow, Cow::Owned};
+use std::cell::RefCell;
+use std::collections::HashMap;
 
-#[derive(Completer, Helper)]
+#[derive(Helper)]
 pub struct BustyLine {
     hinter: HistoryHinter,
+
+    // the functions that can be completed, sorted
+    names: Vec<String>,
+
+    // what the documented ones take and leave, e.g. "( a b -- a+b )"
+    signatures: HashMap<String, String>,
+
+    // for parsing what's typed (with the operators pesc has), to
+    // highlight it
+    parser: RefCell<Pesc>,
 }
 
 impl BustyLine {
-    pub fn new() -> Self {
+    pub fn new(pesc: &Pesc) -> Self {
+        let mut names = pesc.funcs.keys().cloned().collect::<Vec<String>>();
+        names.sort_unstable();
+
+        let signatures = pesc.docs.iter()
+            .map(|(n, d)| (n.clone(), format!("( {} )", d.signature)))
+            .collect();
+
         Self {
             hinter: HistoryHinter {},
+            names,
+            signatures,
+            parser: RefCell::new(highlight::parser(pesc)),
         }
     }
 }
 
-impl Validator for BustyLine {
-    fn validate(&self, ctx: &mut ValidationContext)
-        -> Result<ValidationResult, ReadlineError>
-    {
-        let input = ctx.input();
-
-        match Pesc::parse(&input) {
-            Ok(_) => Ok(Valid(None)),
-            Err(e) => {
-                if let PescErrorType::UnmatchedToken(_) = e.kind {
-                    Ok(Incomplete)
-                } else {
-                    Ok(Valid(None))
-                }
+// the functions whose names start with what's between the '[' before
+// `pos` and `pos`, with their ']', and where that starts
+fn complete_name(names: &[String], line: &str, pos: usize) -> (usize, Vec<String>) {
+    let before = &line[..pos];
+    let start = match before.rfind(|c: char| c == '[' || c == ']' || c.is_whitespace()) {
+        Some(i) if before[i..].starts_with('[') => i + 1,
+        _ => return (pos, Vec::new()),
+    };
+
+    let word = &before[start..];
+    let found = names.iter()
+        .filter(|n| n.starts_with(word))
+        .map(|n| format!("{}]", n))
+        .collect();
+    (start, found)
+}
+
+// the name of the function just typed, with its ']', when it's right
+// before `pos`
+fn name_before(line: &str, pos: usize) -> Option<&str> {
+    let before = line[..pos].strip_suffix(']')?;
+    let start = before.rfind('[')?;
+    let name = &before[start + 1..];
+
+    match name.contains(|c: char| c == ']' || c.is_whitespace()) {
+        true => None,
+        false => Some(name),
+    }
+}
+
+// a function to complete, listed with what it takes and leaves,
+// e.g. "add ( a b -- a+b )"
+pub struct Name {
+    display: String,
+    replacement: String,
+}
+
+impl Candidate for Name {
+    fn display(&self) -> &str {
+        &self.display
+    }
+
+    fn replacement(&self) -> &str {
+        &self.replacement
+    }
+}
+
+impl BustyLine {
+    // the signature of a function, after a space, or nothing
+    fn signature(&self, name: &str) -> String {
+        self.signatures.get(name)
+            .map(|s| format!(" {}", s))
+            .unwrap_or_default()
+    }
+
+    // what's shown after the cursor: the rest of the one function that
+    // fits what's been typed after a '[', and its signature, or the
+    // signature of the one that was just typed. the signature is in a
+    // comment, since the hint goes in the line when it's taken.
+    fn name_hint(&self, line: &str, pos: usize) -> Option<String> {
+        if pos != line.len() {
+            return None;
+        }
+
+        if let Some(name) = name_before(line, pos) {
+            return self.signatures.get(name).map(|s| format!(" # {}", s));
+        }
+
+        let (start, found) = complete_name(&self.names, line, pos);
+        match found.as_slice() {
+            [name] => {
+                let name = name.trim_end_matches(']');
+                let sig = self.signatures.get(name)
+                    .map(|s| format!(" # {}", s))
+                    .unwrap_or_default();
+                Some(format!("{}]{}", &name[pos - start..], sig))
             },
+            _ => None,
         }
     }
+
+    // the functions to complete, and where they start
+    fn candidates(&self, line: &str, pos: usize) -> (usize, Vec<Name>) {
+        let (start, found) = complete_name(&self.names, line, pos);
+        let names = found.into_iter()
+            .map(|n| {
+                let name = n.trim_end_matches(']');
+                Name { display: format!("{}{}", name, self.signature(name)), replacement: n }
+            })
+            .collect();
+
+        (start, names)
+    }
 }
 
+impl Completer for BustyLine {
+    type Candidate = Name;
+
+    fn complete(&self, line: &str, pos: usize, _: &Context<'_>)
+        -> Result<(usize, Vec<Name>), ReadlineError>
+    {
+        Ok(self.candidates(line, pos))
+    }
+}
+
+// (input::Rustyline reads the rest of anything unfinished itself, with
+// its own prompt, so every line is fine as it is)
+impl Validator for BustyLine {}
+
 impl Hinter for BustyLine {
     fn hint(&self, line: &str, pos: usize, ctx: &Context<'_>) -> Option<String> {
-        self.hinter.hint(line, pos, ctx)
+        self.name_hint(line, pos).or_else(|| self.hinter.hint(line, pos, ctx))
     }
 }
 
@@ -72,7 +179,51 @@ impl Highlighter for BustyLine {
             hint, TermStyle::Reset))
     }
 
+    // the signatures in the list of completions are dim, like hints
+    fn highlight_candidate<'c>(&self, candidate: &'c str, _: CompletionType) -> Cow<'c, str> {
+        match candidate.find(" (") {
+            Some(i) => Owned(format!("{}{}", &candidate[..i], self.highlight_hint(&candidate[i..]))),
+            None => Cow::Borrowed(candidate),
+        }
+    }
+
     fn highlight<'l>(&self, line: &'l str, _: usize) -> Cow<'l, str> {
-        Owned(line.to_owned())
+        Owned(highlight::line(&mut self.parser.borrow_mut(), line))
+    }
+
+    fn highlight_char(&self, _: &str, _: usize) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_complete() {
+        let names = ["drop", "dup", "dup2", "swap"].iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        assert_eq!(complete_name(&names, "1 [du", 5), (3, vec![String::from("dup]"), String::from("dup2]")]));
+        assert_eq!(complete_name(&names, "[s] [", 5), (5, names.iter().map(|n| format!("{}]", n)).collect()));
+        assert_eq!(complete_name(&names, "[sw 2", 3), (1, vec![String::from("swap]")]));
+        assert_eq!(complete_name(&names, "[dup] du", 8), (8, Vec::new()));
+    }
+
+    #[test]
+    fn test_name_hint() {
+        let b = BustyLine::new(&Pesc::with_stdlib());
+        let hint = |line: &str| b.name_hint(line, line.len());
+
+        assert_eq!(hint("1 2 [ad").as_deref(), Some("d] # ( a b -- a+b )"));
+        assert_eq!(hint("1 2 [add").as_deref(), Some("] # ( a b -- a+b )"));
+        assert_eq!(hint("1 [dup]").as_deref(), Some(" # ( a -- a a )"));
+        assert_eq!(hint("1 [d"), None);
+        assert_eq!(hint("1 2 +"), None);
+        assert_eq!(b.name_hint("[dup] 1", 5), None);
+
+        let (start, names) = b.candidates("1 [swp", 6);
+        assert_eq!(start, 3);
+        assert_eq!(names.iter().map(|n| (n.display(), n.replacement())).collect::<Vec<_>>(),
+            vec![("swp ( a b -- b a )", "swp]")]);
     }
 }
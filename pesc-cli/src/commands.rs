@@ -0,0 +1,250 @@
+// REPL commands, i.e. lines like ":keep-on-error on" that
+// control pesc itself instead of being evaluated.
+
+use std::cell::RefCell;
+
+use pesc::infix;
+use pesc::pesc::{self as interp, Pesc};
+
+use crate::args::Options;
+use crate::debug;
+use crate::dump;
+use crate::export::History;
+use crate::numbers;
+use crate::output;
+use crate::plot;
+use crate::profile;
+use crate::state::State;
+use crate::undo::Undo;
+
+// commands start with a ':' immediately followed by their name,
+// so that something like ": x" can still mean something else
+pub fn is_command(line: &str) -> bool {
+    let line = line.trim_start();
+    line.starts_with(':')
+        && line[1..].starts_with(|c: char| c.is_ascii_alphabetic())
+}
+
+pub fn run(line: &str, pesc: &mut Pesc, opts: &mut Options, state: &State,
+    history: &mut History, undo: &RefCell<Undo>)
+{
+    let line = line.trim_start()[1..].trim_end();
+    let (name, rest) = line.split_once(char::is_whitespace)
+        .unwrap_or((line, ""));
+    let args = rest.split_whitespace().collect::<Vec<&str>>();
+
+    match name {
+        "apropos" => match rest.trim() {
+            "" => println!("error: :{} takes a word to look for.", name),
+            word => apropos(word, pesc),
+        },
+        "debug" => debug::run(rest, pesc, opts.output),
+        "defs" => defs(pesc),
+        "doc" => match args.as_slice() {
+            [name] => doc(name, pesc),
+            _ => println!("error: :{} takes the name of a function.", name),
+        },
+        "discard" => {
+            state.discard(pesc);
+            history.clear();
+            undo.borrow_mut().clear();
+            println!("starting afresh.");
+        },
+        "export" => match history.export(&args, opts) {
+            Ok(msg) => println!("{}", msg),
+            Err(e) => println!("error: {}", e),
+        },
+        "infix" => match infix::compile(rest) {
+            Ok(toks) => println!("{}", interp::unparse(&toks)),
+            Err(e) => println!("error: {}", e),
+        },
+        "show" | "see" => match args.as_slice() {
+            [n] if n.parse::<usize>().is_ok() => item(n.parse().unwrap_or(0), pesc),
+            [name] => show(name, pesc),
+            _ => println!("error: :{} takes the name of a function, or where a value is on the stack.", name),
+        },
+        "plot" => plot::chart(&pesc.stack),
+        "parse" => if let Err(e) = dump::dump(rest, &pesc.opts) {
+            println!("error: {}", e);
+        },
+        "stack" => stack(pesc, opts),
+        "undo" => match undo.borrow_mut().undo(pesc) {
+            true => crate::print_stack(opts, &pesc.stack),
+            false => println!("there's nothing to undo."),
+        },
+        "keep-on-error" => toggle(name, &args, &mut pesc.opts.keep_on_error),
+        "profile" => match args.as_slice() {
+            [] | ["on"] | ["off"] => toggle(name, &args, &mut opts.profile),
+            _ => profile::run(rest, pesc, opts.output),
+        },
+        "trace" => toggle(name, &args, &mut opts.trace),
+        _ => println!("error: I don't know the command ':{}'.", name),
+    }
+}
+
+// print the definitions of every function the user defined
+fn defs(pesc: &Pesc) {
+    let names = pesc.definitions();
+    if names.is_empty() {
+        println!("nothing has been defined yet.");
+    }
+
+    for name in names {
+        if let Some(code) = pesc.definition(name) {
+            println!("{}", code);
+        }
+    }
+}
+
+// print how a function was defined (as code that would define it
+// again), or say that it's builtin, along with its operators
+fn show(name: &str, pesc: &Pesc) {
+    let func = pesc.named(name);
+    if !pesc.funcs.contains_key(&func) {
+        println!("error: I have no idea what {} means.", name);
+        return;
+    }
+
+    match pesc.definition(&func) {
+        Some(code) => println!("{}", code),
+        None => println!("{} is builtin.", func),
+    }
+
+    let ops = pesc.operators(&func);
+    if !ops.is_empty() {
+        println!("operators: {}", ops.join(" "));
+    }
+}
+
+// print the nth value on the stack (0 being the top) in full, since
+// it may not all fit on the stack
+fn item(n: usize, pesc: &Pesc) {
+    match pesc.stack.iter().rev().nth(n) {
+        Some(t) => println!("{}", full(t)),
+        None => println!("error: there are only {} values on the stack.", pesc.stack.len()),
+    }
+}
+
+// a value in full, with the code in macros
+fn full(t: &interp::PescToken) -> String {
+    match t {
+        interp::PescToken::Macro(_) => interp::unparse(std::slice::from_ref(t)),
+        t => numbers::show(t),
+    }
+}
+
+// print every value on the stack, the top first, each on a line with
+// where it is, through the pager if they don't fit on the screen (and
+// there's no --sandbox)
+fn stack(pesc: &Pesc, opts: &Options) {
+    if pesc.stack.is_empty() {
+        println!("the stack is empty.");
+        return;
+    }
+
+    let width = (pesc.stack.len() - 1).to_string().len();
+    let listing = pesc.stack.iter().rev().enumerate()
+        .map(|(i, t)| format!("{:>w$}  {}\n", i, full(t), w = width))
+        .collect::<String>();
+
+    match opts.sandbox {
+        true => print!("{}", listing),
+        false => output::page(&listing),
+    }
+}
+
+// print what a function does (or how it was defined, if it
+// doesn't say), along with its operators
+fn doc(name: &str, pesc: &Pesc) {
+    let func = pesc.named(name);
+    if !pesc.funcs.contains_key(&func) {
+        println!("error: I have no idea what {} means.", name);
+        return;
+    }
+
+    match (pesc.docs.get(&func), pesc.definition(&func)) {
+        (Some(d), _) => println!("[{}] ( {} )\n    {}", func, d.signature, d.description),
+        (None, Some(code)) => println!("{}", code),
+        (None, None) => println!("[{}] doesn't say what it does.", func),
+    }
+
+    let ops = pesc.operators(&func);
+    if !ops.is_empty() {
+        println!("operators: {}", ops.join(" "));
+    }
+}
+
+// print the functions whose names are like `word`, or whose
+// documentation mentions it, the closest first
+fn apropos(word: &str, pesc: &Pesc) {
+    let mut found = pesc.funcs.keys()
+        .filter_map(|f| closeness(word, f, pesc.docs.get(f).map(|d| d.description.as_str()))
+            .map(|c| (c, f)))
+        .collect::<Vec<_>>();
+    found.sort();
+
+    if found.is_empty() {
+        println!("nothing matches '{}'.", word);
+    }
+
+    for (_, f) in found {
+        match (pesc.docs.get(f), pesc.definition(f)) {
+            (Some(d), _) => println!("[{}] ( {} ) {}", f, d.signature, d.description),
+            (None, Some(code)) => println!("{}", code),
+            (None, None) => println!("[{}]", f),
+        }
+    }
+}
+
+// how well a function matches a word, the lower the better: its name
+// has it in it, or has its letters in order (like "asq" in "assert-eq"),
+// or its description mentions it
+fn closeness(word: &str, name: &str, doc: Option<&str>) -> Option<usize> {
+    let word = word.to_lowercase();
+    let name = name.to_lowercase();
+
+    if name == word {
+        return Some(0);
+    } else if name.contains(&word) {
+        return Some(1);
+    }
+
+    let mut letters = name.chars();
+    if word.chars().all(|c| letters.any(|l| l == c)) {
+        return Some(2);
+    }
+
+    doc.filter(|d| d.to_lowercase().contains(&word)).map(|_| 3)
+}
+
+// turn a setting on or off with "on"/"off", or show
+// its value when no argument is given
+fn toggle(name: &str, args: &[&str], setting: &mut bool) {
+    match args.first() {
+        Some(&"on") => *setting = true,
+        Some(&"off") => *setting = false,
+        Some(a) => {
+            println!("error: :{} takes 'on' or 'off', not '{}'.", name, a);
+            return;
+        },
+        None => (),
+    }
+
+    println!("{} is {}.", name, if *setting { "on" } else { "off" });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_closeness() {
+        assert_eq!(closeness("sqrt", "sqrt", None), Some(0));
+        assert_eq!(closeness("Sort", "sort", None), Some(0));
+        assert_eq!(closeness("to", "to-frac", None), Some(1));
+        assert_eq!(closeness("asq", "assert-eq", None), Some(2));
+        assert_eq!(closeness("root", "cbrt", Some("the cube root of a.")), Some(3));
+        assert_eq!(closeness("root", "cbrt", None), None);
+        assert_eq!(closeness("qs", "sqrt", None), None);
+    }
+}
@@ -0,0 +1,418 @@
+// --config FILE and --dump-config. the configuration is a list of
+// settings named after the options that set them, e.g.
+//
+//     output = "simple"
+//     mode = "infix"        # or "rpn", "dc", "forth", "hp"
+//     div-zero = "nil"
+//     precision = "decimal" # or "float", "exact"
+//     max-depth = 512
+//     max-loops = 10_000    # times around [times], [while] or [until]
+//     max-steps = 1_000_000 # tokens evaluated for each line
+//     timeout = 2.5         # seconds spent on each line
+//     max-stack = 100_000   # values the stack may hold
+//     units = "/usr/share/units/definitions.units"
+//     prelude = "/home/me/defs.pesc"
+//     no-prelude = false    # true to not include one at all
+//     rates = "/home/me/rates.csv"
+//     editor = "reedline"   # or "rustyline", "plain"
+//     persist = true        # keep the stack between sessions
+//     state-file = "/home/me/.pesc-state"
+//     errors = "json"       # or "text"
+//     edit-mode = "emacs"   # or "vi"
+//     theme = "light"       # or "dark", "none"
+//     color.string = "green" # or "bright-green", "bold", "plain", ...
+//     digits = 4            # show at most 4 digits after the point
+//     fixed-digits = 2      # show exactly 2 digits after the point
+//     sig-digits = 6        # or 6 significant digits
+//     thousands = ","       # e.g. 1,234,567
+//     engineering = true    # e.g. 12.5e3
+//     cell-width = 40       # characters of each value shown (0 for all)
+//     history-size = 1000   # lines the REPL remembers
+//     history-file = "/home/me/.pesc-history"
+//
+// there's a color.KIND for each kind of value in output::KINDS, which
+// can be any of the eight terminal colors (or a bright one), "bold",
+// "underline", "italic" or "plain". $NO_COLOR turns them all off.
+//
+// in a TOML file (just the `key = value` part of TOML), or the same
+// as a JSON object if FILE ends with .json. without --config, it's
+// $PESC_CONFIG or config.toml in ~/.config/pesc, if there is one.
+// options given on the command line win over the file, and
+// --dump-config prints what the settings end up as, in a form
+// --config can read back.
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use pesc::pesc::{DivZeroMode, NanMode, ParseMode, Precision};
+
+use crate::args::Options;
+use crate::input::{Backend, Keys};
+use crate::json::{self, Value};
+use crate::numbers::Places;
+use crate::output::{self, OutputMode};
+use crate::tty::{TermStyle, Theme};
+
+// (line, key, value), where the line is 0 for JSON
+type Setting = (usize, String, Value);
+
+// where pesc's own files go, i.e. ~/.config/pesc
+pub fn dir() -> PathBuf {
+    std::env::var("XDG_CONFIG_HOME").map(PathBuf::from)
+        .or_else(|_| std::env::var("HOME").map(|h| PathBuf::from(h).join(".config")))
+        .unwrap_or_default()
+        .join("pesc")
+}
+
+// the file read without --config
+pub fn default_file() -> PathBuf {
+    match std::env::var("PESC_CONFIG") {
+        Ok(p) => PathBuf::from(p),
+        Err(_) => dir().join("config.toml"),
+    }
+}
+
+pub fn load(path: &str, opts: &mut Options) -> Result<(), String> {
+    let data = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+
+    let settings = match path.ends_with(".json") {
+        true => match json::parse(&data) {
+            Some(Value::Object(o)) => o.into_iter().map(|(k, v)| (0, k, v)).collect(),
+            _ => return Err(format!("{}: that's not a JSON object", path)),
+        },
+        false => toml(&data).map_err(|(line, e)| format!("{}:{}: {}", path, line, e))?,
+    };
+
+    for (line, key, value) in settings {
+        set(opts, &key, &value).map_err(|e| match line {
+            0 => format!("{}: {}", path, e),
+            l => format!("{}:{}: {}", path, l, e),
+        })?;
+    }
+
+    Ok(())
+}
+
+fn set(opts: &mut Options, key: &str, value: &Value) -> Result<(), String> {
+    let wrong = || format!("{} can't be {}", key, value);
+    let flag = || match value {
+        Value::Bool(b) => Ok(*b),
+        _ => Err(wrong()),
+    };
+    let word = || value.as_str().ok_or_else(wrong);
+
+    match key {
+        "output" => opts.output = OutputMode::from_name(word()?).ok_or_else(wrong)?,
+        "verbose" => opts.verbose = flag()?,
+        "mode" => {
+            let mode = word()?;
+            if !["rpn", "infix", "dc", "forth", "hp"].contains(&mode) {
+                return Err(wrong());
+            }
+
+            opts.infix = mode == "infix";
+            opts.dc = mode == "dc";
+            opts.forth = mode == "forth";
+            opts.hp = mode == "hp";
+        },
+        "legacy-bools" => opts.legacy_bools = flag()?,
+        "case-sensitive" => opts.ignore_case = !flag()?,
+        "checked" => opts.checked_ints = flag()?,
+        "keep-on-error" => opts.keep_on_error = flag()?,
+        "parse" => opts.parse_mode = match word()? {
+            "normal" => ParseMode::Normal,
+            "strict" => ParseMode::Strict,
+            "lenient" => ParseMode::Lenient,
+            _ => return Err(wrong()),
+        },
+        "div-zero" => opts.div_zero = match word()? {
+            "inf" => DivZeroMode::Ieee,
+            "error" => DivZeroMode::Error,
+            "nil" => DivZeroMode::Nil,
+            _ => return Err(wrong()),
+        },
+        "nan" => opts.nan_mode = match word()? {
+            "error" => NanMode::Error,
+            "propagate" => NanMode::Propagate,
+            "total" => NanMode::Total,
+            _ => return Err(wrong()),
+        },
+        "precision" => opts.precision = match word()? {
+            "float" => Precision::Float,
+            "exact" => Precision::Exact,
+            "decimal" => Precision::Decimal,
+            _ => return Err(wrong()),
+        },
+        "max-depth" => opts.max_depth = match value {
+            Value::Number(n) if *n >= 1.0 && n.fract() == 0.0 => *n as usize,
+            _ => return Err(wrong()),
+        },
+        "max-loops" => opts.max_loops = match value {
+            Value::Number(n) if *n >= 0.0 && n.fract() == 0.0 => *n as usize,
+            _ => return Err(wrong()),
+        },
+        "max-steps" => opts.max_steps = match value {
+            Value::Number(n) if *n >= 0.0 && n.fract() == 0.0 => Some(*n as u64),
+            _ => return Err(wrong()),
+        },
+        "timeout" => opts.timeout = match value {
+            Value::Number(n) if *n > 0.0 && n.is_finite() => Some(Duration::from_secs_f64(*n)),
+            _ => return Err(wrong()),
+        },
+        "max-stack" => opts.max_stack = match value {
+            Value::Number(n) if *n >= 0.0 && n.fract() == 0.0 => Some(*n as usize),
+            _ => return Err(wrong()),
+        },
+        "units" => opts.units = Some(word()?.to_string()),
+        "prelude" => opts.prelude = Some(word()?.to_string()),
+        "no-prelude" => opts.no_prelude = flag()?,
+        "rates" => opts.rates = Some(word()?.to_string()),
+        "editor" => opts.editor = Backend::from_name(word()?)?,
+        "persist" => opts.persist = flag()?,
+        "state-file" => opts.state_file = Some(word()?.to_string()),
+        "errors" => opts.json_errors = match word()? {
+            "text" => false,
+            "json" => true,
+            _ => return Err(wrong()),
+        },
+        "edit-mode" => opts.keys = Keys::from_name(word()?)?,
+        "theme" => opts.theme = Theme::from_name(word()?)?,
+        "digits" => opts.digits = match value {
+            Value::Number(n) if *n >= 0.0 && n.fract() == 0.0 => Some(*n as usize),
+            _ => return Err(wrong()),
+        },
+        "fixed-digits" => opts.numbers.places = match value {
+            Value::Number(n) if *n >= 0.0 && n.fract() == 0.0 => Some(Places::Fixed(*n as usize)),
+            _ => return Err(wrong()),
+        },
+        "sig-digits" => opts.numbers.places = match value {
+            Value::Number(n) if *n >= 1.0 && n.fract() == 0.0 => Some(Places::Significant(*n as usize)),
+            _ => return Err(wrong()),
+        },
+        "thousands" => {
+            let mut chars = word()?.chars();
+            opts.numbers.thousands = match (chars.next(), chars.next()) {
+                (c, None) => c,
+                _ => return Err(wrong()),
+            };
+        },
+        "engineering" => opts.numbers.engineering = flag()?,
+        "cell-width" => opts.cell_width = match value {
+            Value::Number(n) if *n >= 0.0 && n.fract() == 0.0 => *n as usize,
+            _ => return Err(wrong()),
+        },
+        "history-file" => opts.history_file = Some(word()?.to_string()),
+        "history-size" => opts.history_size = match value {
+            Value::Number(n) if *n >= 0.0 && n.fract() == 0.0 => *n as usize,
+            _ => return Err(wrong()),
+        },
+        _ => match key.strip_prefix("color.").and_then(|k| output::KINDS.iter().position(|n| *n == k)) {
+            Some(i) => opts.colors[i] = TermStyle::from_name(word()?)?,
+            None => return Err(format!("I don't know what {} is.", key)),
+        },
+    }
+
+    Ok(())
+}
+
+pub fn dump(opts: &Options) -> String {
+    let mode = match (opts.infix, opts.dc, opts.forth, opts.hp) {
+        (true, ..) => "infix",
+        (_, true, ..) => "dc",
+        (_, _, true, _) => "forth",
+        (.., true) => "hp",
+        _ => "rpn",
+    };
+
+    let mut settings = vec![
+        ("output", word(opts.output.name())),
+        ("verbose", Value::Bool(opts.verbose)),
+        ("mode", word(mode)),
+        ("legacy-bools", Value::Bool(opts.legacy_bools)),
+        ("case-sensitive", Value::Bool(!opts.ignore_case)),
+        ("checked", Value::Bool(opts.checked_ints)),
+        ("keep-on-error", Value::Bool(opts.keep_on_error)),
+        ("parse", word(match opts.parse_mode {
+            ParseMode::Normal => "normal",
+            ParseMode::Strict => "strict",
+            ParseMode::Lenient => "lenient",
+        })),
+        ("div-zero", word(match opts.div_zero {
+            DivZeroMode::Ieee => "inf",
+            DivZeroMode::Error => "error",
+            DivZeroMode::Nil => "nil",
+        })),
+        ("nan", word(match opts.nan_mode {
+            NanMode::Error => "error",
+            NanMode::Propagate => "propagate",
+            NanMode::Total => "total",
+        })),
+        ("precision", word(match opts.precision {
+            Precision::Float => "float",
+            Precision::Exact => "exact",
+            Precision::Decimal => "decimal",
+        })),
+        ("max-depth", Value::Number(opts.max_depth as f64)),
+        ("max-loops", Value::Number(opts.max_loops as f64)),
+        ("editor", word(opts.editor.name())),
+        ("persist", Value::Bool(opts.persist)),
+        ("no-prelude", Value::Bool(opts.no_prelude)),
+        ("errors", word(if opts.json_errors { "json" } else { "text" })),
+        ("edit-mode", word(opts.keys.name())),
+        ("theme", word(opts.theme.name())),
+        ("history-size", Value::Number(opts.history_size as f64)),
+        ("engineering", Value::Bool(opts.numbers.engineering)),
+        ("cell-width", Value::Number(opts.cell_width as f64)),
+    ];
+
+    match opts.numbers.places {
+        Some(Places::Fixed(n)) => settings.push(("fixed-digits", Value::Number(n as f64))),
+        Some(Places::Significant(n)) => settings.push(("sig-digits", Value::Number(n as f64))),
+        None => (),
+    }
+
+    if let Some(c) = opts.numbers.thousands {
+        settings.push(("thousands", Value::Str(c.to_string())));
+    }
+
+    if let Some(h) = &opts.history_file {
+        settings.push(("history-file", word(h)));
+    }
+
+    if let Some(d) = opts.digits {
+        settings.push(("digits", Value::Number(d as f64)));
+    }
+
+    if let Some(n) = opts.max_steps {
+        settings.push(("max-steps", Value::Number(n as f64)));
+    }
+
+    if let Some(t) = opts.timeout {
+        settings.push(("timeout", Value::Number(t.as_secs_f64())));
+    }
+
+    if let Some(n) = opts.max_stack {
+        settings.push(("max-stack", Value::Number(n as f64)));
+    }
+
+    if let Some(u) = &opts.units {
+        settings.push(("units", word(u)));
+    }
+
+    if let Some(p) = &opts.prelude {
+        settings.push(("prelude", word(p)));
+    }
+
+    if let Some(r) = &opts.rates {
+        settings.push(("rates", word(r)));
+    }
+
+    if let Some(s) = &opts.state_file {
+        settings.push(("state-file", word(s)));
+    }
+
+    let colors = output::KINDS.iter().zip(&opts.colors)
+        .map(|(k, c)| format!("color.{} = {}\n", k, word(&c.name())));
+
+    settings.iter().map(|(k, v)| format!("{} = {}\n", k, v)).chain(colors).collect()
+}
+
+fn word(s: &str) -> Value {
+    Value::Str(String::from(s))
+}
+
+// the setting on each `key = value` line
+fn toml(data: &str) -> Result<Vec<Setting>, (usize, String)> {
+    let mut settings = Vec::new();
+
+    for (i, line) in data.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let (key, value) = match line.split_once('=') {
+            Some((k, v)) => (k.trim(), v.trim()),
+            None => return Err((i + 1, String::from("I was expecting `key = value` here."))),
+        };
+
+        let value = match value.chars().next() {
+            // json's strings are close enough to TOML's basic strings
+            Some('"') => {
+                let mut escaped = false;
+                let end = value.char_indices().skip(1).find(|(_, c)| {
+                    let end = *c == '"' && !escaped;
+                    escaped = *c == '\\' && !escaped;
+                    end
+                });
+
+                end.and_then(|(e, _)| json::parse(&value[..=e]).map(|s| (s, &value[e + 1..])))
+            },
+            Some('\'') => value[1..].split_once('\'')
+                .map(|(s, rest)| (Value::Str(s.to_string()), rest)),
+            _ => {
+                let end = value.find('#').unwrap_or(value.len());
+                let v = value[..end].trim();
+                match v {
+                    "true" => Some(Value::Bool(true)),
+                    "false" => Some(Value::Bool(false)),
+                    _ => v.replace('_', "").parse::<f64>().ok().map(Value::Number),
+                }.map(|v| (v, ""))
+            },
+        };
+
+        match value {
+            Some((v, rest)) if rest.trim().is_empty() || rest.trim().starts_with('#') =>
+                settings.push((i + 1, key.to_string(), v)),
+            _ => return Err((i + 1, format!("I can't make sense of the value of {}.", key))),
+        }
+    }
+
+    Ok(settings)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_config() {
+        let conf = toml("# pesc\nmode = \"infix\" # comment\nmax-depth = 1_000\n\
+            div-zero = 'nil'\nchecked = true\nedit-mode = \"emacs\"\ndigits = 3\nsig-digits = 4\nthousands = \"_\"\ncolor.bool = \"bright-blue\"\n").unwrap();
+
+        let mut opts = Options::new();
+        for (_, k, v) in &conf {
+            set(&mut opts, k, v).unwrap();
+        }
+
+        let dumped = dump(&opts);
+        assert!(dumped.contains("mode = \"infix\"\n"));
+        assert!(dumped.contains("max-depth = 1000\n"));
+        assert!(dumped.contains("div-zero = \"nil\"\n"));
+        assert!(dumped.contains("checked = true\n"));
+        assert!(dumped.contains("edit-mode = \"emacs\"\n"));
+        assert!(dumped.contains("digits = 3\n"));
+        assert!(dumped.contains("sig-digits = 4\n"));
+        assert!(dumped.contains("thousands = \"_\"\n"));
+        assert!(dumped.contains("color.bool = \"bright-blue\"\n"));
+        assert!(dumped.contains("color.string = \"cyan\"\n"));
+
+        // what's dumped can be read back
+        let mut again = Options::new();
+        for (_, k, v) in toml(&dumped).unwrap() {
+            set(&mut again, &k, &v).unwrap();
+        }
+        assert_eq!(dump(&again), dumped);
+
+        assert_eq!(toml("a = 1\nb\n").unwrap_err().0, 2);
+        assert!(toml("a = \"x\" y").is_err());
+        assert_eq!(toml("a = \"x\\\"y\"").unwrap()[0].2, word("x\"y"));
+        assert!(set(&mut opts, "nope", &Value::Null).is_err());
+        assert!(set(&mut opts, "mode", &word("apl")).is_err());
+        assert!(set(&mut opts, "theme", &word("neon")).is_err());
+        assert!(set(&mut opts, "color.bool", &word("neon")).is_err());
+        assert!(set(&mut opts, "color.nope", &word("red")).is_err());
+        assert!(set(&mut opts, "sig-digits", &Value::Number(0.0)).is_err());
+        assert!(set(&mut opts, "thousands", &word(",,")).is_err());
+    }
+}
@@ -0,0 +1,131 @@
+// --coverage, which records how often each token of the files
+// that were run was evaluated, and then either lists the tokens
+// that never were, or writes lcov tracefile data.
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+
+use pesc::errors::Span;
+use pesc::pesc::*;
+
+#[derive(Clone, Debug, Default)]
+pub struct Coverage {
+    files: Vec<File>,
+}
+
+#[derive(Clone, Debug)]
+struct File {
+    path: String,
+    data: String,
+    spans: Vec<PescSpans>,
+
+    // how often the token starting at each offset was run
+    hits: HashMap<usize, usize>,
+}
+
+// record which of `toks` (parsed from `data`) get run by `pesc`
+pub fn watch(cov: &Rc<RefCell<Coverage>>, pesc: &mut Pesc, path: &str,
+    data: &str, toks: &[PescToken], spans: &[PescSpans])
+{
+    let idx = {
+        let mut c = cov.borrow_mut();
+        c.files.push(File {
+            path: String::from(path), data: String::from(data),
+            spans: spans.to_vec(), hits: HashMap::new(),
+        });
+        c.files.len() - 1
+    };
+
+    let map = SpanMap::new(toks, spans);
+    let cov = cov.clone();
+
+    pesc.hooks.push(Box::new(move |_, ev| {
+        if let PescEvent::Token(t) = ev {
+            if let Some(s) = map.find(t) {
+                *cov.borrow_mut().files[idx].hits.entry(s.start).or_insert(0) += 1;
+            }
+        }
+
+        Ok(())
+    }));
+}
+
+// write lcov data to `lcov` if it's set, otherwise print a
+// summary of each file to stderr
+pub fn finish(cov: &Rc<RefCell<Coverage>>, lcov: Option<&str>) {
+    let cov = cov.borrow();
+
+    match lcov {
+        Some(path) => if let Err(e) = std::fs::write(path, cov.lcov()) {
+            eprintln!("pesc: error: {}: {}", path, e);
+        },
+        None => cov.files.iter().for_each(|f| f.report()),
+    }
+}
+
+impl Coverage {
+    fn lcov(&self) -> String {
+        let mut out = String::new();
+
+        for f in &self.files {
+            // a line counts as run as often as its most
+            // frequently run token
+            let mut lines = BTreeMap::new();
+            f.walk(&f.spans, &mut |s, hits| {
+                let line = s.line_col(&f.data).0;
+                let e = lines.entry(line).or_insert(0);
+                *e = hits.max(*e);
+                true
+            });
+
+            out += &format!("TN:\nSF:{}\n", f.path);
+            for (line, hits) in &lines {
+                out += &format!("DA:{},{}\n", line, hits);
+            }
+            out += &format!("LF:{}\nLH:{}\nend_of_record\n", lines.len(),
+                lines.values().filter(|h| **h > 0).count());
+        }
+
+        out
+    }
+}
+
+impl File {
+    // call `f` with each span and how often it was run, only
+    // descending into macros when `f` says so
+    fn walk<F>(&self, spans: &[PescSpans], f: &mut F)
+    where
+        F: FnMut(Span, usize) -> bool
+    {
+        for s in spans {
+            let hits = self.hits.get(&s.span.start).copied().unwrap_or(0);
+            if f(s.span, hits) {
+                self.walk(&s.inner, f);
+            }
+        }
+    }
+
+    // print how much of the file was run, and what wasn't (except
+    // for the insides of macros that were never run at all)
+    fn report(&self) {
+        let (mut total, mut run) = (0, 0);
+        self.walk(&self.spans, &mut |_, hits| {
+            total += 1;
+            run += (hits > 0) as usize;
+            true
+        });
+
+        let percent = if total == 0 { 100.0 } else { run as f64 * 100.0 / total as f64 };
+        eprintln!("{}: {} of {} tokens run ({:.1}%)", self.path, run, total, percent);
+
+        self.walk(&self.spans, &mut |s, hits| {
+            if hits == 0 {
+                let (line, col) = s.line_col(&self.data);
+                eprintln!("    {}:{}: never run: {}", line, col,
+                    &self.data[s.start..s.end]);
+            }
+            hits > 0
+        });
+    }
+}
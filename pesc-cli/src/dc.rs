@@ -0,0 +1,350 @@
+// --dc, which runs dc(1) code: it's translated to pesc tokens, with
+// dc's commands that pesc has no equivalent for (printing, registers,
+// the precision, running strings) provided by the "dc-" functions
+// loaded by load(). commands that dc supports but this doesn't (like
+// '?', '!' or input and output bases other than 10) are errors.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use pesc::errors::*;
+use pesc::pesc::*;
+
+type Compare = fn(f64, f64) -> bool;
+
+// shared with the REPL, which saves them along with the stack
+pub type Registers = Rc<RefCell<HashMap<char, Vec<PescToken>>>>;
+
+#[derive(Clone, Debug, Default)]
+struct Dc {
+    registers: Registers,
+
+    // digits kept after the decimal point by /, % and v
+    scale: i32,
+}
+
+pub fn translate(input: &str) -> Result<Vec<PescToken>, PescError> {
+    let chs = input.char_indices().collect::<Vec<(usize, char)>>();
+    let off = |i: usize| chs.get(i).map(|c| c.0).unwrap_or(input.len());
+    let func = |f: &str| PescToken::Func(String::from(f));
+    let reg = |c: char| PescToken::Str(c.to_string());
+
+    let err = |i: usize, kind| Err(PescError::new(Some(Span::new(off(i), off(i + 1))), None, kind));
+
+    let mut toks = Vec::new();
+    let mut i = 0;
+
+    while i < chs.len() {
+        let c = chs[i].1;
+
+        match c {
+            _ if c.is_whitespace() => (),
+            '#' => while i + 1 < chs.len() && chs[i + 1].1 != '\n' {
+                i += 1;
+            },
+
+            // numbers, which dc makes negative with a leading '_'
+            _ if c.is_ascii_digit() || c == '.' || c == '_' => {
+                let start = i;
+                i += 1;
+                while i < chs.len() && (chs[i].1.is_ascii_digit() || chs[i].1 == '.') {
+                    i += 1;
+                }
+
+                let lit = input[off(start)..off(i)].to_string();
+                let num = match lit.strip_prefix('_') {
+                    Some(n) => n.parse::<f64>().map(|n| -n),
+                    None => lit.parse::<f64>(),
+                };
+
+                match num {
+                    Ok(n) => toks.push(PescToken::Number(n)),
+                    Err(_) => return Err(PescError::new(
+                        Some(Span::new(off(start), off(i))), None,
+                        PescErrorType::InvalidNumberLit(lit, String::from("dc can't read it")))),
+                }
+                continue;
+            },
+
+            // strings, in which brackets nest
+            '[' => {
+                let (start, mut depth) = (i, 1);
+                while depth > 0 {
+                    i += 1;
+                    match chs.get(i).map(|c| c.1) {
+                        Some('[') => depth += 1,
+                        Some(']') => depth -= 1,
+                        Some(_) => (),
+                        None => return Err(PescError::new(
+                            Some(Span::new(off(start), off(start + 1))), None,
+                            PescErrorType::UnmatchedToken('['))),
+                    }
+                }
+
+                toks.push(PescToken::Str(input[off(start + 1)..off(i)].to_string()));
+            },
+
+            // commands followed by the name of a register
+            's' | 'l' | 'S' | 'L' | '<' | '>' | '=' | '!' => {
+                let mut cmd = c.to_string();
+                if c == '!' {
+                    i += 1;
+                    match chs.get(i).map(|c| c.1) {
+                        Some(o @ '<') | Some(o @ '>') | Some(o @ '=') => cmd.push(o),
+                        _ => return err(i, PescErrorType::UnexpectedToken('!')),
+                    }
+                }
+
+                i += 1;
+                let r = match chs.get(i) {
+                    Some(r) => r.1,
+                    None => return err(i, PescErrorType::ExpectedValue),
+                };
+
+                toks.push(reg(r));
+                toks.push(func(match cmd.as_str() {
+                    "s" => "dc-store", "l" => "dc-load", "S" => "dc-push", "L" => "dc-pop",
+                    "<" => "dc-lt", ">" => "dc-gt", "=" => "dc-eq",
+                    "!<" => "dc-ge", "!>" => "dc-le", _ => "dc-ne",
+                }));
+            },
+
+            // decimal is all there is
+            'I' | 'O' => toks.push(PescToken::Number(10.0)),
+
+            _ => {
+                let f = match c {
+                    '+' => "add", '-' => "sub", '*' => "mul", '^' => "pow",
+                    '/' => "dc-div", '%' => "dc-mod", '~' => "dc-divmod",
+                    'v' => "dc-sqrt", 'd' => "dup", 'r' => "swp", 'z' => "size",
+                    'c' => "dc-c", 'p' => "dc-p", 'n' => "dc-n", 'P' => "dc-n",
+                    'f' => "dc-f", 'k' => "dc-set-scale", 'K' => "dc-scale", 'x' => "dc-x",
+                    'q' => "dc-q", 'i' | 'o' => "dc-base",
+                    _ => return err(i, PescErrorType::UnknownFunction(format!("'{}'", c))),
+                };
+
+                toks.push(func(f));
+            },
+        }
+
+        i += 1;
+    }
+
+    Ok(toks)
+}
+
+pub fn load(pesc: &mut Pesc) -> Registers {
+    let dc = Rc::new(RefCell::new(Dc::default()));
+    let registers = dc.borrow().registers.clone();
+
+    // a dc function with access to the registers and scale
+    let mut def = |name: &str, f: fn(&mut Pesc, &mut Dc) -> Result<(), PescErrorType>| {
+        let dc = dc.clone();
+        pesc.load(None, name, Rc::new(Box::new(move |p| f(p, &mut dc.borrow_mut()))));
+    };
+
+    def("dc-div", |p, dc| {
+        let b = p.pop_number()?;
+        let a = p.pop_number()?;
+        if b == 0.0 {
+            return Err(PescErrorType::DivideByZero(a, b));
+        }
+
+        p.push(PescToken::Number(dc.truncate(a / b)));
+        Ok(())
+    });
+    def("dc-mod", |p, dc| {
+        let b = p.pop_number()?;
+        let a = p.pop_number()?;
+        if b == 0.0 {
+            return Err(PescErrorType::DivideByZero(a, b));
+        }
+
+        p.push(PescToken::Number(a - dc.truncate(a / b) * b));
+        Ok(())
+    });
+    def("dc-divmod", |p, dc| {
+        let b = p.pop_number()?;
+        let a = p.pop_number()?;
+        if b == 0.0 {
+            return Err(PescErrorType::DivideByZero(a, b));
+        }
+
+        let q = dc.truncate(a / b);
+        p.push(PescToken::Number(q));
+        p.push(PescToken::Number(a - q * b));
+        Ok(())
+    });
+    def("dc-sqrt", |p, dc| {
+        let a = p.pop_number()?;
+        p.push(PescToken::Number(dc.truncate(a.sqrt())));
+        Ok(())
+    });
+    def("dc-set-scale", |p, dc| {
+        dc.scale = p.pop_integer()? as i32;
+        Ok(())
+    });
+    def("dc-scale", |p, dc| {
+        p.push(PescToken::Number(dc.scale as f64));
+        Ok(())
+    });
+    def("dc-base", |p, _| {
+        match p.pop_number()? {
+            10.0 => Ok(()),
+            n => Err(PescErrorType::Other(format!("I only do base 10, not {}.", n))),
+        }
+    });
+
+    def("dc-c", |p, _| {
+        p.stack.clear();
+        Ok(())
+    });
+    def("dc-p", |p, _| {
+        println!("{}", show(p.nth_ref(0.0)?));
+        Ok(())
+    });
+    def("dc-n", |p, _| {
+        print!("{}", show(&p.pop()?));
+        io::stdout().flush().ok();
+        Ok(())
+    });
+    def("dc-f", |p, _| {
+        p.stack.iter().rev().for_each(|t| println!("{}", show(t)));
+        Ok(())
+    });
+
+    def("dc-store", |p, dc| {
+        let r = register(p)?;
+        let v = p.pop()?;
+        let mut registers = dc.registers.borrow_mut();
+        let stack = registers.entry(r).or_default();
+        stack.pop();
+        stack.push(v);
+        Ok(())
+    });
+    def("dc-push", |p, dc| {
+        let r = register(p)?;
+        let v = p.pop()?;
+        dc.registers.borrow_mut().entry(r).or_default().push(v);
+        Ok(())
+    });
+    def("dc-load", |p, dc| {
+        let r = register(p)?;
+        let v = dc.registers.borrow().get(&r).and_then(|s| s.last()).cloned();
+        p.push(v.ok_or_else(|| empty(r))?);
+        Ok(())
+    });
+    def("dc-pop", |p, dc| {
+        let r = register(p)?;
+        let v = dc.registers.borrow_mut().get_mut(&r).and_then(|s| s.pop());
+        p.push(v.ok_or_else(|| empty(r))?);
+        Ok(())
+    });
+
+    // these run code, which might need the registers
+    pesc.load(None, "dc-x", Rc::new(Box::new(|p| {
+        let v = p.pop()?;
+        run(p, v)
+    })));
+    pesc.load(None, "dc-q", Rc::new(Box::new(|_| Err(PescErrorType::Aborted))));
+
+    // run a register's contents if the top two values compare
+    // as asked (note that dc compares the top against the second)
+    let conds: [(&str, Compare); 6] = [
+        ("dc-lt", |a, b| a < b), ("dc-gt", |a, b| a > b), ("dc-eq", |a, b| a == b),
+        ("dc-ge", |a, b| a >= b), ("dc-le", |a, b| a <= b), ("dc-ne", |a, b| a != b),
+    ];
+
+    for (name, cmp) in conds {
+        let dc = dc.clone();
+        pesc.load(None, name, Rc::new(Box::new(move |p| {
+            let r = register(p)?;
+            let a = p.pop_number()?;
+            let b = p.pop_number()?;
+
+            if !cmp(a, b) {
+                return Ok(());
+            }
+
+            let v = dc.borrow().registers.borrow().get(&r).and_then(|s| s.last()).cloned();
+            run(p, v.ok_or_else(|| empty(r))?)
+        })));
+    }
+
+    registers
+}
+
+impl Dc {
+    fn truncate(&self, n: f64) -> f64 {
+        let m = 10_f64.powi(self.scale);
+        (n * m).trunc() / m
+    }
+}
+
+// strings are run as dc code, anything else is put back
+fn run(p: &mut Pesc, v: PescToken) -> Result<(), PescErrorType> {
+    match v {
+        PescToken::Str(s) => {
+            let toks = translate(&s).map_err(|e| e.kind)?;
+            p.try_exec(PescToken::Macro(Rc::new(toks)))
+        },
+        v => {
+            p.push(v);
+            Ok(())
+        },
+    }
+}
+
+fn register(p: &mut Pesc) -> Result<char, PescErrorType> {
+    Ok(p.pop_string()?.chars().next().unwrap_or(' '))
+}
+
+fn empty(r: char) -> PescErrorType {
+    PescErrorType::Other(format!("Register '{}' is empty.", r))
+}
+
+fn show(t: &PescToken) -> String {
+    match t {
+        PescToken::Str(s) => s.clone(),
+        PescToken::Number(n) if *n < 0.0 => format!("-{}", -n),
+        t => t.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pesc::stdlib;
+
+    fn dc(input: &str) -> Vec<PescToken> {
+        let mut p = Pesc::new();
+        for f in stdlib::standard().into_iter().chain(stdlib::extended()) {
+            p.load(f.0, f.1, f.2);
+        }
+        load(&mut p);
+
+        p.eval(&translate(input).unwrap()).unwrap();
+        p.stack
+    }
+
+    #[test]
+    fn test_dc() {
+        use PescToken::Number;
+
+        assert_eq!(dc("2 3+ 4*"), vec![Number(20.0)]);
+        assert_eq!(dc("7 3/ 7 3% _7 2~"), vec![Number(2.0), Number(1.0),
+            Number(-3.0), Number(-1.0)]);
+        assert_eq!(dc("2k 7 3/ K"), vec![Number(2.33), Number(2.0)]);
+        assert_eq!(dc("1sa 2Sa la La La"), vec![Number(2.0), Number(2.0), Number(1.0)]);
+
+        // count down from 5, leaving 5 4 3 2 1
+        assert_eq!(dc("[d1-d1!>a]sa 5lax c 3 2 [[yes]]sb<b"),
+            vec![PescToken::Str(String::from("yes"))]);
+        assert_eq!(dc("[d1-d1!>a]sa 5lax").len(), 6);
+
+        assert!(translate("3 ?").is_err());
+        assert!(translate("[1 [2]").is_err());
+    }
+}
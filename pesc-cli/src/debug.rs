@@ -0,0 +1,124 @@
+// the step debugger behind ":debug <code>", which stops
+// before every token and waits to be told what to do, and
+// the sub-prompt that the `break` word drops into.
+
+use std::io::{self, BufRead, Write};
+
+use pesc::errors::*;
+use pesc::pesc::*;
+
+use crate::output::OutputMode;
+
+const HELP: &str = "s[tep], n[ext] (step over macros), c[ontinue], q[uit]";
+const BREAK_HELP: &str = "run code on the stack, or :c[ontinue], :q[uit]";
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Stepping {
+    Step,
+
+    // stop again once we're back at this depth
+    Over(usize),
+
+    Continue,
+}
+
+pub fn run(code: &str, pesc: &mut Pesc, output: OutputMode) {
+    let parsed = match pesc.parse_code(code) {
+        Ok(r) => r,
+        Err(e) => {
+            println!("error: {}", e);
+            return;
+        },
+    };
+
+    let mut stepping = Stepping::Step;
+    pesc.hooks.push(Box::new(move |p, ev| {
+        // breakpoints are left to the REPL's own hook
+        let t = match ev {
+            PescEvent::Token(t) => t,
+            _ => return Ok(()),
+        };
+
+        match stepping {
+            Stepping::Continue => return Ok(()),
+            Stepping::Over(d) if p.depth() > d => return Ok(()),
+            _ => (),
+        }
+
+        output.format_stack(&p.stack);
+        loop {
+            print!("{:>1$}{2}  debug> ", "", p.depth() * 2, t);
+            io::stdout().flush().ok();
+
+            let mut line = String::new();
+            match io::stdin().lock().read_line(&mut line) {
+                Ok(0) | Err(_) => return Err(PescErrorType::Aborted),
+                Ok(_) => (),
+            }
+
+            stepping = match line.trim() {
+                "" | "s" | "step" => Stepping::Step,
+                "n" | "next" => Stepping::Over(p.depth()),
+                "c" | "continue" => Stepping::Continue,
+                "q" | "quit" => return Err(PescErrorType::Aborted),
+                _ => {
+                    println!("{}", HELP);
+                    continue;
+                },
+            };
+
+            return Ok(());
+        }
+    }));
+
+    if let Err((_, e)) = pesc.eval(&parsed) {
+        println!("error: {}", e);
+    }
+
+    pesc.hooks.pop();
+    output.format_stack(&pesc.stack);
+}
+
+// a hook that only stops at breakpoints
+pub fn hook(output: OutputMode) -> Box<PescHook> {
+    Box::new(move |p, ev| match ev {
+        PescEvent::Break => breakpoint(p, output),
+        _ => Ok(()),
+    })
+}
+
+// let the user poke at the stack until they're ready to go on
+fn breakpoint(p: &mut Pesc, output: OutputMode) -> Result<(), PescErrorType> {
+    println!("stopped at a breakpoint ({}).", BREAK_HELP);
+    output.format_stack(&p.stack);
+
+    loop {
+        print!("break> ");
+        io::stdout().flush().ok();
+
+        let mut line = String::new();
+        match io::stdin().lock().read_line(&mut line) {
+            Ok(0) | Err(_) => return Err(PescErrorType::Aborted),
+            Ok(_) => (),
+        }
+
+        match line.trim() {
+            ":c" | ":continue" => return Ok(()),
+            ":q" | ":quit" => return Err(PescErrorType::Aborted),
+            "" => (),
+            code => {
+                let res = p.parse_code(code)
+                    .map_err(|e| e.kind)
+                    .and_then(|parsed| p.eval(&parsed).map_err(|(_, e)| e.kind));
+
+                if let Err(e) = res {
+                    println!("error: {}", e);
+                }
+
+                p.warnings.clear();
+            },
+        }
+
+        output.format_stack(&p.stack);
+    }
+}
@@ -0,0 +1,152 @@
+// `pesc doc`, which prints a reference for every function pesc knows
+// (the builtins, the ones the CLI adds and those in the prelude), made
+// from what they were documented and declared with, so that it can't
+// fall behind: as markdown, or with `pesc doc man`, as a man page, e.g.
+//
+//     pesc doc man > pesc-functions.7
+//
+// the examples in stdlib::examples() are run to show what they leave.
+
+use pesc::pesc::*;
+use pesc::stdlib;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DocFormat {
+    Markdown,
+    Man,
+}
+
+// what there is to say about a function
+struct Entry {
+    name: String,
+    signature: Option<String>,
+    description: String,
+    operators: Vec<String>,
+
+    // code, and the stack it leaves
+    examples: Vec<(String, String)>,
+}
+
+pub fn run(pesc: &Pesc, format: DocFormat) {
+    let entries = entries(pesc);
+    print!("{}", match format {
+        DocFormat::Markdown => markdown(&entries),
+        DocFormat::Man => man(&entries),
+    });
+}
+
+fn entries(pesc: &Pesc) -> Vec<Entry> {
+    let mut names = pesc.funcs.keys().collect::<Vec<&String>>();
+    names.sort();
+
+    names.into_iter()
+        .map(|name| {
+            let doc = pesc.docs.get(name);
+            let description = match (doc, pesc.definition(name)) {
+                (Some(d), _) => d.description.clone(),
+                (None, Some(code)) => format!("defined as `{}`.", code),
+                (None, None) => String::from("doesn't say what it does."),
+            };
+
+            Entry {
+                name: name.clone(),
+                signature: doc.map(|d| d.signature.clone()),
+                description,
+                operators: pesc.operators(name),
+                examples: stdlib::examples().into_iter()
+                    .filter(|(n, _)| n == name)
+                    .map(|(_, code)| (code.to_string(), example(pesc, code)))
+                    .collect(),
+            }
+        })
+        .collect()
+}
+
+// what some code leaves on the stack, in a pesc of its own
+fn example(pesc: &Pesc, code: &str) -> String {
+    let mut p = Pesc::with_stdlib();
+    p.opts = pesc.opts.clone();
+
+    match p.run(code) {
+        Ok(()) => p.stack.iter().map(|t| t.to_string()).collect::<Vec<String>>().join(" "),
+        Err(e) => format!("error: {}", e),
+    }
+}
+
+fn markdown(entries: &[Entry]) -> String {
+    let mut out = String::from("# pesc functions\n");
+
+    for e in entries {
+        out += &format!("\n## `[{}]`\n\n", e.name);
+        if let Some(sig) = &e.signature {
+            out += &format!("`( {} )`", sig);
+        }
+        if !e.operators.is_empty() {
+            let ops = e.operators.iter().map(|o| format!("`{}`", o)).collect::<Vec<String>>();
+            out += &format!("{}operators: {}", if e.signature.is_some() { ", " } else { "" }, ops.join(" "));
+        }
+        if e.signature.is_some() || !e.operators.is_empty() {
+            out += "\n\n";
+        }
+
+        out += &format!("{}\n", e.description);
+        for (code, result) in &e.examples {
+            out += &format!("\n    {}  # {}\n", code, result);
+        }
+    }
+
+    out
+}
+
+fn man(entries: &[Entry]) -> String {
+    let mut out = String::from(".TH PESC-FUNCTIONS 7\n\
+        .SH NAME\npesc-functions \\- what each of pesc's functions does\n\
+        .SH FUNCTIONS\n");
+
+    for e in entries {
+        out += &format!(".TP\n.B [{}]", roff(&e.name));
+        if let Some(sig) = &e.signature {
+            out += &format!(" ( {} )", roff(sig));
+        }
+        out += &format!("\n{}\n", roff(&e.description));
+
+        if !e.operators.is_empty() {
+            out += &format!(".br\noperators: {}\n", roff(&e.operators.join(" ")));
+        }
+        for (code, result) in &e.examples {
+            out += &format!(".br\n\\fI{}\\fR  # {}\n", roff(code), roff(result));
+        }
+    }
+
+    out
+}
+
+// text that troff won't take for a request or an escape
+fn roff(s: &str) -> String {
+    let s = s.replace('\\', "\\e").replace('-', "\\-");
+    match s.starts_with(['.', '\'']) {
+        true => format!("\\&{}", s),
+        false => s,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_docgen() {
+        let mut p = Pesc::with_stdlib();
+        p.run("{2 *} \"twice\" [def]").unwrap();
+        let entries = entries(&p);
+
+        let md = markdown(&entries);
+        assert!(md.contains("\n## `[add]`\n\n`( a b -- a+b )`, operators: `+`\n\nadds two numbers.\n\n    1 2 +  # 3\n"));
+        assert!(md.contains("\n## `[twice]`\n\ndefined as `{2 *} \"twice\" [def]`.\n"));
+
+        let man = man(&entries);
+        assert!(man.contains(".TP\n.B [swp] ( a b \\-\\- b a )\nswaps the top two values.\n"));
+        assert!(man.contains(".br\noperators: \\e\n"));
+        assert_eq!(roff(".x"), "\\&.x");
+    }
+}
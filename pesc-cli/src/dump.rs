@@ -0,0 +1,48 @@
+// print parsed code as a tree of tokens, for --dump-ast
+// and ":parse".
+
+use pesc::pesc::*;
+
+pub fn dump(input: &str, opts: &PescOptions) -> Result<(), String> {
+    let (toks, spans) = Pesc::parse_spans(input, opts)
+        .map_err(|e| match e.span {
+            Some(s) => {
+                let (line, col) = s.line_col(input);
+                format!("{}:{}: {}", line, col, e)
+            },
+            None => e.to_string(),
+        })?;
+
+    dump_tokens(input, &toks, &spans, 0);
+    Ok(())
+}
+
+fn dump_tokens(input: &str, toks: &[PescToken], spans: &[PescSpans], depth: usize) {
+    for (t, s) in toks.iter().zip(spans) {
+        let (line, col) = s.span.line_col(input);
+        let pos = format!("{}:{}", line, col);
+
+        let (kind, value) = match t {
+            PescToken::Str(v) => ("string", format!("{:?}", v)),
+            PescToken::Number(n) => ("number", n.to_string()),
+            PescToken::Int(n) => ("integer", n.to_string()),
+            PescToken::Decimal(d) => ("decimal", d.to_string()),
+            PescToken::Frac(r) => ("fraction", r.to_string()),
+            PescToken::Func(f) => ("function", f.clone()),
+            PescToken::Macro(m) => ("macro", format!("({} tokens)", m.len())),
+            PescToken::List(l) => ("list", format!("({} values)", l.len())),
+            PescToken::Map(m) => ("map", format!("({} keys)", m.len())),
+            PescToken::Symbol(c) => ("operator", c.to_string()),
+            PescToken::Operator(o) => ("operator", o.clone()),
+            PescToken::Bool(b) => ("boolean", b.to_string()),
+            PescToken::Unknown(c) => ("unknown", c.to_string()),
+            PescToken::Nil => ("nil", String::new()),
+        };
+
+        println!("{:<8}{:indent$}{} {}", pos, "", kind, value, indent = depth * 2);
+
+        if let PescToken::Macro(m) | PescToken::List(m) = t {
+            dump_tokens(input, m, &s.inner, depth + 1);
+        }
+    }
+}
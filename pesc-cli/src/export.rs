@@ -0,0 +1,132 @@
+// :export FILE, which writes the lines of the REPL session that ran
+// without errors as a script, so that running it gets the same stack
+// (unless a line that failed left something behind, with
+// :keep-on-error). after FILE can come
+//
+//   - dedup, to leave out a line that's the same as the one before it
+//     when it didn't change the stack (like a definition typed twice)
+//   - results, to follow each line with a comment showing the stack
+//
+// infix expressions are written as the pesc code they turn into, and
+// dc and Forth code as it is, with a note saying how to run it.
+
+use pesc::pesc::{self as interp, PescToken};
+
+use crate::args::Options;
+
+// the values of the stack shown after a line, at most
+const SHOWN: usize = 5;
+
+struct Entry {
+    code: String,
+    stack: Vec<PescToken>,
+    changed: bool,
+}
+
+#[derive(Default)]
+pub struct History {
+    entries: Vec<Entry>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // a line that ran, with the stack from before and after it
+    pub fn record(&mut self, code: &str, before: &[PescToken], after: &[PescToken]) {
+        self.entries.push(Entry {
+            code: code.to_string(),
+            stack: after.to_vec(),
+            changed: before != after,
+        });
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn export(&self, args: &[&str], opts: &Options) -> Result<String, String> {
+        let (path, flags) = match args.split_first() {
+            Some((p, f)) => (p, f),
+            None => return Err(String::from(":export needs a FILE to write to.")),
+        };
+
+        let mut dedup = false;
+        let mut results = false;
+        for f in flags {
+            match *f {
+                "dedup" => dedup = true,
+                "results" => results = true,
+                _ => return Err(format!("I don't know what '{}' means here.", f)),
+            }
+        }
+
+        let (script, lines) = self.script(opts, dedup, results);
+        std::fs::write(path, script).map_err(|e| format!("{}: {}", path, e))?;
+        let s = if lines == 1 { "" } else { "s" };
+        Ok(format!("wrote {} line{} to {}.", lines, s, path))
+    }
+
+    // the script, and how many of the session's lines are in it
+    fn script(&self, opts: &Options, dedup: bool, results: bool) -> (String, usize) {
+        let comment = match opts.forth {
+            true => "\\",
+            false => "#",
+        };
+
+        let mut buf = format!("{} exported from a pesc session\n", comment);
+        match (opts.dc, opts.forth, opts.hp) {
+            (true, ..) => buf += "# run with: pescli --dc\n",
+            (_, true, _) => buf += "\\ run with: pescli --forth\n",
+            (.., true) => buf += "# run with: pescli --hp\n",
+            _ => (),
+        }
+
+        let mut lines = 0;
+        let mut last: Option<&Entry> = None;
+
+        for e in &self.entries {
+            if dedup && !e.changed && last.is_some_and(|l| l.code == e.code) {
+                continue;
+            }
+
+            buf += &e.code;
+            if results {
+                let start = e.stack.len().saturating_sub(SHOWN);
+                let more = if start > 0 { "... " } else { "" };
+                buf += &format!("  {} {}{}", comment, more, interp::unparse(&e.stack[start..]));
+            }
+
+            buf.push('\n');
+            lines += 1;
+            last = Some(e);
+        }
+
+        (buf, lines)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_export() {
+        let n = |n| PescToken::Number(n);
+        let mut h = History::new();
+        h.record("1 2", &[], &[n(1.0), n(2.0)]);
+        h.record("{[add]} \"plus\" [def]", &[n(1.0), n(2.0)], &[n(1.0), n(2.0)]);
+        h.record("{[add]} \"plus\" [def]", &[n(1.0), n(2.0)], &[n(1.0), n(2.0)]);
+        h.record("[plus]", &[n(1.0), n(2.0)], &[n(3.0)]);
+
+        let opts = Options::new();
+        let (script, lines) = h.script(&opts, false, false);
+        assert_eq!(lines, 4);
+        assert!(script.ends_with("[def]\n[plus]\n"));
+
+        let (script, lines) = h.script(&opts, true, true);
+        assert_eq!(lines, 3);
+        assert!(script.ends_with("1 2  # 1 2\n{[add]} \"plus\" [def]  # 1 2\n[plus]  # 3\n"));
+    }
+}
@@ -0,0 +1,300 @@
+// `pesc fmt`, which reprints code with one space between tokens
+// (except those that would mean something else apart, like the T in
+// pT), nested macros indented, and comments tidied up. line breaks and
+// (single) blank lines are kept where they were, and lines longer
+// than WIDTH are broken up (a macro that doesn't fit on the rest of
+// its line is spread over lines of its own, as though it already was).
+
+use pesc::errors::PescError;
+use pesc::pesc::*;
+
+const INDENT: &str = "    ";
+const WIDTH: usize = 80;
+
+struct Formatter<'a> {
+    input: &'a str,
+    opts: &'a PescOptions,
+    out: String,
+    depth: usize,
+
+    // whether tokens with nothing between them may be spaced out
+    // (when that doesn't change what they are)
+    split: bool,
+
+    // at the start of a line, and with no space before the next
+    // word (e.g. just after a '{')
+    fresh: bool,
+    glue: bool,
+
+    // in a comment, which a line can't be broken in
+    comment: bool,
+    width: usize,
+}
+
+pub fn format(input: &str, opts: &PescOptions) -> Result<String, PescError> {
+    let (toks, spans) = Pesc::parse_spans(input, opts)?;
+
+    let format = |split| {
+        let mut f = Formatter {
+            input, opts, out: String::new(), depth: 0, split,
+            fresh: true, glue: false, comment: false, width: WIDTH,
+        };
+
+        f.block(&toks, &spans, 0, input.len());
+        f.newline(false);
+        f.out
+    };
+
+    // (if spacing tokens out changed what the code means anyway,
+    // leave all of those that were together as they were)
+    let out = format(true);
+    match Pesc::parse_with(&out, opts) {
+        Ok((_, t)) if t == toks => Ok(out),
+        _ => Ok(format(false)),
+    }
+}
+
+// format each file in place, or with `check`, only say which
+// ones aren't formatted. returns whether everything went well.
+pub fn run(files: &[String], opts: &PescOptions, check: bool) -> bool {
+    let mut ok = true;
+
+    for path in files {
+        let data = match std::fs::read_to_string(path) {
+            Ok(d) => d,
+            Err(e) => {
+                println!("pesc: error: {}: {}", path, e);
+                ok = false;
+                continue;
+            },
+        };
+
+        let formatted = match format(&data, opts) {
+            Ok(f) => f,
+            Err(e) => {
+                match e.span {
+                    Some(s) => {
+                        let (line, col) = s.line_col(&data);
+                        println!("pesc: error: {}:{}:{}: {}", path, line, col, e);
+                    },
+                    None => println!("pesc: error: {}: {}", path, e),
+                }
+                ok = false;
+                continue;
+            },
+        };
+
+        if formatted == data {
+            continue;
+        }
+
+        if check {
+            println!("{} isn't formatted.", path);
+            ok = false;
+        } else if let Err(e) = std::fs::write(path, formatted) {
+            println!("pesc: error: {}: {}", path, e);
+            ok = false;
+        }
+    }
+
+    ok
+}
+
+impl Formatter<'_> {
+    fn block(&mut self, toks: &[PescToken], spans: &[PescSpans],
+        mut pos: usize, end: usize)
+    {
+        let mut prev: Option<&str> = None;
+        for (t, s) in toks.iter().zip(spans) {
+            self.gap(&self.input[pos..s.span.start]);
+            let text = &self.input[s.span.start..s.span.end];
+
+            if let Some(p) = prev.filter(|_| pos == s.span.start) {
+                self.glue = self.glue || !self.separable(p, text);
+            }
+            prev = Some(text);
+
+            if let PescToken::Macro(m) = t {
+                // macros that span several lines get their body
+                // indented on lines of its own
+                // (as do those that don't fit, unless this is measuring one)
+                let multi = text.contains('\n')
+                    || (self.width < usize::MAX && !self.fits(self.one_line(t, s)));
+                let close = match text.ends_with('}') {
+                    true => s.span.end - 1,
+                    false => s.span.end,
+                };
+
+                self.word("{");
+                self.glue = true;
+                if multi {
+                    self.depth += 1;
+                    self.newline(false);
+                }
+
+                self.block(m, &s.inner, s.span.start + 1, close);
+
+                if multi {
+                    self.depth -= 1;
+                    self.newline(false);
+                }
+                self.glue = true;
+                self.word("}");
+            } else {
+                self.word(text);
+            }
+
+            pos = s.span.end;
+        }
+
+        self.gap(&self.input[pos..end]);
+    }
+
+    // whether two tokens that are together mean the same with a
+    // space between them (pT doesn't, as T on its own is true)
+    fn separable(&self, a: &str, b: &str) -> bool {
+        let parse = |s: String| Pesc::parse_with(&s, self.opts).ok().map(|(_, t)| t);
+        self.split && parse(format!("{}{}", a, b)) == parse(format!("{} {}", a, b))
+    }
+
+    // how long a macro would be, all on one line
+    fn one_line(&self, t: &PescToken, s: &PescSpans) -> usize {
+        let mut f = Formatter {
+            input: self.input, opts: self.opts, out: String::new(), depth: 0, split: self.split,
+            fresh: true, glue: false, comment: false, width: usize::MAX,
+        };
+
+        f.block(std::slice::from_ref(t), std::slice::from_ref(s), s.span.start, s.span.end);
+        f.out.chars().count()
+    }
+
+    // whether `len` more characters (and a space) fit on the line
+    fn fits(&self, len: usize) -> bool {
+        let column = match self.fresh {
+            true => INDENT.len() * self.depth,
+            false => self.out.rsplit('\n').next().unwrap_or("").chars().count() + 1,
+        };
+
+        column + len <= self.width
+    }
+
+    // whatever is between two tokens: whitespace and comments
+    fn gap(&mut self, text: &str) {
+        let mut lines = 0;
+        let mut chars = text.chars();
+
+        while let Some(c) = chars.next() {
+            match c {
+                '\n' => lines += 1,
+                '#' => {
+                    if lines > 0 {
+                        self.newline(lines > 1);
+                        lines = 0;
+                    }
+
+                    // comments end at a newline or a '\'
+                    let mut comment = String::new();
+                    let mut inline = false;
+                    for c in chars.by_ref() {
+                        match c {
+                            '\n' => { lines = 1; break; },
+                            '\\' => { inline = true; break; },
+                            _ => comment.push(c),
+                        }
+                    }
+
+                    match comment.trim() {
+                        "" => self.word("#"),
+                        c => self.word(&format!("# {}", c)),
+                    }
+
+                    if inline {
+                        self.word("\\");
+                    }
+                },
+                _ => (),
+            }
+        }
+
+        if lines > 0 {
+            self.newline(lines > 1);
+        }
+    }
+
+    fn word(&mut self, w: &str) {
+        let breakable = !self.fresh && !self.glue && !self.comment && w != "\\";
+        if breakable && !self.fits(w.chars().count()) {
+            self.newline(false);
+        }
+
+        if self.fresh {
+            self.out.push_str(&INDENT.repeat(self.depth));
+        } else if !self.glue {
+            self.out.push(' ');
+        }
+
+        self.out.push_str(w);
+        self.fresh = false;
+        self.glue = false;
+        self.comment = match w {
+            "\\" => false,
+            w => self.comment || w.starts_with('#'),
+        };
+    }
+
+    fn newline(&mut self, blank: bool) {
+        if self.out.is_empty() {
+            return;
+        }
+
+        if !self.fresh {
+            self.out.push('\n');
+            self.fresh = true;
+        }
+
+        if blank && !self.out.ends_with("\n\n") {
+            self.out.push('\n');
+        }
+
+        self.glue = false;
+        self.comment = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        let opts = PescOptions::default();
+        let fmt = |s: &str| format(s, &opts).unwrap();
+
+        assert_eq!(fmt("1  2+{ 3 4 }  ;"), "1 2 + {3 4} ;\n");
+        assert_eq!(fmt("#hi   \n\n\n\n1 #a\\ 2 #b  "), "# hi\n\n1 # a \\ 2 # b\n");
+        assert_eq!(fmt("{1\n{2\n3}\n\n4}\"f\"[def]\n"),
+            "{\n    1\n    {\n        2\n        3\n    }\n\n    4\n} \"f\" [def]\n");
+        assert_eq!(fmt("1_000 \"a b\" [x]"), "1_000 \"a b\" [x]\n");
+
+        let once = fmt("{1 #x\n{2}}\n");
+        assert_eq!(fmt(&once), once);
+        assert!(format("{1 2}}", &opts).is_err());
+
+        let long = format!("{} # {}\\ 1", "12345 ".repeat(14), "x ".repeat(10));
+        assert_eq!(fmt(&long), format!("{}12345\n12345 # {}\\ 1\n",
+            "12345 ".repeat(12), "x ".repeat(10)));
+        let mac = format!("{{{}}} \"f\" [def]", "[dup] ".repeat(14).trim_end());
+        assert_eq!(fmt(&mac), format!("{{\n    {}\n    [dup] [dup]\n}} \"f\" [def]\n",
+            "[dup] ".repeat(12).trim_end()));
+        assert_eq!(fmt(&fmt(&mac)), fmt(&mac));
+
+        assert_eq!(fmt("pT 1 2+ TF"), "pT 1 2 + TF\n");
+
+        // formatting never changes what the code means
+        for code in ["pT", "TFx", "p T", "trueT", "3-2 _5", "{\\*}\"sq\"[def]", "1.5.5"] {
+            let parse = |s: &str| Pesc::parse_with(s, &opts).map(|(_, t)| t).ok();
+            let formatted = format(code, &opts).unwrap_or_else(|_| String::from(code));
+            assert_eq!(parse(&formatted), parse(code), "{:?}", code);
+        }
+    }
+}
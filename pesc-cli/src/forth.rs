@@ -0,0 +1,373 @@
+// --forth, which runs a subset of Forth: definitions (`: name ... ;`),
+// IF/ELSE/THEN, comments, the usual stack words, integer arithmetic,
+// comparisons and printing. words are translated to pesc tokens, and
+// the ones pesc has no equivalent for are the "forth-" functions loaded
+// by load(). words that aren't Forth are looked up as pesc functions,
+// so [sqrt] and friends still work (as `sqrt`).
+//
+// flags are -1 for true and 0 for false, as in Forth, and any other
+// number counts as true.
+
+use std::io::{self, Write};
+use std::rc::Rc;
+
+use pesc::errors::*;
+use pesc::pesc::*;
+
+type Compare = fn(f64, f64) -> bool;
+
+// translated words, and the word that ended them (if any)
+type Block<'a> = (Vec<PescToken>, Option<(usize, &'a str)>);
+
+struct Words<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> Words<'a> {
+    // the next word, and where it starts
+    fn next(&mut self) -> Option<(usize, &'a str)> {
+        let rest = &self.input[self.pos..];
+        let start = self.pos + rest.find(|c: char| !c.is_whitespace())?;
+        let len = self.input[start..].find(char::is_whitespace)
+            .unwrap_or(self.input.len() - start);
+
+        self.pos = start + len;
+        Some((start, &self.input[start..self.pos]))
+    }
+
+    // everything up to `end` (which is skipped), after the space
+    // following the word that was just read
+    fn until(&mut self, end: char) -> Option<&'a str> {
+        let start = (self.pos + 1).min(self.input.len());
+        let len = self.input[start..].find(end)?;
+
+        self.pos = start + len + end.len_utf8();
+        Some(&self.input[start..start + len])
+    }
+}
+
+pub fn translate(input: &str) -> Result<Vec<PescToken>, PescError> {
+    let mut words = Words { input, pos: 0 };
+    let (toks, end) = block(&mut words, &[])?;
+
+    match end {
+        None => Ok(toks),
+        Some((s, w)) => Err(unexpected(s, w)),
+    }
+}
+
+// translate words until one of `ends` (or the end of the input), which
+// is returned along with where it was found
+fn block<'a>(words: &mut Words<'a>, ends: &[&str]) -> Result<Block<'a>, PescError> {
+    let func = |f: &str| PescToken::Func(String::from(f));
+    let mut toks = Vec::new();
+
+    while let Some((start, word)) = words.next() {
+        let lower = word.to_lowercase();
+        let span = Some(Span::new(start, start + word.len()));
+
+        if ends.contains(&lower.as_str()) {
+            return Ok((toks, Some((start, word))));
+        }
+
+        match lower.as_str() {
+            "\\" => {
+                words.until('\n');
+            },
+            "(" => if words.until(')').is_none() {
+                return Err(PescError::new(span, None, PescErrorType::UnmatchedToken(')')));
+            },
+            ".\"" | ".(" => {
+                let end = if lower == ".\"" { '"' } else { ')' };
+                match words.until(end) {
+                    Some(s) => toks.push(PescToken::Str(s.to_string())),
+                    None => return Err(PescError::new(span, None,
+                        PescErrorType::UnmatchedToken(end))),
+                }
+                toks.push(func("forth-type"));
+            },
+
+            ":" => {
+                let name = match words.next() {
+                    Some((_, n)) => n.to_lowercase(),
+                    None => return Err(PescError::new(span, None, PescErrorType::EmptyLiteral)),
+                };
+
+                match block(words, &[";"])? {
+                    (body, Some(_)) => {
+                        toks.push(PescToken::Macro(Rc::new(body)));
+                        toks.push(PescToken::Str(name));
+                        toks.push(func("def"));
+                    },
+                    (_, None) => return Err(PescError::new(span, None,
+                        PescErrorType::UnmatchedToken(';'))),
+                }
+            },
+
+            "if" => {
+                let missing = || PescError::new(span, None,
+                    PescErrorType::Other(String::from("Where's the matching THEN?")));
+
+                let (then, end) = block(words, &["else", "then"])?;
+                let otherwise = match end {
+                    Some((_, e)) if e.eq_ignore_ascii_case("else") => match block(words, &["then"])? {
+                        (o, Some(_)) => o,
+                        (_, None) => return Err(missing()),
+                    },
+                    Some(_) => Vec::new(),
+                    None => return Err(missing()),
+                };
+
+                toks.push(PescToken::Macro(Rc::new(then)));
+                toks.push(PescToken::Macro(Rc::new(otherwise)));
+                toks.push(func("forth-if"));
+            },
+            ";" | "else" | "then" => return Err(unexpected(start, word)),
+
+            // a few words that are just a number and an operation
+            "1+" | "1-" | "2*" | "2/" | "negate" => {
+                let (n, f) = match lower.as_str() {
+                    "1+" => (1.0, "add"), "1-" => (1.0, "sub"), "2*" => (2.0, "mul"),
+                    "2/" => (2.0, "forth-div"), _ => (-1.0, "mul"),
+                };
+
+                toks.push(PescToken::Number(n));
+                toks.push(func(f));
+            },
+
+            w if number(w).is_some() => toks.extend(number(w).map(PescToken::Number)),
+            w => toks.push(func(match w {
+                "+" => "add", "-" => "sub", "*" => "mul", "/" => "forth-div",
+                "mod" => "forth-mod", "/mod" => "forth-divmod",
+                "drop" => "pop", "swap" => "swp",
+                "=" => "forth-eq", "<>" => "forth-ne", "<" => "forth-lt", ">" => "forth-gt",
+                "0=" => "forth-0eq", "0<" => "forth-0lt",
+                "and" => "forth-and", "or" => "forth-or", "xor" => "forth-xor",
+                "invert" => "forth-invert",
+                "." => "forth-dot", ".s" => "forth-dot-s", "emit" => "forth-emit",
+                "cr" => "forth-cr", "space" => "forth-space",
+                "over" => "forth-over", "rot" => "forth-rot", "nip" => "forth-nip",
+                "tuck" => "forth-tuck", "2dup" => "forth-2dup", "2drop" => "forth-2drop",
+                w => w,
+            })),
+        }
+    }
+
+    Ok((toks, None))
+}
+
+// anything that doesn't look like a number is a word, even if it
+// starts with digits (like 2dup)
+fn number(w: &str) -> Option<f64> {
+    let digits = w.strip_prefix('-').unwrap_or(w);
+    match digits.starts_with(|c: char| c.is_ascii_digit()) {
+        true => w.parse::<f64>().ok(),
+        false => None,
+    }
+}
+
+fn unexpected(start: usize, word: &str) -> PescError {
+    PescError::new(Some(Span::new(start, start + word.len())), None,
+        PescErrorType::Other(format!("What's that {} doing there?", word.to_uppercase())))
+}
+
+pub fn load(pesc: &mut Pesc) {
+    let mut def = |name: &str, f: fn(&mut Pesc) -> Result<(), PescErrorType>| {
+        pesc.load(None, name, Rc::new(Box::new(f)));
+    };
+
+    def("forth-if", |p| {
+        let otherwise = p.pop()?;
+        let then = p.pop()?;
+
+        match p.pop_number()? != 0.0 {
+            true => p.try_exec(then),
+            false => p.try_exec(otherwise),
+        }
+    });
+
+    // Forth only has integers, so division rounds towards zero
+    def("forth-div", |p| {
+        let (a, b) = divisor(p)?;
+        p.push(PescToken::Number((a / b).trunc()));
+        Ok(())
+    });
+    def("forth-mod", |p| {
+        let (a, b) = divisor(p)?;
+        p.push(PescToken::Number(a % b));
+        Ok(())
+    });
+    def("forth-divmod", |p| {
+        let (a, b) = divisor(p)?;
+        p.push(PescToken::Number(a % b));
+        p.push(PescToken::Number((a / b).trunc()));
+        Ok(())
+    });
+
+    def("forth-and", |p| bitwise(p, |a, b| a & b));
+    def("forth-or", |p| bitwise(p, |a, b| a | b));
+    def("forth-xor", |p| bitwise(p, |a, b| a ^ b));
+    def("forth-invert", |p| {
+        let a = p.pop_number()? as i64;
+        p.push(PescToken::Number(!a as f64));
+        Ok(())
+    });
+
+    def("forth-over", |p| {
+        let v = p.nth_ref(1.0)?.clone();
+        p.push(v);
+        Ok(())
+    });
+    def("forth-rot", |p| {
+        let c = p.pop()?;
+        let b = p.pop()?;
+        let a = p.pop()?;
+        p.push(b);
+        p.push(c);
+        p.push(a);
+        Ok(())
+    });
+    def("forth-nip", |p| {
+        let b = p.pop()?;
+        p.pop()?;
+        p.push(b);
+        Ok(())
+    });
+    def("forth-tuck", |p| {
+        let b = p.pop()?;
+        let a = p.pop()?;
+        p.push(b.clone());
+        p.push(a);
+        p.push(b);
+        Ok(())
+    });
+    def("forth-2dup", |p| {
+        let b = p.nth_ref(0.0)?.clone();
+        let a = p.nth_ref(1.0)?.clone();
+        p.push(a);
+        p.push(b);
+        Ok(())
+    });
+    def("forth-2drop", |p| {
+        p.pop()?;
+        p.pop()?;
+        Ok(())
+    });
+
+    def("forth-dot", |p| {
+        let v = p.pop()?;
+        print!("{} ", show(&v));
+        io::stdout().flush().ok();
+        Ok(())
+    });
+    def("forth-dot-s", |p| {
+        print!("<{}> ", p.stack.len());
+        p.stack.iter().for_each(|t| print!("{} ", show(t)));
+        io::stdout().flush().ok();
+        Ok(())
+    });
+    def("forth-type", |p| {
+        print!("{}", p.pop_string()?);
+        io::stdout().flush().ok();
+        Ok(())
+    });
+    def("forth-emit", |p| {
+        let c = p.pop_integer()? as u32;
+        print!("{}", char::from_u32(c).unwrap_or(char::REPLACEMENT_CHARACTER));
+        io::stdout().flush().ok();
+        Ok(())
+    });
+    def("forth-cr", |_| {
+        println!();
+        Ok(())
+    });
+    def("forth-space", |_| {
+        print!(" ");
+        io::stdout().flush().ok();
+        Ok(())
+    });
+
+    let conds: [(&str, Compare); 6] = [
+        ("forth-eq", |a, b| a == b), ("forth-ne", |a, b| a != b),
+        ("forth-lt", |a, b| a < b), ("forth-gt", |a, b| a > b),
+        ("forth-0eq", |_, b| b == 0.0), ("forth-0lt", |_, b| b < 0.0),
+    ];
+
+    for (name, cmp) in conds {
+        pesc.load(None, name, Rc::new(Box::new(move |p| {
+            let b = p.pop_number()?;
+            let a = match name {
+                "forth-0eq" | "forth-0lt" => 0.0,
+                _ => p.pop_number()?,
+            };
+
+            p.push(PescToken::Number(if cmp(a, b) { -1.0 } else { 0.0 }));
+            Ok(())
+        })));
+    }
+}
+
+fn divisor(p: &mut Pesc) -> Result<(f64, f64), PescErrorType> {
+    let b = p.pop_number()?;
+    let a = p.pop_number()?;
+    if b == 0.0 {
+        return Err(PescErrorType::DivideByZero(a, b));
+    }
+
+    Ok((a, b))
+}
+
+fn bitwise(p: &mut Pesc, f: fn(i64, i64) -> i64) -> Result<(), PescErrorType> {
+    let b = p.pop_number()? as i64;
+    let a = p.pop_number()? as i64;
+    p.push(PescToken::Number(f(a, b) as f64));
+    Ok(())
+}
+
+fn show(t: &PescToken) -> String {
+    match t {
+        PescToken::Str(s) => s.clone(),
+        t => t.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pesc::stdlib;
+
+    fn forth(input: &str) -> Vec<PescToken> {
+        let mut p = Pesc::new();
+        for f in stdlib::standard().into_iter().chain(stdlib::extended()) {
+            p.load(f.0, f.1, f.2);
+        }
+        load(&mut p);
+
+        p.eval(&translate(input).unwrap()).unwrap();
+        p.stack
+    }
+
+    #[test]
+    fn test_forth() {
+        use PescToken::Number;
+
+        assert_eq!(forth("2 3 + 4 *"), vec![Number(20.0)]);
+        assert_eq!(forth("7 2 / 7 2 MOD -7 2 /MOD"),
+            vec![Number(3.0), Number(1.0), Number(-1.0), Number(-3.0)]);
+        assert_eq!(forth("1 2 3 ROT OVER SWAP DROP"),
+            vec![Number(2.0), Number(3.0), Number(3.0)]);
+
+        assert_eq!(forth(": SQUARE ( n -- n*n ) DUP * ; \\ squares\n 5 square"),
+            vec![Number(25.0)]);
+        assert_eq!(forth(": sign dup 0< IF drop -1 ELSE 0= IF 0 ELSE 1 THEN THEN ; \
+            -5 sign 0 sign 7 sign"), vec![Number(-1.0), Number(0.0), Number(1.0)]);
+        assert_eq!(forth("1 2 < 1 2 = 6 3 AND INVERT"),
+            vec![Number(-1.0), Number(0.0), Number(-3.0)]);
+
+        assert!(translate(": foo 1 2").is_err());
+        assert!(translate("1 IF 2").is_err());
+        assert!(translate("1 THEN").is_err());
+        assert!(translate(".\" oops").is_err());
+    }
+}
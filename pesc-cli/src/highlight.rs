@@ -0,0 +1,203 @@
+// highlighting code the way the REPL does as it's typed (values styled
+// the way the stack shows them, macros' braces underlined, unknown
+// operators in red), and `pesc highlight`, which prints files that way:
+// as HTML, for putting pesc in blog posts and docs, or for a terminal.
+//
+//     pesc highlight examples/fib.pesc > fib.html
+//     pesc --color always highlight ansi examples/fib.pesc | less -R
+
+use pesc::pesc::{ParseMode, Pesc, PescSpans, PescToken};
+
+use crate::output;
+use crate::tty::{self, TermColor, TermStyle, Theme};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum HighlightFormat {
+    Html,
+    Ansi,
+}
+
+// an interpreter that only parses, with `pesc`'s operators and options
+pub fn parser(pesc: &Pesc) -> Pesc {
+    let mut parser = Pesc::new();
+    parser.ops = pesc.ops.clone();
+    parser.multi_ops = pesc.multi_ops.clone();
+    parser.opts = pesc.opts.clone();
+
+    // (so that operators that mean nothing are Unknown)
+    parser.opts.parse_mode = ParseMode::Lenient;
+    parser
+}
+
+// print each file highlighted, returning whether they all were
+pub fn run(files: &[String], pesc: &Pesc, format: HighlightFormat) -> bool {
+    let mut parser = parser(pesc);
+    let mut ok = true;
+
+    for path in files {
+        let data = match std::fs::read_to_string(path) {
+            Ok(d) => d,
+            Err(e) => {
+                println!("pesc: error: {}: {}", path, e);
+                ok = false;
+                continue;
+            },
+        };
+
+        let styled = match parser.parse_code_spans(&data) {
+            Ok((toks, spans)) => styled(&toks, &spans),
+            Err(e) => {
+                match e.span {
+                    Some(s) => {
+                        let (line, col) = s.line_col(&data);
+                        println!("pesc: error: {}:{}:{}: {}", path, line, col, e);
+                    },
+                    None => println!("pesc: error: {}: {}", path, e),
+                }
+                ok = false;
+                continue;
+            },
+        };
+        parser.warnings.clear();
+
+        match format {
+            HighlightFormat::Html => println!("{}", html(&data, &styled)),
+            HighlightFormat::Ansi => print!("{}", ansi(&data, &styled)),
+        }
+    }
+
+    ok
+}
+
+// `line` highlighted for a terminal (or as it is, if it doesn't parse yet)
+pub fn line(parser: &mut Pesc, line: &str) -> String {
+    let styled = match parser.parse_code_spans(line) {
+        Ok((toks, spans)) => styled(&toks, &spans),
+        Err(_) => return String::from(line),
+    };
+    parser.warnings.clear();
+
+    ansi(line, &styled)
+}
+
+// the parts of some code to style, and how, in order
+fn styled(toks: &[PescToken], spans: &[PescSpans]) -> Vec<(usize, usize, TermStyle)> {
+    let mut styled = Vec::new();
+    styles(toks, spans, &mut styled);
+    styled.sort_by_key(|(start, _, _)| *start);
+    styled
+}
+
+// macros only have their braces underlined, so that what's in them
+// can have its own style
+fn styles(toks: &[PescToken], spans: &[PescSpans], styled: &mut Vec<(usize, usize, TermStyle)>) {
+    for (t, s) in toks.iter().zip(spans) {
+        match t {
+            PescToken::Macro(m) => {
+                styled.push((s.span.start, s.span.start + 1, TermStyle::Underline));
+                styled.push((s.span.end - 1, s.span.end, TermStyle::Underline));
+                styles(m, &s.inner, styled);
+            },
+            PescToken::List(l) => styles(l, &s.inner, styled),
+            PescToken::Str(_) | PescToken::Number(_) | PescToken::Int(_)
+            | PescToken::Decimal(_) | PescToken::Frac(_) | PescToken::Bool(_)
+            | PescToken::Unknown(_) => styled.push((s.span.start, s.span.end, output::style(t))),
+            _ => (),
+        }
+    }
+}
+
+fn ansi(code: &str, styled: &[(usize, usize, TermStyle)]) -> String {
+    let mut buf = String::new();
+    let mut at = 0;
+    for (start, end, style) in styled {
+        buf += &code[at..*start];
+        buf += &format!("{}{}{}", style, &code[*start..*end], TermStyle::Reset);
+        at = *end;
+    }
+
+    buf += &code[at..];
+    buf
+}
+
+// a <pre> with the colors of the theme (a dark one, unless it's light)
+fn html(code: &str, styled: &[(usize, usize, TermStyle)]) -> String {
+    let light = tty::theme() == Theme::Light;
+    let mut buf = format!("<pre class=\"pesc\" style=\"background: {}; color: {}\"><code>",
+        if light { "#ffffff" } else { "#000000" },
+        if light { "#000000" } else { "#e5e5e5" });
+
+    let mut at = 0;
+    for (start, end, style) in styled {
+        buf += &escape(&code[at..*start]);
+        buf += &match css(*style, light) {
+            Some(css) => format!("<span style=\"{}\">{}</span>", css, escape(&code[*start..*end])),
+            None => escape(&code[*start..*end]),
+        };
+        at = *end;
+    }
+
+    buf += &escape(code[at..].trim_end());
+    buf + "</code></pre>"
+}
+
+fn css(style: TermStyle, light: bool) -> Option<String> {
+    let color = |c: TermColor, bright: bool| match (c, bright) {
+        (TermColor::White, _) if light => "#000000",
+        (TermColor::Black, false) => "#000000",
+        (TermColor::Black, true) => "#7f7f7f",
+        (TermColor::Red, false) => "#cd0000",
+        (TermColor::Red, true) => "#ff5555",
+        (TermColor::Green, false) => "#00cd00",
+        (TermColor::Green, true) => "#55ff55",
+        (TermColor::Yellow, false) => "#cdcd00",
+        (TermColor::Yellow, true) => "#ffff55",
+        (TermColor::Blue, false) => "#0000ee",
+        (TermColor::Blue, true) => "#5c5cff",
+        (TermColor::Magenta, false) => "#cd00cd",
+        (TermColor::Magenta, true) => "#ff55ff",
+        (TermColor::Cyan, false) => "#00cdcd",
+        (TermColor::Cyan, true) => "#55ffff",
+        (TermColor::White, false) => "#e5e5e5",
+        (TermColor::White, true) => "#ffffff",
+    };
+
+    match style {
+        TermStyle::Bold => Some(String::from("font-weight: bold")),
+        TermStyle::Underline => Some(String::from("text-decoration: underline")),
+        TermStyle::Italic => Some(String::from("font-style: italic")),
+        TermStyle::Reset => None,
+        TermStyle::Fg(c) => Some(format!("color: {}", color(c, false))),
+        TermStyle::BrightFg(c) => Some(format!("color: {}", color(c, true))),
+    }
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_highlight() {
+        let mut p = parser(&Pesc::with_stdlib());
+        let (u, r) = (TermStyle::Underline, TermStyle::Reset);
+        let n = TermStyle::BrightFg(TermColor::White);
+        let c = TermStyle::Fg(TermColor::Cyan);
+
+        assert_eq!(line(&mut p, "{2 *} \"x\" [dup]"),
+            format!("{u}{{{r}{n}2{r} *{u}}}{r} {c}\"x\"{r} [dup]", u = u, r = r, n = n, c = c));
+        assert_eq!(line(&mut p, "1 `"),
+            format!("{n}1{r} {e}`{r}", n = n, r = r, e = TermStyle::Fg(TermColor::Red)));
+        assert_eq!(line(&mut p, "{1"), "{1");
+
+        let code = "\"<a>\" 1 # x\n";
+        let (toks, spans) = p.parse_code_spans(code).unwrap();
+        assert_eq!(html(code, &styled(&toks, &spans)), "<pre class=\"pesc\" \
+            style=\"background: #000000; color: #e5e5e5\"><code>\
+            <span style=\"color: #00cdcd\">&quot;&lt;a&gt;&quot;</span> \
+            <span style=\"color: #ffffff\">1</span> # x</code></pre>");
+    }
+}
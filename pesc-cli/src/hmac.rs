@@ -0,0 +1,63 @@
+// HMAC-SHA256, for signing Jupyter messages and checking their signatures
+
+use ::hmac::{Hmac, Mac};
+use ::sha2::Sha256;
+
+fn mac(key: &[u8], parts: &[&[u8]]) -> Hmac<Sha256> {
+    // (any key will do for HMAC)
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC takes keys of any length");
+    for p in parts {
+        mac.update(p);
+    }
+    mac
+}
+
+// the signature of `parts`, in hex
+pub fn sign(key: &[u8], parts: &[&[u8]]) -> String {
+    hex(&mac(key, parts).finalize().into_bytes())
+}
+
+// whether `sig` (in hex) is the signature of `parts`, in constant time
+pub fn verify(key: &[u8], parts: &[&[u8]], sig: &[u8]) -> bool {
+    match unhex(sig) {
+        Some(sig) => mac(key, parts).verify_slice(&sig).is_ok(),
+        None => false,
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn unhex(s: &[u8]) -> Option<Vec<u8>> {
+    let pairs = s.chunks_exact(2);
+    if !pairs.remainder().is_empty() {
+        return None;
+    }
+
+    pairs
+        .map(|c| u8::from_str_radix(std::str::from_utf8(c).ok()?, 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hmac() {
+        // RFC 4231, test cases 2 and 6
+        let sig = "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843";
+        assert_eq!(sign(b"Jefe", &[b"what do ya want ", b"for nothing?"]), sig);
+        assert_eq!(sign(&[0xaa; 131], &[b"Test Using Larger Than Block-Size Key - Hash Key First"]),
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54");
+
+        assert!(verify(b"Jefe", &[b"what do ya want for nothing?"], sig.as_bytes()));
+        assert!(verify(b"Jefe", &[b"what do ya want for nothing?"], sig.to_uppercase().as_bytes()));
+        assert!(!verify(b"jefe", &[b"what do ya want for nothing?"], sig.as_bytes()));
+        assert!(!verify(b"Jefe", &[b"what do ya want for nothing!"], sig.as_bytes()));
+        assert!(!verify(b"Jefe", &[b"what do ya want for nothing?"], &sig.as_bytes()[..62]));
+        assert!(!verify(b"Jefe", &[b"what do ya want for nothing?"], b""));
+        assert!(!verify(b"Jefe", &[b"what do ya want for nothing?"], b"zz"));
+    }
+}
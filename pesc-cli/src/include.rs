@@ -0,0 +1,89 @@
+// [include], which runs the pesc code in another file, e.g.
+//
+//     "lib/stats.pesc" [include]
+//
+// so that its definitions can be used. a relative path is taken
+// from the directory of the file doing the including (or the
+// current one, in the REPL).
+//
+// the prelude is included before anything else, so that what it
+// defines is always there: --prelude FILE, or else $PESC_PRELUDE or
+// prelude.pesc in ~/.config/pesc, if there is one. --no-prelude
+// leaves it out.
+
+use std::path::{Path, PathBuf};
+
+use pesc::errors::*;
+use pesc::pesc::*;
+
+use crate::config;
+
+pub fn load(pesc: &mut Pesc) {
+    pesc.register("include", |p| {
+        let path = p.pop_string()?;
+        file(p, &path)
+    });
+    pesc.document("include", "path --", "runs the pesc code in the file at path.");
+}
+
+pub fn file(pesc: &mut Pesc, path: &str) -> Result<(), PescErrorType> {
+    let dir = pesc.including.last()
+        .and_then(|f| Path::new(f).parent())
+        .map(Path::to_path_buf)
+        .unwrap_or_default();
+
+    let full = dir.join(path);
+    let data = std::fs::read_to_string(&full)
+        .map_err(|e| PescErrorType::Other(format!("{}: {}", full.display(), e)))?;
+
+    pesc.include(&name(&full), &data)
+}
+
+// where the prelude is by default
+pub fn default_prelude() -> PathBuf {
+    if let Ok(p) = std::env::var("PESC_PRELUDE") {
+        return PathBuf::from(p);
+    }
+
+    config::dir().join("prelude.pesc")
+}
+
+// what a file is called in errors (and when looking for cycles): the
+// whole path, or the part after the current directory if it's in it
+pub fn name(path: &Path) -> String {
+    let full = path.canonicalize().unwrap_or_else(|_| PathBuf::from(path));
+    let cwd = std::env::current_dir().unwrap_or_default();
+
+    match full.strip_prefix(&cwd) {
+        Ok(p) => p.display().to_string(),
+        Err(_) => full.display().to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_include() {
+        let dir = std::env::temp_dir().join(format!("pesc-include-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("lib")).unwrap();
+        std::fs::write(dir.join("main.pesc"), "\"lib/sq.pesc\" [include]").unwrap();
+        std::fs::write(dir.join("lib/sq.pesc"), "{[dup] *} \"sq\" [def]\n\"../loop.pesc\" [include]").unwrap();
+        std::fs::write(dir.join("loop.pesc"), "\"lib/sq.pesc\" [include]").unwrap();
+
+        let mut p = Pesc::with_stdlib();
+        load(&mut p);
+        let main = dir.join("main.pesc");
+        let e = file(&mut p, main.to_str().unwrap()).unwrap_err();
+        assert!(matches!(e, PescErrorType::Included(..)));
+        assert!(e.to_string().ends_with("sq.pesc is already being included, so I'd go round in circles."));
+
+        p.stack.clear();
+        p.run("3 [sq]").unwrap();
+        assert_eq!(p.stack, vec![PescToken::Number(9.0)]);
+
+        assert!(matches!(file(&mut p, "nope.pesc"), Err(PescErrorType::Other(_))));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,379 @@
+// reading lines for the REPL, with one of a few line editors behind
+// the LineEditor trait:
+//
+//   - rustyline, by default, which completes functions on Tab (and
+//     lists them on a second Tab)
+//   - reedline (--editor reedline, when built with `--features
+//     reedline`), which has real multi-line editing, a completion
+//     menu on Tab and history search on Ctrl-R
+//   - plain read_line(), when the terminal isn't up to anything else
+//     (TERM is dumb or unset, or stdin isn't a terminal) or when asked
+//     to with --no-readline. it has no hints, history or colors, but
+//     still waits for the rest of unfinished macros and strings.
+//
+// all of them keep reading, with a "....> " prompt, while a line ends
+// in the middle of a macro or a string, and hand back all of it.
+//
+// the first two remember what was typed (except for lines starting
+// with a space) in ~/.local/share/pesc/history, or --history-file.
+
+use std::io::{self, BufRead, Write};
+use std::path::PathBuf;
+
+use pesc::errors::PescErrorType;
+use pesc::pesc::Pesc;
+
+use rustyline::{
+    config::{
+        Builder,
+        CompletionType,
+        EditMode,
+    },
+    error::ReadlineError,
+    Editor,
+};
+
+use crate::args::Options;
+use crate::clihints::BustyLine;
+use crate::tty::{self, OutputStream};
+
+// the prompt for the lines after the first of something unfinished
+pub const CONTINUE: &str = "....> ";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Backend {
+    Rustyline,
+    Reedline,
+    Plain,
+}
+
+// the keys the line editors use
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Keys {
+    Vi,
+    Emacs,
+}
+
+#[derive(Debug)]
+pub enum ReadError {
+    // Ctrl-D, or the end of stdin
+    Eof,
+
+    // Ctrl-C
+    Interrupted,
+
+    Other(String),
+}
+
+pub trait LineEditor {
+    fn readline(&mut self, prompt: &str) -> Result<String, ReadError>;
+
+    // write the history out, for editors that don't as they go
+    fn save_history(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+impl Backend {
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "rustyline" => Ok(Backend::Rustyline),
+            "reedline" if cfg!(feature = "reedline") => Ok(Backend::Reedline),
+            "reedline" => Err(String::from(
+                "this pesc was built without reedline (see `cargo build --features reedline`)")),
+            "plain" => Ok(Backend::Plain),
+            _ => Err(format!("there's no line editor called '{}'", name)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Backend::Rustyline => "rustyline",
+            Backend::Reedline => "reedline",
+            Backend::Plain => "plain",
+        }
+    }
+}
+
+impl Keys {
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "vi" => Ok(Keys::Vi),
+            "emacs" => Ok(Keys::Emacs),
+            _ => Err(format!("there's no edit mode called '{}'", name)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Keys::Vi => "vi",
+            Keys::Emacs => "emacs",
+        }
+    }
+}
+
+// where the history is kept by default
+pub fn default_history() -> PathBuf {
+    if let Ok(p) = std::env::var("PESC_HISTORY") {
+        return PathBuf::from(p);
+    }
+
+    std::env::var("XDG_DATA_HOME").map(PathBuf::from)
+        .or_else(|_| std::env::var("HOME").map(|h| PathBuf::from(h).join(".local/share")))
+        .unwrap_or_default()
+        .join("pesc")
+        .join("history")
+}
+
+// the file to keep the history in, if any
+fn history_file(opts: &Options) -> Option<PathBuf> {
+    match opts.history_file.as_deref() {
+        _ if opts.history_size == 0 => None,
+        Some("") => None,
+        Some(p) => Some(PathBuf::from(p)),
+        None => Some(default_history()),
+    }
+}
+
+// whether a line editor would misbehave here
+pub fn is_dumb() -> bool {
+    let term = std::env::var("TERM").unwrap_or_default();
+    term.is_empty() || term == "dumb" || !tty::is_tty(OutputStream::Stdin)
+}
+
+// the editor to read the REPL's lines with, as the options say. pesc
+// is only used for the names to complete.
+pub fn open(opts: &Options, pesc: &Pesc) -> Box<dyn LineEditor> {
+    match opts.editor {
+        Backend::Rustyline => Box::new(Rustyline::new(opts, pesc)),
+
+        #[cfg(feature = "reedline")]
+        Backend::Reedline => Box::new(reed::Reed::new(opts, pesc)),
+
+        #[cfg(not(feature = "reedline"))]
+        Backend::Reedline => unreachable!("Backend::from_name doesn't allow reedline"),
+
+        Backend::Plain => Box::new(Plain),
+    }
+}
+
+// whether more lines are needed to finish `code`
+fn unfinished(code: &str) -> bool {
+    matches!(Pesc::parse(code), Err(e) if matches!(e.kind, PescErrorType::UnmatchedToken(_)))
+}
+
+struct Rustyline(Editor<BustyLine>, Option<PathBuf>);
+
+impl Rustyline {
+    fn new(opts: &Options, pesc: &Pesc) -> Self {
+        let config = Builder::new()
+            .history_ignore_space(true)
+            .max_history_size(opts.history_size)
+            .completion_type(CompletionType::List)
+            .edit_mode(match opts.keys {
+                Keys::Vi => EditMode::Vi,
+                Keys::Emacs => EditMode::Emacs,
+            })
+            .build();
+
+        let mut rl = Editor::<BustyLine>::with_config(config);
+        rl.set_helper(Some(BustyLine::new(pesc)));
+
+        // (there's no history the first time)
+        let history = history_file(opts);
+        if let Some(h) = &history {
+            rl.load_history(h).ok();
+        }
+
+        Rustyline(rl, history)
+    }
+}
+
+impl LineEditor for Rustyline {
+    fn readline(&mut self, prompt: &str) -> Result<String, ReadError> {
+        let mut buf = String::new();
+        let mut prompt = prompt;
+
+        loop {
+            match self.0.readline(prompt) {
+                Ok(line) => buf.push_str(&line),
+                Err(ReadlineError::Eof) if !buf.is_empty() => break,
+                Err(ReadlineError::Eof) => return Err(ReadError::Eof),
+                Err(ReadlineError::Interrupted) => return Err(ReadError::Interrupted),
+                Err(e) => return Err(ReadError::Other(e.to_string())),
+            }
+
+            match unfinished(&buf) {
+                true => { buf.push('\n'); prompt = CONTINUE; },
+                false => break,
+            }
+        }
+
+        // (all of it, as one entry, so that it comes back as one)
+        self.0.add_history_entry(buf.as_str());
+        Ok(buf)
+    }
+
+    fn save_history(&mut self) -> Result<(), String> {
+        let path = match &self.1 {
+            Some(p) => p,
+            None => return Ok(()),
+        };
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).ok();
+        }
+
+        self.0.save_history(path).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+struct Plain;
+
+impl LineEditor for Plain {
+    fn readline(&mut self, prompt: &str) -> Result<String, ReadError> {
+        let mut buf = String::new();
+        let mut prompt = prompt;
+
+        loop {
+            // (nobody's there to see a prompt if stdin's a pipe)
+            if tty::is_tty(OutputStream::Stdin) {
+                print!("{}", prompt);
+                io::stdout().flush().ok();
+            }
+
+            let read = io::stdin().lock().read_line(&mut buf)
+                .map_err(|e| ReadError::Other(e.to_string()))?;
+            if read == 0 {
+                match buf.is_empty() {
+                    true => return Err(ReadError::Eof),
+                    false => break,
+                }
+            }
+
+            match unfinished(&buf) {
+                true => prompt = CONTINUE,
+                false => break,
+            }
+        }
+
+        let len = buf.trim_end_matches(&['\n', '\r'][..]).len();
+        buf.truncate(len);
+        Ok(buf)
+    }
+}
+
+#[cfg(feature = "reedline")]
+mod reed {
+    use std::borrow::Cow;
+
+    use pesc::pesc::Pesc;
+    use reedline::{
+        default_emacs_keybindings, default_vi_insert_keybindings,
+        default_vi_normal_keybindings, ColumnarMenu, DefaultCompleter,
+        DefaultHinter, EditMode, Emacs, FileBackedHistory, KeyCode,
+        KeyModifiers, Keybindings, MenuBuilder, Prompt, PromptEditMode,
+        PromptHistorySearch, Reedline, ReedlineEvent, ReedlineMenu, Signal,
+        ValidationResult, Validator, Vi,
+    };
+
+    use crate::args::Options;
+    use super::{Keys, LineEditor, ReadError};
+
+    pub struct Reed(Reedline);
+
+    impl Reed {
+        pub fn new(opts: &Options, pesc: &Pesc) -> Self {
+            // functions are completed with their brackets, e.g. [du -> [dup]
+            let names = pesc.funcs.keys().map(|f| format!("[{}]", f)).collect();
+            let mut completer = DefaultCompleter::with_inclusions(&['[', ']', '-', '?', '!']);
+            completer.insert(names);
+
+            let keys: Box<dyn EditMode> = match opts.keys {
+                Keys::Vi => Box::new(Vi::new(bindings(default_vi_insert_keybindings()),
+                    default_vi_normal_keybindings())),
+                Keys::Emacs => Box::new(Emacs::new(bindings(default_emacs_keybindings()))),
+            };
+
+            let menu = ColumnarMenu::default().with_name("completion");
+            let mut editor = Reedline::create()
+                .with_validator(Box::new(Unfinished))
+                .with_completer(Box::new(completer))
+                .with_menu(ReedlineMenu::EngineCompleter(Box::new(menu)))
+                .with_hinter(Box::new(DefaultHinter::default()))
+                .with_edit_mode(keys);
+
+            let history = match super::history_file(opts) {
+                Some(f) => FileBackedHistory::with_file(opts.history_size, f),
+                None => FileBackedHistory::new(opts.history_size),
+            };
+
+            if let Ok(history) = history {
+                editor = editor.with_history(Box::new(history));
+            }
+
+            Reed(editor)
+        }
+    }
+
+    // the keys for typing, with Tab to complete and Ctrl-R to search
+    fn bindings(mut keys: Keybindings) -> Keybindings {
+        keys.add_binding(KeyModifiers::NONE, KeyCode::Tab, ReedlineEvent::UntilFound(vec![
+            ReedlineEvent::Menu(String::from("completion")),
+            ReedlineEvent::MenuNext,
+        ]));
+        keys.add_binding(KeyModifiers::CONTROL, KeyCode::Char('r'),
+            ReedlineEvent::SearchHistory);
+        keys
+    }
+
+    impl LineEditor for Reed {
+        fn readline(&mut self, prompt: &str) -> Result<String, ReadError> {
+            match self.0.read_line(&Plain(prompt)) {
+                Ok(Signal::Success(line)) => Ok(line),
+                Ok(Signal::CtrlC) => Err(ReadError::Interrupted),
+                Ok(Signal::CtrlD) => Err(ReadError::Eof),
+                Err(e) => Err(ReadError::Other(e.to_string())),
+            }
+        }
+    }
+
+    struct Unfinished;
+
+    impl Validator for Unfinished {
+        fn validate(&self, line: &str) -> ValidationResult {
+            match super::unfinished(line) {
+                true => ValidationResult::Incomplete,
+                false => ValidationResult::Complete,
+            }
+        }
+    }
+
+    // the prompt, as it is, with the same CONTINUE as the other readers
+    // for the lines after the first
+    struct Plain<'a>(&'a str);
+
+    impl Prompt for Plain<'_> {
+        fn render_prompt_left(&self) -> Cow<'_, str> {
+            Cow::Borrowed(self.0)
+        }
+
+        fn render_prompt_right(&self) -> Cow<'_, str> {
+            Cow::Borrowed("")
+        }
+
+        fn render_prompt_indicator(&self, _: PromptEditMode) -> Cow<'_, str> {
+            Cow::Borrowed("")
+        }
+
+        fn render_prompt_multiline_indicator(&self) -> Cow<'_, str> {
+            Cow::Borrowed(super::CONTINUE)
+        }
+
+        fn render_prompt_history_search_indicator(&self, search: PromptHistorySearch)
+            -> Cow<'_, str>
+        {
+            Cow::Owned(format!("(search: {}) ", search.term))
+        }
+    }
+}
@@ -0,0 +1,309 @@
+// just enough JSON to write out tokens and stacks, and to read
+// the messages an editor sends to `pesc lsp`
+
+use std::fmt::{self, Display};
+
+use pesc::errors::PescError;
+use pesc::pesc::*;
+
+// how deeply arrays and objects may be nested in what's read, so that
+// a message full of '[' can't overflow the stack
+const MAX_DEPTH: usize = 64;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    Str(String),
+    Array(Vec<Value>),
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    // the value of `key`, if this is an object that has it
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        match self {
+            Value::Object(o) => o.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_usize(&self) -> Option<usize> {
+        match self {
+            Value::Number(n) if *n >= 0.0 => Some(*n as usize),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            Value::Null => write!(f, "null"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Number(n) => write!(f, "{}", n),
+            Value::Str(s) => write!(f, "{}", string(s)),
+            Value::Array(a) => {
+                let items = a.iter().map(|v| v.to_string()).collect::<Vec<String>>();
+                write!(f, "[{}]", items.join(","))
+            },
+            Value::Object(o) => {
+                let items = o.iter()
+                    .map(|(k, v)| format!("{}:{}", string(k), v))
+                    .collect::<Vec<String>>();
+                write!(f, "{{{}}}", items.join(","))
+            },
+        }
+    }
+}
+
+pub fn parse(input: &str) -> Option<Value> {
+    let chs = input.chars().collect::<Vec<char>>();
+    let mut i = 0;
+
+    let v = value(&chs, &mut i, 0)?;
+    skip_space(&chs, &mut i);
+    match i == chs.len() {
+        true => Some(v),
+        false => None,
+    }
+}
+
+fn skip_space(chs: &[char], i: &mut usize) {
+    while *i < chs.len() && chs[*i].is_whitespace() {
+        *i += 1;
+    }
+}
+
+fn value(chs: &[char], i: &mut usize, depth: usize) -> Option<Value> {
+    skip_space(chs, i);
+    if depth > MAX_DEPTH {
+        return None;
+    }
+
+    let word = |i: &mut usize, w: &str, v| {
+        let end = *i + w.len();
+        match chs.get(*i..end)?.iter().collect::<String>() == w {
+            true => {
+                *i = end;
+                Some(v)
+            },
+            false => None,
+        }
+    };
+
+    match chs.get(*i)? {
+        'n' => word(i, "null", Value::Null),
+        't' => word(i, "true", Value::Bool(true)),
+        'f' => word(i, "false", Value::Bool(false)),
+        '"' => str_value(chs, i).map(Value::Str),
+        '[' => {
+            let mut items = Vec::new();
+            *i += 1;
+
+            loop {
+                skip_space(chs, i);
+                match chs.get(*i)? {
+                    ']' if items.is_empty() => break,
+                    _ => items.push(value(chs, i, depth + 1)?),
+                }
+
+                skip_space(chs, i);
+                match chs.get(*i)? {
+                    ',' => *i += 1,
+                    ']' => break,
+                    _ => return None,
+                }
+            }
+
+            *i += 1;
+            Some(Value::Array(items))
+        },
+        '{' => {
+            let mut items = Vec::new();
+            *i += 1;
+
+            loop {
+                skip_space(chs, i);
+                match chs.get(*i)? {
+                    '}' if items.is_empty() => break,
+                    '"' => (),
+                    _ => return None,
+                }
+
+                let key = str_value(chs, i)?;
+                skip_space(chs, i);
+                if chs.get(*i) != Some(&':') {
+                    return None;
+                }
+                *i += 1;
+                items.push((key, value(chs, i, depth + 1)?));
+
+                skip_space(chs, i);
+                match chs.get(*i)? {
+                    ',' => *i += 1,
+                    '}' => break,
+                    _ => return None,
+                }
+            }
+
+            *i += 1;
+            Some(Value::Object(items))
+        },
+        _ => {
+            let start = *i;
+            while *i < chs.len() && "+-0123456789.eE".contains(chs[*i]) {
+                *i += 1;
+            }
+
+            chs[start..*i].iter().collect::<String>().parse::<f64>()
+                .ok().map(Value::Number)
+        },
+    }
+}
+
+fn str_value(chs: &[char], i: &mut usize) -> Option<String> {
+    let mut buf = String::new();
+    *i += 1;
+
+    loop {
+        let c = *chs.get(*i)?;
+        *i += 1;
+
+        match c {
+            '"' => return Some(buf),
+            '\\' => {
+                let e = *chs.get(*i)?;
+                *i += 1;
+
+                buf.push(match e {
+                    'n' => '\n', 't' => '\t', 'r' => '\r',
+                    'b' => '\x08', 'f' => '\x0c',
+                    'u' => {
+                        let hex = chs.get(*i..*i + 4)?.iter().collect::<String>();
+                        *i += 4;
+
+                        // (surrogate pairs come out as U+FFFD)
+                        u32::from_str_radix(&hex, 16).ok()
+                            .map(|c| char::from_u32(c).unwrap_or(char::REPLACEMENT_CHARACTER))?
+                    },
+                    e => e,
+                });
+            },
+            c => buf.push(c),
+        }
+    }
+}
+
+pub fn string(s: &str) -> String {
+    let mut buf = String::from("\"");
+
+    for c in s.chars() {
+        match c {
+            '"' => buf.push_str("\\\""),
+            '\\' => buf.push_str("\\\\"),
+            '\n' => buf.push_str("\\n"),
+            '\t' => buf.push_str("\\t"),
+            '\r' => buf.push_str("\\r"),
+            c if (c as u32) < 0x20 => buf.push_str(&format!("\\u{:04x}", c as u32)),
+            c => buf.push(c),
+        }
+    }
+
+    buf.push('"');
+    buf
+}
+
+// numbers, strings, booleans, nil, lists and maps map to their
+// JSON counterparts; everything else becomes an object saying
+// what it is. JSON has no NaN or inf, so those are strings.
+pub fn token(t: &PescToken) -> String {
+    match t {
+        PescToken::Number(n) if n.is_finite() => n.to_string(),
+        PescToken::Number(n) => string(&n.to_string()),
+        PescToken::Int(n) => n.to_string(),
+        PescToken::Decimal(d) => d.to_string(),
+        PescToken::Frac(r) => format!("{{\"fraction\":{}}}", string(&r.to_string())),
+        PescToken::Str(s) => string(s),
+        PescToken::Bool(b) => b.to_string(),
+        PescToken::Nil => String::from("null"),
+        PescToken::Macro(m) => format!("{{\"macro\":{}}}", string(&unparse(m))),
+        PescToken::List(l) => stack(l),
+        PescToken::Map(m) => {
+            let pairs = m.iter()
+                .map(|(k, v)| format!("{}:{}", string(k), token(v)))
+                .collect::<Vec<String>>();
+            format!("{{{}}}", pairs.join(","))
+        },
+        PescToken::Func(f) => format!("{{\"func\":{}}}", string(f)),
+        PescToken::Symbol(c) | PescToken::Unknown(c) =>
+            format!("{{\"op\":{}}}", string(&c.to_string())),
+        PescToken::Operator(o) => format!("{{\"op\":{}}}", string(o)),
+    }
+}
+
+pub fn stack(s: &[PescToken]) -> String {
+    let items = s.iter().map(token).collect::<Vec<String>>();
+    format!("[{}]", items.join(","))
+}
+
+// an error as an object with its kind, message, where it happened in
+// `code` (which came from `file`, if it came from a file) and the
+// token that failed, with null for what isn't known
+pub fn error(e: &PescError, file: Option<&str>, code: &str) -> String {
+    let (line, col) = match e.span.map(|s| s.line_col(code)) {
+        Some((l, c)) => (l.to_string(), c.to_string()),
+        None => (String::from("null"), String::from("null")),
+    };
+
+    format!("{{\"kind\":{},\"message\":{},\"file\":{},\"line\":{},\"column\":{},\"token\":{}}}",
+        string(e.kind.name()), string(&e.to_string()),
+        file.map(string).unwrap_or_else(|| String::from("null")),
+        line, col, e.token.as_ref().map(token).unwrap_or_else(|| String::from("null")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let v = parse(r#" {"id": 1, "a": [true, null, -2.5e1, "x\n\u00e9"], "b": {}} "#).unwrap();
+
+        assert_eq!(v.get("id"), Some(&Value::Number(1.0)));
+        assert_eq!(v.get("a"), Some(&Value::Array(vec![Value::Bool(true), Value::Null,
+            Value::Number(-25.0), Value::Str(String::from("x\né"))])));
+        assert_eq!(v.get("b"), Some(&Value::Object(Vec::new())));
+        assert_eq!(v.to_string(), r#"{"id":1,"a":[true,null,-25,"x\né"],"b":{}}"#);
+
+        assert_eq!(parse("[1,]"), None);
+        assert_eq!(parse("{\"a\" 1}"), None);
+        assert_eq!(parse("[1] 2"), None);
+
+        // (too deep is as bad as wrong)
+        assert!(parse(&format!("{}{}", "[".repeat(MAX_DEPTH), "]".repeat(MAX_DEPTH))).is_some());
+        assert_eq!(parse(&"[".repeat(200_000)), None);
+    }
+
+    #[test]
+    fn test_error() {
+        let mut p = Pesc::with_stdlib();
+        let code = "1\n  2 [nope]";
+        let e = p.run(code).unwrap_err();
+        assert_eq!(error(&e, Some("a.pesc"), code), "{\"kind\":\"unknown-function\",\
+            \"message\":\"I have no idea what nope means.\",\"file\":\"a.pesc\",\
+            \"line\":2,\"column\":5,\"token\":{\"func\":\"nope\"}}");
+
+        let e = PescError::new(None, None, pesc::errors::PescErrorType::Aborted);
+        assert_eq!(error(&e, None, ""), "{\"kind\":\"aborted\",\"message\":\"OK, I stopped.\",\
+            \"file\":null,\"line\":null,\"column\":null,\"token\":null}");
+    }
+}
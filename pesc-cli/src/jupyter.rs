@@ -0,0 +1,348 @@
+// --jupyter FILE, which runs pesc as a Jupyter kernel: FILE is the
+// connection file Jupyter hands to kernels. each cell is evaluated
+// (with the interpreter kept between cells), and the stack it leaves
+// is the cell's output. to let Jupyter know about the kernel, put
+//
+//     {"argv": ["pesc", "--jupyter", "{connection_file}"],
+//      "display_name": "pesc", "language": "pesc"}
+//
+// in a kernel.json, in a "pesc" directory in one of the kernel
+// directories `jupyter kernelspec list` shows.
+//
+// every socket is served by threads of its own, and messages on the
+// shell and control sockets are passed to the main thread, which has
+// the interpreter. the stdin socket is accepted, but never used.
+
+use std::io;
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use pesc::pesc::*;
+
+use crate::args::Options;
+use crate::hmac;
+use crate::json::{self, Value};
+use crate::zmtp;
+
+const DELIMITER: &[u8] = b"<IDS|MSG>";
+const PROTOCOL_VERSION: &str = "5.3";
+
+struct Message {
+    // what came before the delimiter
+    ids: Vec<Vec<u8>>,
+
+    header: String,
+    content: String,
+}
+
+struct Kernel {
+    key: Vec<u8>,
+    session: String,
+    sent: usize,
+    count: usize,
+    iopub: Arc<Mutex<Vec<TcpStream>>>,
+}
+
+pub fn run(path: &str, pesc: &mut Pesc, opts: &Options) -> Result<(), String> {
+    let data = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    let conf = json::parse(&data).ok_or("that's not JSON")?;
+
+    let get = |k: &str| conf.get(k).cloned().unwrap_or(Value::Null);
+    let ip = get("ip").as_str().unwrap_or("127.0.0.1").to_string();
+    let port = |k: &str| get(k).as_usize().ok_or(format!("there's no {}", k));
+
+    match get("transport").as_str() {
+        Some("tcp") | None => (),
+        Some(t) => return Err(format!("I can only use TCP, not {}", t)),
+    }
+    match get("signature_scheme").as_str() {
+        Some("hmac-sha256") | None => (),
+        Some(s) => return Err(format!("I can only sign with hmac-sha256, not {}", s)),
+    }
+
+    // (without a key, anyone who can reach the ports could run code)
+    let key = get("key").as_str().unwrap_or("").as_bytes().to_vec();
+    if key.is_empty() {
+        return Err(String::from("there's no key to check messages' signatures with"));
+    }
+
+    let listen = |k: &str| -> Result<TcpListener, String> {
+        TcpListener::bind(format!("{}:{}", ip, port(k)?)).map_err(|e| format!("{}: {}", k, e))
+    };
+
+    let (tx, rx) = mpsc::channel();
+    let iopub = Arc::new(Mutex::new(Vec::new()));
+
+    serve(listen("shell_port")?, "ROUTER", Some(tx.clone()));
+    serve(listen("control_port")?, "ROUTER", Some(tx));
+    serve(listen("stdin_port")?, "ROUTER", None);
+    heartbeat(listen("hb_port")?);
+    subscribers(listen("iopub_port")?, iopub.clone());
+
+    let mut kernel = Kernel {
+        key,
+        session: id(0),
+        sent: 0,
+        count: 0,
+        iopub,
+    };
+
+    for (frames, mut conn) in rx {
+        let msg = match kernel.open(frames) {
+            Some(m) => m,
+            None => continue,
+        };
+
+        if !kernel.handle(&msg, &mut conn, pesc, opts) {
+            return Ok(());
+        }
+    }
+
+    Ok(())
+}
+
+// accept connections, passing the messages that arrive on them
+// (and where to reply) to `tx`
+fn serve(listener: TcpListener, socket: &'static str, tx: Option<Sender<(Vec<Vec<u8>>, TcpStream)>>) {
+    thread::spawn(move || for conn in listener.incoming().flatten() {
+        let tx = tx.clone();
+        thread::spawn(move || {
+            let mut conn = conn;
+            zmtp::handshake(&mut conn, socket)?;
+
+            while let Ok(frames) = zmtp::recv(&mut conn) {
+                if let Some(tx) = &tx {
+                    tx.send((frames, conn.try_clone()?)).ok();
+                }
+            }
+
+            Ok::<(), io::Error>(())
+        });
+    });
+}
+
+fn heartbeat(listener: TcpListener) {
+    thread::spawn(move || for conn in listener.incoming().flatten() {
+        thread::spawn(move || {
+            let mut conn = conn;
+            zmtp::handshake(&mut conn, "REP")?;
+
+            loop {
+                let frames = zmtp::recv(&mut conn)?;
+                let frames = frames.iter().map(|f| f.as_slice()).collect::<Vec<&[u8]>>();
+                zmtp::send(&mut conn, &frames)?;
+            }
+
+            #[allow(unreachable_code)]
+            Ok::<(), io::Error>(())
+        });
+    });
+}
+
+// keep track of who's listening on iopub (ignoring what they
+// subscribe to, since Jupyter wants everything anyway)
+fn subscribers(listener: TcpListener, subs: Arc<Mutex<Vec<TcpStream>>>) {
+    thread::spawn(move || for conn in listener.incoming().flatten() {
+        let subs = subs.clone();
+        thread::spawn(move || {
+            let mut conn = conn;
+            zmtp::handshake(&mut conn, "PUB")?;
+            subs.lock().unwrap().push(conn.try_clone()?);
+
+            while zmtp::recv(&mut conn).is_ok() {}
+            Ok::<(), io::Error>(())
+        });
+    });
+}
+
+impl Kernel {
+    // check a message's signature and take it apart
+    fn open(&self, frames: Vec<Vec<u8>>) -> Option<Message> {
+        let d = frames.iter().position(|f| f == DELIMITER)?;
+        let parts = frames.get(d + 1..d + 6)?;
+
+        if !hmac::verify(&self.key, &[&parts[1], &parts[2], &parts[3], &parts[4]], &parts[0]) {
+            eprintln!("pesc: warning: ignoring a message with a bad signature");
+            return None;
+        }
+
+        Some(Message {
+            ids: frames[..d].to_vec(),
+            header: String::from_utf8(parts[1].clone()).ok()?,
+            content: String::from_utf8(parts[4].clone()).ok()?,
+        })
+    }
+
+    // the frames of a new message of type `kind` in response to `parent`
+    fn message(&mut self, parent: &Message, kind: &str, content: &str) -> Vec<Vec<u8>> {
+        self.sent += 1;
+
+        let header = format!("{{\"msg_id\":{},\"session\":{},\"username\":\"pesc\",\
+            \"date\":{},\"msg_type\":{},\"version\":{}}}",
+            json::string(&id(self.sent)), json::string(&self.session),
+            json::string(&now()), json::string(kind), json::string(PROTOCOL_VERSION));
+
+        let parts = [header.as_bytes(), parent.header.as_bytes(), b"{}", content.as_bytes()];
+        let sig = hmac::sign(&self.key, &parts);
+
+        let mut frames = vec![DELIMITER.to_vec(), sig.into_bytes()];
+        frames.extend(parts.iter().map(|p| p.to_vec()));
+        frames
+    }
+
+    fn reply(&mut self, conn: &mut TcpStream, parent: &Message, kind: &str, content: &str) {
+        let mut frames = parent.ids.clone();
+        frames.extend(self.message(parent, kind, content));
+
+        let frames = frames.iter().map(|f| f.as_slice()).collect::<Vec<&[u8]>>();
+        zmtp::send(conn, &frames).ok();
+    }
+
+    fn publish(&mut self, parent: &Message, kind: &str, content: &str) {
+        let mut frames = vec![kind.as_bytes().to_vec()];
+        frames.extend(self.message(parent, kind, content));
+
+        let frames = frames.iter().map(|f| f.as_slice()).collect::<Vec<&[u8]>>();
+        self.iopub.lock().unwrap().retain_mut(|s| zmtp::send(s, &frames).is_ok());
+    }
+
+    // answer a message, returning false when it's time to shut down
+    fn handle(&mut self, msg: &Message, conn: &mut TcpStream, pesc: &mut Pesc, opts: &Options) -> bool {
+        let header = json::parse(&msg.header).unwrap_or(Value::Null);
+        let content = json::parse(&msg.content).unwrap_or(Value::Null);
+        let kind = header.get("msg_type").and_then(Value::as_str).unwrap_or("");
+        let reply = kind.replace("_request", "_reply");
+
+        self.publish(msg, "status", "{\"execution_state\":\"busy\"}");
+
+        let mut running = true;
+        let answer = match kind {
+            "kernel_info_request" => Some(format!("{{\"status\":\"ok\",\
+                \"protocol_version\":{},\"implementation\":\"pesc\",\
+                \"implementation_version\":{},\"banner\":\"pesc\",\"help_links\":[],\
+                \"language_info\":{{\"name\":\"pesc\",\"version\":{},\
+                \"mimetype\":\"text/x-pesc\",\"file_extension\":\".pesc\"}}}}",
+                json::string(PROTOCOL_VERSION), json::string(env!("CARGO_PKG_VERSION")),
+                json::string(env!("CARGO_PKG_VERSION")))),
+            "execute_request" => {
+                let code = content.get("code").and_then(Value::as_str).unwrap_or("");
+                Some(self.execute(msg, code, pesc, opts))
+            },
+            "complete_request" => {
+                let code = content.get("code").and_then(Value::as_str).unwrap_or("");
+                let pos = content.get("cursor_pos").and_then(Value::as_usize).unwrap_or(0);
+                Some(complete(pesc, code, pos))
+            },
+            "is_complete_request" => Some(String::from("{\"status\":\"complete\"}")),
+            "comm_info_request" => Some(String::from("{\"status\":\"ok\",\"comms\":{}}")),
+            "interrupt_request" => Some(String::from("{\"status\":\"ok\"}")),
+            "shutdown_request" => {
+                running = false;
+                let restart = content.get("restart").cloned().unwrap_or(Value::Bool(false));
+                Some(format!("{{\"status\":\"ok\",\"restart\":{}}}", restart))
+            },
+            _ => None,
+        };
+
+        if let Some(a) = answer {
+            self.reply(conn, msg, &reply, &a);
+        }
+
+        self.publish(msg, "status", "{\"execution_state\":\"idle\"}");
+        running
+    }
+
+    fn execute(&mut self, msg: &Message, code: &str, pesc: &mut Pesc, opts: &Options) -> String {
+        self.count += 1;
+        let count = self.count;
+
+        self.publish(msg, "execute_input", &format!("{{\"code\":{},\"execution_count\":{}}}",
+            json::string(code), count));
+
+        let res = crate::parse(pesc, opts, code)
+            .map_err(|e| e.to_string())
+            .and_then(|(toks, _)| pesc.eval(&toks).map_err(|(_, e)| e.to_string()));
+
+        let warnings = pesc.warnings.drain(..)
+            .map(|w| format!("warning: {}\n", w))
+            .collect::<String>();
+        if !warnings.is_empty() {
+            self.publish(msg, "stream", &format!("{{\"name\":\"stderr\",\"text\":{}}}",
+                json::string(&warnings)));
+        }
+
+        match res {
+            Ok(()) => {
+                let stack = pesc.stack.iter().rev()
+                    .map(|t| t.to_string())
+                    .collect::<Vec<String>>()
+                    .join("\n");
+
+                self.publish(msg, "execute_result", &format!("{{\"execution_count\":{},\
+                    \"data\":{{\"text/plain\":{}}},\"metadata\":{{}}}}", count,
+                    json::string(&stack)));
+
+                format!("{{\"status\":\"ok\",\"execution_count\":{},\
+                    \"user_expressions\":{{}},\"payload\":[]}}", count)
+            },
+            Err(e) => {
+                let error = format!("\"ename\":\"error\",\"evalue\":{0},\"traceback\":[{0}]",
+                    json::string(&e));
+
+                self.publish(msg, "error", &format!("{{{}}}", error));
+                format!("{{\"status\":\"error\",\"execution_count\":{},{}}}", count, error)
+            },
+        }
+    }
+}
+
+// complete the name of a function being typed after a '['
+fn complete(pesc: &Pesc, code: &str, pos: usize) -> String {
+    let chs = code.chars().take(pos).collect::<Vec<char>>();
+    let start = chs.iter().rposition(|c| *c == '[' || c.is_whitespace())
+        .map(|i| i + 1).unwrap_or(0);
+
+    let prefix = chs[start..].iter().collect::<String>();
+    let mut matches = match start > 0 && chs[start - 1] == '[' {
+        true => pesc.funcs.keys()
+            .filter(|f| f.starts_with(&prefix))
+            .map(|f| json::string(f))
+            .collect::<Vec<String>>(),
+        false => Vec::new(),
+    };
+    matches.sort();
+
+    format!("{{\"status\":\"ok\",\"matches\":[{}],\"cursor_start\":{},\"cursor_end\":{},\
+        \"metadata\":{{}}}}", matches.join(","), start, chs.len())
+}
+
+fn id(n: usize) -> String {
+    let nanos = SystemTime::now().duration_since(UNIX_EPOCH)
+        .map(|d| d.as_nanos()).unwrap_or(0);
+    format!("{:x}-{}-{}", nanos, std::process::id(), n)
+}
+
+// the time in ISO 8601, in UTC
+fn now() -> String {
+    let t = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+    let (secs, micros) = (t.as_secs() as i64, t.subsec_micros());
+
+    // days since 1970 to a date, from
+    // howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = secs.div_euclid(86400) + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    let s = secs.rem_euclid(86400);
+    format!("{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:06}Z",
+        year, month, day, s / 3600, s / 60 % 60, s % 60, micros)
+}
@@ -0,0 +1,63 @@
+// `pesc lint`, which reports what pesc::lint finds in each
+// file, and `pesc check`, which only reports the functions that
+// will run out of arguments. exits with 1 when there are warnings,
+// and with 2 when a file can't be read or parsed.
+
+use pesc::errors::*;
+use pesc::lint;
+use pesc::pesc::Pesc;
+
+pub fn run(files: &[String], pesc: &Pesc, only_underflows: bool) -> i32 {
+    let mut status = 0;
+
+    for path in files {
+        let data = match std::fs::read_to_string(path) {
+            Ok(d) => d,
+            Err(e) => {
+                println!("pesc: error: {}: {}", path, e);
+                status = 2;
+                continue;
+            },
+        };
+
+        let warnings = match find(pesc, &data, only_underflows) {
+            Ok(w) => w,
+            Err(e) => {
+                match e.span {
+                    Some(s) => {
+                        let (line, col) = s.line_col(&data);
+                        println!("pesc: error: {}:{}:{}: {}", path, line, col, e);
+                    },
+                    None => println!("pesc: error: {}: {}", path, e),
+                }
+                status = 2;
+                continue;
+            },
+        };
+
+        for w in &warnings {
+            match w.span {
+                Some(s) => {
+                    let (line, col) = s.line_col(&data);
+                    println!("pesc: warning: {}:{}:{}: {}", path, line, col, w);
+                },
+                None => println!("pesc: warning: {}: {}", path, w),
+            }
+        }
+
+        if !warnings.is_empty() && status == 0 {
+            status = 1;
+        }
+    }
+
+    status
+}
+
+fn find(pesc: &Pesc, data: &str, only_underflows: bool) -> Result<Vec<PescWarning>, PescError> {
+    if !only_underflows {
+        return lint::lint(pesc, data);
+    }
+
+    let (toks, spans) = Pesc::parse_spans(data, &pesc.opts)?;
+    Ok(lint::underflows(pesc, &toks, &spans, 0))
+}
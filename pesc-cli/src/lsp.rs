@@ -0,0 +1,351 @@
+// `pesc lsp`, a language server for editors to talk to over stdin and
+// stdout. it keeps the open documents around and offers:
+//
+//  - diagnostics: parse errors and whatever pesc::lint finds,
+//    sent whenever a document is opened or changed;
+//  - hover: what a function does, from its documentation (or its
+//    declared stack effect) and operators, or how it's defined in
+//    the document;
+//  - completion: the names of all functions that are loaded or
+//    defined in the document.
+//
+// positions are counted in characters rather than UTF-16 code units,
+// which only makes a difference after characters outside the BMP.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Read, Write};
+
+use pesc::errors::*;
+use pesc::lint;
+use pesc::pesc::*;
+
+use crate::json::{self, Value};
+
+const SEVERITY_ERROR: usize = 1;
+const SEVERITY_WARNING: usize = 2;
+const KIND_FUNCTION: usize = 3;
+
+// the biggest message that's read; bigger ones are skipped
+const MAX_MESSAGE: usize = 16 << 20;
+
+pub fn run(pesc: &Pesc) -> i32 {
+    let stdin = io::stdin();
+    let mut input = stdin.lock();
+    let mut docs: HashMap<String, String> = HashMap::new();
+    let mut shut_down = false;
+
+    while let Some(msg) = read_message(&mut input) {
+        let msg = match json::parse(&msg) {
+            Some(m) => m,
+            None => continue,
+        };
+
+        let id = msg.get("id").cloned();
+        let params = msg.get("params").cloned().unwrap_or(Value::Null);
+        let doc = params.get("textDocument")
+            .and_then(|d| d.get("uri")).and_then(Value::as_str)
+            .map(String::from);
+
+        let result = match msg.get("method").and_then(Value::as_str).unwrap_or("") {
+            "initialize" => Some(String::from("{\"capabilities\":{\
+                \"textDocumentSync\":1,\"hoverProvider\":true,\
+                \"completionProvider\":{}},\
+                \"serverInfo\":{\"name\":\"pesc\"}}")),
+            "shutdown" => {
+                shut_down = true;
+                Some(String::from("null"))
+            },
+            "exit" => return if shut_down { 0 } else { 1 },
+
+            "textDocument/didOpen" | "textDocument/didChange" => {
+                let text = params.get("textDocument").and_then(|d| d.get("text"))
+                    .or_else(|| match params.get("contentChanges") {
+                        Some(Value::Array(c)) => c.last().and_then(|c| c.get("text")),
+                        _ => None,
+                    })
+                    .and_then(Value::as_str);
+
+                if let (Some(uri), Some(text)) = (&doc, text) {
+                    publish(uri, text, pesc);
+                    docs.insert(uri.clone(), text.to_string());
+                }
+                None
+            },
+            "textDocument/didClose" => {
+                if let Some(uri) = &doc {
+                    docs.remove(uri);
+                    send(&format!("{{\"jsonrpc\":\"2.0\",\
+                        \"method\":\"textDocument/publishDiagnostics\",\
+                        \"params\":{{\"uri\":{},\"diagnostics\":[]}}}}", json::string(uri)));
+                }
+                None
+            },
+
+            "textDocument/hover" => {
+                let text = doc.and_then(|d| docs.get(&d));
+                let hover = text.zip(position(&params))
+                    .and_then(|(t, (line, col))| hover(pesc, t, line, col));
+
+                Some(match hover {
+                    Some(h) => format!("{{\"contents\":{{\"kind\":\"markdown\",\"value\":{}}}}}",
+                        json::string(&h)),
+                    None => String::from("null"),
+                })
+            },
+            "textDocument/completion" => {
+                let text = doc.and_then(|d| docs.get(&d)).map(|t| t.as_str());
+                let items = completions(pesc, text.unwrap_or(""))
+                    .iter()
+                    .map(|n| format!("{{\"label\":{},\"kind\":{}}}", json::string(n), KIND_FUNCTION))
+                    .collect::<Vec<String>>();
+
+                Some(format!("[{}]", items.join(",")))
+            },
+
+            // requests we don't know get an error, notifications nothing
+            m => {
+                if let Some(id) = &id {
+                    send(&format!("{{\"jsonrpc\":\"2.0\",\"id\":{},\
+                        \"error\":{{\"code\":-32601,\"message\":{}}}}}", id,
+                        json::string(&format!("{} isn't supported.", m))));
+                }
+                None
+            },
+        };
+
+        if let (Some(id), Some(r)) = (id, result) {
+            send(&format!("{{\"jsonrpc\":\"2.0\",\"id\":{},\"result\":{}}}", id, r));
+        }
+    }
+
+    1
+}
+
+// read one message, skipping the headers except for Content-Length
+// (and the message itself, if it's too big, which leaves it empty)
+fn read_message(input: &mut impl BufRead) -> Option<String> {
+    let mut len = None;
+
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line).ok()? == 0 {
+            return None;
+        }
+
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+
+        if let Some((k, v)) = line.split_once(':') {
+            if k.eq_ignore_ascii_case("content-length") {
+                len = v.trim().parse::<usize>().ok();
+            }
+        }
+    }
+
+    let len = len?;
+    if len > MAX_MESSAGE {
+        io::copy(&mut input.by_ref().take(len as u64), &mut io::sink()).ok()?;
+        return Some(String::new());
+    }
+
+    let mut buf = vec![0; len];
+    input.read_exact(&mut buf).ok()?;
+    String::from_utf8(buf).ok()
+}
+
+fn send(msg: &str) {
+    let stdout = io::stdout();
+    let mut out = stdout.lock();
+    write!(out, "Content-Length: {}\r\n\r\n{}", msg.len(), msg).ok();
+    out.flush().ok();
+}
+
+fn position(params: &Value) -> Option<(usize, usize)> {
+    let pos = params.get("position")?;
+    Some((pos.get("line")?.as_usize()?, pos.get("character")?.as_usize()?))
+}
+
+fn publish(uri: &str, text: &str, pesc: &Pesc) {
+    let items = diagnostics(pesc, text).iter()
+        .map(|(span, severity, msg)| format!("{{\"range\":{},\"severity\":{},\
+            \"source\":\"pesc\",\"message\":{}}}", range(text, *span), severity,
+            json::string(msg)))
+        .collect::<Vec<String>>();
+
+    send(&format!("{{\"jsonrpc\":\"2.0\",\"method\":\"textDocument/publishDiagnostics\",\
+        \"params\":{{\"uri\":{},\"diagnostics\":[{}]}}}}", json::string(uri), items.join(",")));
+}
+
+// (span, severity, message) for each problem in `text`
+fn diagnostics(pesc: &Pesc, text: &str) -> Vec<(Span, usize, String)> {
+    match lint::lint(pesc, text) {
+        Ok(warnings) => warnings.iter()
+            .map(|w| (w.span.unwrap_or(Span::new(0, 0)), SEVERITY_WARNING, w.to_string()))
+            .collect(),
+        Err(e) => vec![(e.span.unwrap_or(Span::new(0, 0)), SEVERITY_ERROR, e.to_string())],
+    }
+}
+
+fn range(text: &str, span: Span) -> String {
+    let pos = |offset| {
+        let (line, col) = Span::new(offset, offset).line_col(text);
+        format!("{{\"line\":{},\"character\":{}}}", line - 1, col - 1)
+    };
+
+    format!("{{\"start\":{},\"end\":{}}}", pos(span.start), pos(span.end))
+}
+
+// the byte offset of a (zero-based) line and character
+fn offset(text: &str, line: usize, col: usize) -> Option<usize> {
+    let start = match line {
+        0 => 0,
+        _ => text.match_indices('\n').nth(line - 1)?.0 + 1,
+    };
+
+    let rest = &text[start..];
+    Some(start + rest.char_indices().nth(col).map(|c| c.0).unwrap_or(rest.len()))
+}
+
+fn hover(pesc: &Pesc, text: &str, line: usize, col: usize) -> Option<String> {
+    let (toks, spans) = Pesc::parse_spans(text, &pesc.opts).ok()?;
+    let at = offset(text, line, col)?;
+
+    let name = match token_at(&toks, &spans, at)? {
+        PescToken::Func(f) => pesc.resolve(&pesc.opts.func_name(f)),
+        PescToken::Symbol(c) => pesc.ops.get(c)?.clone(),
+        PescToken::Operator(o) => pesc.multi_ops.get(o)?.clone(),
+        _ => return None,
+    };
+
+    if let Some(body) = defined(&toks, &name, pesc) {
+        return Some(format!("```\n{{{}}} \"{}\" [def]\n```", body, name));
+    }
+
+    if !pesc.funcs.contains_key(&name) {
+        return None;
+    }
+
+    let mut doc = format!("**[{}]**", name);
+
+    let mut ops = pesc.ops.iter()
+        .filter(|(_, f)| **f == name)
+        .map(|(o, _)| format!("`{}`", o))
+        .collect::<Vec<String>>();
+    ops.sort();
+    if !ops.is_empty() {
+        doc += &format!(" (or {})", ops.join(", "));
+    }
+
+    if let Some(d) = pesc.docs.get(&name) {
+        doc += &format!("\n\n`( {} )` {}", d.signature, d.description);
+    } else if let Some((takes, leaves)) = pesc.arity.get(&name) {
+        doc += &format!("\n\ntakes {} value(s) and leaves {}.", takes, leaves);
+    }
+
+    Some(doc)
+}
+
+// the innermost token whose span contains `at`
+fn token_at<'a>(toks: &'a [PescToken], spans: &[PescSpans], at: usize)
+    -> Option<&'a PescToken>
+{
+    let i = spans.iter().position(|s| s.span.start <= at && at < s.span.end)?;
+
+    match &toks[i] {
+        PescToken::Macro(m) | PescToken::List(m) =>
+            token_at(m, &spans[i].inner, at).or(Some(&toks[i])),
+        t => Some(t),
+    }
+}
+
+// the body of `name`, if it's defined with `{...} "name" [def]`
+// somewhere in the document
+fn defined(toks: &[PescToken], name: &str, pesc: &Pesc) -> Option<String> {
+    for (i, t) in toks.iter().enumerate() {
+        match (t, i.checked_sub(2).map(|j| (&toks[j], &toks[j + 1]))) {
+            (PescToken::Macro(m), _) => if let Some(b) = defined(m, name, pesc) {
+                return Some(b);
+            },
+            (PescToken::Func(f), Some((PescToken::Macro(body), PescToken::Str(n))))
+                if pesc.opts.func_name(f) == "def" && pesc.opts.func_name(n) == name =>
+                return Some(unparse(body)),
+            _ => (),
+        }
+    }
+
+    None
+}
+
+fn completions(pesc: &Pesc, text: &str) -> Vec<String> {
+    let mut names = pesc.funcs.keys().cloned().collect::<Vec<String>>();
+
+    if let Ok((_, toks)) = Pesc::parse_with(text, &pesc.opts) {
+        collect_defs(&toks, pesc, &mut names);
+    }
+
+    names.sort();
+    names.dedup();
+    names
+}
+
+fn collect_defs(toks: &[PescToken], pesc: &Pesc, names: &mut Vec<String>) {
+    for (i, t) in toks.iter().enumerate() {
+        match t {
+            PescToken::Macro(m) => collect_defs(m, pesc, names),
+            PescToken::Func(f) if i > 0 && pesc.opts.func_name(f) == "def" => {
+                if let PescToken::Str(n) = &toks[i - 1] {
+                    names.push(pesc.opts.func_name(n));
+                }
+            },
+            _ => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pesc::stdlib;
+
+    // the builtins, without their docs
+    fn pesc() -> Pesc {
+        let mut p = Pesc::new();
+        for f in stdlib::standard().into_iter().chain(stdlib::extended()) {
+            p.load_def(f);
+        }
+        p
+    }
+
+    #[test]
+    fn test_lsp() {
+        let p = pesc();
+        let text = "1 2 [add]\n{\\ *} \"square\" [def]\n3 [square] +";
+
+        assert_eq!(offset(text, 1, 2), Some(12));
+        assert_eq!(range(text, Span::new(12, 14)),
+            "{\"start\":{\"line\":1,\"character\":2},\"end\":{\"line\":1,\"character\":4}}");
+
+        let add = hover(&p, text, 0, 6).unwrap();
+        assert!(add.starts_with("**[add]** (or `+`)"));
+        assert!(add.contains("takes 2 value(s) and leaves 1."));
+        assert_eq!(hover(&p, text, 2, 11), hover(&p, text, 0, 5));
+        assert_eq!(hover(&p, text, 2, 4).unwrap(), "```\n{\\ *} \"square\" [def]\n```");
+        assert_eq!(hover(&p, text, 0, 0), None);
+        assert!(hover(&Pesc::with_stdlib(), text, 0, 6).unwrap()
+            .ends_with("`( a b -- a+b )` adds two numbers."));
+
+        assert!(completions(&p, text).contains(&String::from("square")));
+        assert!(diagnostics(&p, text).is_empty());
+
+        let mut input = "Content-Length: 99999999999999\r\n\r\n{}".as_bytes();
+        assert_eq!(read_message(&mut input).as_deref(), Some(""));
+        let mut input = "Content-Length: 2\r\n\r\n{}".as_bytes();
+        assert_eq!(read_message(&mut input).as_deref(), Some("{}"));
+
+        let d = diagnostics(&p, "1 2 }");
+        assert_eq!(d.len(), 1);
+        assert_eq!(d[0].1, SEVERITY_ERROR);
+    }
+}
@@ -1,106 +1,620 @@
 mod args;
 mod clihints;
+mod commands;
+mod config;
+mod coverage;
+mod dc;
+mod debug;
+mod docgen;
+mod dump;
+mod export;
+mod fmt;
+mod forth;
+mod highlight;
+mod hmac;
+mod include;
+mod input;
+mod json;
+mod jupyter;
+mod lint;
+mod lsp;
+mod map;
+mod numbers;
+mod plot;
+mod profile;
+mod rates;
+mod serve;
+mod state;
+mod test;
+mod trace;
+#[cfg(feature = "tui")]
+mod tui;
+mod undo;
 mod tty;
 mod output;
+mod zmtp;
 
+use pesc::errors::{PescError, PescErrorType, Span};
+use pesc::hp;
+use pesc::infix;
 use pesc::pesc::*;
-use pesc::stdlib;
+use pesc::lint::underflows;
+use pesc::units::{self, Units};
 
-use crate::clihints::*;
 use crate::args::*;
+use crate::export::History;
+use crate::input::{Backend, ReadError};
+use crate::output::OutputMode;
+use crate::state::State;
+use crate::tty::{OutputStream, Theme};
 
+use std::cell::RefCell;
+use std::io::Read;
+use std::rc::Rc;
 use std::time::Instant;
 
-use rustyline::{
-    config::{
-        Builder,
-        EditMode,
-    },
-    error::ReadlineError,
-    Editor,
-};
+const PROMPT: &str = "pesc> ";
 
 fn main() {
-    let opts = match Options::new().parse() {
+    let mut opts = match Options::new().parse() {
         Ok(o) => o,
-        Err(()) => return,
+        Err(status) => std::process::exit(status),
     };
 
-    let mut pesc = Pesc::new();
+    // (a Windows console that can't show colors gets none)
+    if !tty::enable_escapes() {
+        opts.theme = Theme::Plain;
+    }
+
+    tty::set_theme(opts.theme);
+    output::set_colors(opts.colors);
+    output::set_cell_width(opts.cell_width);
+    numbers::set(opts.numbers.clone());
+
+    let mut pesc = interpreter(&opts);
+    let cov = Rc::new(RefCell::new(coverage::Coverage::default()));
+
+    match &opts.command {
+        Command::Fmt(files) => {
+            if !fmt::run(files, &pesc.opts, opts.check) {
+                std::process::exit(1);
+            }
+            return;
+        },
+        Command::Lint(files) => std::process::exit(lint::run(files, &pesc, false)),
+        Command::Check(files) => std::process::exit(lint::run(files, &pesc, true)),
+        Command::Lsp => std::process::exit(lsp::run(&pesc)),
+        Command::Highlight(format, files) => {
+            if !highlight::run(files, &pesc, *format) {
+                std::process::exit(1);
+            }
+            return;
+        },
+        Command::Doc(format) => {
+            docgen::run(&pesc, *format);
+            return;
+        },
+        Command::Tui => {
+            #[cfg(feature = "tui")]
+            if let Err(e) = tui::run(&mut pesc) {
+                println!("pesc: error: {}", e);
+                std::process::exit(1);
+            }
+            return;
+        },
+        Command::Test(paths) => {
+            let ok = test::run(paths, || interpreter(&opts),
+                opts.coverage.then_some(&cov));
+
+            if opts.coverage {
+                coverage::finish(&cov, opts.lcov.as_deref());
+            }
+
+            if !ok {
+                std::process::exit(1);
+            }
+            return;
+        },
+        Command::Map(_) | Command::Run | Command::Eval(_) => (),
+    }
+
+    let trace = match &opts.trace_file {
+        Some(path) => match trace::start(&mut pesc, path) {
+            Ok(t) => Some(t),
+            Err(e) => {
+                println!("pesc: error: {}: {}", path, e);
+                return;
+            },
+        },
+        None => None,
+    };
+
+    let registers = match opts.dc {
+        true => Some(dc::load(&mut pesc)),
+        false => None,
+    };
+
+    if opts.forth {
+        forth::load(&mut pesc);
+    }
+
+    if opts.hp {
+        hp::load(&mut pesc);
+    }
 
-    // load standard library
-    for func in stdlib::standard() {
-        pesc.load(func.0, func.1, func.2);
+    if let Some(path) = &opts.units {
+        let path = match path.as_str() {
+            "" => units_file(),
+            p => p.to_string(),
+        };
+
+        match std::fs::read_to_string(&path) {
+            Ok(data) => units::load(&mut pesc, Units::parse(&data)),
+            Err(e) => {
+                println!("pesc: error: {}: {}", path, e);
+                std::process::exit(1);
+            },
+        }
     }
 
-    for func in stdlib::extended() {
-        pesc.load(func.0, func.1, func.2);
+    if let Err(e) = rates::setup(&mut pesc, &opts) {
+        println!("pesc: error: {}", e);
+        std::process::exit(1);
+    }
+
+    if let Some(path) = opts.jupyter.clone() {
+        if let Err(e) = jupyter::run(&path, &mut pesc, &opts) {
+            println!("pesc: error: {}: {}", path, e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(addr) = opts.serve.clone() {
+        if let Err(e) = serve::run(&addr, &mut pesc, &opts, || interpreter(&opts)) {
+            println!("pesc: error: {}: {}", addr, e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Command::Map(code) = &opts.command {
+        if !map::run(code, &mut pesc, &opts) {
+            std::process::exit(1);
+        }
+        return;
     }
 
     // waitaminute, let's see if there is a file we
-    // need execute
-    if let Some(path) = opts.file {
-        let data = std::fs::read_to_string(path).unwrap();
-        let parsed = match Pesc::parse(&data) {
+    // need execute (or something piped to us, or code
+    // given with eval)
+    let input = match (&opts.command, opts.file.clone()) {
+        (Command::Eval(code), _) => Some((String::from("<code>"), code.clone())),
+        (_, Some(path)) => match std::fs::read_to_string(&path) {
+            Ok(data) => Some((path, data)),
+            Err(e) => {
+                println!("pesc: error: {}: {}", path, e);
+                std::process::exit(1);
+            },
+        },
+        (_, None) if !opts.interactive && !tty::is_tty(OutputStream::Stdin) => {
+            let mut data = String::new();
+            if let Err(e) = std::io::stdin().read_to_string(&mut data) {
+                println!("pesc: error: -: {}", e);
+                std::process::exit(1);
+            }
+            Some((String::from("-"), data))
+        },
+        (_, None) => None,
+    };
+
+    if let Some((path, data)) = input {
+        if opts.dump_ast {
+            if let Err(e) = dump::dump(&data, &pesc.opts) {
+                println!("pesc: error: {}:{}", path, e);
+                std::process::exit(1);
+            }
+            return;
+        }
+        let (parsed, spans) = match parse(&mut pesc, &opts, &data) {
             Ok(r) => r,
             Err(e) => {
-                println!("pesc: error: {}", e);
-                return;
+                print_error(&opts, Some(&path), &data, &e);
+                std::process::exit(1);
             },
         };
 
-        match pesc.eval(&parsed.1) {
-            Ok(()) => opts.output.format_stack(&pesc.stack),
+        if opts.coverage {
+            coverage::watch(&cov, &mut pesc, &path, &data, &parsed, &spans);
+        }
+
+        if let Some(tr) = &trace {
+            tr.borrow_mut().spans = SpanMap::new(&parsed, &spans);
+        }
+
+        if opts.trace {
+            trace::print(&mut pesc);
+        }
+
+        let prof = opts.profile.then(|| profile::start(&mut pesc));
+        pesc.spans = SpanMap::new(&parsed, &spans);
+        pesc.including.push(include::name(std::path::Path::new(&path)));
+        let ok = match pesc.eval(&parsed) {
+            Ok(()) => true,
+            Err((_, e)) if opts.dc && matches!(e.kind, PescErrorType::Aborted) => true,
             Err((_, e)) => {
-                println!("pesc: error: {}", e);
+                print_error(&opts, Some(&path), &data, &e);
+                false
             },
+        };
+        pesc.spans = SpanMap::default();
+        pesc.including.clear();
+
+        if opts.coverage {
+            coverage::finish(&cov, opts.lcov.as_deref());
+        }
+
+        if let Some(tr) = &trace {
+            trace::flush(tr);
+        }
+
+        if let Some(prof) = prof {
+            profile::finish(&mut pesc, prof);
+        }
+
+        if opts.trace {
+            trace::stop(&mut pesc);
+        }
+
+        if opts.check {
+            pesc.warn_leftovers();
+            let warned = !pesc.warnings.is_empty();
+            print_warnings(&mut pesc, Some((&path, &data)));
+
+            if !ok || warned {
+                std::process::exit(1);
+            }
+        } else {
+            print_warnings(&mut pesc, Some((&path, &data)));
+            if !ok {
+                std::process::exit(1);
+            }
+
+            if !opts.dc && !opts.forth {
+                print_stack(&opts, &pesc.stack);
+            }
+        }
+
+        if opts.exit_top {
+            match exit_code(&pesc.stack) {
+                Ok(c) => std::process::exit(c),
+                Err(e) => {
+                    println!("pesc: error: {}", e);
+                    std::process::exit(2);
+                },
+            }
         }
 
         return;
     }
 
     // nope, display a pretty prompt & take orders
-    // from stdin
-    let config = Builder::new()
-        .auto_add_history(true)
-        .history_ignore_space(true)
-        .edit_mode(EditMode::Vi)
-        .build();
+    // from stdin (plainly, if the terminal can't do better)
+    if input::is_dumb() {
+        opts.editor = Backend::Plain;
+    }
+
+    if opts.editor == Backend::Plain && opts.output == OutputMode::Human {
+        opts.output = OutputMode::Simple;
+    }
+
+    // stop at `break` and let the user look around
+    pesc.hooks.push(debug::hook(opts.output));
+
+    // (before the line editor, so that it knows about [undo])
+    let undo = undo::load(&mut pesc);
 
-    let mut rl = Editor::<BustyLine>::with_config(config);
-    rl.set_helper(Some(BustyLine::new()));
+    let mut rl = input::open(&opts, &pesc);
+
+    // pick up where the last session left off
+    let state = State::new(&opts, registers);
+    if let Err(e) = state.restore(&mut pesc) {
+        println!("pesc: warning: couldn't restore the stack: {}", e);
+    } else if !pesc.stack.is_empty() && !opts.dc && !opts.forth {
+        print_stack(&opts, &pesc.stack);
+    }
+
+    // the lines that worked, for :export
+    let mut history = History::new();
+    if !opts.dc && !opts.forth && !pesc.stack.is_empty() {
+        history.record(&unparse(&pesc.stack), &[], &pesc.stack);
+    }
 
     loop {
-        match rl.readline("pesc> ") {
+        match rl.readline(PROMPT) {
             Ok(line) => {
+                if commands::is_command(&line) {
+                    commands::run(&line, &mut pesc, &mut opts, &state, &mut history, &undo);
+                    continue;
+                }
+
                 let now = Instant::now();
 
-                let parsed = match Pesc::parse(&line) {
+                let (parsed, spans) = match parse(&mut pesc, &opts, &line) {
                     Ok(r) => r,
                     Err(e) => {
-                        println!("error: {}", e);
+                        show_error(&opts, &line, &e);
                         continue;
                     },
                 };
 
-                match pesc.eval(&parsed.1) {
-                    Ok(()) => (),
-                    Err((_, e)) => {
-                        println!("error: {}", e);
+                // show what the expression turned into
+                if opts.infix {
+                    println!("{}", unparse(&parsed));
+                }
+
+                // don't run a line that's sure to run out of arguments
+                // (there are no spans to check with infix, dc or Forth)
+                if !spans.is_empty() && !opts.hp {
+                    if let Some(w) = underflows(&pesc, &parsed, &spans, pesc.stack.len()).first() {
+                        show_where(&opts, &line, w.span);
+                        println!("warning: {} I haven't run it.", w);
+                        continue;
+                    }
+                }
+
+                if let Some(tr) = &trace {
+                    tr.borrow_mut().spans = SpanMap::new(&parsed, &spans);
+                }
+
+                if opts.trace {
+                    trace::print(&mut pesc);
+                }
+
+                let prof = opts.profile.then(|| profile::start(&mut pesc));
+                let before = pesc.stack.clone();
+                pesc.spans = SpanMap::new(&parsed, &spans);
+                let res = pesc.eval(&parsed);
+                pesc.spans = SpanMap::default();
+
+                match &res {
+                    Ok(()) => {
+                        let code = if opts.infix { unparse(&parsed) } else { line.clone() };
+                        history.record(&code, &before, &pesc.stack);
                     },
+                    Err((_, e)) if opts.dc && matches!(e.kind, PescErrorType::Aborted) => (),
+                    Err((_, e)) => show_error(&opts, &line, e),
+                }
+
+                undo.borrow_mut().record(before.clone(), &pesc.stack);
+
+                if let Some(tr) = &trace {
+                    trace::flush(tr);
+                }
+
+                if let Some(prof) = prof {
+                    profile::finish(&mut pesc, prof);
+                }
+
+                if opts.trace {
+                    trace::stop(&mut pesc);
+                }
+
+                print_warnings(&mut pesc, None);
+
+                // Forth says ok, and leaves the stack to .s
+                if opts.forth {
+                    if res.is_ok() {
+                        println!(" ok");
+                    }
+                    continue;
+                }
+
+                // dc quits with q, and only shows the stack when asked
+                if opts.dc {
+                    match res {
+                        Err((_, e)) if matches!(e.kind, PescErrorType::Aborted) => break,
+                        _ => continue,
+                    }
                 }
 
-                opts.output.format_stack(&pesc.stack);
+                print_changes(&opts, &pesc.stack, Some(&before));
 
                 if opts.verbose {
                     println!();
                     opts.output.format_elapsed(now.elapsed());
                 }
             },
-            Err(ReadlineError::Eof) => break,
-            Err(ReadlineError::Interrupted) =>
+            Err(ReadError::Eof) => break,
+            Err(ReadError::Interrupted) =>
                 println!("Use Ctrl-D to quit."),
-            Err(_) => opts.output.format_stack(&pesc.stack),
+            Err(ReadError::Other(e)) => println!("error: {}", e),
+        }
+    }
+
+    if let Err(e) = rl.save_history() {
+        println!("pesc: warning: couldn't save the history: {}", e);
+    }
+
+    if let Err(e) = state.save(&pesc) {
+        println!("pesc: warning: couldn't save the stack: {}", e);
+    }
+}
+
+// parse pesc code, or compile an infix expression with --infix or
+// translate dc or Forth code with --dc or --forth (in which case
+// there are no spans)
+pub fn parse(pesc: &mut Pesc, opts: &Options, code: &str)
+    -> Result<(Vec<PescToken>, Vec<PescSpans>), PescError>
+{
+    if opts.infix {
+        Ok((infix::compile(code)?, Vec::new()))
+    } else if opts.dc {
+        Ok((dc::translate(code)?, Vec::new()))
+    } else if opts.forth {
+        Ok((forth::translate(code)?, Vec::new()))
+    } else {
+        pesc.parse_code_spans(code)
+    }
+}
+
+// a new interpreter with the standard library loaded
+fn interpreter(opts: &Options) -> Pesc {
+    let mut pesc = Pesc::with_stdlib();
+    pesc.opts.legacy_bools = opts.legacy_bools;
+    pesc.opts.parse_mode = opts.parse_mode;
+    pesc.opts.div_zero = opts.div_zero;
+    pesc.opts.nan_mode = opts.nan_mode;
+    pesc.opts.ignore_case = opts.ignore_case;
+    pesc.opts.checked_ints = opts.checked_ints;
+    pesc.opts.precision = opts.precision;
+    pesc.opts.max_depth = opts.max_depth;
+    pesc.opts.max_loops = opts.max_loops;
+    pesc.opts.max_steps = opts.max_steps;
+    pesc.opts.timeout = opts.timeout;
+    pesc.opts.max_stack = opts.max_stack;
+    pesc.opts.keep_on_error = opts.keep_on_error;
+    if !opts.sandbox {
+        include::load(&mut pesc);
+    }
+    numbers::load(&mut pesc);
+    plot::load(&mut pesc);
+
+    let prelude = match &opts.prelude {
+        _ if opts.no_prelude => None,
+        Some(p) => Some(p.clone()),
+        None => Some(include::default_prelude())
+            .filter(|p| p.exists())
+            .map(|p| p.display().to_string()),
+    };
+
+    if let Some(path) = prelude {
+        if let Err(e) = include::file(&mut pesc, &path) {
+            print_error(opts, None, "", &PescError::new(None, None, e));
+            std::process::exit(1);
+        }
+    }
+
+    pesc
+}
+
+// an error in the line that was just typed
+fn show_error(opts: &Options, code: &str, e: &PescError) {
+    if opts.json_errors || opts.output == OutputMode::Json {
+        println!("{}", json::error(e, None, code));
+        return;
+    }
+
+    show_where(opts, code, e.span);
+    println!("error: {}", e);
+}
+
+// point at where an error happened in the line that was just typed,
+// under the line itself (or under a copy of the part of it that's at
+// fault, when it went over several lines)
+fn show_where(opts: &Options, code: &str, span: Option<Span>) {
+    let s = match span {
+        Some(s) if matches!(opts.output, OutputMode::Human | OutputMode::Simple) => s,
+        _ => return,
+    };
+
+    match code.lines().count() {
+        0 | 1 => println!("{}{}", " ".repeat(PROMPT.len()), s.caret(code)),
+        _ => {
+            let (line, _) = s.line_col(code);
+            println!("{}\n{}", code.lines().nth(line - 1).unwrap_or(""), s.caret(code));
+        },
+    }
+}
+
+fn print_stack(opts: &Options, stack: &[PescToken]) {
+    print_changes(opts, stack, None);
+}
+
+// the stack, showing what changed since it was `before` (see
+// OutputMode::format_changes())
+fn print_changes(opts: &Options, stack: &[PescToken], before: Option<&[PescToken]>) {
+    let rounded;
+    let stack = match opts.digits {
+        Some(d) => {
+            rounded = stack.iter().map(|t| output::round(t, d)).collect::<Vec<_>>();
+            &rounded[..]
+        },
+        None => stack,
+    };
+
+    if opts.hp {
+        opts.output.format_registers(stack);
+    } else {
+        opts.output.format_changes(stack, before);
+    }
+}
+
+// the exit status for --exit-top: a whole number from 0 to 255 is the
+// status, any other number is 1, and anything else is 0 for true and
+// 1 for false
+fn exit_code(stack: &[PescToken]) -> Result<i32, PescErrorType> {
+    let top = stack.last().ok_or(PescErrorType::NotEnoughArguments)?;
+    let n = match top {
+        PescToken::Number(n) => Some(*n),
+        PescToken::Int(n) => Some(n.to_f64()),
+        PescToken::Decimal(d) => Some(d.to_f64()),
+        PescToken::Frac(r) => Some(r.to_f64()),
+        _ => None,
+    };
+
+    if let Some(n) = n {
+        return Ok(match n.fract() == 0.0 && (0.0..=255.0).contains(&n) {
+            true => n as i32,
+            false => 1,
+        });
+    }
+
+    let mut p = Pesc::new();
+    p.push(top.clone());
+    Ok(if p.pop_boolean()? { 0 } else { 1 })
+}
+
+// where units(1) definitions usually are
+fn units_file() -> String {
+    let places = ["/usr/share/units/definitions.units", "/usr/local/share/units/definitions.units",
+        "/usr/share/misc/units.lib"];
+
+    std::env::var("PESC_UNITS").ok()
+        .or_else(|| places.iter().find(|p| std::path::Path::new(p).exists()).map(|p| p.to_string()))
+        .unwrap_or_else(|| places[0].to_string())
+}
+
+// an error in a file (or code given with eval, or without a path, one
+// that says where it is itself), saying where in it the error is when
+// that's known
+fn print_error(opts: &Options, path: Option<&str>, data: &str, e: &PescError) {
+    if opts.json_errors || opts.output == OutputMode::Json {
+        println!("{}", json::error(e, path, data));
+        return;
+    }
+
+    match (path, e.span) {
+        (Some(p), Some(s)) => {
+            let (line, col) = s.line_col(data);
+            println!("pesc: error: {}:{}:{}: {}", p, line, col, e);
+        },
+        (Some(p), None) => println!("pesc: error: {}: {}", p, e),
+        (None, _) => println!("pesc: error: {}", e),
+    }
+}
+
+// print (and forget) the warnings pesc has collected so far. when
+// they come from a file, `src` is its path and contents.
+fn print_warnings(pesc: &mut Pesc, src: Option<(&str, &str)>) {
+    for w in pesc.warnings.drain(..) {
+        match (src, w.span) {
+            (Some((path, data)), Some(s)) => {
+                let (line, col) = s.line_col(data);
+                eprintln!("pesc: warning: {}:{}:{}: {}", path, line, col, w);
+            },
+            (Some(_), None) => eprintln!("pesc: warning: {}", w),
+            (None, _) => eprintln!("warning: {}", w),
         }
     }
 }
@@ -0,0 +1,110 @@
+// `pesc map CODE`, which runs CODE once for each line of stdin, on a
+// stack with nothing but that line on it (as a number, if it is one,
+// or else as a string), and prints what's left, e.g.
+//
+//     seq 10 | pesc map '2*'
+//
+// each line's stack is printed on a line of its own, the bottom first
+// (or as JSON, with -o json), and nothing is printed for an empty one,
+// so that CODE can leave lines out. definitions are kept from one line
+// to the next.
+
+use std::io::{self, BufRead, Write};
+
+use pesc::pesc::*;
+
+use crate::args::Options;
+use crate::json;
+use crate::numbers;
+use crate::output::OutputMode;
+
+pub fn run(code: &str, pesc: &mut Pesc, opts: &Options) -> bool {
+    each(code, pesc, opts, io::stdin().lock(), io::stdout().lock())
+}
+
+fn each(code: &str, pesc: &mut Pesc, opts: &Options, input: impl BufRead, mut out: impl Write) -> bool {
+    let (toks, _) = match crate::parse(pesc, opts, code) {
+        Ok(r) => r,
+        Err(e) => {
+            writeln!(out, "pesc: error: {}", e).ok();
+            return false;
+        },
+    };
+
+    let mut ok = true;
+    for (n, line) in input.lines().enumerate() {
+        let line = match line {
+            Ok(l) => l,
+            Err(e) => {
+                writeln!(out, "pesc: error: -: {}", e).ok();
+                return false;
+            },
+        };
+
+        pesc.stack = vec![value(&line, &pesc.opts)];
+        let res = pesc.eval(&toks);
+        for w in pesc.warnings.drain(..) {
+            writeln!(out, "pesc: warning: line {}: {}", n + 1, w).ok();
+        }
+
+        let printed = match res {
+            Err((_, e)) => {
+                ok = false;
+                writeln!(out, "pesc: error: line {}: {}", n + 1, e)
+            },
+            Ok(()) if pesc.stack.is_empty() => Ok(()),
+            Ok(()) if opts.output == OutputMode::Json => writeln!(out, "{}", json::stack(&pesc.stack)),
+            Ok(()) => writeln!(out, "{}", pesc.stack.iter()
+                .map(|t| match t {
+                    PescToken::Str(s) => s.clone(),
+                    t => numbers::show(t),
+                })
+                .collect::<Vec<String>>().join(" ")),
+        };
+
+        // (e.g. when piped to head)
+        if printed.is_err() {
+            break;
+        }
+    }
+
+    ok
+}
+
+// a line as a number, if it's one (with a '-' taken as a '_'), or as
+// a string
+fn value(line: &str, opts: &PescOptions) -> PescToken {
+    let trimmed = line.trim();
+    let number = match trimmed.strip_prefix('-') {
+        Some(n) => format!("_{}", n),
+        None => trimmed.to_string(),
+    };
+
+    match Pesc::parse_with(&number, opts).as_ref().map(|(_, t)| t.as_slice()) {
+        Ok([t @ (PescToken::Number(_) | PescToken::Int(_) | PescToken::Decimal(_))]) => t.clone(),
+        _ => PescToken::Str(line.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_map() {
+        let opts = Options::new();
+        let mut p = Pesc::with_stdlib();
+        assert_eq!(value(" -2.5 ", &p.opts), PescToken::Number(-2.5));
+        assert_eq!(value("2 3", &p.opts), PescToken::Str(String::from("2 3")));
+        assert_eq!(value("x", &p.opts), PescToken::Str(String::from("x")));
+
+        let mut out = Vec::new();
+        assert!(each("2*", &mut p, &opts, "1\n2\n3\n".as_bytes(), &mut out));
+        assert_eq!(String::from_utf8(out).unwrap(), "2\n4\n6\n");
+
+        // lines can be left out, or fail
+        let mut out = Vec::new();
+        assert!(!each("{$} 1 [get] 2 [gt?] [if]", &mut p, &opts, "1\nab\n5\n".as_bytes(), &mut out));
+        assert_eq!(String::from_utf8(out).unwrap(), "pesc: error: line 2: I wanted a number, but you gave a \"ab\"\n5\n");
+    }
+}
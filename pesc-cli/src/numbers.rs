@@ -0,0 +1,297 @@
+// how numbers are shown on the stack: with a fixed number of digits
+// after the point or a number of significant digits, with separators
+// between the thousands, and in engineering notation (where the
+// exponent is a multiple of 3), e.g.
+//
+//     2 [set-precision]        # 1234.5 is 1234.50
+//     "sig 3" [set-precision]  # 1234.5 is 1230, 0.012345 is 0.0123
+//     "off" [set-precision]    # 1234.5 is 1234.5
+//
+// or with fixed-digits, sig-digits, thousands and engineering in the
+// config. only what's shown changes, never the values themselves.
+
+use std::sync::Mutex;
+
+use pesc::errors::*;
+use pesc::pesc::*;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Places {
+    Fixed(usize),
+    Significant(usize),
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct NumberFormat {
+    // how many digits to round to, if any
+    pub places: Option<Places>,
+
+    // what goes between each three digits before the point, e.g. ','
+    pub thousands: Option<char>,
+    pub engineering: bool,
+}
+
+static FORMAT: Mutex<NumberFormat> = Mutex::new(NumberFormat {
+    places: None,
+    thousands: None,
+    engineering: false,
+});
+
+pub fn set(format: NumberFormat) {
+    *FORMAT.lock().unwrap_or_else(|e| e.into_inner()) = format;
+}
+
+fn get() -> NumberFormat {
+    FORMAT.lock().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+pub fn load(pesc: &mut Pesc) {
+    pesc.register("set-precision", |p| {
+        let places = match p.pop()? {
+            PescToken::Str(s) => match s.split_once(' ') {
+                _ if s == "off" => None,
+                Some(("sig", n)) => Some(Places::Significant(count(n)?)),
+                Some(("fix", n)) => Some(Places::Fixed(count(n)?)),
+                _ => return Err(PescErrorType::Other(format!(
+                    "I was expecting \"sig N\", \"fix N\" or \"off\", not {:?}.", s))),
+            },
+            t => {
+                p.push(t);
+                match p.pop_number()? {
+                    n if n < 0.0 || n.fract() != 0.0 => return Err(PescErrorType::Other(
+                        format!("{} isn't a number of digits.", n))),
+                    n => Some(Places::Fixed(n as usize)),
+                }
+            },
+        };
+
+        set(NumberFormat { places, ..get() });
+        Ok(())
+    });
+    pesc.document("set-precision", "n --",
+        "shows numbers with n digits after the point (or with n significant \
+        ones, given \"sig n\"), or as they are, given \"off\".");
+    pesc.declare("set-precision", 1, 0);
+}
+
+fn count(n: &str) -> Result<usize, PescErrorType> {
+    n.trim().parse()
+        .map_err(|_| PescErrorType::Other(format!("{} isn't a number of digits.", n)))
+}
+
+// a value as it's shown on the stack
+pub fn show(t: &PescToken) -> String {
+    let f = get();
+    match f == NumberFormat::default() {
+        true => t.to_string(),
+        false => show_with(t, &f),
+    }
+}
+
+fn show_with(t: &PescToken, f: &NumberFormat) -> String {
+    match t {
+        PescToken::Number(n) if n.is_finite() => number(&t.to_string(), f, Some(f64::DIGITS)),
+        PescToken::Int(_) | PescToken::Decimal(_) => number(&t.to_string(), f, None),
+        PescToken::List(l) => format!("({})", l.iter()
+            .map(|t| show_with(t, f)).collect::<Vec<String>>().join(" ")),
+        _ => t.to_string(),
+    }
+}
+
+// the digits of a number, without the zeros at either end, and how
+// many of them come before the point, so that 0.05 is ([5], -1) and
+// 1200 is ([1, 2], 4). zero has no digits.
+#[derive(Clone)]
+struct Digits {
+    digits: Vec<u8>,
+    point: i64,
+}
+
+impl Digits {
+    // from digits with a '.' in them, maybe
+    fn parse(s: &str) -> Self {
+        let (whole, frac) = s.split_once('.').unwrap_or((s, ""));
+        let mut digits = whole.bytes().chain(frac.bytes())
+            .map(|b| b - b'0')
+            .collect::<Vec<u8>>();
+
+        let zeros = digits.iter().take_while(|d| **d == 0).count();
+        digits.drain(..zeros);
+        while digits.last() == Some(&0) {
+            digits.pop();
+        }
+
+        Self { digits, point: whole.len() as i64 - zeros as i64 }
+    }
+
+    // rounded (half away from zero) to the first `keep` digits
+    fn round(&self, keep: i64) -> Self {
+        if keep >= self.digits.len() as i64 {
+            return self.clone();
+        } else if keep < 0 {
+            return Self { digits: Vec::new(), point: 0 };
+        }
+
+        let keep = keep as usize;
+        let mut digits = self.digits[..keep].to_vec();
+        let mut point = self.point;
+
+        if self.digits[keep] >= 5 {
+            match digits.iter().rposition(|d| *d != 9) {
+                Some(i) => {
+                    digits[i] += 1;
+                    digits.truncate(i + 1);
+                },
+                None => {
+                    digits = vec![1];
+                    point += 1;
+                },
+            }
+        }
+
+        while digits.last() == Some(&0) {
+            digits.pop();
+        }
+
+        Self { digits, point }
+    }
+
+    // the exponent to show this with in engineering notation
+    fn exponent(&self) -> i64 {
+        match self.digits.is_empty() {
+            true => 0,
+            false => (self.point - 1).div_euclid(3) * 3,
+        }
+    }
+}
+
+// a number written with digits (and a '-' or a '.', maybe), as `f` says.
+// when only so many of its digits can be relied on (e.g. 15, for an
+// f64), no more than that are shown, rather than making zeros up.
+fn number(s: &str, f: &NumberFormat, sure: Option<u32>) -> String {
+    let (neg, s) = match s.strip_prefix('-') {
+        Some(s) => (true, s),
+        None => (false, s),
+    };
+
+    let exact = Digits::parse(s);
+    let places = match (f.places, sure.map(i64::from)) {
+        (Some(Places::Fixed(n)), Some(sure)) => {
+            let before = exact.point - if f.engineering { exact.exponent() } else { 0 };
+            Some(Places::Fixed(n.min((sure - before).max(0) as usize)))
+        },
+        (Some(Places::Significant(n)), Some(sure)) => Some(Places::Significant(n.min(sure as usize))),
+        (places, _) => places,
+    };
+
+    let round = |exp: i64| match places {
+        Some(Places::Fixed(n)) => exact.round(exact.point - exp + n as i64),
+        Some(Places::Significant(n)) => exact.round(n as i64),
+        None => exact.clone(),
+    };
+
+    // (rounding up can make the number need a bigger exponent)
+    let mut exp = if f.engineering { exact.exponent() } else { 0 };
+    let mut d = round(exp);
+    if f.engineering && d.exponent() != exp {
+        exp = d.exponent();
+        d = round(exp);
+    }
+
+    let point = d.point - exp;
+    let digit = |i: i64| match i >= 0 && i < d.digits.len() as i64 {
+        true => (b'0' + d.digits[i as usize]) as char,
+        false => '0',
+    };
+
+    let mut whole = match point {
+        p if p < 1 => String::from("0"),
+        p => (0..p).map(digit).collect::<String>(),
+    };
+
+    let frac_len = match places {
+        Some(Places::Fixed(n)) => n as i64,
+        Some(Places::Significant(n)) if !d.digits.is_empty() => n as i64 - point,
+        _ => d.digits.len() as i64 - point,
+    };
+    let frac = (0..frac_len.max(0)).map(|i| digit(point + i)).collect::<String>();
+
+    if let Some(sep) = f.thousands {
+        let len = whole.len();
+        whole = whole.chars().enumerate()
+            .flat_map(|(i, c)| match i > 0 && (len - i) % 3 == 0 {
+                true => vec![sep, c],
+                false => vec![c],
+            })
+            .collect();
+    }
+
+    let mut out = match (neg && !d.digits.is_empty(), frac.is_empty()) {
+        (true, true) => format!("-{}", whole),
+        (true, false) => format!("-{}.{}", whole, frac),
+        (false, true) => whole,
+        (false, false) => format!("{}.{}", whole, frac),
+    };
+
+    if exp != 0 {
+        out += &format!("e{}", exp);
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fmt(s: &str, places: Option<Places>, thousands: Option<char>, engineering: bool) -> String {
+        number(s, &NumberFormat { places, thousands, engineering }, None)
+    }
+
+    #[test]
+    fn test_number() {
+        let (fix, sig) = (Places::Fixed, Places::Significant);
+
+        assert_eq!(fmt("1234.5", Some(fix(2)), None, false), "1234.50");
+        assert_eq!(fmt("-0.125", Some(fix(2)), None, false), "-0.13");
+        assert_eq!(fmt("-0.001", Some(fix(2)), None, false), "0.00");
+        assert_eq!(fmt("9.996", Some(fix(2)), None, false), "10.00");
+        assert_eq!(fmt("5", Some(fix(0)), None, false), "5");
+
+        assert_eq!(fmt("1234.5", Some(sig(3)), None, false), "1230");
+        assert_eq!(fmt("0.012345", Some(sig(3)), None, false), "0.0123");
+        assert_eq!(fmt("1.5", Some(sig(3)), None, false), "1.50");
+        assert_eq!(fmt("0", Some(sig(3)), None, false), "0");
+
+        assert_eq!(fmt("1234567.25", None, Some(','), false), "1,234,567.25");
+        assert_eq!(fmt("-123456", None, Some('_'), false), "-123_456");
+        assert_eq!(fmt("999", None, Some(','), false), "999");
+
+        assert_eq!(fmt("12345", None, None, true), "12.345e3");
+        assert_eq!(fmt("0.00015", None, None, true), "150e-6");
+        assert_eq!(fmt("999.96", Some(sig(3)), None, true), "1.00e3");
+        assert_eq!(fmt("1.5", None, None, true), "1.5");
+
+        // (no more digits than an f64 has)
+        let f = NumberFormat { places: Some(fix(30)), ..NumberFormat::default() };
+        assert_eq!(number("0.3333333333333333", &f, Some(f64::DIGITS)), "0.333333333333333");
+        assert_eq!(number("1234.5", &f, Some(f64::DIGITS)), "1234.50000000000");
+        assert_eq!(number("0.3333333333333333", &f, None).len(), 32);
+    }
+
+    #[test]
+    fn test_set_precision() {
+        let mut p = Pesc::with_stdlib();
+        load(&mut p);
+
+        p.run("\"sig 2\" [set-precision]").unwrap();
+        assert_eq!(get().places, Some(Places::Significant(2)));
+        assert_eq!(show(&PescToken::Number(1.23456)), "1.2");
+
+        p.run("\"off\" [set-precision]").unwrap();
+        assert_eq!(get(), NumberFormat::default());
+        assert!(p.run("\"sig x\" [set-precision]").is_err());
+        assert!(p.run("_1 [set-precision]").is_err());
+        assert!(p.run("1.5 [set-precision]").is_err());
+    }
+}
@@ -1,28 +1,76 @@
+use std::io::Write;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
 use std::time;
 use pesc::pesc::*;
 
+use crate::json;
+use crate::numbers;
 use crate::tty::{
     self, OutputStream,
     TermStyle, TermColor
 };
 
 const PADDING: usize = 3;
-const MORE_STR: &'static str = " »";
+const MORE_STR: &str = " »";
+
+// how many characters of a value fit in its cell on the stack, in
+// Human mode (see set_cell_width())
+pub const CELL_WIDTH: usize = 24;
+static CELL: AtomicUsize = AtomicUsize::new(CELL_WIDTH);
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum OutputMode {
-    Human, Simple, Quiet
+    Human, Simple, Quiet,
+
+    // for scripts: a line for each value, top first, with its type
+    // and the value separated by a tab, and an empty line after the
+    // last one. tabs, newlines and backslashes in values are escaped
+    // as \t, \n and \\.
+    Machine,
+
+    // for other programs: the whole stack as a JSON array on one line,
+    // bottom first, with each value as json::token() has it
+    Json,
 }
 
 impl OutputMode {
+    // for a human on a terminal, or anywhere with $CLICOLOR_FORCE (and
+    // with $NO_COLOR, without the colors: see Theme::auto())
     pub fn auto() -> OutputMode {
-        if tty::is_tty(OutputStream::Stdout) {
+        let dumb = std::env::var("TERM").map(|t| t == "dumb").unwrap_or(false);
+        let forced = std::env::var("CLICOLOR_FORCE").map(|v| !v.is_empty() && v != "0")
+            .unwrap_or(false);
+
+        if forced || (tty::is_tty(OutputStream::Stdout) && !dumb) {
             OutputMode::Human
         } else {
             OutputMode::Simple
         }
     }
 
+    pub fn from_name(name: &str) -> Option<OutputMode> {
+        match name {
+            "human" => Some(OutputMode::Human),
+            "simple" => Some(OutputMode::Simple),
+            "quiet" => Some(OutputMode::Quiet),
+            "machine" => Some(OutputMode::Machine),
+            "json" => Some(OutputMode::Json),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            OutputMode::Human => "human",
+            OutputMode::Simple => "simple",
+            OutputMode::Quiet => "quiet",
+            OutputMode::Machine => "machine",
+            OutputMode::Json => "json",
+        }
+    }
+
     pub fn format_elapsed(&self, dur: time::Duration) {
         match self {
             OutputMode::Human => {
@@ -33,13 +81,28 @@ impl OutputMode {
             },
             OutputMode::Simple => println!("elapsed: {:.2?}", dur),
             OutputMode::Quiet => (),
+            OutputMode::Machine => println!("elapsed\t{}\n", dur.as_secs_f64()),
+            OutputMode::Json => println!("{{\"elapsed\":{}}}", dur.as_secs_f64()),
         }
     }
 
-    pub fn format_stack(&self, stack: &Vec<PescToken>) {
+    pub fn format_stack(&self, stack: &[PescToken]) {
+        self.format_changes(stack, None);
+    }
+
+    // show the stack, and in Human mode, what changed since it was
+    // `before`: the brackets of values that were pushed are green, and
+    // those of values that were there but are different now are yellow
+    pub fn format_changes(&self, stack: &[PescToken], before: Option<&[PescToken]>) {
+        let brackets = |at: usize| match before {
+            Some(b) if at >= b.len() => TermStyle::Fg(TermColor::Green),
+            Some(b) if b[at] != stack[at] => TermStyle::Fg(TermColor::Yellow),
+            _ => TermStyle::BrightFg(TermColor::Black),
+        };
+
         match self {
             OutputMode::Human => {
-                if stack.len() == 0 {
+                if stack.is_empty() {
                     println!("{g}(empty stack){r}",
                     g = TermStyle::BrightFg(TermColor::Black),
                     r = TermStyle::Reset);
@@ -52,29 +115,23 @@ impl OutputMode {
                     TermStyle::BrightFg(TermColor::Black));
                 let mut ctr = 0;
 
-                let mut format_output = |i: &PescToken, ctr, first| -> bool {
-                    let item_color = match i {
-                        PescToken::Str(_) => TermStyle::Fg(TermColor::Cyan),
-                        PescToken::Number(_) => TermStyle::BrightFg(TermColor::White),
-                        PescToken::Macro(_) => TermStyle::Underline,
-                        PescToken::Bool(_) => TermStyle::Fg(TermColor::Yellow),
-                        _ => TermStyle::Fg(TermColor::White),
-                    };
+                let mut format_output = |i: &PescToken, ctr: usize, first| -> bool {
+                    let item_color = style(i);
 
                     let fmt_item = format!("{g}[{r}{f}{c}{item:>0$}{r}{g}]{r}",
                         PADDING, c = item_color,
-                        g = TermStyle::BrightFg(TermColor::Black),
-                        r = TermStyle::Reset, item = i.to_string(),
+                        g = brackets(stack.len() - 1 - ctr),
+                        r = TermStyle::Reset, item = truncate(&numbers::show(i)),
                         f = if first { TermStyle::Bold } else { TermStyle::Reset });
 
-                    if TermStyle::strip(&item_buf).len()
-                        + TermStyle::strip(&fmt_item).len() + 1 >= max_sz {
+                    if TermStyle::strip(&item_buf).chars().count()
+                        + TermStyle::strip(&fmt_item).chars().count() + 1 >= max_sz {
                             item_buf += MORE_STR;
                             true
                     } else {
                         item_buf += &fmt_item;
                         num_buf  += &format!("{c:>0$}",
-                            TermStyle::strip(&fmt_item).len(), c = &ctr);
+                            TermStyle::strip(&fmt_item).chars().count(), c = &ctr);
                         false
                     }
                 };
@@ -98,7 +155,199 @@ impl OutputMode {
             OutputMode::Simple
             | OutputMode::Quiet => stack.iter()
                     .rev()
-                    .for_each(|i| println!("{} ", i)),
+                    .for_each(|i| println!("{} ", numbers::show(i))),
+            OutputMode::Machine => {
+                stack.iter().rev().for_each(|i| println!("{}", machine(i)));
+                println!();
+            },
+            OutputMode::Json => println!("{}", json::stack(stack)),
         }
     }
+
+    // show the stack as HP-style registers, T at the top and X
+    // at the bottom
+    pub fn format_registers(&self, stack: &[PescToken]) {
+        let names = ["X", "Y", "Z", "T"];
+        let regs = stack.iter().rev().zip(names.iter()).collect::<Vec<_>>();
+
+        match self {
+            OutputMode::Human => regs.iter().rev()
+                .for_each(|(i, n)| println!("{g}{n}:{r} {b}{i}{r}", i = numbers::show(i),
+                    g = TermStyle::BrightFg(TermColor::Black),
+                    b = if **n == "X" { TermStyle::Bold } else { TermStyle::Reset },
+                    r = TermStyle::Reset)),
+            OutputMode::Simple => regs.iter().rev()
+                .for_each(|(i, n)| println!("{}: {}", n, numbers::show(i))),
+            OutputMode::Quiet => if let Some((i, _)) = regs.first() {
+                println!("{}", numbers::show(i));
+            },
+            OutputMode::Machine => {
+                regs.iter().rev().for_each(|(i, n)| println!("{}\t{}", n, machine(i)));
+                println!();
+            },
+            OutputMode::Json => {
+                let regs = regs.iter()
+                    .map(|(i, n)| format!("{}:{}", json::string(n), json::token(i)))
+                    .collect::<Vec<String>>();
+                println!("{{{}}}", regs.join(","));
+            },
+        }
+    }
+}
+
+// cut values shown on the stack down to `width` characters (or
+// don't, with 0), so that a long one doesn't push the rest away.
+// :show N shows one in full.
+pub fn set_cell_width(width: usize) {
+    CELL.store(width, Ordering::Relaxed);
+}
+
+// `s`, with what goes past the cell width replaced by '…'
+fn truncate(s: &str) -> String {
+    let width = CELL.load(Ordering::Relaxed);
+    match width > 0 && s.chars().count() > width {
+        true => s.chars().take(width - 1).chain(Some('…')).collect(),
+        false => String::from(s),
+    }
+}
+
+// print `text`, through $PAGER (or less) if it's too long to fit on
+// the terminal, or as it is if there's no pager to be had
+pub fn page(text: &str) {
+    let pager = std::env::var("PAGER").unwrap_or_else(|_| String::from("less -R"));
+    let mut words = pager.split_whitespace();
+
+    let cmd = match words.next() {
+        Some(cmd) if tty::is_tty(OutputStream::Stdout)
+            && text.lines().count() >= tty::tty_sz().1 => cmd,
+        _ => return print!("{}", text),
+    };
+
+    let child = std::process::Command::new(cmd).args(words)
+        .stdin(std::process::Stdio::piped())
+        .spawn();
+
+    match child {
+        Ok(mut c) => {
+            if let Some(mut stdin) = c.stdin.take() {
+                // (the pager quitting early isn't a problem)
+                stdin.write_all(text.as_bytes()).ok();
+            }
+            c.wait().ok();
+        },
+        Err(_) => print!("{}", text),
+    }
+}
+
+// the kinds of values that each have their own style, which
+// color.KIND in the config can change
+pub const KINDS: [&str; 6] = ["string", "number", "macro", "bool", "unknown", "other"];
+
+// a style for each of KINDS
+pub type Colors = [TermStyle; 6];
+
+pub const DEFAULT_COLORS: Colors = [
+    TermStyle::Fg(TermColor::Cyan),
+    TermStyle::BrightFg(TermColor::White),
+    TermStyle::Underline,
+    TermStyle::Fg(TermColor::Yellow),
+    TermStyle::Fg(TermColor::Red),
+    TermStyle::Fg(TermColor::White),
+];
+
+static COLORS: Mutex<Colors> = Mutex::new(DEFAULT_COLORS);
+
+pub fn set_colors(colors: Colors) {
+    *COLORS.lock().unwrap_or_else(|e| e.into_inner()) = colors;
+}
+
+// the style a value is shown in (in the REPL's input too)
+pub fn style(t: &PescToken) -> TermStyle {
+    let kind = match t {
+        PescToken::Str(_) => 0,
+        PescToken::Number(_) | PescToken::Int(_)
+        | PescToken::Decimal(_) | PescToken::Frac(_) => 1,
+        PescToken::Macro(_) => 2,
+        PescToken::Bool(_) => 3,
+        PescToken::Unknown(_) => 4,
+        _ => 5,
+    };
+
+    COLORS.lock().unwrap_or_else(|e| e.into_inner())[kind]
+}
+
+// a value as it's shown with at most `places` digits after the point
+// (which only floats can have too many of)
+pub fn round(t: &PescToken, places: usize) -> PescToken {
+    match t {
+        PescToken::Number(n) if n.is_finite() => {
+            let scale = 10_f64.powi(places.min(15) as i32);
+            let r = (n * scale).round() / scale;
+            PescToken::Number(if r.is_finite() { r } else { *n })
+        },
+        PescToken::List(l) => PescToken::List(Rc::new(l.iter().map(|t| round(t, places)).collect())),
+        t => t.clone(),
+    }
+}
+
+// a value's type and the value, for OutputMode::Machine
+pub fn machine(t: &PescToken) -> String {
+    let (kind, value) = match t {
+        PescToken::Str(s) => ("string", s.clone()),
+        PescToken::Number(n) => ("number", n.to_string()),
+        PescToken::Int(n) => ("integer", n.to_string()),
+        PescToken::Decimal(d) => ("decimal", d.to_string()),
+        PescToken::Frac(r) => ("fraction", r.to_string()),
+        PescToken::Func(f) => ("function", f.clone()),
+        PescToken::Macro(m) => ("macro", unparse(m)),
+        PescToken::List(l) => ("list", unparse(l)),
+        PescToken::Map(_) => {
+            // (without the '{ and })
+            let code = unparse(std::slice::from_ref(t));
+            ("map", code[2..code.len() - 1].to_string())
+        },
+        PescToken::Symbol(c) | PescToken::Unknown(c) => ("symbol", c.to_string()),
+        PescToken::Operator(o) => ("symbol", o.clone()),
+        PescToken::Bool(b) => ("bool", b.to_string()),
+        PescToken::Nil => ("nil", String::new()),
+    };
+
+    let value = value.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n");
+    format!("{}\t{}", kind, value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("\"short\""), "\"short\"");
+        assert_eq!(truncate(&"x".repeat(CELL_WIDTH + 1)),
+            format!("{}…", "x".repeat(CELL_WIDTH - 1)));
+    }
+
+    #[test]
+    fn test_machine() {
+        assert_eq!(machine(&PescToken::Number(-2.5)), "number\t-2.5");
+        assert_eq!(machine(&PescToken::Str(String::from("a\tb\\\n"))), "string\ta\\tb\\\\\\n");
+        assert_eq!(machine(&PescToken::Nil), "nil\t");
+
+        let m = PescToken::Macro(Rc::new(vec![PescToken::Number(2.0), PescToken::Symbol('*')]));
+        assert_eq!(machine(&m), "macro\t2 *");
+    }
+
+    #[test]
+    fn test_round() {
+        let n = |n| PescToken::Number(n);
+        assert_eq!(round(&n(1.23456), 4), n(1.2346));
+        assert_eq!(round(&n(0.1 + 0.2), 2), n(0.3));
+        assert_eq!(round(&n(-2.5), 0), n(-3.0));
+        assert_eq!(round(&n(1e300), 10), n(1e300));
+
+        let l = PescToken::List(Rc::new(vec![n(2.0 / 3.0), PescToken::Nil]));
+        assert_eq!(round(&l, 3), PescToken::List(Rc::new(vec![n(0.667), PescToken::Nil])));
+    }
 }
@@ -0,0 +1,256 @@
+// [plot] and :plot, which draw the numbers on the stack, the bottom
+// one first, so that a sequence can be eyeballed: [plot] as a one-line
+// sparkline, e.g.
+//
+//     ▁▂▄▆█▆▄▂▁
+//
+// and :plot as a chart HEIGHT lines high, with the biggest and the
+// smallest number beside it. when there are more numbers than fit
+// across the terminal, the ones nearest the top of the stack are kept.
+//
+// [graph] draws a function instead, in braille (two dots across and
+// four down to a character), e.g. a sine wave from 0 to 2π in 100 steps:
+//
+//     {[sin]} 0 6.2832 100 [graph]
+
+use pesc::errors::*;
+use pesc::pesc::*;
+
+use crate::tty;
+
+const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+const HEIGHT: usize = 8;
+const GRAPH_HEIGHT: usize = 12;
+
+// the braille dot at each place in a character, by row and then column
+const DOTS: [[u32; 2]; 4] = [[0x01, 0x08], [0x02, 0x10], [0x04, 0x20], [0x40, 0x80]];
+
+pub fn load(pesc: &mut Pesc) {
+    pesc.register("plot", |p| {
+        let values = numbers(&p.stack, tty::tty_sz().0);
+        if values.is_empty() {
+            return Err(PescErrorType::Other(String::from("there are no numbers to plot.")));
+        }
+
+        println!("{}", sparkline(&values));
+        Ok(())
+    });
+    pesc.document("plot", "--", "draws the numbers on the stack as a sparkline.");
+    pesc.declare("plot", 0, 0);
+
+    pesc.register("graph", |p| {
+        let steps = p.pop_integer()?.max(1);
+        let hi = p.pop_number()?;
+        let lo = p.pop_number()?;
+        let f = p.pop()?;
+
+        if steps > p.opts.max_loops {
+            return Err(PescErrorType::LoopLimitExceeded(p.opts.max_loops));
+        }
+
+        // (each x is given to f on a stack of its own)
+        let stack = std::mem::take(&mut p.stack);
+        let points = (0..=steps)
+            .map(|i| {
+                let x = lo + (hi - lo) * i as f64 / steps as f64;
+                p.stack = vec![PescToken::Number(x)];
+                p.try_exec(f.clone()).and_then(|_| p.pop_number())
+            })
+            .collect::<Result<Vec<f64>, PescErrorType>>();
+        p.stack = stack;
+
+        for line in graph(&points?, (lo, hi), tty::tty_sz().0) {
+            println!("{}", line);
+        }
+        Ok(())
+    });
+    pesc.document("graph", "{f} lo hi steps --",
+        "draws f(x) for x from lo to hi, in that many steps.");
+    pesc.declare("graph", 4, 0);
+}
+
+// :plot
+pub fn chart(stack: &[PescToken]) {
+    let (lo, hi) = match range(&numbers(stack, usize::MAX)) {
+        Some(r) => r,
+        None => return println!("there are no numbers to plot."),
+    };
+
+    let labels = [hi.to_string(), lo.to_string()];
+    let width = labels.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+    let values = numbers(stack, tty::tty_sz().0.saturating_sub(width + 2).max(1));
+
+    for (i, row) in rows(&values, HEIGHT).into_iter().enumerate() {
+        let label = match i {
+            0 => &labels[0],
+            i if i == HEIGHT - 1 => &labels[1],
+            _ => "",
+        };
+        println!("{:>w$} │{}", label, row, w = width);
+    }
+}
+
+// the last `most` numbers on the stack, as floats
+fn numbers(stack: &[PescToken], most: usize) -> Vec<f64> {
+    let all = stack.iter()
+        .filter_map(|t| match t {
+            PescToken::Number(n) => Some(*n),
+            PescToken::Int(n) => Some(n.to_f64()),
+            PescToken::Decimal(d) => Some(d.to_f64()),
+            PescToken::Frac(r) => Some(r.to_f64()),
+            _ => None,
+        })
+        .filter(|n| n.is_finite())
+        .collect::<Vec<f64>>();
+
+    all[all.len().saturating_sub(most)..].to_vec()
+}
+
+fn range(values: &[f64]) -> Option<(f64, f64)> {
+    let lo = values.iter().copied().reduce(f64::min)?;
+    let hi = values.iter().copied().reduce(f64::max)?;
+    Some((lo, hi))
+}
+
+// how high each value is, in eighths of a line, out of `height` lines
+// (all of them half-way, when they're all the same)
+fn levels(values: &[f64], height: usize) -> Vec<usize> {
+    let (lo, hi) = range(values).unwrap_or((0.0, 0.0));
+    let top = height * BARS.len();
+
+    values.iter()
+        .map(|v| match hi > lo {
+            true => 1 + ((v - lo) / (hi - lo) * (top - 1) as f64).round() as usize,
+            false => top / 2,
+        })
+        .collect()
+}
+
+pub fn sparkline(values: &[f64]) -> String {
+    levels(values, 1).into_iter()
+        .map(|l| BARS[l.clamp(1, BARS.len()) - 1])
+        .collect()
+}
+
+// the chart's lines, the top one first
+fn rows(values: &[f64], height: usize) -> Vec<String> {
+    let levels = levels(values, height);
+
+    (0..height).rev()
+        .map(|row| levels.iter()
+            .map(|l| match l.saturating_sub(row * BARS.len()) {
+                0 => ' ',
+                n => BARS[n.min(BARS.len()) - 1],
+            })
+            .collect())
+        .collect()
+}
+
+// the lines of a graph of `ys`, for xs evenly spread over `xs`, no
+// more than `width` characters wide. what isn't finite is left out.
+fn graph(ys: &[f64], xs: (f64, f64), width: usize) -> Vec<String> {
+    let finite = ys.iter().copied().filter(|y| y.is_finite()).collect::<Vec<f64>>();
+    let (lo, hi) = range(&finite).unwrap_or((0.0, 0.0));
+
+    let labels = [label(hi), label(lo)];
+    let label_w = labels.iter().map(|l| l.chars().count()).max().unwrap_or(0);
+    let cols = width.saturating_sub(label_w + 2).max(1).min(ys.len().div_ceil(2));
+    let (dots_w, dots_h) = (cols * 2, GRAPH_HEIGHT * 4);
+
+    // which dot each point goes at (the top row being 0)
+    let at = |i: usize, y: f64| {
+        let x = (i as f64 / (ys.len() - 1).max(1) as f64 * (dots_w - 1) as f64).round() as usize;
+        let y = match hi > lo {
+            true => ((hi - y) / (hi - lo) * (dots_h - 1) as f64).round() as usize,
+            false => dots_h / 2,
+        };
+        (x, y)
+    };
+
+    let mut cells = vec![vec![0u32; cols]; GRAPH_HEIGHT];
+    let mut last: Option<(usize, usize)> = None;
+    for (i, y) in ys.iter().enumerate() {
+        if !y.is_finite() {
+            last = None;
+            continue;
+        }
+
+        // (joined up with the point before, when it's in the same column
+        // or the next one, so that steep parts aren't just a few dots)
+        let (x, y) = at(i, *y);
+        let from = match last {
+            Some((lx, ly)) if x - lx <= 1 => ly,
+            _ => y,
+        };
+        for y in from.min(y)..=from.max(y) {
+            cells[y / 4][x / 2] |= DOTS[y % 4][x % 2];
+        }
+        last = Some((x, y));
+    }
+
+    let mut lines = cells.iter().enumerate()
+        .map(|(i, row)| {
+            let label = match i {
+                0 => &labels[0],
+                i if i == GRAPH_HEIGHT - 1 => &labels[1],
+                _ => "",
+            };
+            let dots = row.iter()
+                .map(|d| char::from_u32(0x2800 + d).unwrap_or(' '))
+                .collect::<String>();
+            format!("{:>w$} │{}", label, dots, w = label_w)
+        })
+        .collect::<Vec<String>>();
+
+    let (left, right) = (label(xs.0), label(xs.1));
+    lines.push(format!("{:w$} └{}", "", "─".repeat(cols), w = label_w));
+    lines.push(format!("{:w$}  {}{:>r$}", "", left, right,
+        w = label_w, r = cols.saturating_sub(left.chars().count()).max(right.chars().count() + 1)));
+    lines
+}
+
+// a number on a graph's axis, to four places at most
+fn label(n: f64) -> String {
+    let s = format!("{:.4}", n);
+    let s = s.trim_end_matches('0').trim_end_matches('.');
+    match s {
+        "-0" => String::from("0"),
+        s => s.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plot() {
+        assert_eq!(sparkline(&[1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0]), "▁▂▃▄▅▆▇█");
+        assert_eq!(sparkline(&[0.0, 10.0, 0.0]), "▁█▁");
+        assert_eq!(sparkline(&[3.0, 3.0]), "▄▄");
+
+        assert_eq!(rows(&[0.0, 1.0, 2.0], 2), vec![" ▁█", "▁██"]);
+
+        let stack = vec![PescToken::Number(1.0), PescToken::Str(String::from("x")),
+            PescToken::Number(f64::NAN), PescToken::Number(2.0), PescToken::Number(3.0)];
+        assert_eq!(numbers(&stack, 2), vec![2.0, 3.0]);
+    }
+
+    #[test]
+    fn test_graph() {
+        let lines = graph(&[0.0, 1.0, 2.0, 3.0], (0.0, 1.5), 80);
+        assert_eq!(lines.len(), GRAPH_HEIGHT + 2);
+        assert_eq!(lines[0], "3 │⠀⢸");
+        assert_eq!(lines[GRAPH_HEIGHT - 1], "0 │⣸⠀");
+        assert_eq!(lines[GRAPH_HEIGHT + 1], "   0 1.5");
+        assert_eq!(label(-0.00001), "0");
+        assert_eq!(label(1.23456), "1.2346");
+
+        let mut p = Pesc::with_stdlib();
+        load(&mut p);
+        p.run("7 {[dup] *} _1 1 4 [graph]").unwrap();
+        assert_eq!(p.stack, vec![PescToken::Number(7.0)]);
+        assert!(p.run("{\"x\"} 0 1 4 [graph]").is_err());
+        assert_eq!(p.stack.len(), 5);
+    }
+}
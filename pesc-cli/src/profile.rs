@@ -0,0 +1,100 @@
+// the profiler behind --profile and ":profile on", which
+// counts how often each function is called and how long
+// it takes. ":profile <code>" profiles just that code.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use pesc::pesc::*;
+
+use crate::output::OutputMode;
+
+#[derive(Clone, Debug, Default)]
+struct Entry {
+    calls: usize,
+
+    // including the functions it called, and without
+    total: Duration,
+    own: Duration,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Profile {
+    funcs: HashMap<String, Entry>,
+
+    // (start, time spent in callees) of each running function
+    running: Vec<(Instant, Duration)>,
+}
+
+// start recording every call pesc makes, until finish()
+pub fn start(pesc: &mut Pesc) -> Rc<RefCell<Profile>> {
+    let prof = Rc::new(RefCell::new(Profile::default()));
+    let p = prof.clone();
+
+    pesc.hooks.push(Box::new(move |_, ev| {
+        let mut p = p.borrow_mut();
+
+        match ev {
+            PescEvent::Call(_) => p.running.push((Instant::now(), Duration::ZERO)),
+            PescEvent::Return(f) => {
+                let (start, inner) = match p.running.pop() {
+                    Some(r) => r,
+                    None => return Ok(()),
+                };
+
+                let elapsed = start.elapsed();
+                if let Some(parent) = p.running.last_mut() {
+                    parent.1 += elapsed;
+                }
+
+                let e = p.funcs.entry(String::from(f)).or_default();
+                e.calls += 1;
+                e.total += elapsed;
+                e.own += elapsed.saturating_sub(inner);
+            },
+            _ => (),
+        }
+
+        Ok(())
+    }));
+
+    prof
+}
+
+// stop recording, and print what was recorded to stderr,
+// slowest functions first
+pub fn finish(pesc: &mut Pesc, prof: Rc<RefCell<Profile>>) {
+    pesc.hooks.pop();
+
+    let prof = prof.borrow();
+    let mut funcs = prof.funcs.iter().collect::<Vec<_>>();
+    funcs.sort_by(|a, b| b.1.own.cmp(&a.1.own).then(a.0.cmp(b.0)));
+
+    eprintln!("{:>8}  {:>12}  {:>12}  function", "calls", "self", "total");
+    for (name, e) in funcs {
+        eprintln!("{:>8}  {:>12.2?}  {:>12.2?}  {}", e.calls, e.own, e.total, name);
+    }
+}
+
+// run code with the profiler on, and show the stack after it
+pub fn run(code: &str, pesc: &mut Pesc, output: OutputMode) {
+    let parsed = match pesc.parse_code(code) {
+        Ok(r) => r,
+        Err(e) => {
+            println!("error: {}", e);
+            return;
+        },
+    };
+
+    let prof = start(pesc);
+    let res = pesc.eval(&parsed);
+    finish(pesc, prof);
+
+    if let Err((_, e)) = res {
+        println!("error: {}", e);
+    }
+
+    output.format_stack(&pesc.stack);
+}
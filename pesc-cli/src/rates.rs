@@ -0,0 +1,184 @@
+// [cur-convert], which converts between currencies with the rates in
+// a file, e.g.
+//
+//     100 "USD" "EUR" [cur-convert]
+//
+// the file (--rates FILE, or $PESC_RATES, or rates.json or rates.csv
+// in ~/.config/pesc) is either JSON, in the shape most rate APIs use:
+//
+//     {"base": "USD", "rates": {"EUR": 0.92, "JPY": 149.3}}
+//
+// (or just the "rates" object), or CSV, with a currency and its rate
+// on each line. rates are how much of the currency one unit of the
+// base currency buys. --fetch-rates URL downloads a new file (with
+// curl) before anything else, and only happens when asked for.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::process::Command;
+use std::rc::Rc;
+
+use pesc::errors::*;
+use pesc::pesc::*;
+
+use crate::args::Options;
+use crate::config;
+use crate::json::{self, Value};
+
+// load the rates (fetching them first if asked to). it's fine for
+// there to be no rates, unless there was supposed to be a file.
+pub fn setup(pesc: &mut Pesc, opts: &Options) -> Result<(), String> {
+    let path = match &opts.rates {
+        Some(p) => p.clone(),
+        None => default_file().to_string_lossy().into_owned(),
+    };
+
+    // (fetching runs curl, which a sandbox doesn't)
+    match &opts.fetch_rates {
+        Some(_) if opts.sandbox =>
+            return Err(String::from("--fetch-rates can't be used with --sandbox (or --serve)")),
+        Some(url) => fetch(url, &path)?,
+        None => (),
+    }
+
+    let data = match std::fs::read_to_string(&path) {
+        Ok(d) => d,
+        Err(e) if opts.rates.is_some() || opts.fetch_rates.is_some() =>
+            return Err(format!("{}: {}", path, e)),
+        Err(_) => return Ok(()),
+    };
+
+    let rates = parse(&data, !path.ends_with(".csv")).map_err(|e| format!("{}: {}", path, e))?;
+    load(pesc, rates);
+    Ok(())
+}
+
+pub fn parse(data: &str, json: bool) -> Result<HashMap<String, f64>, String> {
+    let mut rates = HashMap::new();
+
+    if json {
+        let v = json::parse(data).ok_or("that's not JSON")?;
+        let table = match v.get("rates") {
+            Some(r) => r,
+            None => &v,
+        };
+
+        match table {
+            Value::Object(o) => for (code, rate) in o {
+                match rate {
+                    Value::Number(n) => rates.insert(code.to_uppercase(), *n),
+                    _ => return Err(format!("the rate for {} isn't a number", code)),
+                };
+            },
+            _ => return Err(String::from("there are no rates in it")),
+        }
+
+        if let Some(base) = v.get("base").and_then(Value::as_str) {
+            rates.insert(base.to_uppercase(), 1.0);
+        }
+    } else {
+        for (i, line) in data.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            let (code, rate) = match line.split_once(',') {
+                Some((c, r)) => (c.trim(), r.trim()),
+                None if line.is_empty() => continue,
+                None => return Err(format!("line {} isn't CODE,RATE", i + 1)),
+            };
+
+            match rate.parse::<f64>() {
+                Ok(r) => rates.insert(code.to_uppercase(), r),
+
+                // probably a header
+                Err(_) if i == 0 => continue,
+                Err(_) => return Err(format!("the rate for {} isn't a number", code)),
+            };
+        }
+    }
+
+    Ok(rates)
+}
+
+// where the rates are by default
+pub fn default_file() -> PathBuf {
+    if let Ok(p) = std::env::var("PESC_RATES") {
+        return PathBuf::from(p);
+    }
+
+    let dir = config::dir();
+
+    match dir.join("rates.csv").exists() {
+        true => dir.join("rates.csv"),
+        false => dir.join("rates.json"),
+    }
+}
+
+// download the rates at url to path. they're downloaded next to it
+// first, and only put in its place if they can be read, so that a
+// download that fails doesn't leave path broken.
+pub fn fetch(url: &str, path: &str) -> Result<(), String> {
+    let part = format!("{}.{}.part", path, std::process::id());
+    let status = Command::new("curl")
+        .args(["-fsSL", "-o", &part, "--", url])
+        .status()
+        .map_err(|e| format!("couldn't run curl: {}", e));
+
+    let res = match status {
+        Ok(s) if s.success() => std::fs::read_to_string(&part)
+            .map_err(|e| e.to_string())
+            .and_then(|data| parse(&data, !path.ends_with(".csv")))
+            .map_err(|e| format!("{}: {}", url, e))
+            .and_then(|_| std::fs::rename(&part, path).map_err(|e| format!("{}: {}", path, e))),
+        Ok(_) => Err(format!("couldn't download {}", url)),
+        Err(e) => Err(e),
+    };
+
+    if res.is_err() {
+        std::fs::remove_file(&part).ok();
+    }
+    res
+}
+
+pub fn load(pesc: &mut Pesc, rates: HashMap<String, f64>) {
+    let rates = Rc::new(rates);
+
+    pesc.load_documented(None, "cur-convert", Rc::new(Box::new(move |p| {
+        let to = p.pop_string()?.to_uppercase();
+        let from = p.pop_string()?.to_uppercase();
+        let n = p.pop_number()?;
+
+        let rate = |c: &str| rates.get(c).copied().ok_or_else(||
+            PescErrorType::Other(format!("I don't know what {} is worth.", c)));
+
+        p.push(PescToken::Number(n * rate(&to)? / rate(&from)?));
+        Ok(())
+    })), Some((3, 1)), "n from to -- n'",
+        "n in the currency from, in the currency to, e.g. 5 \"usd\" \"eur\" [cur-convert].");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rates() {
+        let r = parse(r#"{"base": "USD", "rates": {"EUR": 0.5, "jpy": 150}}"#, true).unwrap();
+        assert_eq!((r["USD"], r["EUR"], r["JPY"]), (1.0, 0.5, 150.0));
+
+        let r = parse("currency,rate\nUSD,1\nEUR, 0.5 # cheap\n\n", false).unwrap();
+        assert_eq!((r["USD"], r["EUR"]), (1.0, 0.5));
+
+        assert!(parse("[1, 2]", true).is_err());
+        assert!(parse("USD,1\nEUR,lots", false).is_err());
+
+        let mut p = Pesc::new();
+        load(&mut p, r);
+        let code = p.parse_code("10 \"eur\" \"USD\" [cur-convert]").unwrap();
+        p.eval(&code).unwrap();
+        assert_eq!(p.stack, vec![PescToken::Number(20.0)]);
+
+        let mut opts = Options::new();
+        opts.fetch_rates = Some(String::from("http://localhost:1/rates.json"));
+        opts.sandbox = true;
+        assert!(setup(&mut p, &opts).unwrap_err().contains("--sandbox"));
+    }
+}
@@ -0,0 +1,501 @@
+// --serve ADDR (or `pesc serve [ADDR]`), which evaluates code sent to
+// it over a Unix socket (unix:/path/to.sock) or TCP (tcp:host:port, or
+// just a port to listen on localhost; 127.0.0.1:7878 by default).
+//
+// a connection can send lines, which are all evaluated by one
+// interpreter that's kept around, and are each answered with any
+// warnings, each on a line of its own, followed by one of
+//
+//     warning: <message>
+//     ok <the stack, as JSON>
+//     error: <message>
+//
+// or it can be an HTTP request, e.g.
+//
+//     curl -d '2 3+' localhost:7878
+//
+// where the code in the body of a POST is evaluated by an interpreter
+// of its own, or with an `X-Pesc-Session: KEY` header, by the one kept
+// for KEY, and answered with {"stack": [...], "warnings": [...]}, or
+// {"error": ..., "warnings": [...]}.
+//
+// if $PESC_SERVE_TOKEN is set, the first line of every connection
+// has to be `auth <token>` (or an HTTP request has to have an
+// `Authorization: Bearer <token>` header). as with --sandbox, there's
+// no [include].
+//
+// each connection is served on a thread of its own (MAX_CONNECTIONS
+// at most), but what's sent is run one line or request at a time, by
+// the thread the interpreters are on. so that nothing keeps the others
+// waiting for long, each line or request runs for MAX_TIME (or
+// MAX_STEPS) at most, unless the config has a timeout (or max-steps)
+// of its own, bodies and lines are MAX_BODY bytes at most, a
+// connection is closed after IDLE without anything sent, or after
+// it's been open for MAX_HELD, and only the MAX_SESSIONS sessions used
+// last are kept.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use pesc::pesc::*;
+
+use crate::args::Options;
+use crate::json;
+
+pub const DEFAULT_ADDR: &str = "127.0.0.1:7878";
+
+const MAX_TIME: Duration = Duration::from_secs(5);
+const MAX_STEPS: u64 = 10_000_000;
+const MAX_BODY: usize = 1 << 20;
+const IDLE: Duration = Duration::from_secs(30);
+const MAX_HELD: Duration = Duration::from_secs(60 * 60);
+const MAX_CONNECTIONS: usize = 64;
+const MAX_SESSIONS: usize = 64;
+
+// which interpreter to run some code in
+enum Target {
+    // the one for lines
+    Shared,
+    Fresh,
+    Session(String),
+}
+
+// the warnings, and the stack (as JSON) or the error
+type Outcome = (Vec<String>, Result<String, String>);
+
+// some code for the interpreters' thread to run, and where to send
+// how it went
+struct Job {
+    target: Target,
+    code: String,
+    done: mpsc::Sender<Outcome>,
+}
+
+struct Server<'a, F> {
+    // for lines
+    pesc: &'a mut Pesc,
+
+    // for HTTP requests, by their X-Pesc-Session, with when each was
+    // last used (counting requests)
+    sessions: HashMap<String, (u64, Pesc)>,
+    requests: u64,
+    fresh: F,
+
+    opts: &'a Options,
+}
+
+// what a connection's thread needs: the token the connection has to
+// give, and a way to run code
+struct Client<'a> {
+    token: Option<&'a str>,
+    run: &'a mut dyn FnMut(Target, String) -> Outcome,
+}
+
+pub fn run<F>(addr: &str, pesc: &mut Pesc, opts: &Options, fresh: F) -> Result<(), io::Error>
+    where F: Fn() -> Pesc
+{
+    limit(pesc);
+    let mut server = Server { pesc, sessions: HashMap::new(), requests: 0, fresh, opts };
+    let token = std::env::var("PESC_SERVE_TOKEN").ok();
+    let (jobs, queue) = mpsc::channel();
+
+    if let Some(path) = addr.strip_prefix("unix:") {
+        unix(path, jobs, token)?;
+    } else {
+        let addr = addr.strip_prefix("tcp:").unwrap_or(addr);
+        let addr = match addr.parse::<u16>() {
+            Ok(port) => format!("127.0.0.1:{}", port),
+            Err(_) => addr.to_string(),
+        };
+
+        let listener = TcpListener::bind(&addr)?;
+        eprintln!("pesc: listening on {}", listener.local_addr()?);
+        thread::spawn(move || accept(listener.incoming(), jobs, token));
+    }
+
+    for job in queue {
+        job.done.send(server.run(job.target, &job.code)).ok();
+    }
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn unix(path: &str, jobs: mpsc::Sender<Job>, token: Option<String>) -> Result<(), io::Error> {
+    let listener = std::os::unix::net::UnixListener::bind(path)?;
+    eprintln!("pesc: listening on {}", path);
+    thread::spawn(move || accept(listener.incoming(), jobs, token));
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn unix(_: &str, _: mpsc::Sender<Job>, _: Option<String>) -> Result<(), io::Error> {
+    Err(io::Error::new(io::ErrorKind::Unsupported,
+        "there are no Unix sockets here; use tcp:HOST:PORT instead"))
+}
+
+// a connection, whose reads and writes can be given a time limit
+trait Timed {
+    fn limit(&self, t: Duration) -> io::Result<()>;
+}
+
+impl Timed for TcpStream {
+    fn limit(&self, t: Duration) -> io::Result<()> {
+        self.set_read_timeout(Some(t))?;
+        self.set_write_timeout(Some(t))
+    }
+}
+
+#[cfg(unix)]
+impl Timed for std::os::unix::net::UnixStream {
+    fn limit(&self, t: Duration) -> io::Result<()> {
+        self.set_read_timeout(Some(t))?;
+        self.set_write_timeout(Some(t))
+    }
+}
+
+// a connection that can't be read from for more than IDLE at a time,
+// or at all once it's been open for MAX_HELD
+struct Held<'c, S> {
+    conn: &'c S,
+    until: Instant,
+}
+
+impl<'c, S: Timed> Read for Held<'c, S>
+    where &'c S: Read
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let left = self.until.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return Err(io::Error::new(io::ErrorKind::TimedOut, "the connection was open for too long"));
+        }
+
+        self.conn.limit(left.min(IDLE))?;
+        let mut conn = self.conn;
+        conn.read(buf)
+    }
+}
+
+// serve each connection on a thread of its own, sending what they want
+// run to the interpreters' thread. connections beyond MAX_CONNECTIONS
+// are closed straight away.
+fn accept<S>(conns: impl Iterator<Item = io::Result<S>>, jobs: mpsc::Sender<Job>, token: Option<String>)
+    where S: Timed + Send + 'static, for<'c> &'c S: Read + Write
+{
+    let open = Arc::new(AtomicUsize::new(0));
+
+    for conn in conns {
+        let conn = match conn {
+            Ok(c) => c,
+            Err(e) => {
+                eprintln!("pesc: warning: {}", e);
+                continue;
+            },
+        };
+
+        if open.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+            open.fetch_sub(1, Ordering::SeqCst);
+            continue;
+        }
+
+        let (open, jobs, token) = (open.clone(), jobs.clone(), token.clone());
+        thread::spawn(move || {
+            let mut run = |target, code| {
+                let (done, outcome) = mpsc::channel();
+                jobs.send(Job { target, code, done }).ok();
+                outcome.recv()
+                    .unwrap_or_else(|_| (Vec::new(), Err(String::from("the server has stopped."))))
+            };
+
+            Client { token: token.as_deref(), run: &mut run }.session(&conn);
+            open.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+}
+
+impl<F: Fn() -> Pesc> Server<'_, F> {
+    fn run(&mut self, target: Target, code: &str) -> Outcome {
+        let opts = self.opts;
+        let mut fresh;
+        let pesc = match target {
+            Target::Shared => &mut *self.pesc,
+            Target::Session(k) => self.kept(&k),
+            Target::Fresh => {
+                fresh = limited((self.fresh)());
+                &mut fresh
+            },
+        };
+
+        let (warnings, res) = eval(pesc, opts, code);
+        (warnings, res.map(|()| json::stack(&pesc.stack)))
+    }
+
+    // the interpreter kept for a session, forgetting the one that was
+    // used longest ago to make room for it if need be
+    fn kept(&mut self, key: &str) -> &mut Pesc {
+        if !self.sessions.contains_key(key) && self.sessions.len() >= MAX_SESSIONS {
+            let oldest = self.sessions.iter()
+                .min_by_key(|(_, (used, _))| *used)
+                .map(|(k, _)| k.clone());
+            if let Some(k) = oldest {
+                self.sessions.remove(&k);
+            }
+        }
+
+        self.requests += 1;
+        let fresh = &self.fresh;
+        let (used, pesc) = self.sessions.entry(key.to_string())
+            .or_insert_with(|| (0, limited(fresh())));
+        *used = self.requests;
+        pesc
+    }
+}
+
+impl Client<'_> {
+    fn session<'c, S: Timed>(&mut self, conn: &'c S)
+        where &'c S: Read + Write
+    {
+        let mut out = conn;
+        let mut input = BufReader::new(Held { conn, until: Instant::now() + MAX_HELD });
+
+        let mut first = String::new();
+        if !matches!(read_line(&mut input, &mut first), Ok(n) if n > 0) {
+            return;
+        }
+
+        if http_request(first.trim_end()).is_some() {
+            self.http(first.trim_end(), &mut input, out);
+            return;
+        }
+
+        let mut lines = Some(Ok(first.trim_end().to_string())).into_iter()
+            .chain(std::iter::from_fn(|| {
+                let mut line = String::new();
+                match read_line(&mut input, &mut line) {
+                    Ok(0) => None,
+                    r => Some(r.map(|_| line.trim_end().to_string())),
+                }
+            }));
+
+        if let Some(token) = self.token {
+            let auth = lines.next().and_then(|l| l.ok());
+            if auth.as_deref().and_then(|l| l.strip_prefix("auth ")) != Some(token) {
+                writeln!(out, "error: Who are you?").ok();
+                return;
+            }
+        }
+
+        for line in lines {
+            let line = match line {
+                Ok(l) => l,
+                Err(_) => return,
+            };
+
+            let (warnings, res) = (self.run)(Target::Shared, line);
+            let mut reply = warnings.iter()
+                .map(|w| format!("warning: {}\n", w))
+                .collect::<String>();
+
+            match res {
+                Ok(stack) => reply += &format!("ok {}", stack),
+                Err(e) => reply += &format!("error: {}", e),
+            }
+
+            if writeln!(out, "{}", reply).is_err() {
+                return;
+            }
+        }
+    }
+
+    // answer one HTTP request, whose first line was `request`
+    fn http(&mut self, request: &str, input: &mut impl BufRead, mut out: impl Write) {
+        let mut headers = HashMap::new();
+        loop {
+            let mut line = String::new();
+            match read_line(input, &mut line) {
+                Ok(0) | Err(_) => return,
+                _ if line.trim_end().is_empty() => break,
+                _ => if let Some((k, v)) = line.split_once(':') {
+                    headers.insert(k.trim().to_lowercase(), v.trim().to_string());
+                },
+            }
+        }
+
+        let authorized = match self.token {
+            Some(t) => headers.get("authorization").and_then(|a| a.strip_prefix("Bearer "))
+                == Some(t),
+            None => true,
+        };
+
+        // (the body isn't read unless it's going to be run)
+        let len = headers.get("content-length").and_then(|l| l.parse().ok()).unwrap_or(0);
+        let (status, reply) = match http_request(request) {
+            _ if !authorized => ("401 Unauthorized", error("Who are you?", &[])),
+            Some("POST") if len > MAX_BODY => ("413 Payload Too Large",
+                error(&format!("send at most {} bytes of code.", MAX_BODY), &[])),
+            Some("POST") => {
+                let mut body = vec![0; len];
+                if input.read_exact(&mut body).is_err() {
+                    return;
+                }
+                self.post(String::from_utf8_lossy(&body).into_owned(), headers.remove("x-pesc-session"))
+            },
+            _ => ("405 Method Not Allowed", error("send the code to run in the body of a POST.", &[])),
+        };
+
+        write!(out, "HTTP/1.1 {}\r\nContent-Type: application/json\r\n\
+            Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status, reply.len(), reply).ok();
+    }
+
+    fn post(&mut self, code: String, key: Option<String>) -> (&'static str, String) {
+        let target = match key {
+            Some(k) => Target::Session(k),
+            None => Target::Fresh,
+        };
+
+        match (self.run)(target, code) {
+            (warnings, Ok(stack)) => ("200 OK", format!("{{\"stack\":{},\"warnings\":{}}}",
+                stack, strings(&warnings))),
+            (warnings, Err(e)) => ("400 Bad Request", error(&e, &warnings)),
+        }
+    }
+}
+
+// the limits on what's run, unless there were some already
+fn limit(pesc: &mut Pesc) {
+    pesc.opts.timeout.get_or_insert(MAX_TIME);
+    pesc.opts.max_steps.get_or_insert(MAX_STEPS);
+}
+
+fn limited(mut pesc: Pesc) -> Pesc {
+    limit(&mut pesc);
+    pesc
+}
+
+// read_line, but with lines no longer than MAX_BODY
+fn read_line(input: &mut impl BufRead, line: &mut String) -> io::Result<usize> {
+    let n = input.by_ref().take(MAX_BODY as u64).read_line(line)?;
+    match n == MAX_BODY && !line.ends_with('\n') {
+        true => Err(io::Error::new(io::ErrorKind::InvalidData, "that line is too long")),
+        false => Ok(n),
+    }
+}
+
+// the method of an HTTP request, given its first line
+fn http_request(line: &str) -> Option<&str> {
+    match line.split(' ').collect::<Vec<&str>>().as_slice() {
+        [method, _, version] if version.starts_with("HTTP/")
+            && method.chars().all(|c| c.is_ascii_uppercase()) => Some(method),
+        _ => None,
+    }
+}
+
+// run some code, returning the warnings and how it went
+fn eval(pesc: &mut Pesc, opts: &Options, code: &str) -> (Vec<String>, Result<(), String>) {
+    let res = crate::parse(pesc, opts, code)
+        .map_err(|e| e.to_string())
+        .and_then(|(toks, _)| pesc.eval(&toks).map_err(|(_, e)| e.to_string()));
+
+    let warnings = pesc.warnings.drain(..).map(|w| w.to_string()).collect();
+    (warnings, res)
+}
+
+fn strings(s: &[String]) -> String {
+    format!("[{}]", s.iter().map(|s| json::string(s)).collect::<Vec<String>>().join(","))
+}
+
+fn error(message: &str, warnings: &[String]) -> String {
+    format!("{{\"error\":{},\"warnings\":{}}}", json::string(message), strings(warnings))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(server: &mut Server<fn() -> Pesc>, token: Option<&str>, req: &str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        client.write_all(req.as_bytes()).unwrap();
+        client.shutdown(std::net::Shutdown::Write).unwrap();
+
+        let mut run = |target, code: String| server.run(target, &code);
+        Client { token, run: &mut run }.session(&listener.accept().unwrap().0);
+        let mut reply = String::new();
+        client.read_to_string(&mut reply).unwrap();
+        reply
+    }
+
+    fn post(code: &str, header: &str) -> String {
+        format!("POST / HTTP/1.1\r\n{}Content-Length: {}\r\n\r\n{}", header, code.len(), code)
+    }
+
+    #[test]
+    fn test_serve() {
+        let mut shared = Pesc::with_stdlib();
+        let opts = Options::new();
+        let mut server: Server<fn() -> Pesc> = Server {
+            pesc: &mut shared, sessions: HashMap::new(), requests: 0, fresh: Pesc::with_stdlib,
+            opts: &opts,
+        };
+
+        assert_eq!(request(&mut server, None, "1 2+\n3\n[nope]\n"),
+            "ok [3]\nok [3,3]\nerror: I have no idea what nope means.\n");
+
+        let reply = request(&mut server, None, &post("2 3+", ""));
+        assert!(reply.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(reply.ends_with("\r\n\r\n{\"stack\":[5],\"warnings\":[]}"));
+
+        // a session is kept, and nothing else is
+        request(&mut server, None, &post("7", "X-Pesc-Session: a\r\n"));
+        assert!(request(&mut server, None, &post("1", "X-Pesc-Session: a\r\n")).ends_with("{\"stack\":[7,1],\"warnings\":[]}"));
+        assert!(request(&mut server, None, &post("1", "")).ends_with("{\"stack\":[1],\"warnings\":[]}"));
+        assert!(request(&mut server, None, &post("+", "")).starts_with("HTTP/1.1 400 Bad Request\r\n"));
+        assert!(request(&mut server, None, "GET / HTTP/1.1\r\n\r\n").starts_with("HTTP/1.1 405"));
+        assert!(request(&mut server, None, "POST / HTTP/1.1\r\nContent-Length: 99999999999999\r\n\r\n")
+            .starts_with("HTTP/1.1 413"));
+
+        // what's run is limited, and only so many sessions are kept
+        assert_eq!(server.kept("a").opts.max_steps, Some(MAX_STEPS));
+        for i in 0..MAX_SESSIONS {
+            server.run(Target::Session(i.to_string()), "1").1.unwrap();
+        }
+        assert_eq!(server.sessions.len(), MAX_SESSIONS);
+        assert!(!server.sessions.contains_key("a"));
+        assert!(server.sessions.contains_key("0"));
+
+        let token = Some("t");
+        assert!(request(&mut server, token, &post("1", "")).starts_with("HTTP/1.1 401"));
+        assert!(request(&mut server, token, "POST / HTTP/1.1\r\nContent-Length: 99999999999999\r\n\r\n")
+            .starts_with("HTTP/1.1 401"));
+        assert!(request(&mut server, token, &post("1", "Authorization: Bearer t\r\n")).starts_with("HTTP/1.1 200"));
+        assert_eq!(request(&mut server, token, "auth t\n1\n"), "ok [3,3,1]\n");
+    }
+
+    #[test]
+    fn test_connections() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (jobs, queue) = mpsc::channel();
+        thread::spawn(move || accept(listener.incoming(), jobs, None));
+
+        // a connection that's kept open doesn't keep the next one waiting
+        let _idle = TcpStream::connect(addr).unwrap();
+        let mut client = TcpStream::connect(addr).unwrap();
+        client.write_all(b"1 2+\n").unwrap();
+
+        let job: Job = queue.recv().unwrap();
+        assert!(matches!(job.target, Target::Shared));
+        assert_eq!(job.code, "1 2+");
+        job.done.send((Vec::new(), Ok(String::from("[3]")))).unwrap();
+
+        let mut reply = String::new();
+        BufReader::new(&client).read_line(&mut reply).unwrap();
+        assert_eq!(reply, "ok [3]\n");
+    }
+}
@@ -0,0 +1,193 @@
+// keeping the REPL's stack (and dc's registers) between sessions.
+// with `persist = true` in the config, they're saved when the REPL
+// exits, to the config's state-file (or $PESC_STATE, or state.pesc
+// in ~/.local/state/pesc), and restored when it next starts. the
+// file has a line for the stack and for each register:
+//
+//     stack 1 2 "three" {4 [add]}
+//     register a 5
+//
+// the values are written as pesc literals, except for nil and
+// non-finite numbers, which have none, and are written as [nil],
+// [inf], [-inf] and [nan]. :discard forgets it all.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use pesc::pesc::{self as interp, Pesc, PescOptions, PescToken};
+
+use crate::args::Options;
+use crate::dc::Registers;
+
+pub struct State {
+    // where it's kept, if it's kept at all
+    path: Option<PathBuf>,
+
+    registers: Option<Registers>,
+}
+
+impl State {
+    pub fn new(opts: &Options, registers: Option<Registers>) -> Self {
+        let path = match &opts.state_file {
+            _ if !opts.persist => None,
+            Some(p) => Some(PathBuf::from(p)),
+            None => Some(default_file()),
+        };
+
+        Self { path, registers }
+    }
+
+    // bring back what was saved, if anything was
+    pub fn restore(&self, pesc: &mut Pesc) -> Result<(), String> {
+        let path = match &self.path {
+            Some(p) => p,
+            None => return Ok(()),
+        };
+
+        let data = match std::fs::read_to_string(path) {
+            Ok(d) => d,
+            Err(_) => return Ok(()),
+        };
+
+        let (stack, registers) = read(&data, &pesc.opts)
+            .map_err(|e| format!("{}: {}", path.display(), e))?;
+
+        pesc.stack = stack;
+        if let Some(r) = &self.registers {
+            *r.borrow_mut() = registers;
+        }
+
+        Ok(())
+    }
+
+    pub fn save(&self, pesc: &Pesc) -> Result<(), String> {
+        let path = match &self.path {
+            Some(p) => p,
+            None => return Ok(()),
+        };
+
+        let registers = self.registers.as_ref()
+            .map(|r| r.borrow().clone())
+            .unwrap_or_default();
+
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).ok();
+        }
+
+        std::fs::write(path, write(&pesc.stack, &registers))
+            .map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    // start afresh, here and next time
+    pub fn discard(&self, pesc: &mut Pesc) {
+        pesc.stack.clear();
+        if let Some(r) = &self.registers {
+            r.borrow_mut().clear();
+        }
+
+        if let Some(p) = &self.path {
+            std::fs::remove_file(p).ok();
+        }
+    }
+}
+
+// where the state is kept by default
+pub fn default_file() -> PathBuf {
+    if let Ok(p) = std::env::var("PESC_STATE") {
+        return PathBuf::from(p);
+    }
+
+    std::env::var("XDG_STATE_HOME").map(PathBuf::from)
+        .or_else(|_| std::env::var("HOME").map(|h| PathBuf::from(h).join(".local/state")))
+        .unwrap_or_default()
+        .join("pesc")
+        .join("state.pesc")
+}
+
+fn write(stack: &[PescToken], registers: &HashMap<char, Vec<PescToken>>) -> String {
+    let values = |toks: &[PescToken]| {
+        let toks = toks.iter().map(|t| match t {
+            PescToken::Nil => PescToken::Func(String::from("nil")),
+            PescToken::Number(n) if !n.is_finite() =>
+                PescToken::Func(n.to_string().to_lowercase()),
+            t => t.clone(),
+        }).collect::<Vec<PescToken>>();
+        interp::unparse(&toks)
+    };
+
+    let mut names = registers.keys().collect::<Vec<&char>>();
+    names.sort();
+
+    let mut buf = format!("stack {}\n", values(stack));
+    for r in names {
+        buf += &format!("register {} {}\n", r, values(&registers[r]));
+    }
+
+    buf
+}
+
+type Saved = (Vec<PescToken>, HashMap<char, Vec<PescToken>>);
+
+fn read(data: &str, opts: &PescOptions) -> Result<Saved, String> {
+    let values = |code: &str| Pesc::parse_with(code, opts)
+        .map(|(_, toks)| toks.into_iter().map(|t| match t {
+            PescToken::Func(f) => match f.as_str() {
+                "nil" => PescToken::Nil,
+                "inf" => PescToken::Number(f64::INFINITY),
+                "-inf" => PescToken::Number(f64::NEG_INFINITY),
+                "nan" => PescToken::Number(f64::NAN),
+                _ => PescToken::Func(f),
+            },
+            t => t,
+        }).collect::<Vec<PescToken>>())
+        .map_err(|e| e.to_string());
+
+    let mut stack = Vec::new();
+    let mut registers = HashMap::new();
+
+    for (i, line) in data.lines().enumerate() {
+        let (kind, rest) = line.split_once(' ').unwrap_or((line, ""));
+        match kind {
+            "" => (),
+            "stack" => stack = values(rest).map_err(|e| format!("{}: {}", i + 1, e))?,
+            "register" => {
+                let mut chars = rest.chars();
+                let r = chars.next().ok_or_else(|| format!("{}: which register?", i + 1))?;
+                let v = values(chars.as_str()).map_err(|e| format!("{}: {}", i + 1, e))?;
+                registers.insert(r, v);
+            },
+            _ => return Err(format!("{}: I don't know what '{}' is.", i + 1, kind)),
+        }
+    }
+
+    Ok((stack, registers))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_state() {
+        let mut p = Pesc::new();
+        let code = p.parse_code("1 _2.5 \"a \\\"b\\\"\" {3 [add]}").unwrap();
+        p.eval(&code).unwrap();
+        p.push(PescToken::Bool(true));
+        p.push(PescToken::Nil);
+        p.push(PescToken::Number(f64::NEG_INFINITY));
+
+        let mut registers = HashMap::new();
+        registers.insert('x', vec![PescToken::Number(4.0), PescToken::Str(String::from("q"))]);
+
+        let saved = write(&p.stack, &registers);
+        assert!(saved.starts_with("stack 1 _2.5 \"a \\\"b\\\"\" {3 [add]}"));
+        assert!(saved.ends_with("\nregister x 4 \"q\"\n"));
+
+        let (stack, again) = read(&saved, &p.opts).unwrap();
+        assert_eq!(again, registers);
+        assert_eq!(format!("{:?}", stack), format!("{:?}", p.stack));
+
+        assert!(read("stack 1\nsomething else\n", &p.opts).is_err());
+        assert!(read("stack \"unfinished\n", &p.opts).is_err());
+    }
+}
@@ -0,0 +1,117 @@
+// `pesc test`, which runs every *_test.pesc file it can find
+// with the testing words (assert, assert-eq) loaded. a file
+// passes if it runs without errors, and the assertions that
+// passed and failed are counted along the way.
+
+use std::cell::{Cell, RefCell};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use pesc::errors::{PescError, PescErrorType};
+use pesc::pesc::{Pesc, SpanMap};
+use pesc::stdlib;
+
+use crate::coverage::{self, Coverage};
+use crate::include;
+
+const TEST_SUFFIX: &str = "_test.pesc";
+
+// run the tests in `paths` (files, or directories to search),
+// each in a fresh interpreter made by `new`, recording coverage
+// in `cov` if it's given. returns whether every test passed.
+pub fn run<F>(paths: &[String], new: F, cov: Option<&Rc<RefCell<Coverage>>>) -> bool
+where
+    F: Fn() -> Pesc
+{
+    let mut files = Vec::new();
+    for path in paths {
+        let path = Path::new(path);
+        match path.is_dir() {
+            true => find(path, &mut files),
+            false => files.push(path.to_path_buf()),
+        }
+    }
+
+    let (mut passed, mut failed) = (0, 0);
+    let asserts = Rc::new(Cell::new((0, 0)));
+
+    for file in &files {
+        let before = asserts.get().0;
+        match run_file(file, new(), cov, &asserts) {
+            Ok(()) => {
+                let n = asserts.get().0 - before;
+                println!("ok      {} ({} assertion{})", file.display(), n, if n == 1 { "" } else { "s" });
+                passed += 1;
+            },
+            Err(e) => {
+                println!("FAILED  {}: {}", file.display(), e);
+                failed += 1;
+            },
+        }
+    }
+
+    let (ok, wrong) = asserts.get();
+    println!("\n{} passed, {} failed ({} assertions passed, {} failed).", passed, failed, ok, wrong);
+    failed == 0
+}
+
+// run a test file, adding the assertions that pass and fail in it
+// to `asserts`
+fn run_file(path: &Path, mut pesc: Pesc, cov: Option<&Rc<RefCell<Coverage>>>,
+    asserts: &Rc<Cell<(usize, usize)>>) -> Result<(), String>
+{
+    for (op, name, func, arity) in stdlib::testing() {
+        let asserts = asserts.clone();
+        pesc.load_def((op, name, Rc::new(Box::new(move |p: &mut Pesc| {
+            let res = func(p);
+            let (ok, wrong) = asserts.get();
+            match &res {
+                Ok(()) => asserts.set((ok + 1, wrong)),
+                Err(PescErrorType::AssertionFailed(_)) => asserts.set((ok, wrong + 1)),
+                Err(_) => (),
+            }
+            res
+        })), arity));
+    }
+
+    let data = fs::read_to_string(path)
+        .map_err(|e| e.to_string())?;
+
+    let at = |e: PescError| match e.span {
+        Some(s) => {
+            let (line, col) = s.line_col(&data);
+            format!("{}:{}: {}", line, col, e)
+        },
+        None => e.to_string(),
+    };
+
+    let (parsed, spans) = pesc.parse_code_spans(&data).map_err(at)?;
+
+    if let Some(cov) = cov {
+        coverage::watch(cov, &mut pesc, &path.to_string_lossy(), &data, &parsed, &spans);
+    }
+
+    pesc.spans = SpanMap::new(&parsed, &spans);
+    pesc.including.push(include::name(path));
+    pesc.eval(&parsed).map_err(|(_, e)| at(e))
+}
+
+// every test file in `dir` and its subdirectories, sorted
+fn find(dir: &Path, files: &mut Vec<PathBuf>) {
+    let mut entries = match fs::read_dir(dir) {
+        Ok(e) => e.filter_map(|e| e.ok())
+            .map(|e| e.path())
+            .collect::<Vec<PathBuf>>(),
+        Err(_) => return,
+    };
+    entries.sort();
+
+    for path in entries {
+        if path.is_dir() {
+            find(&path, files);
+        } else if path.to_string_lossy().ends_with(TEST_SUFFIX) {
+            files.push(path);
+        }
+    }
+}
@@ -0,0 +1,121 @@
+// --trace-file, which writes a line of JSON for every token that's
+// evaluated: the token, where it came from, the stack before and
+// after, and when it started and finished (in microseconds since
+// tracing started). lines are written as tokens finish, so a macro's
+// tokens come before the token that ran it. tokens that failed are
+// written when tracing stops, with "after" set to null.
+//
+// --trace (or ":trace on") is the readable kind: every function that's
+// called, and the stack before and after it, printed to stderr and
+// indented by how deep in macros the call is, e.g.
+//
+//     > sq  3
+//       > dup  3
+//       < dup  3 3
+//       > mul  3 3
+//       < mul  9
+//     < sq  9
+
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::rc::Rc;
+use std::time::Instant;
+
+use pesc::errors::Span;
+use pesc::pesc::*;
+
+use crate::json;
+
+pub struct Trace {
+    out: BufWriter<File>,
+    start: Instant,
+
+    // where the tokens being evaluated came from
+    pub spans: SpanMap,
+
+    // (token, span, depth, stack before, start time) of the tokens
+    // that haven't finished yet
+    running: Vec<(PescToken, Option<Span>, usize, String, u128)>,
+}
+
+pub fn start(pesc: &mut Pesc, path: &str) -> io::Result<Rc<RefCell<Trace>>> {
+    let trace = Rc::new(RefCell::new(Trace {
+        out: BufWriter::new(File::create(path)?),
+        start: Instant::now(),
+        spans: SpanMap::default(),
+        running: Vec::new(),
+    }));
+
+    let tr = trace.clone();
+    pesc.hooks.push(Box::new(move |p, ev| {
+        let mut tr = tr.borrow_mut();
+        let now = tr.start.elapsed().as_micros();
+
+        match ev {
+            PescEvent::Token(t) => {
+                let span = tr.spans.find(t);
+                tr.running.push((t.clone(), span, p.depth(), json::stack(&p.stack), now));
+            },
+            PescEvent::Evaluated(_) => {
+                if let Some(r) = tr.running.pop() {
+                    let after = json::stack(&p.stack);
+                    tr.write(r, Some(after), now);
+                }
+            },
+            _ => (),
+        }
+
+        Ok(())
+    }));
+
+    Ok(trace)
+}
+
+// print calls until stop()
+pub fn print(pesc: &mut Pesc) {
+    pesc.hooks.push(Box::new(|p, ev| {
+        let (arrow, f) = match ev {
+            PescEvent::Call(f) => ('>', f),
+            PescEvent::Return(f) => ('<', f),
+            _ => return Ok(()),
+        };
+
+        let line = format!("{}{} {}  {}", "  ".repeat(p.depth()), arrow, f, unparse(&p.stack));
+        eprintln!("{}", line.trim_end());
+        Ok(())
+    }));
+}
+
+pub fn stop(pesc: &mut Pesc) {
+    pesc.hooks.pop();
+}
+
+// write out whatever didn't finish, e.g. after an error
+pub fn flush(trace: &Rc<RefCell<Trace>>) {
+    let mut tr = trace.borrow_mut();
+    let now = tr.start.elapsed().as_micros();
+
+    while let Some(r) = tr.running.pop() {
+        tr.write(r, None, now);
+    }
+
+    tr.out.flush().ok();
+}
+
+impl Trace {
+    fn write(&mut self, r: (PescToken, Option<Span>, usize, String, u128),
+        after: Option<String>, end: u128)
+    {
+        let (tok, span, depth, before, start) = r;
+        let span = match span {
+            Some(s) => format!("[{},{}]", s.start, s.end),
+            None => String::from("null"),
+        };
+
+        writeln!(self.out,
+            "{{\"token\":{},\"span\":{},\"depth\":{},\"before\":{},\"after\":{},\"start\":{},\"end\":{}}}",
+            json::token(&tok), span, depth, before,
+            after.as_deref().unwrap_or("null"), start, end).ok();
+    }
+}
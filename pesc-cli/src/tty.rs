@@ -2,11 +2,12 @@
 // with "vArIaNt Is NeVeR cOnStRuCtEd" warnings.
 
 use std::fmt;
-use std::os::raw::c_int;
+use std::io::IsTerminal;
 use std::result::Result;
+use std::sync::atomic::{AtomicU8, Ordering};
 use terminal_size::{Width, Height, terminal_size};
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum TermStyle {
     Bold,
     Underline,
@@ -23,9 +24,37 @@ pub enum TermStyle {
 }
 
 impl TermStyle {
+    // a style as the config names it: a color (e.g. "cyan" or
+    // "bright-cyan"), "bold", "underline", "italic" or "plain"
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        let style = match name {
+            "bold" => Some(TermStyle::Bold),
+            "underline" => Some(TermStyle::Underline),
+            "italic" => Some(TermStyle::Italic),
+            "plain" => Some(TermStyle::Reset),
+            _ => match name.strip_prefix("bright-") {
+                Some(c) => TermColor::from_name(c).map(TermStyle::BrightFg),
+                None => TermColor::from_name(name).map(TermStyle::Fg),
+            },
+        };
+
+        style.ok_or_else(|| format!("there's no color called '{}'", name))
+    }
+
+    pub fn name(&self) -> String {
+        match self {
+            TermStyle::Bold => String::from("bold"),
+            TermStyle::Underline => String::from("underline"),
+            TermStyle::Italic => String::from("italic"),
+            TermStyle::Reset => String::from("plain"),
+            TermStyle::Fg(c) => String::from(c.name()),
+            TermStyle::BrightFg(c) => format!("bright-{}", c.name()),
+        }
+    }
+
     pub fn strip(s: &str) -> String {
         // TODO: cleanup
-        let input = s.clone().chars()
+        let input = s.chars()
             .collect::<Vec<char>>();
         let mut buf = Vec::new();
 
@@ -52,9 +81,72 @@ impl TermStyle {
     }
 }
 
+// the colors that styles come out in, for the whole program (see
+// set_theme())
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Theme {
+    // for light text on a dark background
+    Dark,
+
+    // for dark text on a light background, where white is black
+    Light,
+
+    // no styles at all
+    Plain,
+}
+
+static THEME: AtomicU8 = AtomicU8::new(Theme::Dark as u8);
+
+impl Theme {
+    // dark, unless $NO_COLOR says not to color anything
+    pub fn auto() -> Self {
+        match std::env::var("NO_COLOR") {
+            Ok(v) if !v.is_empty() => Theme::Plain,
+            _ => Theme::Dark,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Result<Self, String> {
+        match name {
+            "dark" => Ok(Theme::Dark),
+            "light" => Ok(Theme::Light),
+            "none" => Ok(Theme::Plain),
+            _ => Err(format!("there's no theme called '{}'", name)),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Theme::Dark => "dark",
+            Theme::Light => "light",
+            Theme::Plain => "none",
+        }
+    }
+}
+
+pub fn set_theme(theme: Theme) {
+    THEME.store(theme as u8, Ordering::Relaxed);
+}
+
+pub fn theme() -> Theme {
+    match THEME.load(Ordering::Relaxed) {
+        t if t == Theme::Light as u8 => Theme::Light,
+        t if t == Theme::Plain as u8 => Theme::Plain,
+        _ => Theme::Dark,
+    }
+}
+
 impl fmt::Display for TermStyle {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
-        let r = match self {
+        let style = match (theme(), self) {
+            (Theme::Plain, _) => return Ok(()),
+            (Theme::Light, TermStyle::Fg(TermColor::White))
+            | (Theme::Light, TermStyle::BrightFg(TermColor::White)) =>
+                TermStyle::Fg(TermColor::Black),
+            _ => *self,
+        };
+
+        let r = match style {
             TermStyle::Bold => String::from("\x1b[1m"),
             TermStyle::Underline => String::from("\x1b[4m"),
             TermStyle::Italic => String::from("\x1b[3m"),
@@ -73,27 +165,44 @@ impl fmt::Display for TermStyle {
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum TermColor {
     Black,
-    //Red,
-    //Green,
+    Red,
+    Green,
     Yellow,
-    //Blue,
-    //Magenta,
+    Blue,
+    Magenta,
     Cyan,
     White,
 }
 
+const COLOR_NAMES: [(TermColor, &str); 8] = [
+    (TermColor::Black, "black"), (TermColor::Red, "red"),
+    (TermColor::Green, "green"), (TermColor::Yellow, "yellow"),
+    (TermColor::Blue, "blue"), (TermColor::Magenta, "magenta"),
+    (TermColor::Cyan, "cyan"), (TermColor::White, "white"),
+];
+
+impl TermColor {
+    pub fn from_name(name: &str) -> Option<Self> {
+        COLOR_NAMES.iter().find(|(_, n)| *n == name).map(|(c, _)| *c)
+    }
+
+    pub fn name(&self) -> &'static str {
+        COLOR_NAMES.iter().find(|(c, _)| c == self).map(|(_, n)| *n).unwrap_or_default()
+    }
+}
+
 impl fmt::Display for TermColor {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
         let r = match self {
             TermColor::Black        => "0",
-            //TermColor::Red          => "1",
-            //TermColor::Green        => "2",
+            TermColor::Red          => "1",
+            TermColor::Green        => "2",
             TermColor::Yellow       => "3",
-            //TermColor::Blue         => "4",
-            //TermColor::Magenta      => "5",
+            TermColor::Blue         => "4",
+            TermColor::Magenta      => "5",
             TermColor::Cyan         => "6",
             TermColor::White        => "7",
         };
@@ -104,41 +213,70 @@ impl fmt::Display for TermColor {
 
 pub enum OutputStream {
     Stdout,
+    Stdin,
 
     // listen, rustc, I understand they're not being
     // used right now, but you really don't need to raise
     // a fuss about that now
 
     //Stderr,
-    //Stdin,
     //Other(usize),
 }
 
-impl Into<c_int> for OutputStream {
-    fn into(self) -> c_int {
-        match self {
-            OutputStream::Stdout   => 1 as c_int,
-            //OutputStream::Stderr   => 2 as c_int,
-            //OutputStream::Other(f) => f as c_int,
-            //OutputStream::Stdin    => 0 as c_int,
-        }
+pub fn is_tty(fd: OutputStream) -> bool {
+    match fd {
+        OutputStream::Stdout => std::io::stdout().is_terminal(),
+        OutputStream::Stdin => std::io::stdin().is_terminal(),
     }
 }
 
-extern "C" {
-    pub fn isatty(fd: c_int) -> c_int;
+// the terminal's width and height, or else what $COLUMNS and $LINES
+// say (e.g. when stdout isn't the console on Windows), or 80x24
+pub fn tty_sz() -> (usize, usize) {
+    if let Some((Width(w), Height(h))) = terminal_size() {
+        return (w as usize, h as usize);
+    }
+
+    let env = |var, default| std::env::var(var).ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|n| *n > 0)
+        .unwrap_or(default);
+    (env("COLUMNS", 80), env("LINES", 24))
 }
 
-pub fn is_tty(fd: OutputStream) -> bool {
-    let r = unsafe { isatty(fd.into()) };
-    r != 0
+// make sure escapes (for colors and such) work in the terminal, and
+// return whether they do. they always do, except in a Windows console
+// without virtual terminal processing, which this turns on if it can.
+#[cfg(not(windows))]
+pub fn enable_escapes() -> bool {
+    true
 }
 
-pub fn tty_sz() -> (usize, usize) {
-    if let Some((Width(w), Height(h))) = terminal_size() {
-        (w as usize, h as usize)
-    } else {
-        (80, 24)
+#[cfg(windows)]
+pub fn enable_escapes() -> bool {
+    use std::ffi::c_void;
+
+    const STD_OUTPUT_HANDLE: u32 = -11i32 as u32;
+    const ENABLE_VIRTUAL_TERMINAL_PROCESSING: u32 = 0x0004;
+
+    #[link(name = "kernel32")]
+    extern "system" {
+        fn GetStdHandle(which: u32) -> *mut c_void;
+        fn GetConsoleMode(console: *mut c_void, mode: *mut u32) -> i32;
+        fn SetConsoleMode(console: *mut c_void, mode: u32) -> i32;
+    }
+
+    unsafe {
+        let out = GetStdHandle(STD_OUTPUT_HANDLE);
+        let mut mode = 0;
+
+        // (not a console, so whatever's reading it can deal with them)
+        if out.is_null() || GetConsoleMode(out, &mut mode) == 0 {
+            return true;
+        }
+
+        mode & ENABLE_VIRTUAL_TERMINAL_PROCESSING != 0
+            || SetConsoleMode(out, mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING) != 0
     }
 }
 
@@ -156,4 +294,15 @@ mod tests {
         assert_eq!(&TermStyle::strip("\x1b[90m[\x1b[m     0\x1b[90m]\x1b[m"), "[     0]");
         assert_eq!(&TermStyle::strip("\u{1b}[90m[\u{1b}[m          8\u{1b}[90m]\u{1b}[m\u{1b}[90m[\u{1b}[m          3\u{1b}[90m]\u{1b}[m"), "[          8][          3]");
     }
+
+    #[test]
+    fn test_style_names() {
+        for name in ["bold", "plain", "red", "bright-magenta"] {
+            assert_eq!(TermStyle::from_name(name).unwrap().name(), name);
+        }
+
+        assert_eq!(TermStyle::from_name("bright-black"), Ok(TermStyle::BrightFg(TermColor::Black)));
+        assert!(TermStyle::from_name("bright-bold").is_err());
+        assert!(TermStyle::from_name("mauve").is_err());
+    }
 }
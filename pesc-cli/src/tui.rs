@@ -0,0 +1,227 @@
+// `pesc tui`, a full-screen REPL (for pesc built with `--features
+// tui`), with the stack, the lines that were run and how they went,
+// and the functions that fit the word being typed each in a pane of
+// their own, all kept up to date as you type. Enter runs the line, Up
+// and Down bring back earlier ones, and Esc or Ctrl-D quits.
+
+use std::io::{self, Write};
+
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::style::Print;
+use crossterm::{cursor, execute, queue, terminal};
+
+use pesc::pesc::*;
+
+use crate::numbers;
+use crate::output;
+use crate::tty::{TermColor, TermStyle};
+
+const PROMPT: &str = "pesc> ";
+
+#[derive(Default)]
+struct Tui {
+    input: Vec<char>,
+    cursor: usize,
+
+    // each line that was run, and what came of it (an error or
+    // warnings, or nothing)
+    history: Vec<(String, Vec<String>)>,
+
+    // the earlier line that Up and Down have brought back
+    recall: Option<usize>,
+}
+
+pub fn run(pesc: &mut Pesc) -> io::Result<()> {
+    let mut out = io::stdout();
+    terminal::enable_raw_mode()?;
+    execute!(out, terminal::EnterAlternateScreen)?;
+
+    let res = Tui::default().keys(pesc, &mut out);
+
+    execute!(out, terminal::LeaveAlternateScreen)?;
+    terminal::disable_raw_mode()?;
+    res
+}
+
+impl Tui {
+    fn keys(&mut self, pesc: &mut Pesc, out: &mut impl Write) -> io::Result<()> {
+        loop {
+            self.draw(pesc, out)?;
+
+            let key = match event::read()? {
+                Event::Key(k) if k.kind != KeyEventKind::Release => k,
+                _ => continue,
+            };
+            let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+
+            match key.code {
+                KeyCode::Esc => return Ok(()),
+                KeyCode::Char('c') | KeyCode::Char('d') if ctrl => return Ok(()),
+                KeyCode::Char('u') if ctrl => self.set_input(""),
+                KeyCode::Char(c) => {
+                    self.input.insert(self.cursor, c);
+                    self.cursor += 1;
+                },
+                KeyCode::Backspace if self.cursor > 0 => {
+                    self.cursor -= 1;
+                    self.input.remove(self.cursor);
+                },
+                KeyCode::Delete if self.cursor < self.input.len() => {
+                    self.input.remove(self.cursor);
+                },
+                KeyCode::Left => self.cursor = self.cursor.saturating_sub(1),
+                KeyCode::Right => self.cursor = (self.cursor + 1).min(self.input.len()),
+                KeyCode::Home => self.cursor = 0,
+                KeyCode::End => self.cursor = self.input.len(),
+                KeyCode::Up if !self.history.is_empty() => {
+                    let i = self.recall.map_or(self.history.len() - 1, |i| i.saturating_sub(1));
+                    self.recall = Some(i);
+                    self.set_input(&self.history[i].0.clone());
+                },
+                KeyCode::Down => match self.recall {
+                    Some(i) if i + 1 < self.history.len() => {
+                        self.recall = Some(i + 1);
+                        self.set_input(&self.history[i + 1].0.clone());
+                    },
+                    _ => {
+                        self.recall = None;
+                        self.set_input("");
+                    },
+                },
+                KeyCode::Enter => self.enter(pesc),
+                _ => (),
+            }
+        }
+    }
+
+    fn set_input(&mut self, line: &str) {
+        self.input = line.chars().collect();
+        self.cursor = self.input.len();
+    }
+
+    // run the line
+    fn enter(&mut self, pesc: &mut Pesc) {
+        let line = self.input.iter().collect::<String>();
+        self.set_input("");
+        self.recall = None;
+
+        if line.trim().is_empty() {
+            return;
+        }
+
+        let mut notes = match pesc.run(&line) {
+            Ok(()) => Vec::new(),
+            Err(e) => vec![format!("error: {}", e)],
+        };
+        notes.extend(pesc.warnings.drain(..).map(|w| format!("warning: {}", w)));
+
+        self.history.push((line, notes));
+    }
+
+    // the word being typed, e.g. "du" in "1 [du"
+    fn word(&self) -> String {
+        let before = &self.input[..self.cursor];
+        let start = before.iter()
+            .rposition(|c| c.is_whitespace() || *c == '[')
+            .map_or(0, |i| i + 1);
+        before[start..].iter().collect()
+    }
+
+    fn draw(&self, pesc: &Pesc, out: &mut impl Write) -> io::Result<()> {
+        let (w, h) = terminal::size()?;
+        let (w, h) = (w as usize, h as usize);
+        let dim = TermStyle::BrightFg(TermColor::Black);
+
+        // (the function reference only when there's room for it)
+        let ref_w = if w >= 60 { (w / 3).min(40) } else { 0 };
+        let left_w = w - ref_w;
+        let stack_h = h.saturating_sub(1) / 2;
+        let hist_h = h.saturating_sub(1) - stack_h;
+
+        queue!(out, terminal::Clear(terminal::ClearType::All))?;
+
+        // the stack, the top first
+        title(out, 0, 0, left_w, "stack")?;
+        let values = pesc.stack.iter().rev().enumerate().take(stack_h.saturating_sub(1));
+        for (row, (i, t)) in values.enumerate() {
+            let text = fit(&format!("{:>3}  {}", i, numbers::show(t)), left_w);
+            put(out, 0, row + 1, &format!("{}{}{}", output::style(t), text, TermStyle::Reset))?;
+        }
+
+        // what was run, the last at the bottom
+        title(out, 0, stack_h, left_w, "history")?;
+        let lines = self.history.iter()
+            .flat_map(|(line, notes)| {
+                let notes = notes.iter().map(move |n| format!("{}{}{}", dim, fit(n, left_w), TermStyle::Reset));
+                Some(fit(&format!("{}{}", PROMPT, line), left_w)).into_iter().chain(notes)
+            })
+            .collect::<Vec<String>>();
+        let shown = hist_h.saturating_sub(1);
+        for (row, l) in lines.iter().skip(lines.len().saturating_sub(shown)).enumerate() {
+            put(out, 0, stack_h + 1 + row, l)?;
+        }
+
+        // the functions that fit the word being typed
+        if ref_w > 0 {
+            let word = self.word();
+            let mut names = pesc.funcs.keys()
+                .filter(|n| n.contains(&word))
+                .collect::<Vec<&String>>();
+            names.sort_by_key(|n| (!n.starts_with(&word), n.to_string()));
+
+            for row in 0..h.saturating_sub(1) {
+                put(out, left_w, row, &format!("{}│{}", dim, TermStyle::Reset))?;
+            }
+            title(out, left_w + 1, 0, ref_w - 1, "functions")?;
+            for (row, n) in names.iter().take(h.saturating_sub(2)).enumerate() {
+                let sig = pesc.docs.get(*n).map(|d| format!(" ( {} )", d.signature)).unwrap_or_default();
+                let text = fit(&format!("{}{}", n, sig), ref_w - 2);
+                put(out, left_w + 2, row + 1, &text)?;
+            }
+        }
+
+        // the line being typed, scrolled so that the cursor's on screen
+        let room = w.saturating_sub(PROMPT.len() + 1).max(1);
+        let from = self.cursor.saturating_sub(room);
+        let input = self.input[from..].iter().take(room).collect::<String>();
+        put(out, 0, h.saturating_sub(1), &format!("{}{}", PROMPT, input))?;
+
+        queue!(out, cursor::MoveTo((PROMPT.len() + self.cursor - from) as u16, h.saturating_sub(1) as u16))?;
+        out.flush()
+    }
+}
+
+// `s`, cut down to `width` characters
+fn fit(s: &str, width: usize) -> String {
+    s.chars().take(width).collect()
+}
+
+fn put(out: &mut impl Write, x: usize, y: usize, s: &str) -> io::Result<()> {
+    queue!(out, cursor::MoveTo(x as u16, y as u16), Print(s))
+}
+
+// a pane's name, in a line across it
+fn title(out: &mut impl Write, x: usize, y: usize, width: usize, name: &str) -> io::Result<()> {
+    let line = fit(&format!("── {} {}", name, "─".repeat(width)), width);
+    put(out, x, y, &format!("{}{}{}", TermStyle::BrightFg(TermColor::Black), line, TermStyle::Reset))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tui() {
+        let mut p = Pesc::with_stdlib();
+        let mut tui = Tui::default();
+
+        tui.set_input("1 2 [ad");
+        assert_eq!(tui.word(), "ad");
+
+        tui.set_input("1 2 + [nope]");
+        tui.enter(&mut p);
+        assert_eq!(tui.history.len(), 1);
+        assert!(tui.history[0].1[0].starts_with("error: "));
+        assert!(tui.input.is_empty());
+    }
+}
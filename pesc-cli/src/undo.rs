@@ -0,0 +1,106 @@
+// :undo in the REPL, or [undo], which puts the stack back the way it
+// was before the last line that changed it, so that a stray $ (or a
+// macro that ate half the stack) can be taken back. the stacks from
+// before the last LIMIT such lines are kept; definitions aren't undone.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+
+use pesc::errors::*;
+use pesc::pesc::*;
+
+const LIMIT: usize = 100;
+
+#[derive(Default)]
+pub struct Undo {
+    stacks: VecDeque<Vec<PescToken>>,
+
+    // whether the line being run undid something (in which case
+    // there's nothing to keep from before it)
+    undone: bool,
+}
+
+pub fn load(pesc: &mut Pesc) -> Rc<RefCell<Undo>> {
+    let undo = Rc::new(RefCell::new(Undo::default()));
+
+    let u = undo.clone();
+    pesc.register("undo", move |p| {
+        match u.borrow_mut().undo(p) {
+            true => Ok(()),
+            false => Err(PescErrorType::Other(String::from("there's nothing to undo."))),
+        }
+    });
+    pesc.document("undo", "--",
+        "puts the stack back the way it was before the last line that changed it.");
+
+    undo
+}
+
+impl Undo {
+    // a line ran, with the stack from before and after it
+    pub fn record(&mut self, before: Vec<PescToken>, after: &[PescToken]) {
+        if std::mem::take(&mut self.undone) || before == after {
+            return;
+        }
+
+        if self.stacks.len() == LIMIT {
+            self.stacks.pop_front();
+        }
+
+        self.stacks.push_back(before);
+    }
+
+    // put the stack back, or return false if there's nothing to go back to
+    pub fn undo(&mut self, pesc: &mut Pesc) -> bool {
+        match self.stacks.pop_back() {
+            Some(s) => {
+                pesc.stack = s;
+                self.undone = true;
+                true
+            },
+            None => false,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.stacks.clear();
+        self.undone = false;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // run a line the way the REPL does
+    fn line(p: &mut Pesc, undo: &RefCell<Undo>, code: &str) {
+        let before = p.stack.clone();
+        p.run(code).ok();
+        undo.borrow_mut().record(before, &p.stack);
+    }
+
+    #[test]
+    fn test_undo() {
+        let mut p = Pesc::with_stdlib();
+        let undo = load(&mut p);
+
+        line(&mut p, &undo, "1 2 3");
+        line(&mut p, &undo, "+");
+        line(&mut p, &undo, "{1} \"one\" [def]");
+        line(&mut p, &undo, "$ $");
+        assert!(p.stack.is_empty());
+
+        line(&mut p, &undo, "[undo]");
+        assert_eq!(p.stack, vec![PescToken::Number(1.0), PescToken::Number(5.0)]);
+
+        // the stack from before +, since [def] didn't change it and
+        // [undo] doesn't count
+        line(&mut p, &undo, "[undo]");
+        assert_eq!(p.stack.len(), 3);
+
+        assert!(undo.borrow_mut().undo(&mut p));
+        assert!(p.stack.is_empty());
+        assert!(p.run("[undo]").is_err());
+    }
+}
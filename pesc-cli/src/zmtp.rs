@@ -0,0 +1,150 @@
+// just enough of ZMTP 3.0 (the protocol ZeroMQ sockets speak, see
+// rfc.zeromq.org/spec/23) for a Jupyter kernel: the NULL mechanism,
+// and multi-part messages over a TCP connection. each connection
+// plays one kind of socket (ROUTER, PUB, REP), but since the kernel
+// answers everything on the connection it came from, there's no need
+// for routing; peers' identities are never seen.
+//
+// (the zmq crate would need libzmq, which is a lot to ask for this)
+
+use std::convert::TryInto;
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+
+const MORE: u8 = 0x01;
+const LONG: u8 = 0x02;
+const COMMAND: u8 = 0x04;
+
+// the most a message's frames may add up to, since their lengths
+// are whatever the peer says
+const MAX_MESSAGE: usize = 16 << 20;
+
+// exchange greetings and READY commands, saying we're a `socket`
+pub fn handshake(conn: &mut TcpStream, socket: &str) -> io::Result<()> {
+    let mut greeting = [0u8; 64];
+    greeting[0] = 0xff;
+    greeting[9] = 0x7f;
+    greeting[10] = 3;
+    greeting[12..16].copy_from_slice(b"NULL");
+    conn.write_all(&greeting)?;
+
+    let mut theirs = [0u8; 64];
+    conn.read_exact(&mut theirs)?;
+    if theirs[0] != 0xff || theirs[9] != 0x7f || theirs[10] < 3 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData,
+            "that's not ZMTP 3 on the other end"));
+    }
+
+    let mut ready = vec![5];
+    ready.extend_from_slice(b"READY");
+    ready.push(11);
+    ready.extend_from_slice(b"Socket-Type");
+    ready.extend_from_slice(&(socket.len() as u32).to_be_bytes());
+    ready.extend_from_slice(socket.as_bytes());
+    write_frame(conn, COMMAND, &ready)?;
+
+    // their READY (whatever it says)
+    read_frame(conn, MAX_MESSAGE)?;
+    Ok(())
+}
+
+// the frames of the next message (commands are skipped)
+pub fn recv(conn: &mut impl Read) -> io::Result<Vec<Vec<u8>>> {
+    let mut frames = Vec::new();
+    let mut left = MAX_MESSAGE;
+
+    loop {
+        let (flags, body) = read_frame(conn, left)?;
+        if flags & COMMAND != 0 {
+            continue;
+        }
+
+        left -= body.len();
+        frames.push(body);
+        if flags & MORE == 0 {
+            return Ok(frames);
+        }
+    }
+}
+
+pub fn send(conn: &mut TcpStream, frames: &[&[u8]]) -> io::Result<()> {
+    for (i, f) in frames.iter().enumerate() {
+        let more = if i + 1 < frames.len() { MORE } else { 0 };
+        write_frame(conn, more, f)?;
+    }
+
+    conn.flush()
+}
+
+// the next frame, which can't be longer than `most`
+fn read_frame(conn: &mut impl Read, most: usize) -> io::Result<(u8, Vec<u8>)> {
+    let mut flags = [0u8; 1];
+    conn.read_exact(&mut flags)?;
+
+    let len = match flags[0] & LONG {
+        0 => {
+            let mut l = [0u8; 1];
+            conn.read_exact(&mut l)?;
+            l[0] as usize
+        },
+        _ => {
+            let mut l = [0u8; 8];
+            conn.read_exact(&mut l)?;
+            u64::from_be_bytes(l).try_into().unwrap_or(usize::MAX)
+        },
+    };
+
+    if len > most {
+        return Err(io::Error::new(io::ErrorKind::InvalidData,
+            format!("a frame of {} bytes is too big", len)));
+    }
+
+    let mut body = vec![0; len];
+    conn.read_exact(&mut body)?;
+    Ok((flags[0], body))
+}
+
+fn write_frame(conn: &mut TcpStream, flags: u8, body: &[u8]) -> io::Result<()> {
+    let mut buf = Vec::with_capacity(body.len() + 9);
+
+    match body.len() > 255 {
+        true => {
+            buf.push(flags | LONG);
+            buf.extend_from_slice(&(body.len() as u64).to_be_bytes());
+        },
+        false => {
+            buf.push(flags);
+            buf.push(body.len() as u8);
+        },
+    }
+
+    buf.extend_from_slice(body);
+    conn.write_all(&buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frames() {
+        let mut input: &[u8] = &[MORE, 1, b'a', COMMAND, 0, 0, 2, b'b', b'c'];
+        assert_eq!(recv(&mut input).unwrap(), vec![b"a".to_vec(), b"bc".to_vec()]);
+
+        // whatever a peer says, it can't make us allocate too much
+        let mut input = vec![LONG];
+        input.extend_from_slice(&u64::MAX.to_be_bytes());
+        assert!(recv(&mut input.as_slice()).is_err());
+
+        let mut input = Vec::new();
+        for _ in 0..17 {
+            input.extend_from_slice(&[MORE | LONG, 0, 0, 0, 0, 0, 0x10, 0, 0]);
+            input.extend_from_slice(&[0; 1 << 20]);
+        }
+        assert!(recv(&mut input.as_slice()).is_err());
+
+        // (or wait for more than there is)
+        assert!(recv(&mut &[MORE, 1, b'a'][..]).is_err());
+        assert!(recv(&mut &[0, 5, b'a'][..]).is_err());
+    }
+}
@@ -0,0 +1,376 @@
+// arbitrary-precision integers, for Precision::Exact, where 2 64^ is
+// 18446744073709551616 rather than roughly that. nothing clever: the
+// digits are kept in base 10^9 (which makes printing them easy), and
+// multiplied and divided the way they're done on paper.
+
+use std::cmp::Ordering;
+use std::fmt::{self, Display};
+use std::ops::{Add, Mul, Neg, Sub};
+
+const BASE: u64 = 1_000_000_000;
+
+// how many digits pow() and factorial() are willing to produce
+const MAX_DIGITS: f64 = 100_000.0;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct BigInt {
+    neg: bool,
+
+    // least significant first, with no zeros at the end (so
+    // zero is empty)
+    mag: Vec<u32>,
+}
+
+impl BigInt {
+    fn new(neg: bool, mut mag: Vec<u32>) -> Self {
+        trim(&mut mag);
+        Self { neg: neg && !mag.is_empty(), mag }
+    }
+
+    pub fn zero() -> Self {
+        Self::default()
+    }
+
+    // from a string of digits, which may start with a '-'
+    pub fn parse(s: &str) -> Option<Self> {
+        let (neg, digits) = match s.strip_prefix('-') {
+            Some(d) => (true, d),
+            None => (false, s),
+        };
+
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+
+        let mag = digits.as_bytes().rchunks(9)
+            .map(|c| c.iter().fold(0, |n, d| n * 10 + (d - b'0') as u32))
+            .collect();
+        Some(Self::new(neg, mag))
+    }
+
+    // the integer that a whole (and finite) float is
+    pub fn from_f64(n: f64) -> Option<Self> {
+        if !n.is_finite() || n.fract() != 0.0 {
+            return None;
+        }
+
+        Self::parse(&format!("{:.0}", n))
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        // (leaving the rounding to the standard library)
+        self.to_string().parse().unwrap_or(f64::NAN)
+    }
+
+    pub fn to_u64(&self) -> Option<u64> {
+        if self.neg {
+            return None;
+        }
+
+        self.mag.iter().rev()
+            .try_fold(0_u64, |n, d| n.checked_mul(BASE)?.checked_add(*d as u64))
+    }
+
+    // self in base 2 to 36, e.g. "-ff" for -255 in base 16
+    pub fn to_radix(&self, radix: u32) -> String {
+        let base = Self::from(radix as u64);
+        let mut n = self.abs();
+        let mut digits = Vec::new();
+
+        loop {
+            let (q, d) = n.div_rem(&base).unwrap_or_default();
+            let d = d.to_u64().unwrap_or(0) as u32;
+            digits.push(std::char::from_digit(d, radix).unwrap_or('?'));
+
+            n = q;
+            if n.is_zero() {
+                break;
+            }
+        }
+
+        if self.neg {
+            digits.push('-');
+        }
+
+        digits.iter().rev().collect()
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.mag.is_empty()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.neg
+    }
+
+    pub fn abs(&self) -> Self {
+        Self::new(false, self.mag.clone())
+    }
+
+    // the quotient and the remainder, rounding towards zero like %
+    // does for floats (so the remainder has the sign of self), or
+    // None when dividing by zero
+    pub fn div_rem(&self, other: &Self) -> Option<(Self, Self)> {
+        if other.is_zero() {
+            return None;
+        }
+
+        let (q, r) = div_mag(&self.mag, &other.mag);
+        Some((Self::new(self.neg != other.neg, q), Self::new(self.neg, r)))
+    }
+
+    // self to the nth power, unless that's unreasonably big
+    pub fn pow(&self, mut n: u64) -> Option<Self> {
+        if self.abs().to_f64().log10() * n as f64 > MAX_DIGITS {
+            return None;
+        }
+
+        let mut base = self.clone();
+        let mut acc = Self::from(1);
+        while n > 0 {
+            if n & 1 == 1 {
+                acc = &acc * &base;
+            }
+
+            n >>= 1;
+            if n > 0 {
+                base = &base * &base;
+            }
+        }
+
+        Some(acc)
+    }
+
+    // n!, unless that's unreasonably big
+    pub fn factorial(n: u64) -> Option<Self> {
+        // (by Stirling's approximation, near enough)
+        let x = n as f64;
+        if x * (x / std::f64::consts::E).log10() > MAX_DIGITS {
+            return None;
+        }
+
+        let mag = (2..=n).fold(vec![1], |acc, k| mul_small(&acc, k as u32));
+        Some(Self::new(false, mag))
+    }
+}
+
+impl From<u64> for BigInt {
+    fn from(mut n: u64) -> Self {
+        let mut mag = Vec::new();
+        while n > 0 {
+            mag.push((n % BASE) as u32);
+            n /= BASE;
+        }
+
+        Self::new(false, mag)
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.neg, other.neg) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp_mag(&self.mag, &other.mag),
+            (true, true) => cmp_mag(&other.mag, &self.mag),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        let mut digits = self.mag.iter().rev();
+        match digits.next() {
+            None => return write!(f, "0"),
+            Some(d) if self.neg => write!(f, "-{}", d)?,
+            Some(d) => write!(f, "{}", d)?,
+        }
+
+        for d in digits {
+            write!(f, "{:09}", d)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Neg for &BigInt {
+    type Output = BigInt;
+
+    fn neg(self) -> BigInt {
+        BigInt::new(!self.neg, self.mag.clone())
+    }
+}
+
+impl Add for &BigInt {
+    type Output = BigInt;
+
+    fn add(self, other: &BigInt) -> BigInt {
+        if self.neg == other.neg {
+            return BigInt::new(self.neg, add_mag(&self.mag, &other.mag));
+        }
+
+        match cmp_mag(&self.mag, &other.mag) {
+            Ordering::Less => BigInt::new(other.neg, sub_mag(&other.mag, &self.mag)),
+            _ => BigInt::new(self.neg, sub_mag(&self.mag, &other.mag)),
+        }
+    }
+}
+
+impl Sub for &BigInt {
+    type Output = BigInt;
+
+    fn sub(self, other: &BigInt) -> BigInt {
+        self + &-other
+    }
+}
+
+impl Mul for &BigInt {
+    type Output = BigInt;
+
+    fn mul(self, other: &BigInt) -> BigInt {
+        BigInt::new(self.neg != other.neg, mul_mag(&self.mag, &other.mag))
+    }
+}
+
+// --- magnitudes, i.e. digits without a sign ---
+
+fn trim(mag: &mut Vec<u32>) {
+    while mag.last() == Some(&0) {
+        mag.pop();
+    }
+}
+
+fn cmp_mag(a: &[u32], b: &[u32]) -> Ordering {
+    a.len().cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut sum = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let mut carry = 0;
+
+    for i in 0..a.len().max(b.len()) {
+        let d = *a.get(i).unwrap_or(&0) as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+        sum.push((d % BASE) as u32);
+        carry = d / BASE;
+    }
+
+    sum.push(carry as u32);
+    trim(&mut sum);
+    sum
+}
+
+// a - b, where a >= b
+fn sub_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut diff = Vec::with_capacity(a.len());
+    let mut borrow = 0;
+
+    for (i, d) in a.iter().enumerate() {
+        let mut d = *d as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+        borrow = 0;
+        if d < 0 {
+            d += BASE as i64;
+            borrow = 1;
+        }
+
+        diff.push(d as u32);
+    }
+
+    trim(&mut diff);
+    diff
+}
+
+fn mul_mag(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut prod = vec![0_u64; a.len() + b.len()];
+
+    for (i, x) in a.iter().enumerate() {
+        let mut carry = 0;
+        for (j, y) in b.iter().enumerate() {
+            let d = prod[i + j] + *x as u64 * *y as u64 + carry;
+            prod[i + j] = d % BASE;
+            carry = d / BASE;
+        }
+
+        prod[i + b.len()] += carry;
+    }
+
+    let mut prod = prod.into_iter().map(|d| d as u32).collect();
+    trim(&mut prod);
+    prod
+}
+
+fn mul_small(a: &[u32], m: u32) -> Vec<u32> {
+    mul_mag(a, &[m])
+}
+
+// long division, finding each digit of the quotient by bisection
+fn div_mag(a: &[u32], b: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    let mut quot = vec![0; a.len()];
+    let mut rem = Vec::new();
+
+    for i in (0..a.len()).rev() {
+        rem.insert(0, a[i]);
+        trim(&mut rem);
+
+        // the biggest d for which b * d <= rem
+        let (mut lo, mut hi) = (0, BASE as u32 - 1);
+        while lo < hi {
+            let mid = lo + (hi - lo).div_ceil(2);
+            match cmp_mag(&mul_small(b, mid), &rem) {
+                Ordering::Greater => hi = mid - 1,
+                _ => lo = mid,
+            }
+        }
+
+        rem = sub_mag(&rem, &mul_small(b, lo));
+        quot[i] = lo;
+    }
+
+    trim(&mut quot);
+    (quot, rem)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn big(s: &str) -> BigInt {
+        BigInt::parse(s).unwrap()
+    }
+
+    #[test]
+    fn test_bigint() {
+        assert_eq!(big("2").pow(64).unwrap(), big("18446744073709551616"));
+        assert_eq!(big("-000123").to_string(), "-123");
+        assert_eq!(big("-0"), BigInt::zero());
+        assert_eq!(BigInt::from_f64(-1e21), Some(big("-1000000000000000000000")));
+        assert_eq!(BigInt::from_f64(0.5), None);
+
+        let a = big("123456789012345678901234567890");
+        let b = big("-987654321098765432109876543210");
+        assert_eq!(&a + &b, big("-864197532086419753208641975320"));
+        assert_eq!(&a - &b, big("1111111110111111111011111111100"));
+        assert_eq!(&a * &b, big("-121932631137021795226185032733622923332237463801111263526900"));
+        assert!(b < a && -&b > a);
+
+        let (q, r) = b.div_rem(&a).unwrap();
+        assert_eq!((q, r), (big("-8"), big("-9000000000900000000090")));
+        assert_eq!(a.div_rem(&BigInt::zero()), None);
+
+        assert_eq!(BigInt::factorial(25).unwrap(), big("15511210043330985984000000"));
+        assert_eq!(big("10").pow(1_000_000), None);
+        assert_eq!(big("1").pow(u64::MAX), Some(big("1")));
+        assert_eq!(big("18446744073709551615").to_u64(), Some(u64::MAX));
+        assert_eq!(big("18446744073709551616").to_u64(), None);
+        assert_eq!(big("-255").to_radix(16), "-ff");
+        assert_eq!(big("0").to_radix(2), "0");
+        assert_eq!(b.to_radix(8), "-1435644201473776661103432161477352");
+    }
+}
@@ -0,0 +1,280 @@
+// exact decimal fractions, for Precision::Decimal, where 0.1 0.2+
+// is 0.3 and not 0.30000000000000004. a decimal is a big integer
+// and how many of its digits come after the point. adding,
+// subtracting and multiplying are exact; dividing is too when the
+// result has an end, and is rounded to DIV_PLACES places (half to
+// even, like banks do) when it doesn't.
+
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt::{self, Display};
+use std::ops::{Add, Mul, Neg, Sub};
+use std::rc::Rc;
+
+use crate::bigint::BigInt;
+use crate::pesc::PescToken;
+use crate::ratio::Ratio;
+
+pub const DIV_PLACES: u32 = 28;
+
+// the biggest exponent parse() takes, e.g. in 1e100000
+const MAX_EXPONENT: i64 = 100_000;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Decimal {
+    digits: BigInt,
+
+    // how many of the digits are after the point. there are no
+    // zeros at the end of those, so each number is written one way.
+    scale: u32,
+}
+
+impl Decimal {
+    fn new(mut digits: BigInt, mut scale: u32) -> Self {
+        let ten = BigInt::from(10);
+        while scale > 0 {
+            match digits.div_rem(&ten) {
+                Some((q, r)) if r.is_zero() => { digits = q; scale -= 1; },
+                _ => break,
+            }
+        }
+
+        Self { digits, scale }
+    }
+
+    // from digits with at most one '.', which may start with a '-' and
+    // end with an exponent, e.g. "-1.5e-3"
+    pub fn parse(s: &str) -> Option<Self> {
+        if let Some((m, e)) = s.split_once(&['e', 'E'][..]) {
+            let exp = e.strip_prefix('+').unwrap_or(e).parse::<i64>().ok()?;
+            if exp.abs() > MAX_EXPONENT {
+                return None;
+            }
+
+            let m = Self::parse(m)?;
+            return Some(match u32::try_from(exp) {
+                Ok(e) if e >= m.scale => Self::new(&m.digits * &pow10(e - m.scale), 0),
+                Ok(e) => Self::new(m.digits, m.scale - e),
+                Err(_) => Self::new(m.digits, m.scale + (-exp) as u32),
+            });
+        }
+
+        let (neg, s) = match s.strip_prefix('-') {
+            Some(d) => (true, d),
+            None => (false, s),
+        };
+
+        let (whole, frac) = s.split_once('.').unwrap_or((s, ""));
+        if whole.is_empty() && frac.is_empty() {
+            return None;
+        }
+
+        let digits = BigInt::parse(&format!("{}{}{}", if neg { "-" } else { "" }, whole, frac))?;
+        Some(Self::new(digits, frac.len() as u32))
+    }
+
+    // the token for it: a big integer if it's whole
+    pub fn token(self) -> PescToken {
+        match self.scale {
+            0 => PescToken::Int(self.digits),
+            _ => PescToken::Decimal(Rc::new(self)),
+        }
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        self.to_string().parse().unwrap_or(f64::NAN)
+    }
+
+    // the integer it is, if it's whole
+    pub fn to_integer(&self) -> Option<&BigInt> {
+        match self.scale {
+            0 => Some(&self.digits),
+            _ => None,
+        }
+    }
+
+    pub fn to_ratio(&self) -> Ratio {
+        Ratio::new(self.digits.clone(), pow10(self.scale)).unwrap_or_else(|| unreachable!())
+    }
+
+    // in scientific notation, e.g. "-1.5e-3", which is how
+    // floats are written with {:e}
+    pub fn to_sci(&self) -> String {
+        let digits = self.digits.abs().to_string();
+        let exp = digits.len() as i64 - 1 - self.scale as i64;
+        let sign = if self.is_negative() { "-" } else { "" };
+
+        let (first, rest) = digits.split_at(1);
+        match rest.trim_end_matches('0') {
+            "" => format!("{}{}e{}", sign, first, exp),
+            rest => format!("{}{}.{}e{}", sign, first, rest, exp),
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.digits.is_zero()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.digits.is_negative()
+    }
+
+    pub fn abs(&self) -> Self {
+        Self::new(self.digits.abs(), self.scale)
+    }
+
+    // self / other, exactly or to DIV_PLACES places, or None
+    // when dividing by zero
+    pub fn div(&self, other: &Self) -> Option<Self> {
+        // (a / 10^sa) / (b / 10^sb) is a 10^(sb + DIV_PLACES) / b 10^sa
+        // in 10^DIV_PLACES-ths
+        let num = &self.digits * &pow10(other.scale + DIV_PLACES);
+        let den = &other.digits * &pow10(self.scale);
+        let (mut q, r) = num.div_rem(&den)?;
+
+        // round half to even
+        let twice = &r.abs() + &r.abs();
+        let odd = !q.div_rem(&BigInt::from(2))?.1.is_zero();
+        if twice > den.abs() || (twice == den.abs() && odd) {
+            let one = BigInt::from(1);
+            q = match num.is_negative() != den.is_negative() {
+                true => &q - &one,
+                false => &q + &one,
+            };
+        }
+
+        Some(Self::new(q, DIV_PLACES))
+    }
+
+    // the remainder of self / other, with the sign of self (like % for
+    // floats), or None when dividing by zero
+    pub fn rem(&self, other: &Self) -> Option<Self> {
+        let (a, b, scale) = align(self, other);
+        let (_, r) = a.div_rem(&b)?;
+        Some(Self::new(r, scale))
+    }
+
+    pub fn pow(&self, n: u64) -> Option<Self> {
+        let scale = (self.scale as u64).checked_mul(n)?;
+        Some(Self::new(self.digits.pow(n)?, u32::try_from(scale).ok()?))
+    }
+}
+
+// 10^n
+fn pow10(n: u32) -> BigInt {
+    BigInt::parse(&format!("1{}", "0".repeat(n as usize))).unwrap_or_default()
+}
+
+// both numbers' digits, with the same number of them after the point
+fn align(a: &Decimal, b: &Decimal) -> (BigInt, BigInt, u32) {
+    let scale = a.scale.max(b.scale);
+    (&a.digits * &pow10(scale - a.scale), &b.digits * &pow10(scale - b.scale), scale)
+}
+
+impl From<BigInt> for Decimal {
+    fn from(n: BigInt) -> Self {
+        Self { digits: n, scale: 0 }
+    }
+}
+
+impl Ord for Decimal {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let (a, b, _) = align(self, other);
+        a.cmp(&b)
+    }
+}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        let digits = self.digits.abs().to_string();
+        let scale = self.scale as usize;
+        let sign = if self.is_negative() { "-" } else { "" };
+
+        if scale == 0 {
+            return write!(f, "{}{}", sign, digits);
+        }
+
+        // (with enough zeros in front for there to be one before the point)
+        let digits = format!("{}{}", "0".repeat((scale + 1).saturating_sub(digits.len())), digits);
+        let (whole, frac) = digits.split_at(digits.len() - scale);
+        write!(f, "{}{}.{}", sign, whole, frac)
+    }
+}
+
+impl Neg for &Decimal {
+    type Output = Decimal;
+
+    fn neg(self) -> Decimal {
+        Decimal::new(-&self.digits, self.scale)
+    }
+}
+
+impl Add for &Decimal {
+    type Output = Decimal;
+
+    fn add(self, other: &Decimal) -> Decimal {
+        let (a, b, scale) = align(self, other);
+        Decimal::new(&a + &b, scale)
+    }
+}
+
+impl Sub for &Decimal {
+    type Output = Decimal;
+
+    fn sub(self, other: &Decimal) -> Decimal {
+        self + &-other
+    }
+}
+
+impl Mul for &Decimal {
+    type Output = Decimal;
+
+    fn mul(self, other: &Decimal) -> Decimal {
+        Decimal::new(&self.digits * &other.digits, self.scale + other.scale)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dec(s: &str) -> Decimal {
+        Decimal::parse(s).unwrap()
+    }
+
+    #[test]
+    fn test_decimal() {
+        assert_eq!(&dec("0.1") + &dec("0.2"), dec("0.3"));
+        assert_eq!((&dec("0.1") + &dec("0.2")).to_string(), "0.3");
+        assert_eq!(dec("-.050").to_string(), "-0.05");
+        assert_eq!(dec("12.50").to_string(), "12.5");
+        assert_eq!(dec("2.5").token(), PescToken::Decimal(Rc::new(dec("2.5"))));
+        assert_eq!(dec("2.0").token(), PescToken::Int(BigInt::from(2)));
+        assert_eq!(Decimal::parse("."), None);
+        assert_eq!(dec("1.5e-3"), dec("0.0015"));
+        assert_eq!(dec("-2.5E+2").token(), PescToken::Int(BigInt::parse("-250").unwrap()));
+        assert_eq!(Decimal::parse("1e"), None);
+        assert_eq!(Decimal::parse("1e999999"), None);
+        assert_eq!(dec("-0.0015").to_sci(), "-1.5e-3");
+        assert_eq!(dec("2500").to_sci(), "2.5e3");
+        assert_eq!(dec("0").to_sci(), "0e0");
+
+        assert_eq!(&dec("1.5") * &dec("-0.2"), dec("-0.3"));
+        assert_eq!(&dec("1") - &dec("0.001"), dec("0.999"));
+        assert!(dec("0.25") > dec("0.125") && dec("-1.5") < dec("-1.25"));
+        assert_eq!(dec("1.1").pow(3), Some(dec("1.331")));
+
+        assert_eq!(dec("1").div(&dec("8")), Some(dec("0.125")));
+        assert_eq!(dec("2").div(&dec("3")).unwrap().to_string(), "0.6666666666666666666666666667");
+        assert_eq!(dec("-1").div(&dec("3")).unwrap().to_string(), "-0.3333333333333333333333333333");
+        assert_eq!(dec("1").div(&dec("0")), None);
+        assert_eq!(dec("5.5").rem(&dec("2")), Some(dec("1.5")));
+        assert_eq!(dec("-5.5").rem(&dec("2")), Some(dec("-1.5")));
+    }
+}
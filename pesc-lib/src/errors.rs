@@ -7,98 +7,332 @@ pub enum PescErrorType {
     // <func>
     UnknownFunction(String),
 
+    // <func>, <lowercase func>
+    WrongCase(String, String),
+
     // <token> (e.g. "[", "(")
     UnmatchedToken(char),
 
+    // <token> (e.g. "}")
+    UnexpectedToken(char),
+
     NotEnoughArguments,
 
     // <expected>, <found>
     InvalidArgumentType(String, String),
 
-    // <found>
-    InvalidNumberLit(String),
+    // <found>, <problem>
+    InvalidNumberLit(String, String),
 
     EmptyLiteral,
 
+    // (in an infix expression)
+    ExpectedValue,
+
     // <a>, <b>
     DivideByZero(f64, f64),
 
     // <index>, <length>
     OutOfBounds(f64, usize),
 
+    // <index>, <length of the list>
+    NoSuchIndex(usize, usize),
+
+    // <problem>
+    BadMapLiteral(String),
+
     // <found>
     InvalidBoolean(PescToken),
 
+    // <limit>
+    RecursionLimitExceeded(usize),
+
+    // <limit>
+    LoopLimitExceeded(usize),
+
+    // <what ran out> (e.g. "1000 steps")
+    BudgetExhausted(String),
+
+    // <limit>
+    StackLimitExceeded(usize),
+
+    // <a>, <b>
+    NaNComparison(f64, f64),
+
+    // <found>
+    NotAnInteger(f64),
+
+    // <operation>
+    IntegerOverflow(String),
+
+    Aborted,
+
+    // <what was expected>
+    AssertionFailed(String),
+
+    // <unit>
+    UnknownUnit(String),
+
+    // <from>, <to>
+    IncompatibleUnits(String, String),
+
+    // <name>
+    UnknownVariable(String),
+
+    // <message> (from [throw])
+    Thrown(String),
+
+    // <namespace>
+    UnknownNamespace(String),
+
+    // <file>
+    IncludeCycle(String),
+
+    // <file>, <line and column>, <what went wrong there>
+    Included(String, Option<(usize, usize)>, Box<PescErrorType>),
+
     Other(String),
 }
 
-impl ToString for PescErrorType {
-    fn to_string(&self) -> String {
+impl PescErrorType {
+    // what kind of error it is, e.g. "unknown-function", for other
+    // programs to go by (for an error in an included file, the kind
+    // of that error)
+    pub fn name(&self) -> &'static str {
         match self {
+            PescErrorType::UnknownFunction(_) => "unknown-function",
+            PescErrorType::WrongCase(_, _) => "wrong-case",
+            PescErrorType::UnmatchedToken(_) => "unmatched-token",
+            PescErrorType::UnexpectedToken(_) => "unexpected-token",
+            PescErrorType::NotEnoughArguments => "not-enough-arguments",
+            PescErrorType::InvalidArgumentType(_, _) => "invalid-argument-type",
+            PescErrorType::InvalidNumberLit(_, _) => "invalid-number",
+            PescErrorType::EmptyLiteral => "empty-literal",
+            PescErrorType::ExpectedValue => "expected-value",
+            PescErrorType::DivideByZero(_, _) => "divide-by-zero",
+            PescErrorType::OutOfBounds(_, _) => "out-of-bounds",
+            PescErrorType::NoSuchIndex(_, _) => "no-such-index",
+            PescErrorType::BadMapLiteral(_) => "bad-map-literal",
+            PescErrorType::InvalidBoolean(_) => "invalid-boolean",
+            PescErrorType::RecursionLimitExceeded(_) => "recursion-limit-exceeded",
+            PescErrorType::LoopLimitExceeded(_) => "loop-limit-exceeded",
+            PescErrorType::BudgetExhausted(_) => "budget-exhausted",
+            PescErrorType::StackLimitExceeded(_) => "stack-limit-exceeded",
+            PescErrorType::NaNComparison(_, _) => "nan-comparison",
+            PescErrorType::NotAnInteger(_) => "not-an-integer",
+            PescErrorType::IntegerOverflow(_) => "integer-overflow",
+            PescErrorType::Aborted => "aborted",
+            PescErrorType::AssertionFailed(_) => "assertion-failed",
+            PescErrorType::UnknownUnit(_) => "unknown-unit",
+            PescErrorType::IncompatibleUnits(_, _) => "incompatible-units",
+            PescErrorType::UnknownVariable(_) => "unknown-variable",
+            PescErrorType::Thrown(_) => "thrown",
+            PescErrorType::UnknownNamespace(_) => "unknown-namespace",
+            PescErrorType::IncludeCycle(_) => "include-cycle",
+            PescErrorType::Included(_, _, e) => e.name(),
+            PescErrorType::Other(_) => "other",
+        }
+    }
+}
+
+impl Display for PescErrorType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        let r = match self {
             PescErrorType::UnknownFunction(f) =>
                 format!("I have no idea what {} means.", f),
+            PescErrorType::WrongCase(f, l) =>
+                format!("I have no idea what {} means, did you mean {}?", f, l),
             PescErrorType::UnmatchedToken(t) =>
                 format!("Where's the matching '{}'?", t),
+            PescErrorType::UnexpectedToken(t) =>
+                format!("What's that '{}' doing there?", t),
             PescErrorType::NotEnoughArguments =>
-                format!("I need just 1 more argument, OK?"),
+                String::from("I need just 1 more argument, OK?"),
             PescErrorType::InvalidArgumentType(h, a) =>
                 format!("I wanted a {}, but you gave a {}", h, a),
-            PescErrorType::InvalidNumberLit(f) =>
-                format!("What makes you think '{}' is a number?", f),
+            PescErrorType::InvalidNumberLit(f, p) =>
+                format!("What makes you think '{}' is a number? ({})", f, p),
             PescErrorType::EmptyLiteral =>
-                format!("I don't know what to do with an empty literal."),
+                String::from("I don't know what to do with an empty literal."),
+            PescErrorType::ExpectedValue =>
+                String::from("There should be a number (or something in parentheses) here."),
             PescErrorType::DivideByZero(a, b) =>
                 format!("You can't divide {} by {}, so don't try.", a, b),
             PescErrorType::OutOfBounds(i, _) =>
                 format!("The stack isn't as big as you think ({} is out of bounds)", *i as usize),
+            PescErrorType::NoSuchIndex(i, len) =>
+                format!("There's nothing at {} in a list of {}.", i, len),
+            PescErrorType::BadMapLiteral(problem) =>
+                format!("That's not much of a map, {}.", problem),
             PescErrorType::InvalidBoolean(found) =>
                 format!("Uh, is {} supposed to be true or false?", found),
+            PescErrorType::RecursionLimitExceeded(l) =>
+                format!("I'm not going more than {} macros deep.", l),
+            PescErrorType::LoopLimitExceeded(l) =>
+                format!("I gave up on that loop after {} times around.", l),
+            PescErrorType::BudgetExhausted(b) =>
+                format!("I gave up after {}, which is all I was given.", b),
+            PescErrorType::StackLimitExceeded(l) =>
+                format!("The stack can't hold more than {} things.", l),
+            PescErrorType::NaNComparison(a, b) =>
+                format!("How am I supposed to compare {} and {}?", a, b),
+            PescErrorType::NotAnInteger(n) =>
+                format!("I wanted a whole number, but {} isn't one.", n),
+            PescErrorType::IntegerOverflow(op) =>
+                format!("The result of {} is too big to be exact.", op),
+            PescErrorType::Aborted => String::from("OK, I stopped."),
+            PescErrorType::AssertionFailed(e) =>
+                format!("That's wrong, {}.", e),
+            PescErrorType::UnknownUnit(u) =>
+                format!("I've never heard of a {}.", u),
+            PescErrorType::IncompatibleUnits(a, b) =>
+                format!("You can't turn {} into {}, they don't measure the same thing.", a, b),
+            PescErrorType::UnknownVariable(v) =>
+                format!("Nothing has been stored as {} yet.", v),
+            PescErrorType::Thrown(msg) => msg.clone(),
+            PescErrorType::UnknownNamespace(ns) =>
+                format!("There's nothing called {}.something to import.", ns),
+            PescErrorType::IncludeCycle(file) =>
+                format!("{} is already being included, so I'd go round in circles.", file),
+            PescErrorType::Included(file, Some((line, col)), e) =>
+                format!("{}:{}:{}: {}", file, line, col, e),
+            PescErrorType::Included(file, None, e) => format!("{}: {}", file, e),
             PescErrorType::Other(msg) => msg.clone(),
-        }
+        };
+
+        write!(f, "{}", r)
+    }
+}
+
+// a range of bytes in the input, from `start` up to
+// (but not including) `end`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self {
+        Self { start, end }
+    }
+
+    // the (line, column) at which the span starts, both starting
+    // from 1. columns are counted in characters, not bytes.
+    pub fn line_col(&self, input: &str) -> (usize, usize) {
+        let before = &input[..self.start.min(input.len())];
+        let line = before.matches('\n').count() + 1;
+        let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+
+        (line, before[line_start..].chars().count() + 1)
+    }
+
+    // a line with a '^' under each character of the span (up to the
+    // end of its line), to be printed under the line it starts on
+    pub fn caret(&self, input: &str) -> String {
+        let (_, col) = self.line_col(input);
+        let rest = &input[self.start.min(input.len())..self.end.min(input.len())];
+        let width = rest.split('\n').next().unwrap_or("").chars().count().max(1);
+
+        format!("{}{}", " ".repeat(col - 1), "^".repeat(width))
     }
 }
 
 #[derive(Clone, Debug)]
 pub struct PescError {
-    pub ch: Option<usize>,
+    pub span: Option<Span>,
     pub token: Option<PescToken>,
     pub kind: PescErrorType,
 }
 
 impl PescError {
-    pub fn new(c: Option<usize>, t: Option<PescToken>, k: PescErrorType)
+    pub fn new(s: Option<Span>, t: Option<PescToken>, k: PescErrorType)
         -> Self
     {
         Self {
-            ch: c,
+            span: s,
             token: t,
             kind: k
         }
     }
 
-    fn hints(&self) -> Vec<String> {
+    pub fn hints(&self) -> Vec<String> {
         match self.kind {
             PescErrorType::UnknownFunction(_) => vec![
                 "is the function loaded correctly?".to_string(),
             ],
+            PescErrorType::WrongCase(_, _) => vec![
+                "function names are case-sensitive right now.".to_string(),
+            ],
             PescErrorType::UnmatchedToken(_) => vec![],
+            PescErrorType::UnexpectedToken(_) => vec![],
 
             // TODO: check function documentation and hint
             // with the correct number of arguments
             PescErrorType::NotEnoughArguments => vec![],
             PescErrorType::InvalidArgumentType(_, _) => vec![],
-            PescErrorType::InvalidNumberLit(_) => vec![
-                "number literals may only contain character [0-9_\\.]".to_string(),
-                "bases other than decimal are currently not supported.".to_string(),
+            PescErrorType::InvalidNumberLit(_, _) => vec![
+                "numbers are digits with an optional point and exponent (12, 1.5, 1e-3), or \
+                    hexadecimal, binary or octal integers (0xff, 0b1010, 0o755).".to_string(),
+                "a leading '_' makes a number negative; elsewhere, '_' may only separate digits.".to_string(),
             ],
             PescErrorType::EmptyLiteral => vec![],
-            PescErrorType::DivideByZero(_, _) => vec![],
+            PescErrorType::ExpectedValue => vec![],
+            PescErrorType::DivideByZero(_, _) => vec![
+                "dividing by zero can push inf or nil instead, see --div-zero.".to_string(),
+            ],
             PescErrorType::OutOfBounds(_, a) => vec![
                 format!("the stack is {} elements long.", a),
             ],
+            PescErrorType::NoSuchIndex(_, _) => vec![
+                "lists are indexed from 0.".to_string(),
+            ],
+            PescErrorType::BadMapLiteral(_) => vec![
+                "maps are written as keys followed by their values, e.g. '{\"a\" 1 \"b\" 2}.".to_string(),
+            ],
             PescErrorType::InvalidBoolean(_) => vec![
                 "only tokens of type `number`, `string`, and `bool` can be cast as boolean.".to_string()
             ],
+            PescErrorType::RecursionLimitExceeded(_) => vec![
+                "does a macro call itself without ever stopping?".to_string(),
+            ],
+            PescErrorType::LoopLimitExceeded(_) => vec![
+                "does the loop's condition ever change?".to_string(),
+                "the limit can be raised with max-loops in the config.".to_string(),
+            ],
+            PescErrorType::BudgetExhausted(_) => vec![
+                "is something going round in circles?".to_string(),
+                "the limits are max-steps and timeout in the config.".to_string(),
+            ],
+            PescErrorType::StackLimitExceeded(_) => vec![
+                "does a loop keep pushing things without using them up?".to_string(),
+                "the limit can be raised with --max-stack.".to_string(),
+            ],
+            PescErrorType::NaNComparison(_, _) => vec![
+                "NaN can't be compared with anything, see --nan.".to_string(),
+            ],
+            PescErrorType::NotAnInteger(_) => vec![
+                format!("integers must be between 0 and {}.", (1_u64 << 53) - 1),
+            ],
+            PescErrorType::IntegerOverflow(_) => vec![
+                format!("integers can't be larger than {}.", (1_u64 << 53) - 1),
+            ],
+            PescErrorType::Aborted => vec![],
+            PescErrorType::AssertionFailed(_) => vec![],
+            PescErrorType::UnknownUnit(_) => vec![
+                "units come from the units(1) definitions that were loaded.".to_string(),
+            ],
+            PescErrorType::IncompatibleUnits(_, _) => vec![],
+            PescErrorType::UnknownVariable(_) => vec![
+                "values are stored with [store], e.g. 5 \"x\" [store].".to_string(),
+            ],
+            PescErrorType::Thrown(_) => vec![],
+            PescErrorType::UnknownNamespace(_) => vec![],
+            PescErrorType::IncludeCycle(_) => vec![
+                "do two files include each other?".to_string(),
+            ],
+            PescErrorType::Included(_, _, ref e) =>
+                PescError::new(None, None, (**e).clone()).hints(),
             PescErrorType::Other(_) => vec![],
         }
     }
@@ -109,6 +343,92 @@ impl Error for PescError {
 
 impl Display for PescError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
-        write!(f, "{}", self.kind.to_string())
+        write!(f, "{}", self.kind)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum PescWarningType {
+    // <symbol>
+    UnknownSymbol(char),
+
+    // <func>
+    Redefinition(String),
+
+    // <operator>, <func>
+    ShadowedOperator(String, String),
+
+    // <macro>
+    UnusedMacro(PescToken),
+
+    // <count>
+    ValuesLeft(usize),
+
+    // <func>
+    UnknownFunction(String),
+
+    // <func>, <needed>, <available>
+    StackUnderflow(String, usize, usize),
+
+    // <name>, <the function it doesn't mean>
+    NameCollision(String, String),
+
+    // <func>
+    UnusedDefinition(String),
+
+    // <the function that always fails>
+    Unreachable(String),
+
+    // <literal>, <what's wrong with it>
+    SuspiciousLiteral(String, String),
+}
+
+impl Display for PescWarningType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        match self {
+            PescWarningType::UnknownSymbol(c) =>
+                write!(f, "'{}' isn't an operator (yet?), it'll fail if it's run.", c),
+            PescWarningType::Redefinition(n) =>
+                write!(f, "{} was already defined, I hope you meant to replace it.", n),
+            PescWarningType::NameCollision(n, full) =>
+                write!(f, "[{}] isn't {}, which has to be called by its full name.", n, full),
+            PescWarningType::ShadowedOperator(o, n) =>
+                write!(f, "'{}' now runs your {} instead of the old one.", o, n),
+            PescWarningType::UnusedMacro(m) =>
+                write!(f, "{} was never run.", m),
+            PescWarningType::ValuesLeft(c) =>
+                write!(f, "{} value(s) were left on the stack.", c),
+            PescWarningType::UnknownFunction(n) =>
+                write!(f, "{} isn't defined anywhere, it'll fail if it's run.", n),
+            PescWarningType::StackUnderflow(n, w, a) =>
+                write!(f, "{} needs {} value(s), but there will only be {}.", n, w, a),
+            PescWarningType::UnusedDefinition(n) =>
+                write!(f, "{} is defined, but never used.", n),
+            PescWarningType::Unreachable(n) =>
+                write!(f, "this will never run, since {} always fails.", n),
+            PescWarningType::SuspiciousLiteral(l, why) =>
+                write!(f, "{} {}.", l, why),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PescWarning {
+    pub span: Option<Span>,
+    pub kind: PescWarningType,
+}
+
+impl PescWarning {
+    pub fn new(s: Option<Span>, k: PescWarningType) -> Self {
+        Self {
+            span: s,
+            kind: k,
+        }
+    }
+}
+
+impl Display for PescWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "{}", self.kind)
     }
 }
@@ -0,0 +1,139 @@
+// a mode that works like a classic HP calculator: the stack always
+// has exactly four levels (X, Y, Z and T, X being the top). values
+// pushed onto a full stack push T off the end, and when values are
+// taken off, T is copied down from the top. `enter` copies X into Y,
+// and the next number entered then replaces X (as it does after
+// `clx`); `lastx` brings back X as it was before the last function
+// was run.
+//
+// this is all done with hooks, so load() it into a Pesc that
+// already has the standard library.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::pesc::*;
+
+pub const LEVELS: usize = 4;
+
+// functions that leave LASTX alone
+const KEEP_LASTX: &[&str] = &["enter", "lastx", "clx", "rdn", "swp"];
+
+#[derive(Clone, Debug)]
+struct Hp {
+    // whether the next number pushes the stack up, or replaces X
+    lift: bool,
+    last_x: PescToken,
+}
+
+pub fn load(pesc: &mut Pesc) {
+    let hp = Rc::new(RefCell::new(Hp {
+        lift: true,
+        last_x: PescToken::Number(0.0),
+    }));
+
+    let h = hp.clone();
+    pesc.load_documented(None, "lastx", Rc::new(Box::new(move |p| {
+        p.push(h.borrow().last_x.clone());
+        Ok(())
+    })), Some((0, 1)), "-- x", "the X register from before the last operation.");
+    pesc.load_documented(None, "enter", Rc::new(Box::new(|p| {
+        let x = p.pop()?;
+        p.push(x.clone());
+        p.push(x);
+        Ok(())
+    })), Some((1, 2)), "x -- x x", "copies X into Y, like ENTER on an HP calculator.");
+    pesc.load_documented(None, "clx", Rc::new(Box::new(|p| {
+        p.pop()?;
+        p.push(PescToken::Number(0.0));
+        Ok(())
+    })), Some((1, 1)), "x -- 0", "clears the X register.");
+
+    // roll down, moving X to T
+    pesc.load_documented(None, "rdn", Rc::new(Box::new(|p| {
+        let x = p.pop()?;
+        p.stack.insert(0, x);
+        Ok(())
+    })), None, "t z y x -- x t z y", "rolls the stack down, moving X to T.");
+
+    fill(&mut pesc.stack);
+
+    pesc.hooks.push(Box::new(move |p, ev| {
+        let mut hp = hp.borrow_mut();
+
+        match ev {
+            PescEvent::Token(t) if is_value(t) => {
+                if !hp.lift {
+                    p.stack.pop();
+                }
+                hp.lift = true;
+            },
+            PescEvent::Call(f) if p.depth() == 0 && !KEEP_LASTX.contains(&f) => {
+                if let Some(x) = p.stack.last() {
+                    hp.last_x = x.clone();
+                }
+            },
+            PescEvent::Return(f) => hp.lift = f != "enter" && f != "clx",
+            PescEvent::Evaluated(_) => fill(&mut p.stack),
+            _ => (),
+        }
+
+        Ok(())
+    }));
+}
+
+fn is_value(t: &PescToken) -> bool {
+    !matches!(t, PescToken::Func(_) | PescToken::Symbol(_)
+        | PescToken::Operator(_) | PescToken::Unknown(_))
+}
+
+// drop whatever is past T, and copy T down into empty levels
+fn fill(stack: &mut Vec<PescToken>) {
+    if stack.len() > LEVELS {
+        stack.drain(..stack.len() - LEVELS);
+    }
+
+    if stack.is_empty() {
+        stack.push(PescToken::Number(0.0));
+    }
+
+    while stack.len() < LEVELS {
+        stack.insert(0, stack[0].clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stdlib;
+
+    fn hp(input: &str) -> Vec<f64> {
+        let mut p = Pesc::new();
+        for f in stdlib::standard().into_iter().chain(stdlib::extended()) {
+            p.load(f.0, f.1, f.2);
+        }
+        load(&mut p);
+
+        let code = p.parse_code(input).unwrap();
+        p.eval(&code).unwrap();
+        p.stack.iter().map(|t| match t {
+            PescToken::Number(n) => *n,
+            _ => f64::NAN,
+        }).collect()
+    }
+
+    #[test]
+    fn test_hp() {
+        assert_eq!(hp(""), vec![0.0, 0.0, 0.0, 0.0]);
+        assert_eq!(hp("3 [enter] 4 +"), vec![0.0, 0.0, 0.0, 7.0]);
+        assert_eq!(hp("1 2 3 4 5"), vec![2.0, 3.0, 4.0, 5.0]);
+
+        // T is copied down as the stack drops
+        assert_eq!(hp("1 2 3 4 + + +"), vec![1.0, 1.0, 1.0, 10.0]);
+        assert_eq!(hp("6 [enter] [enter] [enter] *"), vec![6.0, 6.0, 6.0, 36.0]);
+
+        assert_eq!(hp("2 3 * [lastx]"), vec![0.0, 0.0, 6.0, 3.0]);
+        assert_eq!(hp("1 2 [clx] 5"), vec![0.0, 0.0, 1.0, 5.0]);
+        assert_eq!(hp("1 2 3 4 [rdn]"), vec![4.0, 1.0, 2.0, 3.0]);
+    }
+}
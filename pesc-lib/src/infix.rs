@@ -0,0 +1,228 @@
+// compiles conventional infix expressions like "3 * (4 + 5)" to the
+// tokens pesc would run for them (here, "3 4 5 + *"). numbers follow
+// pesc's rules, names are functions (with their arguments, if any,
+// in parentheses, separated by commas), and the operators are, from
+// the loosest to the tightest: + and -, then *, / and %, then unary
+// minus, and finally ^ (which groups to the right).
+
+use crate::errors::*;
+use crate::pesc::*;
+
+const PREC_UNARY: usize = 3;
+
+struct Compiler<'a> {
+    chs: Vec<(usize, char)>,
+    input: &'a str,
+    i: usize,
+    out: Vec<PescToken>,
+}
+
+pub fn compile(input: &str) -> Result<Vec<PescToken>, PescError> {
+    let mut c = Compiler {
+        chs: input.char_indices().collect(),
+        input, i: 0,
+        out: Vec::new(),
+    };
+
+    c.expr(0)?;
+
+    // everything should have been used up by now
+    match c.peek() {
+        None => Ok(c.out),
+        Some(')') => Err(c.error(PescErrorType::UnexpectedToken(')'))),
+        Some(ch) => Err(c.error(PescErrorType::UnexpectedToken(ch))),
+    }
+}
+
+// (precedence, function, groups to the right?)
+fn binary(op: char) -> Option<(usize, &'static str, bool)> {
+    match op {
+        '+' => Some((1, "add", false)),
+        '-' => Some((1, "sub", false)),
+        '*' => Some((2, "mul", false)),
+        '/' => Some((2, "div", false)),
+        '%' => Some((2, "mod", false)),
+        '^' => Some((4, "pow", true)),
+        _ => None,
+    }
+}
+
+impl Compiler<'_> {
+    fn expr(&mut self, min: usize) -> Result<(), PescError> {
+        self.unary()?;
+
+        while let Some((prec, func, right)) = self.peek().and_then(binary) {
+            if prec < min {
+                break;
+            }
+
+            self.i += 1;
+            self.expr(if right { prec } else { prec + 1 })?;
+            self.out.push(PescToken::Func(String::from(func)));
+        }
+
+        Ok(())
+    }
+
+    fn unary(&mut self) -> Result<(), PescError> {
+        match self.peek() {
+            Some('-') => {
+                self.i += 1;
+                self.expr(PREC_UNARY)?;
+                self.out.push(PescToken::Number(-1.0));
+                self.out.push(PescToken::Func(String::from("mul")));
+                Ok(())
+            },
+            Some('+') => {
+                self.i += 1;
+                self.expr(PREC_UNARY)
+            },
+            _ => self.primary(),
+        }
+    }
+
+    fn primary(&mut self) -> Result<(), PescError> {
+        let start = self.i;
+
+        match self.peek() {
+            Some('(') => {
+                self.i += 1;
+                self.expr(0)?;
+
+                if self.peek() != Some(')') {
+                    return Err(PescError::new(Some(self.span(start, start + 1)),
+                        None, PescErrorType::UnmatchedToken('(')));
+                }
+                self.i += 1;
+            },
+            Some(c) if c.is_ascii_digit() || c == '.' || c == '_' => {
+                // (letters too, for hex, binary and octal)
+                let mut lit = self.take(|c| c.is_ascii_alphanumeric() || c == '.' || c == '_');
+
+                // (and 1e-3's minus isn't a minus, though 0x1e-3's is)
+                let radix = matches!(lit.trim_start_matches('_').get(..2), Some("0x" | "0b" | "0o"));
+                if lit.ends_with(&['e', 'E'][..]) && !radix {
+                    let chs = self.chs.iter().map(|c| c.1).collect::<Vec<char>>();
+                    if let Some(end) = exponent_end(&chs, self.i - 1) {
+                        lit.extend(&chs[self.i..end]);
+                        self.i = end;
+                    }
+                }
+                let num = number_literal(&lit, false, &PescOptions::default())
+                    .map_err(|(s, e, problem)| PescError::new(
+                        Some(self.span(start + s, start + e)), None,
+                        PescErrorType::InvalidNumberLit(lit.clone(), problem)))?;
+
+                self.out.push(num);
+            },
+            Some(c) if c.is_alphabetic() => {
+                let name = self.take(|c| c.is_alphanumeric() || c == '?' || c == '_');
+
+                if self.peek() == Some('(') {
+                    self.args()?;
+                }
+
+                self.out.push(PescToken::Func(name));
+            },
+            Some(c) => return Err(self.error(PescErrorType::UnexpectedToken(c))),
+            None => return Err(self.error(PescErrorType::ExpectedValue)),
+        }
+
+        Ok(())
+    }
+
+    // a function's arguments, e.g. "(1, 2 + 3)"
+    fn args(&mut self) -> Result<(), PescError> {
+        let open = self.i;
+        self.i += 1;
+
+        if self.peek() == Some(')') {
+            self.i += 1;
+            return Ok(());
+        }
+
+        loop {
+            self.expr(0)?;
+
+            match self.peek() {
+                Some(',') => self.i += 1,
+                Some(')') => { self.i += 1; return Ok(()); },
+                _ => return Err(PescError::new(Some(self.span(open, open + 1)),
+                    None, PescErrorType::UnmatchedToken('('))),
+            }
+        }
+    }
+
+    // the next character that isn't whitespace
+    fn peek(&mut self) -> Option<char> {
+        while self.i < self.chs.len() && self.chs[self.i].1.is_whitespace() {
+            self.i += 1;
+        }
+
+        self.chs.get(self.i).map(|c| c.1)
+    }
+
+    fn take<F>(&mut self, f: F) -> String
+    where
+        F: Fn(char) -> bool
+    {
+        let mut buf = String::new();
+        while self.i < self.chs.len() && f(self.chs[self.i].1) {
+            buf.push(self.chs[self.i].1);
+            self.i += 1;
+        }
+        buf
+    }
+
+    // the span from the start of the start'th character to the
+    // start of the end'th
+    fn span(&self, start: usize, end: usize) -> Span {
+        let off = |i: usize| self.chs.get(i).map(|c| c.0).unwrap_or(self.input.len());
+        Span::new(off(start), off(end))
+    }
+
+    fn error(&self, kind: PescErrorType) -> PescError {
+        PescError::new(Some(self.span(self.i, self.i + 1)), None, kind)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stdlib;
+
+    fn postfix(input: &str) -> String {
+        unparse(&compile(input).unwrap())
+    }
+
+    #[test]
+    fn test_compile() {
+        assert_eq!(postfix("3*(4+5)"), "3 4 5 [add] [mul]");
+        assert_eq!(postfix("1 - 2 - 3"), "1 2 [sub] 3 [sub]");
+        assert_eq!(postfix("2^3^2"), "2 3 2 [pow] [pow]");
+        assert_eq!(postfix("-2^2"), "2 2 [pow] _1 [mul]");
+        assert_eq!(postfix("max(1, 2 * pi) % 4"), "1 2 [pi] [mul] [max] 4 [mod]");
+        assert_eq!(postfix("1_000 / sqrt(4)"), "1000 4 [sqrt] [div]");
+        assert_eq!(postfix("0xff + 0b11"), "255 3 [add]");
+        assert_eq!(postfix("2e3 - 1.5E-3"), "2000 0.0015 [sub]");
+
+        assert!(matches!(compile("(1 + 2").unwrap_err().kind,
+            PescErrorType::UnmatchedToken('(')));
+        assert!(matches!(compile("1 +").unwrap_err().kind,
+            PescErrorType::ExpectedValue));
+        let e = compile("1 + 2)").unwrap_err();
+        assert!(matches!(e.kind, PescErrorType::UnexpectedToken(')')));
+        assert_eq!(e.span, Some(Span::new(5, 6)));
+    }
+
+    #[test]
+    fn test_eval() {
+        let mut p = Pesc::new();
+        for f in stdlib::standard().into_iter().chain(stdlib::extended()) {
+            p.load(f.0, f.1, f.2);
+        }
+
+        p.eval(&compile("3 * (4 + 5) - 2 ^ 3 / -4").unwrap()).unwrap();
+        assert_eq!(p.stack, vec![PescToken::Number(29.0)]);
+    }
+}
@@ -1,5 +1,28 @@
+// PescError carries its span and the offending token around,
+// which makes it a bit large; boxing it everywhere isn't worth it.
+#![allow(clippy::result_large_err)]
+
+// the pesc interpreter, for embedding in other programs (pescli is
+// one of them). the usual start is
+//
+//     let mut pesc = pesc::pesc::Pesc::with_stdlib();
+//     pesc.register("answer", |p| {
+//         p.push(PescToken::Number(42.0));
+//         Ok(())
+//     });
+//     pesc.run("[answer] 2*")?;
+//
+// after which pesc.stack holds the result.
+
+pub mod bigint;
+pub mod decimal;
 pub mod errors;
+pub mod hp;
+pub mod infix;
+pub mod lint;
 pub mod pesc;
 pub mod rand;
+pub mod ratio;
 pub mod stdlib;
+pub mod units;
 pub mod utils;
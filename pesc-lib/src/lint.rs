@@ -0,0 +1,278 @@
+// static checks that can be done without running the code: unknown
+// operators and functions, functions that will obviously run out of
+// arguments (going by the arity declared for each one, see
+// Pesc::declare()), definitions that replace existing functions or
+// that are never used, code after a [throw], and number literals
+// that probably don't mean what they look like, e.g. 010.
+
+use crate::errors::*;
+use crate::pesc::*;
+
+struct Linter<'a> {
+    pesc: &'a Pesc,
+
+    // the code being checked, when there is some (and not just tokens)
+    input: &'a str,
+
+    // every function defined in the input, and the ones defined
+    // so far while walking through it
+    defined: Vec<String>,
+    seen: Vec<String>,
+
+    // every function called in the input
+    used: Vec<String>,
+
+    warnings: Vec<PescWarning>,
+}
+
+// check `input` against the functions and operators loaded in `pesc`,
+// returning warnings in the order they appear in the input
+pub fn lint(pesc: &Pesc, input: &str) -> Result<Vec<PescWarning>, PescError> {
+    let (toks, spans) = Pesc::parse_spans(input, &pesc.opts)?;
+    Ok(Linter::new(pesc, input).run(&toks, &spans, 0))
+}
+
+// the functions in some parsed code that are sure to run out of
+// arguments, when it's run with `depth` values on the stack
+pub fn underflows(pesc: &Pesc, toks: &[PescToken], spans: &[PescSpans], depth: usize)
+    -> Vec<PescWarning>
+{
+    Linter::new(pesc, "").run(toks, spans, depth).into_iter()
+        .filter(|w| matches!(w.kind, PescWarningType::StackUnderflow(..)))
+        .collect()
+}
+
+impl<'a> Linter<'a> {
+    fn new(pesc: &'a Pesc, input: &'a str) -> Self {
+        Self {
+            pesc,
+            input,
+            defined: Vec::new(),
+            seen: Vec::new(),
+            used: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    // warnings in the order they appear in the code
+    fn run(mut self, toks: &[PescToken], spans: &[PescSpans], depth: usize)
+        -> Vec<PescWarning>
+    {
+        self.collect(toks);
+        self.check(toks, spans, Some(depth));
+
+        self.warnings.sort_by_key(|w| w.span.map(|s| s.start));
+        self.warnings
+    }
+
+    // find the names of the functions defined with `"name" [def]`,
+    // and of the ones that are called
+    fn collect(&mut self, toks: &[PescToken]) {
+        for (i, t) in toks.iter().enumerate() {
+            match t {
+                PescToken::Macro(m) => self.collect(m),
+                PescToken::Func(f) => match self.definition(toks, i) {
+                    Some(name) => self.defined.push(name),
+                    None => self.used.push(self.pesc.opts.func_name(f)),
+                },
+                _ => (),
+            }
+        }
+    }
+
+    // the name defined by toks[i], if it's a `def` after a string
+    fn definition(&self, toks: &[PescToken], i: usize) -> Option<String> {
+        match (&toks[i], i.checked_sub(1).map(|j| &toks[j])) {
+            (PescToken::Func(f), Some(PescToken::Str(name)))
+                if self.pesc.opts.func_name(f) == "def" =>
+                Some(self.pesc.opts.func_name(name)),
+            _ => None,
+        }
+    }
+
+    // `depth` is how many values are known to be on the stack, if
+    // anything is known at all. it's only known at the start of the
+    // input (where it's 0 for a file, or the stack's size in the REPL), and stops being known at the first function whose
+    // stack effect can't be worked out beforehand.
+    fn check(&mut self, toks: &[PescToken], spans: &[PescSpans],
+        mut depth: Option<usize>)
+    {
+        for (i, (t, s)) in toks.iter().zip(spans).enumerate() {
+            let name = match t {
+                PescToken::Macro(m) => {
+                    self.check(m, &s.inner, None);
+                    depth = depth.map(|d| d + 1);
+                    continue;
+                },
+                PescToken::Symbol(c) | PescToken::Unknown(c) => {
+                    match self.pesc.ops.get(c) {
+                        Some(f) => f.clone(),
+                        None => {
+                            self.warn(s.span, PescWarningType::UnknownSymbol(*c));
+                            depth = None;
+                            continue;
+                        },
+                    }
+                },
+                PescToken::Operator(o) => match self.pesc.multi_ops.get(o) {
+                    Some(f) => f.clone(),
+                    None => { depth = None; continue; },
+                },
+                PescToken::Func(f) => self.pesc.opts.func_name(f),
+                _ => {
+                    self.check_literal(t, s.span);
+                    depth = depth.map(|d| d + 1);
+                    continue;
+                },
+            };
+
+            let user = self.defined.contains(&name) || self.pesc.defs.contains_key(&name);
+            if !user && !self.pesc.funcs.contains_key(&self.pesc.resolve(&name)) {
+                self.warn(s.span, PescWarningType::UnknownFunction(name));
+                depth = None;
+                continue;
+            }
+
+            if let Some(def) = self.definition(toks, i) {
+                self.check_definition(def, spans[i - 1].span);
+            }
+
+            let effect = match user {
+                true => None,
+                false => self.pesc.arity.get(&self.pesc.resolve(&name)).copied(),
+            };
+
+            let throws = self.pesc.resolve(&name) == "throw";
+            depth = match (depth, effect) {
+                (Some(d), Some((pops, _))) if d < pops => {
+                    self.warn(s.span, PescWarningType::StackUnderflow(name, pops, d));
+                    None
+                },
+                (Some(d), Some((pops, pushes))) => Some(d - pops + pushes),
+                _ => None,
+            };
+
+            // (the rest isn't worth checking)
+            if throws {
+                if let Some(next) = spans.get(i + 1) {
+                    self.warn(next.span, PescWarningType::Unreachable(String::from("throw")));
+                }
+                return;
+            }
+        }
+    }
+
+    // a number that looks like it's octal, or that's too big for a
+    // float to hold exactly
+    fn check_literal(&mut self, t: &PescToken, sp: Span) {
+        let lit = match self.input.get(sp.start..sp.end) {
+            Some(l) => l,
+            None => return,
+        };
+        let digits = lit.trim_start_matches(&['_', '-'][..]).replace('_', "");
+        if !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return;
+        }
+
+        let n = digits.trim_start_matches('0');
+        let why = match t {
+            _ if digits.starts_with('0') && !n.is_empty() =>
+                format!("is {}, not octal (which would be 0o{})", n, n),
+            PescToken::Number(n) if format!("{:.0}", n.abs()) != digits =>
+                format!("is too big to be exact as a float, it's really {:.0}", n),
+            _ => return,
+        };
+
+        self.warn(sp, PescWarningType::SuspiciousLiteral(lit.to_string(), why));
+    }
+
+    fn check_definition(&mut self, name: String, sp: Span) {
+        // (replacing a function counts as using it)
+        let known = self.seen.contains(&name) || self.pesc.funcs.contains_key(&name);
+        if !known && !self.used.contains(&name) {
+            self.warn(sp, PescWarningType::UnusedDefinition(name.clone()));
+        }
+
+        if known {
+            self.warn(sp, PescWarningType::Redefinition(name.clone()));
+        }
+
+        for op in self.pesc.operators(&name) {
+            self.warn(sp, PescWarningType::ShadowedOperator(op, name.clone()));
+        }
+
+        self.seen.push(name);
+    }
+
+    fn warn(&mut self, sp: Span, kind: PescWarningType) {
+        self.warnings.push(PescWarning::new(Some(sp), kind));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pesc() -> Pesc {
+        Pesc::with_stdlib()
+    }
+
+    fn kinds(input: &str) -> Vec<String> {
+        lint(&pesc(), input).unwrap().iter()
+            .map(|w| format!("{:?}", w.kind))
+            .collect()
+    }
+
+    #[test]
+    fn test_lint() {
+        assert!(kinds("1 2+ {3 4 [foo]}; \"foo\" [def] [foo]").is_empty());
+
+        assert_eq!(kinds("1 + 2 [bar] ⁂"), vec![
+            "StackUnderflow(\"add\", 2, 1)",
+            "UnknownFunction(\"bar\")",
+            "UnknownSymbol('⁂')",
+        ]);
+
+        // nothing is known about the stack after a macro runs
+        assert!(kinds("{1 2}; +").is_empty());
+
+        assert_eq!(kinds("{} \"dup\" [def] {} \"x\" [def] {} \"X\" [def] [x]"), vec![
+            "Redefinition(\"dup\")",
+            "ShadowedOperator(\"\\\\\", \"dup\")",
+            "Redefinition(\"x\")",
+        ]);
+
+        assert_eq!(kinds("{1} \"one\" [def] {\"no\" [throw] 1 2} {$} [try]"), vec![
+            "UnusedDefinition(\"one\")",
+            "Unreachable(\"throw\")",
+        ]);
+
+        assert_eq!(kinds("0 010 0x10 _007 0.5 9007199254740993 9007199254740992"), vec![
+            "SuspiciousLiteral(\"010\", \"is 10, not octal (which would be 0o10)\")",
+            "SuspiciousLiteral(\"_007\", \"is 7, not octal (which would be 0o7)\")",
+            "SuspiciousLiteral(\"9007199254740993\", \
+                \"is too big to be exact as a float, it's really 9007199254740992\")",
+        ]);
+    }
+
+    #[test]
+    fn test_underflows() {
+        let mut p = pesc();
+        let (toks, spans) = p.parse_code_spans("[dup] * + [foo]").unwrap();
+        let kinds = |w: Vec<PescWarning>| w.iter()
+            .map(|w| format!("{:?}", w.kind))
+            .collect::<Vec<String>>();
+
+        assert!(underflows(&p, &toks, &spans, 2).is_empty());
+        assert_eq!(kinds(underflows(&p, &toks, &spans, 1)),
+            vec!["StackUnderflow(\"add\", 2, 1)"]);
+
+        // a function with no declared arity could leave anything
+        p.register("foo", |_| Ok(()));
+        let (toks, spans) = p.parse_code_spans("[foo] +").unwrap();
+        assert!(underflows(&p, &toks, &spans, 0).is_empty());
+
+        p.declare("foo", 0, 0);
+        assert_eq!(underflows(&p, &toks, &spans, 1).len(), 1);
+    }
+}
@@ -1,41 +1,375 @@
 use std::rc::Rc;
+use std::cmp::Ordering;
 use std::fmt::{self, Display};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::time::{Duration, Instant};
+use crate::bigint::BigInt;
+use crate::decimal::Decimal;
+use crate::ratio::Ratio;
 use crate::errors::*;
 
 const BOOLEAN_TRUE:  char = 'T';
 const BOOLEAN_FALSE: char = 'F';
+// the largest integer n for which an f64 can hold n and n + 1
+// exactly, i.e. 2^53 - 1. beyond this, results may be rounded.
+pub(crate) const MAX_EXACT_INT: usize = (1 << 53) - 1;
+
+const BOOLEAN_TRUE_WORD:  &str = "true";
+const BOOLEAN_FALSE_WORD: &str = "false";
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum PescToken {
     Str(String),
     Number(PescNumber),
+
+    // a whole number, exactly (see Precision::Exact)
+    Int(BigInt),
+
+    // a fraction, exactly (see Precision::Decimal)
+    Decimal(Rc<Decimal>),
+
+    // a fraction, exactly, e.g. (1/3) (see ratio.rs)
+    Frac(Rc<Ratio>),
+
     Func(String),
-    Macro(Vec<PescToken>),
+    Macro(Rc<Vec<PescToken>>),
+
+    // a list of values, e.g. '(1 2 3), which (unlike a macro)
+    // is never run
+    List(Rc<Vec<PescToken>>),
+
+    // values kept under (string) keys, e.g. '{"a" 1 "b" 2}
+    Map(Rc<BTreeMap<String, PescToken>>),
+
     Symbol(char),
+
+    // an operator of more than one character, e.g. "**", which
+    // the parser only knows about once it's in Pesc::multi_ops
+    Operator(String),
+
     Bool(bool),
+
+    // a symbol that isn't bound to any operator,
+    // kept around by ParseMode::Lenient
+    Unknown(char),
+
+    // the absence of a value (e.g. the result of 1 0/
+    // with DivZeroMode::Nil)
+    Nil,
+}
+
+impl PescToken {
+    // the value of a number of any kind, as a float
+    pub fn as_number(&self) -> Option<PescNumber> {
+        match self {
+            PescToken::Number(n) => Some(*n),
+            PescToken::Int(n) => Some(n.to_f64()),
+            PescToken::Decimal(d) => Some(d.to_f64()),
+            PescToken::Frac(r) => Some(r.to_f64()),
+            _ => None,
+        }
+    }
 }
 
 impl Display for PescToken {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
         match self {
-            PescToken::Macro(m) => write!(f, "<mac {:p}>", m),
+            PescToken::Macro(m) => write!(f, "<mac {:p}>", Rc::as_ptr(m)),
+            PescToken::List(l) => write!(f, "({})", l.iter()
+                .map(|t| t.to_string()).collect::<Vec<String>>().join(" ")),
+            PescToken::Map(m) => write!(f, "{{{}}}", m.iter()
+                .map(|(k, v)| format!("{:?}: {}", k, v)).collect::<Vec<String>>().join(", ")),
             PescToken::Symbol(y) => write!(f, "<sym '{}'>", y),
+            PescToken::Operator(o) => write!(f, "<op '{}'>", o),
             PescToken::Str(s) => write!(f, "{:?}", s),
             PescToken::Number(n) => write!(f, "{}", n),
+            PescToken::Int(n) => write!(f, "{}", n),
+            PescToken::Decimal(d) => write!(f, "{}", d),
+            PescToken::Frac(r) => write!(f, "{}", r),
             PescToken::Func(s) => write!(f, "<fn {}>", s),
             PescToken::Bool(b) => write!(f, "({})", b),
+            PescToken::Unknown(y) => write!(f, "<unk '{}'>", y),
+            PescToken::Nil => write!(f, "nil"),
+        }
+    }
+}
+
+// what a function does, for people (see Pesc::document())
+#[derive(Clone, Debug, PartialEq)]
+pub struct PescDoc {
+    // what it takes from the stack and what it leaves there, the
+    // top last, e.g. "a b -- a+b"
+    pub signature: String,
+    pub description: String,
+}
+
+impl Display for PescDoc {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "( {} ) {}", self.signature, self.description)
+    }
+}
+
+// where a token was found in the input and, for macros,
+// where each token in its body was found
+#[derive(Clone, Debug, PartialEq)]
+pub struct PescSpans {
+    pub span: Span,
+    pub inner: Vec<PescSpans>,
+}
+
+// finds where a token being evaluated came from, by looking up
+// its address in the blocks of tokens the map was made from. it's
+// only meaningful while those tokens are still around (and so, as
+// macros share their bodies, while they're being evaluated).
+#[derive(Clone, Debug, Default)]
+pub struct SpanMap {
+    // (address, length, spans) of each block
+    blocks: Vec<(usize, usize, Vec<Span>)>,
+}
+
+impl SpanMap {
+    pub fn new(toks: &[PescToken], spans: &[PescSpans]) -> Self {
+        let mut map = Self::default();
+        map.add(toks, spans);
+        map
+    }
+
+    fn add(&mut self, toks: &[PescToken], spans: &[PescSpans]) {
+        self.blocks.push((toks.as_ptr() as usize, toks.len(),
+            spans.iter().map(|s| s.span).collect()));
+
+        for (t, s) in toks.iter().zip(spans) {
+            if let PescToken::Macro(m) = t {
+                self.add(m, &s.inner);
+            }
         }
     }
+
+    pub fn find(&self, tok: &PescToken) -> Option<Span> {
+        let addr = tok as *const PescToken as usize;
+        let size = std::mem::size_of::<PescToken>();
+
+        self.blocks.iter()
+            .filter(|(base, len, _)| addr >= *base && addr < base + len * size)
+            .find_map(|(base, _, spans)| spans.get((addr - base) / size).copied())
+    }
 }
 
 pub type PescNumber = f64;
 pub type PescFunc = dyn Fn(&mut Pesc) -> Result<(), PescErrorType>;
 
+// things that happen during evaluation, reported to Pesc::hooks
+#[derive(Clone, Debug)]
+pub enum PescEvent<'a> {
+    // about to evaluate a token, and done evaluating it (only
+    // if that worked)
+    Token(&'a PescToken),
+    Evaluated(&'a PescToken),
+
+    // the `break` word was run
+    Break,
+
+    // about to call a function, and done calling it (whether
+    // it worked or not)
+    Call(&'a str),
+    Return(&'a str),
+}
+
+// returning an error from a hook stops the evaluation
+pub type PescHook = dyn FnMut(&mut Pesc, PescEvent) -> Result<(), PescErrorType>;
+
+// what to do with operators and functions that don't exist
+// when parsing with Pesc::parse_code()
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ParseMode {
+    // turn unknown operators into PescToken::Unknown, with a warning
+    Lenient,
+
+    // don't check anything, leave it to evaluation
+    Normal,
+
+    // reject unknown operators and functions right away
+    Strict,
+}
+
+// what dividing (or taking the modulus) by zero does
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DivZeroMode {
+    // follow IEEE 754, i.e. push inf, -inf or NaN
+    Ieee,
+
+    // fail with PescErrorType::DivideByZero
+    Error,
+
+    // push PescToken::Nil
+    Nil,
+}
+
+// how comparisons (gt?, lt?, lte, gte, eq?, min, max, clamp,
+// and sort) treat NaN. comparisons that don't involve NaN
+// aren't affected.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum NanMode {
+    // fail with PescErrorType::NaNComparison
+    Error,
+
+    // the result is unknown: comparison words push nil, min/max/clamp
+    // push NaN, and sort moves NaNs to the top of the stack
+    Propagate,
+
+    // order numbers with f64::total_cmp, where NaN is greater
+    // than inf (and -NaN less than -inf), and NaN equals NaN
+    Total,
+}
+
+// how numbers are kept
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Precision {
+    // as f64s
+    Float,
+
+    // whole numbers are big integers (PescToken::Int), which stay
+    // exact through add, sub, mul, mod, pow, abs and fact, and div,
+    // which makes fractions (PescToken::Frac) when it doesn't come out
+    // even. anything else makes floats of them.
+    Exact,
+
+    // like Exact, but numbers with a point are decimals (see
+    // decimal.rs), which stay exact through the same words, and div
+    Decimal,
+}
+
+#[derive(Clone, Debug)]
+pub struct PescOptions {
+    // treat every 'T' and 'F' as a boolean, even when it's
+    // glued to other letters (e.g. "TF" is two booleans).
+    // when unset, booleans must be standalone words: "T",
+    // "F", "true", or "false".
+    pub legacy_bools: bool,
+
+    // how deeply macros may be nested while evaluating
+    // before we give up, instead of overflowing the stack.
+    pub max_depth: usize,
+
+    // how many times times, while and until may go around
+    // before we decide the loop is never going to end
+    pub max_loops: usize,
+
+    // how many tokens one eval() may evaluate (macros' tokens
+    // included), and for how long, before it gives up. neither
+    // is limited by default.
+    pub max_steps: Option<u64>,
+    pub timeout: Option<Duration>,
+
+    // how many values the stack may hold, if there's a limit
+    pub max_stack: Option<usize>,
+
+    pub parse_mode: ParseMode,
+    pub div_zero: DivZeroMode,
+    pub nan_mode: NanMode,
+
+    // when a function fails, leave the stack as the function left
+    // it rather than restoring it to how it was before the call
+    pub keep_on_error: bool,
+
+    // treat [DUP] and [dup] (and definitions thereof) as the same word
+    pub ignore_case: bool,
+
+    // make integer operations (and arithmetic on whole numbers) fail
+    // when the result can't be represented exactly, rather than
+    // wrapping around or silently losing precision
+    pub checked_ints: bool,
+
+    pub precision: Precision,
+}
+
+impl Default for PescOptions {
+    fn default() -> Self {
+        Self {
+            legacy_bools: false,
+            max_depth: 256,
+            max_loops: 1_000_000,
+            max_steps: None,
+            timeout: None,
+            max_stack: None,
+            parse_mode: ParseMode::Normal,
+            div_zero: DivZeroMode::Error,
+            nan_mode: NanMode::Propagate,
+            keep_on_error: false,
+            ignore_case: true,
+            checked_ints: false,
+            precision: Precision::Float,
+        }
+    }
+}
+
+impl PescOptions {
+    // the name a function is stored under
+    pub fn func_name(&self, name: &str) -> String {
+        if self.ignore_case {
+            name.to_lowercase()
+        } else {
+            String::from(name)
+        }
+    }
+}
+
 pub struct Pesc {
     pub stack: Vec<PescToken>,
     pub funcs: HashMap<String, Rc<Box<PescFunc>>>,
     pub ops: HashMap<char, String>,
+
+    // operators of more than one character, matched by the
+    // parser before those of one (see load_op())
+    pub multi_ops: HashMap<String, String>,
+
+    // the bodies of functions defined with `def`
+    pub defs: HashMap<String, Rc<Vec<PescToken>>>,
+
+    // what functions do, for those that say (see document())
+    pub docs: HashMap<String, PescDoc>,
+
+    // how many values functions take from the stack and leave on it,
+    // for those where that doesn't depend on the values (see declare())
+    pub arity: HashMap<String, (usize, usize)>,
+
+    // values kept with `store`, to be brought back with `load`
+    pub vars: HashMap<String, PescToken>,
+
+    // namespaces brought in with `import`, whose functions can be
+    // called without the namespace (e.g. [sin] for [math.sin]),
+    // the last one first
+    pub imports: Vec<String>,
+
+    // the files being run by include(), the innermost last
+    pub including: Vec<String>,
+
+    pub opts: PescOptions,
+    pub warnings: Vec<PescWarning>,
+
+    // called on every PescEvent, in order, e.g. for debugging
+    // or profiling. hooks aren't called again while they're
+    // already running.
+    pub hooks: Vec<Box<PescHook>>,
+
+    // where the tokens being run came from, if that's known, so
+    // that errors can say where they happened (see run())
+    pub spans: SpanMap,
+
+    // where the innermost token that failed was, on the way out
+    failed: Option<Span>,
+
+    // current macro nesting depth
+    depth: usize,
+
+    // (tokens evaluated, when to give up) in the outermost eval()
+    budget: Option<(u64, Option<Instant>)>,
+}
+
+impl Default for Pesc {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Pesc {
@@ -44,7 +378,55 @@ impl Pesc {
             stack: Vec::new(),
             funcs: HashMap::new(),
             ops: HashMap::new(),
+            multi_ops: HashMap::new(),
+            defs: HashMap::new(),
+            docs: HashMap::new(),
+            arity: HashMap::new(),
+            vars: HashMap::new(),
+            imports: Vec::new(),
+            including: Vec::new(),
+            opts: PescOptions::default(),
+            warnings: Vec::new(),
+            hooks: Vec::new(),
+            spans: SpanMap::default(),
+            failed: None,
+            depth: 0,
+            budget: None,
+        }
+    }
+
+    // an interpreter with the standard and extended libraries, which
+    // is usually the place to start when embedding pesc
+    pub fn with_stdlib() -> Self {
+        let mut pesc = Pesc::new();
+        for func in crate::stdlib::standard().into_iter().chain(crate::stdlib::extended()) {
+            pesc.load_def(func);
+        }
+
+        for (name, signature, description) in crate::stdlib::docs() {
+            pesc.document(name, signature, description);
         }
+
+        pesc
+    }
+
+    // parse and evaluate some code, e.g. pesc.run("2 3+"). errors
+    // have the span of the token that failed.
+    pub fn run(&mut self, code: &str) -> Result<(), PescError> {
+        let (toks, spans) = self.parse_code_spans(code)?;
+
+        self.spans = SpanMap::new(&toks, &spans);
+        let res = self.eval(&toks).map_err(|(_, e)| e);
+        self.spans = SpanMap::default();
+        res
+    }
+
+    // load() without the boxing, for functions without operators
+    pub fn register<F>(&mut self, name: &str, func: F)
+    where
+        F: Fn(&mut Pesc) -> Result<(), PescErrorType> + 'static
+    {
+        self.load(None, name, Rc::new(Box::new(func)));
     }
 
     pub fn load(&mut self, op: Option<char>, fnname: &str,
@@ -59,31 +441,268 @@ impl Pesc {
             self.ops.insert(o, String::from(fnname));
         }
 
-        let s_fnname = String::from(fnname);
+        self.funcs.entry(String::from(fnname)).or_insert(func);
+    }
+
+    // load() a builtin, declaring how many values it takes and leaves
+    pub fn load_def(&mut self, (op, fnname, func, arity): crate::stdlib::PescFuncDef) {
+        self.load(op, fnname, func);
+        if let Some((takes, leaves)) = arity {
+            self.declare(fnname, takes, leaves);
+        }
+    }
+
+    // load_def() along with what the function does
+    pub fn load_documented(&mut self, op: Option<char>, fnname: &str,
+        func: Rc<Box<PescFunc>>, arity: Option<(usize, usize)>,
+        signature: &str, description: &str)
+    {
+        self.load_def((op, fnname, func, arity));
+        self.document(fnname, signature, description);
+    }
+
+    // say what a function does, for :doc and [help], e.g.
+    // pesc.document("add", "a b -- a+b", "adds a and b.")
+    pub fn document(&mut self, fnname: &str, signature: &str, description: &str) {
+        self.docs.insert(String::from(fnname), PescDoc {
+            signature: String::from(signature),
+            description: String::from(description),
+        });
+    }
+
+    // say how many values a function always takes from the stack and
+    // leaves on it, so that lint::underflows() can see it coming, e.g.
+    // pesc.declare("add", 2, 1)
+    pub fn declare(&mut self, fnname: &str, takes: usize, leaves: usize) {
+        self.arity.insert(String::from(fnname), (takes, leaves));
+    }
+
+    // the function a name or an operator (e.g. "+" or "**") means
+    pub fn named(&self, name: &str) -> String {
+        let mut chars = name.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) if self.ops.contains_key(&c) => self.ops[&c].clone(),
+            _ if self.multi_ops.contains_key(name) => self.multi_ops[name].clone(),
+            _ => self.resolve(&self.opts.func_name(name)),
+        }
+    }
+
+    // load functions into a namespace, e.g. "math" for [math.sin]
+    pub fn load_module(&mut self, ns: &str, funcs: Vec<crate::stdlib::PescFuncDef>) {
+        for (op, name, func, arity) in funcs {
+            self.load_def((op, &format!("{}.{}", ns, name), func, arity));
+        }
+    }
+
+    // the function a name means: the one with that name, or else
+    // the one with that name in the last namespace imported that
+    // has one (if there isn't one, the name as it is)
+    pub fn resolve(&self, name: &str) -> String {
+        if self.funcs.contains_key(name) {
+            return String::from(name);
+        }
+
+        self.imports.iter().rev()
+            .map(|ns| format!("{}.{}", ns, name))
+            .find(|f| self.funcs.contains_key(f))
+            .unwrap_or_else(|| String::from(name))
+    }
+
+    // bring a namespace's functions into scope, warning about the
+    // names that now mean something else than they could
+    pub fn import(&mut self, ns: &str) -> Result<(), PescErrorType> {
+        let prefix = format!("{}.", ns);
+        let mut names = self.funcs.keys()
+            .filter_map(|f| f.strip_prefix(&prefix))
+            .map(String::from)
+            .collect::<Vec<String>>();
+        names.sort_unstable();
+
+        if names.is_empty() {
+            return Err(PescErrorType::UnknownNamespace(String::from(ns)));
+        }
+
+        self.imports.retain(|i| i != ns);
+        for name in names {
+            let full = format!("{}{}", prefix, name);
+            match self.resolve(&name) {
+                // (a function with just that name wins)
+                f if f == name && self.funcs.contains_key(&f) =>
+                    self.warn(PescWarningType::NameCollision(name, full)),
+                f if f != name => self.warn(PescWarningType::NameCollision(name, f)),
+                _ => (),
+            }
+        }
+
+        self.imports.push(String::from(ns));
+        Ok(())
+    }
 
-        if !self.funcs.contains_key(&s_fnname) {
-            self.funcs.insert(s_fnname, func);
+    // run the code of a file called `name` (which reading files is
+    // left to the caller), e.g. for an [include] word. errors say
+    // where in the file they happened, and a file can't include
+    // itself, however indirectly.
+    pub fn include(&mut self, name: &str, code: &str) -> Result<(), PescErrorType> {
+        if self.including.iter().any(|f| f == name) {
+            return Err(PescErrorType::IncludeCycle(String::from(name)));
         }
+
+        let at = |s: Option<Span>| s.map(|s| s.line_col(code));
+        let wrap = |s, e| match e {
+            PescErrorType::Aborted => e,
+            e => PescErrorType::Included(String::from(name), at(s), Box::new(e)),
+        };
+
+        let (toks, spans) = self.parse_code_spans(code).map_err(|e| wrap(e.span, e.kind))?;
+
+        let outer = std::mem::replace(&mut self.spans, SpanMap::new(&toks, &spans));
+        self.including.push(String::from(name));
+        let res = self.eval(&toks).map_err(|(_, e)| e);
+
+        // (deeper in, the span is left for the outermost eval())
+        let failed = self.failed.take();
+        self.including.pop();
+        self.spans = outer;
+
+        res.map_err(|e| wrap(e.span.or(failed), e.kind))
+    }
+
+    // make a sequence of operator characters, e.g. "<=", run a
+    // function. where sequences overlap, the longest one wins, and a
+    // single-character operator only runs when no sequence matches.
+    pub fn load_op(&mut self, op: &str, fnname: &str) {
+        assert!(op.chars().count() > 1,
+            "cannot add operator {:?}: use load() for one character", op);
+        assert!(op.chars().all(operator_char),
+            "cannot add operator {:?}: it has characters that aren't operators", op);
+
+        self.multi_ops.insert(String::from(op), String::from(fnname));
+    }
+
+    // the operators that run a function, sorted
+    pub fn operators(&self, fnname: &str) -> Vec<String> {
+        let mut ops = self.ops.iter()
+            .filter(|(_, f)| *f == fnname)
+            .map(|(o, _)| o.to_string())
+            .chain(self.multi_ops.iter().filter(|(_, f)| *f == fnname).map(|(o, _)| o.clone()))
+            .collect::<Vec<String>>();
+        ops.sort_unstable();
+        ops
     }
 
     pub fn eval(&mut self, code: &[PescToken])
         -> Result<(), (Vec<PescToken>, PescError)>
     {
+        // (evals inside this one share its budget)
+        let outer = self.budget.is_none();
+        if outer {
+            self.budget = Some((0, self.opts.timeout.map(|t| Instant::now() + t)));
+        }
+
+        let mut res = Ok(());
         for t in code {
-            match t {
-                PescToken::Symbol(_)
-                | PescToken::Func(_) => {
-                    match self.exec(t.clone()) {
-                        Ok(()) => (),
-                        Err((b, e)) => return Err((b,
-                            PescError::new(None, Some(t.clone()), e))),
-                    };
+            match self.step(t) {
+                // (an error that was dealt with doesn't count)
+                Ok(()) => self.failed = None,
+                Err((b, e)) => {
+                    res = Err((b, self.failure(t, e)));
+                    break;
                 },
-                _ => self.stack.push(t.clone()),
             }
         }
 
-        Ok(())
+        if outer {
+            self.budget = None;
+        }
+
+        res
+    }
+
+    // the error for a token that failed. the innermost token that
+    // failed is found first, but only the outermost eval() says
+    // where it is.
+    #[cold]
+    #[inline(never)]
+    fn failure(&mut self, t: &PescToken, e: PescErrorType) -> PescError {
+        self.failed = self.failed.or_else(|| self.spans.find(t));
+        let span = if self.depth == 0 { self.failed.take() } else { None };
+        PescError::new(span, Some(t.clone()), e)
+    }
+
+    // evaluate a single token, letting the hooks know
+    fn step(&mut self, t: &PescToken)
+        -> Result<(), (Vec<PescToken>, PescErrorType)>
+    {
+        self.start(t).map_err(|e| (self.stack.clone(), e))?;
+
+        match t {
+            PescToken::Symbol(_)
+            | PescToken::Operator(_)
+            | PescToken::Unknown(_)
+            | PescToken::Func(_) => self.exec(t.clone())?,
+            _ => self.stack.push(t.clone()),
+        }
+
+        self.finish(t)
+    }
+
+    // what comes before and after the token itself in step(). these
+    // are kept apart from it (as call() and expand() are from exec())
+    // so that the functions that macros recurse through take as
+    // little of the stack as they can.
+    fn start(&mut self, t: &PescToken) -> Result<(), PescErrorType> {
+        self.spend()?;
+        self.emit(PescEvent::Token(t))
+    }
+
+    fn finish(&mut self, t: &PescToken)
+        -> Result<(), (Vec<PescToken>, PescErrorType)>
+    {
+        // (leaving the stack as full as it may be)
+        if let Some(max) = self.opts.max_stack.filter(|m| self.stack.len() > *m) {
+            let badstack = self.stack.clone();
+            self.stack.truncate(max);
+            return Err((badstack, PescErrorType::StackLimitExceeded(max)));
+        }
+
+        self.emit(PescEvent::Evaluated(t)).map_err(|e| (self.stack.clone(), e))
+    }
+
+    // count a step against the budget
+    fn spend(&mut self) -> Result<(), PescErrorType> {
+        let (steps, deadline) = match &mut self.budget {
+            Some(b) => b,
+            None => return Ok(()),
+        };
+
+        *steps += 1;
+        match (self.opts.max_steps, deadline) {
+            (Some(max), _) if *steps > max =>
+                Err(PescErrorType::BudgetExhausted(format!("{} steps", max))),
+            (_, Some(d)) if Instant::now() >= *d =>
+                Err(PescErrorType::BudgetExhausted(format!("{:?}", self.opts.timeout.unwrap_or_default()))),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn emit(&mut self, ev: PescEvent) -> Result<(), PescErrorType> {
+        if self.hooks.is_empty() {
+            return Ok(());
+        }
+
+        let mut hooks = std::mem::take(&mut self.hooks);
+        let res = hooks.iter_mut()
+            .try_for_each(|hook| hook(self, ev.clone()));
+
+        // keep any hooks that were added in the meantime
+        hooks.append(&mut self.hooks);
+        self.hooks = hooks;
+        res
+    }
+
+    // how many macros deep the evaluation currently is
+    pub fn depth(&self) -> usize {
+        self.depth
     }
 
     pub fn try_exec(&mut self, tok: PescToken) -> Result<(), PescErrorType> {
@@ -93,188 +712,354 @@ impl Pesc {
         }
     }
 
+    // the function an operator runs
+    fn operator(&self, tok: &PescToken) -> Result<String, PescErrorType> {
+        let f = match tok {
+            PescToken::Symbol(o) => self.ops.get(o),
+            PescToken::Operator(o) => self.multi_ops.get(o),
+            _ => None,
+        };
+
+        f.cloned().ok_or_else(|| PescErrorType::UnknownFunction(format!("'{}'", unparse(std::slice::from_ref(tok)))))
+    }
+
     fn exec(&mut self, tok: PescToken)
         -> Result<(), (Vec<PescToken>, PescErrorType)>
     {
         match tok {
-            PescToken::Symbol(o) => {
-                if !self.ops.contains_key(&o) {
-                    return Err((self.stack.clone(),
-                        PescErrorType::UnknownFunction(format!("'{}'", o))));
-                }
-
-                self.exec(PescToken::Func(self.ops[&o].clone()))
+            PescToken::Unknown(o) => Err((self.stack.clone(),
+                PescErrorType::UnknownFunction(format!("'{}'", o)))),
+            PescToken::Symbol(_) | PescToken::Operator(_) => match self.operator(&tok) {
+                Ok(f) => self.call(&f),
+                Err(e) => Err((self.stack.clone(), e)),
             },
-            PescToken::Func(_func) => {
-                let func = _func.to_lowercase();
-                if !self.funcs.contains_key(&func) {
-                    return Err((self.stack.clone(),
-                        PescErrorType::UnknownFunction(func)));
-                }
+            PescToken::Func(f) => self.call(&f),
+            PescToken::Macro(mac) => self.expand(&mac),
+            _ => Err((self.stack.clone(), PescErrorType::InvalidArgumentType(
+                String::from("macro/function"), tok.to_string())))
+        }
+    }
 
-                let backup = self.stack.clone();
-                match (&self.funcs.clone()[&func])(self) {
-                    Ok(()) => Ok(()),
-                    Err(e) => {
-                        let badstack = self.stack.clone();
-                        self.stack = backup;
-                        Err((badstack, e))
-                    },
+    #[inline(never)]
+    fn call(&mut self, name: &str)
+        -> Result<(), (Vec<PescToken>, PescErrorType)>
+    {
+        let func = self.resolve(&self.opts.func_name(name));
+        if !self.funcs.contains_key(&func) {
+            // a common mistake when case matters
+            let lower = func.to_lowercase();
+            if self.funcs.contains_key(&lower) {
+                return Err((self.stack.clone(),
+                    PescErrorType::WrongCase(func, lower)));
+            }
+
+            return Err((self.stack.clone(),
+                PescErrorType::UnknownFunction(func)));
+        }
+
+        if let Err(e) = self.emit(PescEvent::Call(&func)) {
+            return Err((self.stack.clone(), e));
+        }
+
+        let backup = self.stack.clone();
+        let res = (self.funcs.clone()[&func])(self);
+        let res = res.and(self.emit(PescEvent::Return(&func)));
+
+        match res {
+            Ok(()) => Ok(()),
+            Err(e) => {
+                let badstack = self.stack.clone();
+                if !self.opts.keep_on_error {
+                    self.stack = backup;
                 }
+                Err((badstack, e))
             },
-            PescToken::Macro(mac) => match self.eval(&mac) {
-                Ok(()) => Ok(()),
-                Err((b, e)) => Err((b, e.kind)),
-            },
-            _ => Err((self.stack.clone(), PescErrorType::InvalidArgumentType(
-                String::from("macro/function"), tok.to_string())))
+        }
+    }
+
+    #[inline(never)]
+    fn expand(&mut self, mac: &[PescToken])
+        -> Result<(), (Vec<PescToken>, PescErrorType)>
+    {
+        if self.depth >= self.opts.max_depth {
+            return Err((self.stack.clone(),
+                PescErrorType::RecursionLimitExceeded(self.opts.max_depth)));
+        }
+
+        self.depth += 1;
+        let res = self.eval(mac);
+        self.depth -= 1;
+
+        match res {
+            Ok(()) => Ok(()),
+            Err((b, e)) => Err((b, e.kind)),
         }
     }
 
     // TODO: cleanup, remove duplicated code
     // here be atrocious code
+    //
+    // returns how many characters were read (all of them, now that a
+    // stray '}' is an error) along with the tokens.
     pub fn parse(input: &str) -> Result<(usize, Vec<PescToken>), PescError> {
-        let mut toks = Vec::new();
+        Pesc::parse_with(input, &PescOptions::default())
+    }
+
+    pub fn parse_with(input: &str, opts: &PescOptions)
+        -> Result<(usize, Vec<PescToken>), PescError>
+    {
+        let toks = Pesc::parse_input(input, opts, None)?.0;
+        Ok((input.chars().count(), toks))
+    }
 
+    // like parse_with(), but also return where each token came from
+    pub fn parse_spans(input: &str, opts: &PescOptions)
+        -> Result<(Vec<PescToken>, Vec<PescSpans>), PescError>
+    {
+        let (toks, spans, _) = Pesc::parse_input(input, opts, None)?;
+        Ok((toks, spans))
+    }
+
+    // parse with this interpreter's options, checking symbols and
+    // function names against the ones that are loaded (see ParseMode).
+    // any warnings are added to self.warnings.
+    pub fn parse_code(&mut self, input: &str)
+        -> Result<Vec<PescToken>, PescError>
+    {
+        Ok(self.parse_code_spans(input)?.0)
+    }
+
+    // like parse_code(), but also return where each token came from
+    pub fn parse_code_spans(&mut self, input: &str)
+        -> Result<(Vec<PescToken>, Vec<PescSpans>), PescError>
+    {
+        let (toks, spans, warnings) = Pesc::parse_input(input, &self.opts, Some(self))?;
+        self.warnings.extend(warnings);
+        Ok((toks, spans))
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn parse_input(input: &str, opts: &PescOptions, pesc: Option<&Pesc>)
+        -> Result<(Vec<PescToken>, Vec<PescSpans>, Vec<PescWarning>), PescError>
+    {
         let chs = input.chars()
             .collect::<Vec<char>>();
-        let mut i = 0;
-
-        // chomp the chars, placing them in a buffer.
-        // return (String, usize, bool) when until() -> true or
-        // when it reaches the end of the chars
-        //
-        //     String = the buffer,
-        //     usize  = the end index,
-        //     bool   = did we reach the end of the data
-        //              without having until() return true?
-        //
-        fn chomp<F>(ch: &[char], mut c: usize, until: F)
-            -> (String, usize, bool)
-        where
-            F: Fn(char) -> bool
-        {
-            let mut buf = String::new();
-            let early_return;
 
-            loop {
-                if c >= ch.len() {
-                    early_return = true;
-                    break;
-                }
+        // we walk the input char-by-char, but positions reported
+        // in errors are byte offsets into the input; keep a table
+        // mapping the former to the latter.
+        let offs = input.char_indices()
+            .map(|(o, _)| o)
+            .chain(std::iter::once(input.len()))
+            .collect::<Vec<usize>>();
 
-                if until(ch[c]) == true {
-                    early_return = false;
-                    break;
-                }
+        let mut st = ParseState {
+            chs: &chs, offs: &offs, opts, pesc,
+            warnings: Vec::new(),
+            calls: Vec::new(),
+            defined: Vec::new(),
+        };
 
-                buf += &format!("{}", ch[c]);
-                c += 1;
-            }
+        let (end, toks, spans) = Pesc::parse_block(&mut st, 0, '}')?;
 
-            (buf, c, early_return)
+        // parse_block() only stops early when it finds a '}',
+        // and there's no macro for it to close here
+        if end < chs.len() {
+            return Err(PescError::new(Some(Span::new(offs[end], offs[end + 1])),
+                None, PescErrorType::UnexpectedToken('}')));
         }
 
+        // now that we've seen every definition in the input,
+        // check that all the functions called exist
+        if let (ParseMode::Strict, Some(p)) = (opts.parse_mode, pesc) {
+            for (name, sp) in &st.calls {
+                let name = opts.func_name(name);
+                if !p.funcs.contains_key(&p.resolve(&name)) && !st.defined.contains(&name) {
+                    return Err(PescError::new(Some(*sp), None,
+                        PescErrorType::UnknownFunction(name)));
+                }
+            }
+        }
+
+        Ok((toks, spans, st.warnings))
+    }
+
+    // parse tokens starting from chs[i] until the end of the input
+    // or until the `close` ('}' or ')') of the current macro or list,
+    // returning the index where we stopped along with the tokens and
+    // their spans.
+    #[allow(clippy::type_complexity)]
+    fn parse_block(st: &mut ParseState, mut i: usize, close: char)
+        -> Result<(usize, Vec<PescToken>, Vec<PescSpans>), PescError>
+    {
+        let (chs, offs, opts) = (st.chs, st.offs, st.opts);
+
+        let mut toks = Vec::new();
+        let mut spans = Vec::new();
+        let span = |start: usize, end: usize| Some(Span::new(offs[start], offs[end]));
+
         while i < chs.len() {
+            let (start, count) = (i, toks.len());
+            let mut inner = Vec::new();
+
             match chs[i] {
                 // integer literals
-                _ if chs[i].is_numeric() || chs[i] == '.'
-                                         || chs[i] == '_' => {
-                    let mut sign = 1_f64;
+                _ if chs[i].is_ascii_digit() || chs[i] == '.'
+                                         || chs[i] == '_' || minus_literal(chs, i) => {
+                    let sign = matches!(chs[i], '_' | '-') as usize;
+
+                    // (hex, binary and octal literals have letters in them)
+                    let at = |j: usize| chs.get(i + j + sign).copied();
+                    let radix = at(0) == Some('0') && matches!(at(1), Some('x' | 'b' | 'o'));
 
-                    let n = chomp(&chs, i, |c| {
-                        !c.is_digit(10) && c != '_' && c != '.'
+                    let mut n = chomp(chs, i + sign, |c| match radix {
+                        true => !c.is_ascii_alphanumeric() && c != '_',
+                        false => !c.is_ascii_digit() && c != '_' && c != '.',
                     });
-                    i = n.1;
+                    if sign == 1 {
+                        n.0.insert(0, chs[i]);
+                    }
 
-                    if n.0.chars().nth(0).unwrap() == '_' {
-                        sign = -1_f64;
+                    // an exponent, e.g. 1.5e-3 (where otherwise the 'e'
+                    // would be [e])
+                    if let Some(end) = exponent_end(chs, n.1).filter(|_| !radix) {
+                        n.0.extend(&chs[n.1..end]);
+                        n.1 = end;
                     }
 
-                    let num = match n.0.replace("_", "").parse::<PescNumber>() {
-                        Ok(o) => o,
-                        Err(_) => return Err(PescError::new(Some(i), None,
-                            PescErrorType::InvalidNumberLit(n.0)))
+                    let tok = match number_literal(&n.0, true, opts) {
+                        Ok(t) => t,
+                        Err((s, e, problem)) => return Err(PescError::new(
+                            span(i + s, i + e), None,
+                            PescErrorType::InvalidNumberLit(n.0, problem)))
                     };
 
-                    toks.push(PescToken::Number(num * sign));
+                    i = n.1;
+                    toks.push(tok);
                 },
 
                 '(' => {
-                    let mut sign = 1_f64;
-
-                    let n = chomp(&chs, i + 1, |c| c == ')');
-                    i = n.1 + 1;
+                    let n = chomp(chs, i + 1, |c| c == ')');
 
                     if n.2 {
                         // we hit the end of the data
                         // without finding a matching bracket
-                        return Err(PescError::new(Some(i), None,
-                            PescErrorType::UnmatchedToken('(')));
+                        return Err(PescError::new(span(i, i + 1),
+                            None, PescErrorType::UnmatchedToken('(')));
                     }
 
-                    if n.0.len() == 0 {
-                        return Err(PescError::new(Some(i), None,
-                            PescErrorType::EmptyLiteral));
-                    } else {
-                        if n.0.chars().nth(0).unwrap() == '_' {
-                            // we don't need to check for a '-' prefix,
-                            // as the parse::<f64>() function will detect
-                            // that for us
-                            sign = -1_f64;
-                        }
+                    if n.0.is_empty() {
+                        return Err(PescError::new(span(i, n.1 + 1),
+                            None, PescErrorType::EmptyLiteral));
                     }
 
-                    let num = match n.0.replace("_", "").parse::<PescNumber>() {
-                        Ok(o) => o,
-                        Err(_) => return Err(PescError::new(Some(i), None,
-                            PescErrorType::InvalidNumberLit(n.0)))
+                    let tok = match paren_token(&n.0, opts) {
+                        Ok(t) => t,
+                        Err((s, e, problem)) => return Err(PescError::new(
+                            span(i + 1 + s, i + 1 + e), None,
+                            PescErrorType::InvalidNumberLit(n.0, problem)))
                     };
 
-                    toks.push(PescToken::Number(num * sign));
+                    i = n.1 + 1;
+                    toks.push(tok);
                 },
 
                 // strings
                 '"' => {
-                    let s = chomp(&chs, i + 1, |c| c == '"');
-                    i = s.1 + 1;
+                    let s = chomp_quoted(chs, i + 1, '"');
 
                     if s.2 {
                         // we hit the end of the data
                         // without finding a matching quote
-                        return Err(PescError::new(Some(i), None,
-                            PescErrorType::UnmatchedToken('"')));
+                        return Err(PescError::new(span(i, i + 1),
+                            None, PescErrorType::UnmatchedToken('"')));
                     }
 
+                    i = s.1 + 1;
+
                     toks.push(PescToken::Str(s.0));
                 },
 
                 // functions
                 '[' => {
-                    let s = chomp(&chs, i + 1, |c| c == ']');
-                    i = s.1 + 1;
+                    let s = chomp_quoted(chs, i + 1, ']');
 
                     if s.2 {
                         // we hit the end of the data
                         // without finding a matching bracket
-                        return Err(PescError::new(Some(i), None,
-                            PescErrorType::UnmatchedToken('[')));
+                        return Err(PescError::new(span(i, i + 1),
+                            None, PescErrorType::UnmatchedToken('[')));
                     }
 
+                    // remember what's being defined and called, so
+                    // that we can check for unknown functions later
+                    if let Some(PescToken::Str(name)) = toks.last() {
+                        if s.0 == "def" {
+                            st.defined.push(opts.func_name(name));
+                        }
+                    }
+                    st.calls.push((s.0.clone(), Span::new(offs[i], offs[s.1 + 1])));
+
+                    i = s.1 + 1;
+
                     toks.push(PescToken::Func(s.0));
                 },
 
                 // macros
                 '{' => {
-                    let res = Pesc::parse(&input[i + 1..])?;
-                    toks.push(PescToken::Macro(res.1));
+                    let res = Pesc::parse_block(st, i + 1, '}')?;
+
+                    if res.0 >= chs.len() {
+                        // we hit the end of the data
+                        // without finding a matching brace
+                        return Err(PescError::new(span(i, i + 1),
+                            None, PescErrorType::UnmatchedToken('{')));
+                    }
+
+                    toks.push(PescToken::Macro(Rc::new(res.1)));
+                    inner = res.2;
 
                     // move pointer past matching '}', or we
                     // will exit prematurely (see next item)
-                    i += res.0 + 2;
+                    i = res.0 + 1;
+                },
+
+                '}' | ')' if chs[i] == close => return Ok((i, toks, spans)),
+
+                // (a '}' in a list, or a ']' or ')' that nothing opened)
+                '}' | ']' | ')' => return Err(PescError::new(span(i, i + 1),
+                    None, PescErrorType::UnexpectedToken(chs[i]))),
+
+                // lists
+                '\'' if chs.get(i + 1) == Some(&'(') => {
+                    let res = Pesc::parse_block(st, i + 2, ')')?;
+
+                    if res.0 >= chs.len() {
+                        return Err(PescError::new(span(i, i + 2),
+                            None, PescErrorType::UnmatchedToken('(')));
+                    }
+
+                    toks.push(PescToken::List(Rc::new(res.1)));
+                    inner = res.2;
+                    i = res.0 + 1;
                 },
 
-                '}' => return Ok((i, toks)),
+                // maps, which are parsed like a macro and then
+                // paired up into keys and values
+                '\'' if chs.get(i + 1) == Some(&'{') => {
+                    let res = Pesc::parse_block(st, i + 2, '}')?;
+
+                    if res.0 >= chs.len() {
+                        return Err(PescError::new(span(i, i + 2),
+                            None, PescErrorType::UnmatchedToken('{')));
+                    }
+
+                    let end = res.0 + 1;
+                    let map = map_literal(res.1)
+                        .map_err(|e| PescError::new(span(i, end), None, e))?;
+                    toks.push(PescToken::Map(Rc::new(map)));
+                    i = end;
+                },
 
                 // whitespace
                 '\n'
@@ -283,28 +1068,127 @@ impl Pesc {
 
                 // comments
                 '#' =>
-                    i = chomp(&chs, i + 1, |c| c == '\n' || c == '\\').1 + 1,
+                    i = chomp(chs, i + 1, |c| c == '\n' || c == '\\').1 + 1,
 
                 // boolean values
-                BOOLEAN_TRUE => {
+                BOOLEAN_TRUE if opts.legacy_bools => {
                     toks.push(PescToken::Bool(true));
                     i += 1;
                 },
 
-                BOOLEAN_FALSE => {
+                BOOLEAN_FALSE if opts.legacy_bools => {
                     toks.push(PescToken::Bool(false));
                     i += 1;
                 },
 
-                // treat unknown characters as symbols aka operators
-                _ => {
-                    toks.push(PescToken::Symbol(chs[i]));
-                    i += 1;
-                }
+                // words: either a boolean, or a run of
+                // single-letter operators
+                _ if chs[i].is_ascii_alphabetic() && !opts.legacy_bools => {
+                    let w = chomp(chs, i, |c| !c.is_ascii_alphabetic());
+                    i = w.1;
+
+                    match w.0.as_str() {
+                        BOOLEAN_TRUE_WORD => toks.push(PescToken::Bool(true)),
+                        BOOLEAN_FALSE_WORD => toks.push(PescToken::Bool(false)),
+                        _ if w.0 == BOOLEAN_TRUE.to_string() =>
+                            toks.push(PescToken::Bool(true)),
+                        _ if w.0 == BOOLEAN_FALSE.to_string() =>
+                            toks.push(PescToken::Bool(false)),
+                        _ => for (k, c) in w.0.chars().enumerate() {
+                            let at = w.1 - w.0.len() + k;
+                            toks.push(st.symbol(c, Span::new(offs[at], offs[at + 1]))?);
+                        },
+                    }
+                },
+
+                // treat unknown characters as symbols aka operators,
+                // unless they start an operator of more than one
+                _ => match st.multi_op(i) {
+                    Some(op) => {
+                        i += op.chars().count();
+                        toks.push(PescToken::Operator(String::from(op)));
+                    },
+                    None => {
+                        toks.push(st.symbol(chs[i], Span::new(offs[i], offs[i + 1]))?);
+                        i += 1;
+                    },
+                },
+            }
+
+            // a run of operators is one token per character,
+            // everything else is one token for all of it
+            let end = i.min(chs.len());
+            match toks.len() - count {
+                0 => (),
+                1 => spans.push(PescSpans {
+                    span: Span::new(offs[start], offs[end]), inner,
+                }),
+                n => spans.extend((start..start + n).map(|k| PescSpans {
+                    span: Span::new(offs[k], offs[k + 1]), inner: Vec::new(),
+                })),
             }
         }
 
-        Ok((i, toks))
+        Ok((i, toks, spans))
+    }
+
+    // the names of the functions defined with `def` (as opposed
+    // to the builtin ones), sorted
+    pub fn definitions(&self) -> Vec<&str> {
+        let mut names = self.defs.keys()
+            .map(|n| n.as_str())
+            .collect::<Vec<&str>>();
+        names.sort_unstable();
+        names
+    }
+
+    // code that would define a function again, if it was
+    // defined with `def`
+    pub fn definition(&self, name: &str) -> Option<String> {
+        let body = self.defs.get(name)?;
+
+        Some(unparse(&[
+            PescToken::Macro(body.clone()),
+            PescToken::Str(String::from(name)),
+            PescToken::Func(String::from("def")),
+        ]))
+    }
+
+    pub fn warn(&mut self, kind: PescWarningType) {
+        self.warnings.push(PescWarning::new(None, kind));
+    }
+
+    // once a program has finished, warn about the things it left on
+    // the stack -- particularly macros, which must never have been run.
+    pub fn warn_leftovers(&mut self) {
+        let macros = self.stack.iter()
+            .filter(|t| matches!(t, PescToken::Macro(_)))
+            .cloned()
+            .collect::<Vec<PescToken>>();
+
+        for m in macros {
+            self.warn(PescWarningType::UnusedMacro(m));
+        }
+
+        if !self.stack.is_empty() {
+            self.warn(PescWarningType::ValuesLeft(self.stack.len()));
+        }
+    }
+
+    // compare two numbers, taking care of NaN as requested by
+    // self.opts.nan_mode. None means the result is unknown.
+    pub fn compare(&self, a: PescNumber, b: PescNumber)
+        -> Result<Option<Ordering>, PescErrorType>
+    {
+        if !a.is_nan() && !b.is_nan() {
+            return Ok(a.partial_cmp(&b));
+        }
+
+        match self.opts.nan_mode {
+            NanMode::Error => Err(PescErrorType::NaNComparison(a, b)),
+            NanMode::Propagate => Ok(None),
+            NanMode::Total => Ok(Some(a.total_cmp(&b))),
+        }
     }
 
     pub fn nth_ref(&self, i: PescNumber) -> Result<&PescToken, PescErrorType> {
@@ -340,7 +1224,7 @@ impl Pesc {
     pub fn pop_number(&mut self) -> Result<PescNumber, PescErrorType> {
         let v = self.pop()?;
 
-        if let PescToken::Number(n) = v {
+        if let Some(n) = v.as_number() {
             Ok(n)
         } else {
             Err(PescErrorType::InvalidArgumentType(
@@ -348,6 +1232,54 @@ impl Pesc {
         }
     }
 
+    // pop a number to be used as a (non-negative) integer. unless
+    // self.opts.checked_ints is set, anything goes: fractions are
+    // truncated and negative numbers become zero.
+    pub fn pop_integer(&mut self) -> Result<usize, PescErrorType> {
+        let n = self.pop_number()?;
+
+        if self.opts.checked_ints && (n.fract() != 0.0 || n < 0.0
+            || n > MAX_EXACT_INT as PescNumber)
+        {
+            return Err(PescErrorType::NotAnInteger(n));
+        }
+
+        Ok(n as usize)
+    }
+
+    // push the result of an integer operation: `checked` is the
+    // result if it didn't overflow, `wrapped` is what it wrapped
+    // around to otherwise.
+    pub fn push_integer(&mut self, op: &str, checked: Option<usize>,
+        wrapped: usize) -> Result<(), PescErrorType>
+    {
+        let v = match checked {
+            Some(v) if v <= MAX_EXACT_INT => v,
+            _ if self.opts.checked_ints =>
+                return Err(PescErrorType::IntegerOverflow(String::from(op))),
+            _ => wrapped,
+        };
+
+        self.push(PescToken::Number(v as PescNumber));
+        Ok(())
+    }
+
+    // push the result of some arithmetic on a and b, checking that
+    // it's exact when both are whole numbers and checked_ints is set
+    pub fn push_exact(&mut self, op: &str, a: PescNumber, b: PescNumber,
+        r: PescNumber) -> Result<(), PescErrorType>
+    {
+        let whole = |n: PescNumber| n.fract() == 0.0
+            && n.abs() <= MAX_EXACT_INT as PescNumber;
+
+        if self.opts.checked_ints && whole(a) && whole(b) && !whole(r) {
+            return Err(PescErrorType::IntegerOverflow(String::from(op)));
+        }
+
+        self.push(PescToken::Number(r));
+        Ok(())
+    }
+
     pub fn pop_string(&mut self) -> Result<String, PescErrorType> {
         let v = self.pop()?;
 
@@ -359,7 +1291,7 @@ impl Pesc {
         }
     }
 
-    pub fn pop_macro(&mut self) -> Result<Vec<PescToken>, PescErrorType> {
+    pub fn pop_macro(&mut self) -> Result<Rc<Vec<PescToken>>, PescErrorType> {
         let v = self.pop()?;
 
         if let PescToken::Macro(m) = v {
@@ -370,10 +1302,32 @@ impl Pesc {
         }
     }
 
+    pub fn pop_list(&mut self) -> Result<Rc<Vec<PescToken>>, PescErrorType> {
+        let v = self.pop()?;
+
+        if let PescToken::List(l) = v {
+            Ok(l)
+        } else {
+            Err(PescErrorType::InvalidArgumentType(
+                String::from("list"), v.to_string()))
+        }
+    }
+
+    pub fn pop_map(&mut self) -> Result<Rc<BTreeMap<String, PescToken>>, PescErrorType> {
+        let v = self.pop()?;
+
+        if let PescToken::Map(m) = v {
+            Ok(m)
+        } else {
+            Err(PescErrorType::InvalidArgumentType(
+                String::from("map"), v.to_string()))
+        }
+    }
+
     pub fn pop_boolean(&mut self) -> Result<bool, PescErrorType> {
         let v = self.pop()?;
         match v {
-            PescToken::Str(s) => if s == String::from("") {
+            PescToken::Str(s) => if s.is_empty() {
                 Ok(false)
             } else {
                 Ok(true)
@@ -383,8 +1337,1252 @@ impl Pesc {
             } else {
                 Ok(true)
             },
+            PescToken::Int(n) => Ok(!n.is_zero()),
+            PescToken::Decimal(d) => Ok(!d.is_zero()),
+            PescToken::Frac(_) => Ok(true),
             PescToken::Bool(b) => Ok(b),
+            PescToken::Nil => Ok(false),
             _ => Err(PescErrorType::InvalidBoolean(v))
         }
     }
 }
+
+// what parse_block() needs to keep track of while
+// walking through the input
+struct ParseState<'a> {
+    chs: &'a [char],
+    offs: &'a [usize],
+    opts: &'a PescOptions,
+
+    // the interpreter whose functions and operators we check
+    // the input against, if any
+    pesc: Option<&'a Pesc>,
+
+    warnings: Vec<PescWarning>,
+
+    // names of the functions called in the input,
+    // and those defined with [def]
+    calls: Vec<(String, Span)>,
+    defined: Vec<String>,
+}
+
+impl<'a> ParseState<'a> {
+    // decide what to do with an operator symbol
+    // the longest operator of more than one character at chs[i]
+    fn multi_op(&self, i: usize) -> Option<&'a str> {
+        let rest = &self.chs[i..];
+        self.pesc?.multi_ops.keys()
+            .filter(|o| o.chars().count() <= rest.len() && o.chars().zip(rest).all(|(a, b)| a == *b))
+            .max_by_key(|o| o.chars().count())
+            .map(|o| o.as_str())
+    }
+
+    fn symbol(&mut self, c: char, sp: Span) -> Result<PescToken, PescError> {
+        let known = match self.pesc {
+            Some(p) => p.ops.contains_key(&c),
+            None => true,
+        };
+
+        match self.opts.parse_mode {
+            _ if known => Ok(PescToken::Symbol(c)),
+            ParseMode::Normal => Ok(PescToken::Symbol(c)),
+            ParseMode::Lenient => {
+                self.warnings.push(PescWarning::new(Some(sp),
+                    PescWarningType::UnknownSymbol(c)));
+                Ok(PescToken::Unknown(c))
+            },
+            ParseMode::Strict => Err(PescError::new(Some(sp), None,
+                PescErrorType::UnknownFunction(format!("'{}'", c)))),
+        }
+    }
+}
+
+// chomp the chars, placing them in a buffer.
+// return (String, usize, bool) when until() -> true or
+// when it reaches the end of the chars
+//
+//     String = the buffer,
+//     usize  = the end index,
+//     bool   = did we reach the end of the data
+//              without having until() return true?
+//
+fn chomp<F>(ch: &[char], mut c: usize, until: F)
+    -> (String, usize, bool)
+where
+    F: Fn(char) -> bool
+{
+    let mut buf = String::new();
+    let early_return;
+
+    loop {
+        if c >= ch.len() {
+            early_return = true;
+            break;
+        }
+
+        if until(ch[c]) {
+            early_return = false;
+            break;
+        }
+
+        buf += &format!("{}", ch[c]);
+        c += 1;
+    }
+
+    (buf, c, early_return)
+}
+
+// like chomp(), but stop at the first unescaped `close`. a backslash
+// escapes the closing character and itself, and "\n"/"\t" produce a
+// newline/tab; any other backslash is kept as-is.
+fn chomp_quoted(ch: &[char], mut c: usize, close: char)
+    -> (String, usize, bool)
+{
+    let mut buf = String::new();
+
+    while c < ch.len() {
+        match ch[c] {
+            '\\' if c + 1 < ch.len() => {
+                match ch[c + 1] {
+                    'n' => buf.push('\n'),
+                    't' => buf.push('\t'),
+                    e if e == close || e == '\\' => buf.push(e),
+                    e => { buf.push('\\'); buf.push(e); },
+                }
+                c += 2;
+            },
+            d if d == close => return (buf, c, false),
+            d => { buf.push(d); c += 1; },
+        }
+    }
+
+    (buf, c, true)
+}
+
+// turn tokens back into code that parses to the same tokens, with
+// a few exceptions: nil and non-finite numbers have no literals.
+pub fn unparse(toks: &[PescToken]) -> String {
+    toks.iter()
+        .map(|t| match t {
+            PescToken::Str(s) => format!("\"{}\"", quote(s, '"')),
+            PescToken::Number(n) if *n < 0.0 => format!("_{}", -n),
+            PescToken::Number(n) => n.to_string(),
+            PescToken::Int(n) if n.is_negative() => format!("_{}", -n),
+            PescToken::Int(n) => n.to_string(),
+            PescToken::Decimal(d) if d.is_negative() => format!("_{}", -&**d),
+            PescToken::Decimal(d) => d.to_string(),
+            PescToken::Frac(r) => format!("({})", r),
+            PescToken::Func(f) => format!("[{}]", quote(f, ']')),
+            PescToken::Macro(m) => format!("{{{}}}", unparse(m)),
+            PescToken::List(l) => format!("'({})", unparse(l)),
+            PescToken::Map(m) => {
+                let pairs = m.iter()
+                    .flat_map(|(k, v)| vec![PescToken::Str(k.clone()), v.clone()])
+                    .collect::<Vec<PescToken>>();
+                format!("'{{{}}}", unparse(&pairs))
+            },
+            PescToken::Symbol(c) | PescToken::Unknown(c) => c.to_string(),
+            PescToken::Operator(o) => o.clone(),
+            PescToken::Bool(b) => b.to_string(),
+            PescToken::Nil => String::from("nil"),
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+// pair up the tokens in a map literal
+fn map_literal(toks: Vec<PescToken>) -> Result<BTreeMap<String, PescToken>, PescErrorType> {
+    let mut map = BTreeMap::new();
+    let mut toks = toks.into_iter();
+
+    while let Some(k) = toks.next() {
+        let key = match k {
+            PescToken::Str(s) => s,
+            k => return Err(PescErrorType::BadMapLiteral(
+                format!("its keys have to be strings, not {}", k))),
+        };
+
+        let value = toks.next().ok_or_else(|| PescErrorType::BadMapLiteral(
+            format!("\"{}\" has no value", key)))?;
+        map.insert(key, value);
+    }
+
+    Ok(map)
+}
+
+// the token for a number literal that's been checked by parse_number():
+// a big integer for whole numbers with Precision::Exact or Decimal
+// (though with Exact, a literal with a point is a float), a decimal
+// for the rest with Precision::Decimal, or a float
+fn number_token(lit: &str, num: PescNumber, opts: &PescOptions) -> PescToken {
+    let digits = lit.trim_start_matches(&['_', '-'][..]).replace('_', "");
+    let negative = lit.starts_with(&['_', '-'][..]);
+
+    let exact = match opts.precision {
+        Precision::Float => None,
+        _ => Decimal::parse(&digits).map(|d| if negative { -&d } else { d }),
+    };
+
+    match (opts.precision, exact) {
+        (Precision::Decimal, Some(d)) => d.token(),
+        (Precision::Exact, Some(d)) if !lit.contains('.') => match d.to_integer() {
+            Some(n) => PescToken::Int(n.clone()),
+            None => PescToken::Number(num),
+        },
+        _ => PescToken::Number(num),
+    }
+}
+
+// the token for a literal in parentheses: a number that may be negative,
+// e.g. (-5), or a fraction, e.g. (1/3), which is exact unless we're
+// using floats
+fn paren_token(lit: &str, opts: &PescOptions) -> Result<PescToken, (usize, usize, String)> {
+    let (a, b) = match lit.split_once('/') {
+        Some(parts) => parts,
+        None => return number_literal(lit, true, opts),
+    };
+
+    let off = a.chars().count() + 1;
+    let x = number_literal(a, true, opts)?;
+    let y = number_literal(b, false, opts).map_err(|(s, e, p)| (s + off, e + off, p))?;
+
+    let (fx, fy) = (x.as_number().unwrap_or(f64::NAN), y.as_number().unwrap_or(f64::NAN));
+    if fy == 0.0 {
+        return Err((off, lit.chars().count(), String::from("its denominator is zero")));
+    }
+
+    // (with floats, neither side is exact)
+    match (Ratio::from_token(&x), Ratio::from_token(&y)) {
+        (Some(x), Some(y)) => Ok(x.div(&y).map_or(PescToken::Nil, Ratio::token)),
+        _ => Ok(PescToken::Number(fx / fy)),
+    }
+}
+
+// the token for a number literal, e.g. 1_000, _2.5 or 0xff (see
+// parse_number() and parse_radix() for what they may look like)
+pub(crate) fn number_literal(lit: &str, allow_minus: bool, opts: &PescOptions)
+    -> Result<PescToken, (usize, usize, String)>
+{
+    let negative = lit.starts_with('_') || (allow_minus && lit.starts_with('-'));
+    let body = if negative { &lit[1..] } else { lit };
+
+    let radix = match body.get(..2) {
+        Some("0x") => 16,
+        Some("0b") => 2,
+        Some("0o") => 8,
+        _ => return parse_number(lit, allow_minus).map(|n| number_token(lit, n, opts)),
+    };
+
+    let skip = negative as usize + 2;
+    let n = parse_radix(&body[2..], radix)
+        .map_err(|(s, e, p)| (s + skip, e + skip, p))?;
+    let n = if negative { -&n } else { n };
+
+    Ok(match opts.precision {
+        Precision::Float => PescToken::Number(n.to_f64()),
+        _ => PescToken::Int(n),
+    })
+}
+
+// whether a character can be part of an operator of more than one
+// character, i.e. it isn't part of a number, word, string or the like
+fn operator_char(c: char) -> bool {
+    !c.is_alphanumeric() && !c.is_whitespace()
+        && !"\"[]{}()'#_.".contains(c)
+}
+
+// whether an operator can be made to run something at run time: one
+// character that the parser makes an operator (letters included,
+// besides T and F), or several that could be part of one
+pub(crate) fn bindable_operator(op: &str) -> bool {
+    let mut chars = op.chars();
+    match (chars.next(), chars.next()) {
+        (None, _) => false,
+        (Some(c), None) => (operator_char(c) || c.is_alphabetic())
+            && c != BOOLEAN_TRUE && c != BOOLEAN_FALSE,
+        _ => op.chars().all(operator_char),
+    }
+}
+
+// whether the '-' at chs[i] starts a number literal, like -5, rather
+// than being [sub]: it has to be followed by a digit, and not come
+// straight after something else (so 5 3-2 is 5 3 [sub] 2)
+fn minus_literal(chs: &[char], i: usize) -> bool {
+    let digit = |j: usize| chs.get(j).is_some_and(|c| c.is_ascii_digit());
+    let after = digit(i + 1) || (chs.get(i + 1) == Some(&'.') && digit(i + 2));
+    let before = i == 0 || chs[i - 1].is_whitespace() || matches!(chs[i - 1], '{' | '(');
+
+    chs[i] == '-' && after && before
+}
+
+// where the exponent of a number literal that's at chs[i] ends, if
+// there is one, i.e. an 'e' or 'E' and digits that may have a sign
+pub(crate) fn exponent_end(chs: &[char], i: usize) -> Option<usize> {
+    if !matches!(chs.get(i), Some('e' | 'E')) {
+        return None;
+    }
+
+    let start = i + 1 + matches!(chs.get(i + 1), Some('-' | '+')) as usize;
+    let end = start + chs[start.min(chs.len())..].iter().take_while(|c| c.is_ascii_digit()).count();
+    match end > start {
+        true => Some(end),
+        false => None,
+    }
+}
+
+// the digits of a hex, binary or octal literal, after its 0x, 0b or
+// 0o. like decimal ones, they may be separated by single underscores.
+fn parse_radix(digits: &str, radix: u32) -> Result<BigInt, (usize, usize, String)> {
+    let chs = digits.chars().collect::<Vec<char>>();
+    if chs.is_empty() {
+        return Err((0, 0, String::from("it has no digits")));
+    }
+
+    let is_digit = |c: Option<&char>| c.is_some_and(|c| c.is_digit(radix));
+    let base = BigInt::from(radix as u64);
+    let mut n = BigInt::zero();
+
+    for (c, ch) in chs.iter().enumerate() {
+        match ch.to_digit(radix) {
+            Some(d) => n = &(&n * &base) + &BigInt::from(d as u64),
+            None if *ch == '_' && c > 0 && is_digit(chs.get(c - 1)) && is_digit(chs.get(c + 1)) => (),
+            None if *ch == '_' => return Err((c, c + 1, String::from(
+                "'_' may only appear between two digits"))),
+            None => return Err((c, c + 1, format!("'{}' isn't a {} digit", ch, match radix {
+                16 => "hex",
+                8 => "octal",
+                _ => "binary",
+            }))),
+        }
+    }
+
+    Ok(n)
+}
+
+// the opposite of chomp_quoted()
+fn quote(s: &str, close: char) -> String {
+    let mut buf = String::new();
+
+    for c in s.chars() {
+        match c {
+            '\n' => buf.push_str("\\n"),
+            '\t' => buf.push_str("\\t"),
+            '\\' => buf.push_str("\\\\"),
+            _ if c == close => { buf.push('\\'); buf.push(c); },
+            _ => buf.push(c),
+        }
+    }
+
+    buf
+}
+
+// check the shape of a number literal and convert it. the literal
+// may begin with a '_' (or a '-', if `allow_minus` is set) to make
+// it negative; other underscores may only appear between digits,
+// where they are ignored (e.g. "1_000_000"). it may end with an
+// exponent, e.g. "1.5e-3" or "2E10".
+//
+// on failure, return the range of the offending characters (relative
+// to the start of the literal) along with a description of the problem.
+pub(crate) fn parse_number(lit: &str, allow_minus: bool)
+    -> Result<PescNumber, (usize, usize, String)>
+{
+    let all = lit.chars().collect::<Vec<char>>();
+
+    // (the exponent, if there is one, is checked on its own)
+    let e = all.iter().position(|c| *c == 'e' || *c == 'E').unwrap_or(all.len());
+    let chs = &all[..e];
+
+    let negative = !chs.is_empty()
+        && (chs[0] == '_' || (allow_minus && chs[0] == '-'));
+    let start = if negative { 1 } else { 0 };
+
+    if !chs[start..].iter().any(|c| c.is_ascii_digit()) {
+        return Err((0, chs.len(), String::from("it has no digits")));
+    }
+
+    if e < all.len() {
+        let sign = matches!(all.get(e + 1), Some('-' | '+')) as usize;
+        let exp = &all[e + 1 + sign..];
+
+        if exp.is_empty() {
+            return Err((e, all.len(), String::from("its exponent has no digits")));
+        }
+
+        if let Some(c) = exp.iter().position(|c| !c.is_ascii_digit()) {
+            let c = e + 1 + sign + c;
+            return Err((c, c + 1, format!("'{}' isn't a digit", all[c])));
+        }
+    }
+
+    let mut seen_dot = false;
+    for c in start..chs.len() {
+        match chs[c] {
+            '.' if seen_dot => return Err((c, c + 1,
+                String::from("it has more than one decimal point"))),
+            '.' => seen_dot = true,
+            '_' => {
+                let mut end = c;
+                while end < chs.len() && chs[end] == '_' {
+                    end += 1;
+                }
+
+                let digit_before = c > start && chs[c - 1].is_ascii_digit();
+                let digit_after = end < chs.len() && chs[end].is_ascii_digit();
+
+                if !digit_before || !digit_after || end - c > 1 {
+                    return Err((c, end, String::from(
+                        "'_' may only appear between two digits")));
+                }
+            },
+            d if d.is_ascii_digit() => (),
+            d => return Err((c, c + 1,
+                format!("'{}' isn't a digit", d))),
+        }
+    }
+
+    let num = all[start..].iter()
+        .filter(|c| **c != '_')
+        .collect::<String>()
+        .parse::<PescNumber>()
+        .map_err(|_| (0, all.len(), String::from("it isn't valid")))?;
+
+    Ok(if negative { -num } else { num })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stdlib;
+
+    fn parse_err(input: &str) -> PescError {
+        Pesc::parse(input).expect_err(input)
+    }
+
+    fn pesc() -> Pesc {
+        let mut p = Pesc::new();
+        for func in stdlib::standard().into_iter().chain(stdlib::extended()) {
+            p.load_def(func);
+        }
+        p
+    }
+
+    fn eval_err(p: &mut Pesc, input: &str) -> PescErrorType {
+        let code = Pesc::parse_with(input, &p.opts).unwrap().1;
+        p.eval(&code).expect_err(input).1.kind
+    }
+
+    #[test]
+    fn test_parse_numbers() {
+        assert_eq!(Pesc::parse("1_000 _2.5 .5").unwrap().1, vec![
+            PescToken::Number(1000.0), PescToken::Number(-2.5),
+            PescToken::Number(0.5),
+        ]);
+        assert_eq!(Pesc::parse("(_3)(-4)").unwrap().1, vec![
+            PescToken::Number(-3.0), PescToken::Number(-4.0),
+        ]);
+        assert_eq!(Pesc::parse("0xff_FF _0b101 0o17(-0x10)").unwrap().1, vec![
+            PescToken::Number(65535.0), PescToken::Number(-5.0),
+            PescToken::Number(15.0), PescToken::Number(-16.0),
+        ]);
+        assert_eq!(Pesc::parse("1.5e-3 _2E10 1e+2(-1e1/4) 2e").unwrap().1, vec![
+            PescToken::Number(0.0015), PescToken::Number(-2e10),
+            PescToken::Number(100.0), PescToken::Number(-2.5),
+            PescToken::Number(2.0), PescToken::Symbol('e'),
+        ]);
+        assert_eq!(Pesc::parse("-5 3-2 7 -.5- -0x10").unwrap().1, vec![
+            PescToken::Number(-5.0), PescToken::Number(3.0),
+            PescToken::Symbol('-'), PescToken::Number(2.0),
+            PescToken::Number(7.0), PescToken::Number(-0.5),
+            PescToken::Symbol('-'), PescToken::Number(-16.0),
+        ]);
+    }
+
+    #[test]
+    fn test_parse_malformed_numbers() {
+        let e = parse_err("1.2.3");
+        assert!(matches!(e.kind, PescErrorType::InvalidNumberLit(_, _)));
+        assert_eq!(e.span, Some(Span::new(3, 4)));
+
+        assert_eq!(parse_err("2 ..").span, Some(Span::new(2, 4)));
+        assert_eq!(parse_err("1__").span, Some(Span::new(1, 3)));
+        assert_eq!(parse_err("4 _").span, Some(Span::new(2, 3)));
+        assert_eq!(parse_err("1__2").span, Some(Span::new(1, 3)));
+        assert_eq!(parse_err("1_.2").span, Some(Span::new(1, 2)));
+        assert_eq!(parse_err("(1x)").span, Some(Span::new(2, 3)));
+        assert_eq!(parse_err("(1e)").span, Some(Span::new(2, 3)));
+        assert_eq!(parse_err("(1e-x)").span, Some(Span::new(4, 5)));
+        assert_eq!(parse_err("0b102").span, Some(Span::new(4, 5)));
+
+        // digits that aren't ASCII aren't numbers at all
+        assert_eq!(Pesc::parse("٣ 2²").unwrap().1, vec![PescToken::Symbol('٣'),
+            PescToken::Number(2.0), PescToken::Symbol('²')]);
+        assert_eq!(parse_err("_0x").span, Some(Span::new(3, 3)));
+        assert_eq!(parse_err("0o7__7").span, Some(Span::new(3, 4)));
+        assert_eq!(parse_err("{-1.2.3}").span, Some(Span::new(5, 6)));
+    }
+
+    #[test]
+    fn test_parse_multibyte_positions() {
+        let input = "\"日本\" 😀\n  1.2.3";
+        let sp = parse_err(input).span.unwrap();
+        assert_eq!(&input[sp.start..sp.end], ".");
+        assert_eq!(sp.line_col(input), (2, 6));
+
+        let input = "😀{\"絵\" 1__}";
+        let sp = parse_err(input).span.unwrap();
+        assert_eq!(&input[sp.start..sp.end], "__");
+        assert_eq!(sp.line_col(input), (1, 8));
+
+        let input = "日本 [語";
+        let sp = parse_err(input).span.unwrap();
+        assert_eq!(sp, Span::new(7, 8));
+        assert_eq!(sp.line_col(input), (1, 4));
+    }
+
+    #[test]
+    fn test_parse_spans() {
+        let input = "1 \"日\" {2 +-} # hi\n[dup]";
+        let (toks, spans) = Pesc::parse_spans(input, &PescOptions::default()).unwrap();
+        let text = |s: &PescSpans| &input[s.span.start..s.span.end];
+
+        assert_eq!(toks.len(), spans.len());
+        assert_eq!(spans.iter().map(text).collect::<Vec<_>>(),
+            vec!["1", "\"日\"", "{2 +-}", "[dup]"]);
+        assert_eq!(spans[2].inner.iter().map(text).collect::<Vec<_>>(),
+            vec!["2", "+", "-"]);
+    }
+
+    #[test]
+    fn test_unparse() {
+        let input = "1 _2.5 \"a\\\"b\\n\" {[x\\]y] {true} ; +} false";
+        let toks = Pesc::parse(input).unwrap().1;
+        assert_eq!(unparse(&toks), input);
+        assert_eq!(Pesc::parse(&unparse(&toks)).unwrap().1, toks);
+    }
+
+    #[test]
+    fn test_parse_stray_brace() {
+        let e = parse_err("1 2} 3+");
+        assert!(matches!(e.kind, PescErrorType::UnexpectedToken('}')));
+        assert_eq!(e.span, Some(Span::new(3, 4)));
+
+        let e = parse_err("{1 {2}}}");
+        assert!(matches!(e.kind, PescErrorType::UnexpectedToken('}')));
+        assert_eq!(e.span, Some(Span::new(7, 8)));
+
+        assert_eq!(Pesc::parse("{1 {2}} 3").unwrap().1, vec![
+            PescToken::Macro(Rc::new(vec![PescToken::Number(1.0),
+                PescToken::Macro(Rc::new(vec![PescToken::Number(2.0)]))])),
+            PescToken::Number(3.0),
+        ]);
+
+        assert!(matches!(parse_err("1 ]").kind, PescErrorType::UnexpectedToken(']')));
+        assert_eq!(parse_err("{1 )}").span, Some(Span::new(3, 4)));
+    }
+
+    #[test]
+    fn test_parse_unterminated() {
+        for (input, c, at) in [("{1 2+", '{', 0), ("1 {{2} 3", '{', 2), ("x \"abc", '"', 2),
+                               ("[foo", '[', 0), ("{(1 }", '(', 1), ("'(1 {2}", '(', 0)] {
+            let e = parse_err(input);
+            assert!(matches!(e.kind, PescErrorType::UnmatchedToken(t) if t == c), "{}", input);
+            assert_eq!(e.span.map(|s| s.start), Some(at), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_parse_quoted() {
+        use PescToken::*;
+
+        assert_eq!(Pesc::parse(r#"{"a}b" "c\"d"} [na\]me]"#).unwrap().1, vec![
+            Macro(Rc::new(vec![Str(String::from("a}b")), Str(String::from("c\"d"))])),
+            Func(String::from("na]me")),
+        ]);
+        assert_eq!(Pesc::parse(r#""x\\" "\n\t" "C:\dir""#).unwrap().1, vec![
+            Str(String::from("x\\")), Str(String::from("\n\t")),
+            Str(String::from("C:\\dir")),
+        ]);
+        assert_eq!(Pesc::parse(r#"{[f\]]"]" "{"}"#).unwrap().1, vec![
+            Macro(Rc::new(vec![Func(String::from("f]")), Str(String::from("]")),
+                Str(String::from("{"))])),
+        ]);
+
+        let e = parse_err(r#"1 "abc\""#);
+        assert!(matches!(e.kind, PescErrorType::UnmatchedToken('"')));
+        assert_eq!(e.span, Some(Span::new(2, 3)));
+    }
+
+    #[test]
+    fn test_parse_booleans() {
+        use PescToken::*;
+
+        assert_eq!(Pesc::parse("T F!true{false}").unwrap().1, vec![
+            Bool(true), Bool(false), Symbol('!'), Bool(true),
+            Macro(Rc::new(vec![Bool(false)])),
+        ]);
+        assert_eq!(Pesc::parse("TF pT").unwrap().1, vec![
+            Symbol('T'), Symbol('F'), Symbol('p'), Symbol('T'),
+        ]);
+
+        let legacy = PescOptions { legacy_bools: true, ..Default::default() };
+        assert_eq!(Pesc::parse_with("TF pT", &legacy).unwrap().1, vec![
+            Bool(true), Bool(false), Symbol('p'), Bool(true),
+        ]);
+    }
+
+    #[test]
+    fn test_recursion_limit() {
+        let mut p = pesc();
+        let e = eval_err(&mut p, "1 {[f]}\"f\"[def] [f]");
+        assert!(matches!(e, PescErrorType::RecursionLimitExceeded(256)));
+        assert_eq!(p.stack, vec![PescToken::Number(1.0)]);
+
+        p.opts.max_depth = 3;
+        let e = eval_err(&mut p, "{{{{2};};};};");
+        assert!(matches!(e, PescErrorType::RecursionLimitExceeded(3)));
+
+        let code = Pesc::parse("{{{2};};};").unwrap().1;
+        assert!(p.eval(&code).is_ok());
+    }
+
+    #[test]
+    fn test_budget() {
+        let mut p = pesc();
+        p.opts.max_steps = Some(100);
+        let e = eval_err(&mut p, "{1}\"f\"[def] {[f]} 1000 [times]");
+        assert!(matches!(e, PescErrorType::BudgetExhausted(ref s) if s == "100 steps"));
+
+        // (each eval() gets its own)
+        p.stack.clear();
+        assert!(p.run("{[f]} 20 [times]").is_ok());
+
+        p.opts.max_steps = None;
+        p.opts.max_loops = usize::MAX;
+        p.opts.timeout = Some(Duration::from_millis(10));
+        let e = eval_err(&mut p, "{T} {1 $} [while]");
+        assert!(matches!(e, PescErrorType::BudgetExhausted(ref s) if s == "10ms"));
+    }
+
+    #[test]
+    fn test_stack_limit() {
+        let mut p = pesc();
+        p.opts.max_stack = Some(5);
+        let e = eval_err(&mut p, "1 {T} {\\} [while]");
+        assert!(matches!(e, PescErrorType::StackLimitExceeded(5)));
+
+        p.stack.clear();
+        p.run("[stack-limit] 1 2 3 4").unwrap();
+        assert_eq!(p.stack[0], PescToken::Number(5.0));
+        assert!(p.run("5").is_err());
+        assert_eq!(p.stack.len(), 5);
+
+        p.opts.max_stack = None;
+        p.stack.clear();
+        p.run("[stack-limit]").unwrap();
+        assert_eq!(p.stack, vec![PescToken::Nil]);
+    }
+
+    #[test]
+    fn test_parse_modes() {
+        use PescToken::*;
+
+        let mut p = pesc();
+        assert_eq!(p.parse_code("1 2 z+").unwrap(), vec![
+            Number(1.0), Number(2.0), Symbol('z'), Symbol('+'),
+        ]);
+        assert!(p.warnings.is_empty());
+
+        p.opts.parse_mode = ParseMode::Lenient;
+        assert_eq!(p.parse_code("1 2 z+").unwrap(), vec![
+            Number(1.0), Number(2.0), Unknown('z'), Symbol('+'),
+        ]);
+        assert_eq!(p.warnings.len(), 1);
+        assert_eq!(p.warnings[0].span, Some(Span::new(4, 5)));
+
+        p.opts.parse_mode = ParseMode::Strict;
+        let e = p.parse_code("1 2 z+").unwrap_err();
+        assert!(matches!(e.kind, PescErrorType::UnknownFunction(_)));
+        assert_eq!(e.span, Some(Span::new(4, 5)));
+
+        let e = p.parse_code("1 {[nope]}").unwrap_err();
+        assert!(matches!(e.kind, PescErrorType::UnknownFunction(_)));
+        assert_eq!(e.span, Some(Span::new(3, 9)));
+
+        assert!(p.parse_code("3 [double] {2*}\"Double\"[def] [DUP]").is_ok());
+    }
+
+    #[test]
+    fn test_div_zero_modes() {
+        let mut p = pesc();
+        let e = eval_err(&mut p, "1 0/");
+        assert!(matches!(e, PescErrorType::DivideByZero(_, _)));
+        for precision in &[Precision::Float, Precision::Exact, Precision::Decimal] {
+            p.opts.precision = *precision;
+            assert!(matches!(eval_err(&mut p, "0 _1^"), PescErrorType::DivideByZero(_, _)));
+            p.stack.clear();
+        }
+        p.opts.precision = Precision::Float;
+
+        p.stack.clear();
+        p.opts.div_zero = DivZeroMode::Ieee;
+        p.eval(&Pesc::parse("1 0/ _1 0/ 1 0%").unwrap().1).unwrap();
+        assert_eq!(&p.stack[..2], &[PescToken::Number(f64::INFINITY),
+            PescToken::Number(f64::NEG_INFINITY)]);
+        assert!(matches!(p.stack[2], PescToken::Number(n) if n.is_nan()));
+
+        p.stack.clear();
+        p.opts.div_zero = DivZeroMode::Nil;
+        p.eval(&Pesc::parse("1 0/ 4 2/ 0 _2^").unwrap().1).unwrap();
+        assert_eq!(p.stack, vec![PescToken::Nil, PescToken::Number(2.0), PescToken::Nil]);
+    }
+
+    #[test]
+    fn test_nan_modes() {
+        use PescToken::*;
+
+        let nan = Number(f64::NAN);
+        let mut p = pesc();
+
+        p.stack = vec![nan.clone(), Number(1.0)];
+        p.eval(&Pesc::parse("[gt?]").unwrap().1).unwrap();
+        assert_eq!(p.stack, vec![Nil]);
+
+        p.stack = vec![Number(3.0), nan.clone(), Number(1.0), Number(2.0)];
+        p.eval(&Pesc::parse("4[sort]").unwrap().1).unwrap();
+        assert_eq!(&p.stack[..3], &[Number(1.0), Number(2.0), Number(3.0)]);
+        assert!(matches!(p.stack[3], Number(n) if n.is_nan()));
+
+        p.opts.nan_mode = NanMode::Total;
+        p.stack = vec![nan.clone(), Number(5.0)];
+        p.eval(&Pesc::parse("[lt?]").unwrap().1).unwrap();
+        assert_eq!(p.stack, vec![Bool(true)]);
+
+        p.stack = vec![nan.clone(), nan.clone()];
+        p.eval(&Pesc::parse("[eq?]").unwrap().1).unwrap();
+        assert_eq!(p.stack, vec![Bool(true)]);
+
+        p.opts.nan_mode = NanMode::Error;
+        p.stack = vec![nan.clone(), Number(1.0)];
+        let e = eval_err(&mut p, "[gte]");
+        assert!(matches!(e, PescErrorType::NaNComparison(_, _)));
+    }
+
+    #[test]
+    fn test_keep_on_error() {
+        use PescToken::*;
+
+        let mut p = pesc();
+        p.eval(&Pesc::parse("{1 2+ \"a\"+}\"f\"[def]").unwrap().1).unwrap();
+
+        eval_err(&mut p, "[f]");
+        assert!(p.stack.is_empty());
+
+        p.opts.keep_on_error = true;
+        eval_err(&mut p, "[f]");
+        assert_eq!(p.stack, vec![Number(3.0)]);
+    }
+
+    #[test]
+    fn test_warnings() {
+        let mut p = pesc();
+        p.eval(&Pesc::parse("{1}\"one\"[def] {2}\"one\"[def] {}\"add\"[def]").unwrap().1).unwrap();
+
+        let w = p.warnings.drain(..).map(|w| w.kind).collect::<Vec<_>>();
+        assert!(matches!(&w[0], PescWarningType::Redefinition(n) if n == "one"));
+        assert!(matches!(&w[1], PescWarningType::Redefinition(n) if n == "add"));
+        assert!(matches!(&w[2], PescWarningType::ShadowedOperator(o, n) if o == "+" && n == "add"));
+
+        p.eval(&Pesc::parse("1 {2}").unwrap().1).unwrap();
+        p.warn_leftovers();
+        let w = p.warnings.drain(..).map(|w| w.kind).collect::<Vec<_>>();
+        assert!(matches!(&w[0], PescWarningType::UnusedMacro(_)));
+        assert!(matches!(&w[1], PescWarningType::ValuesLeft(2)));
+    }
+
+    #[test]
+    fn test_ignore_case() {
+        let mut p = pesc();
+        p.eval(&Pesc::parse("{2*}\"Double\"[def] 3[DOUBLE][double]").unwrap().1).unwrap();
+        assert_eq!(p.stack, vec![PescToken::Number(12.0)]);
+
+        p.opts.ignore_case = false;
+        let e = eval_err(&mut p, "[DUP]");
+        assert!(matches!(e, PescErrorType::WrongCase(_, ref l) if l == "dup"));
+
+        p.eval(&Pesc::parse("{3*}\"Triple\"[def] [Triple]").unwrap().1).unwrap();
+        assert_eq!(p.stack, vec![PescToken::Number(36.0)]);
+        assert!(matches!(eval_err(&mut p, "[triple]"), PescErrorType::UnknownFunction(_)));
+    }
+
+    #[test]
+    fn test_checked_ints() {
+        use PescToken::*;
+
+        let mut p = pesc();
+        p.eval(&Pesc::parse("0[fact] 2 53^ 1+ 21[fact] 1 64<").unwrap().1).unwrap();
+        assert_eq!(&p.stack[..2], &[Number(1.0), Number(9007199254740992.0)]);
+
+        p.stack.clear();
+        p.opts.checked_ints = true;
+        for code in &["2 53^ 1+", "21[fact]", "1 64<", "2 32^ 2 32^*", "2 0.5|", "_1 0|"] {
+            let e = eval_err(&mut p, code);
+            assert!(matches!(e, PescErrorType::IntegerOverflow(_)
+                | PescErrorType::NotAnInteger(_)), "{}", code);
+            p.stack.clear();
+        }
+
+        p.eval(&Pesc::parse("2 52^ 1+ 18[fact] 0.1 0.2+").unwrap().1).unwrap();
+        assert_eq!(&p.stack[..2], &[Number(4503599627370497.0),
+            Number(6402373705728000.0)]);
+
+        // bnot keeps to the numbers that are exact
+        p.stack.clear();
+        p.eval(&Pesc::parse("5[bnot]").unwrap().1).unwrap();
+        assert_eq!(p.stack, vec![Number(9007199254740986.0)]);
+
+        // and without checked_ints, factorials too big for an integer
+        // are floats instead of wrapping around
+        p.stack.clear();
+        p.opts.checked_ints = false;
+        p.eval(&Pesc::parse("5[bnot] 25[fact] 200[fact]").unwrap().1).unwrap();
+        assert_eq!(p.stack, vec![Number(9007199254740986.0),
+            Number(15511210043330985984000000.0), Number(f64::INFINITY)]);
+    }
+
+    #[test]
+    fn test_exact() {
+        use PescToken::*;
+
+        let mut p = pesc();
+        p.opts.precision = Precision::Exact;
+        let code = p.parse_code("2 64^ 1- 25[fact] _7 3% 6 3/ 7 2/ 1.5 2*").unwrap();
+        p.eval(&code).unwrap();
+        assert_eq!(p.stack, vec![
+            Int(BigInt::parse("18446744073709551615").unwrap()),
+            Int(BigInt::parse("15511210043330985984000000").unwrap()),
+            Int(BigInt::parse("-1").unwrap()), Int(BigInt::from(2)),
+            Frac(Rc::new(Ratio::from_f64(3.5).unwrap())), Number(3.0),
+        ]);
+        assert_eq!(unparse(&p.stack[2..4]), "_1 2");
+
+        p.stack.clear();
+        let e = eval_err(&mut p, "2 64^ 2 64^ 1+ [gt?] 3 3.0 [eq?] 1 0/");
+        assert!(matches!(e, PescErrorType::DivideByZero(_, _)));
+        assert_eq!(&p.stack[..2], &[Bool(true), Bool(true)]);
+
+        p.stack.clear();
+        p.opts.precision = Precision::Decimal;
+        p.run("0.1 0.2+ 1.5 2* 10 4/ _0.5 3^ 2 3/ 0.3 0.1 0.2+ [eq?] 1.25 1.2 [lt?]").unwrap();
+        let dec = |s| Rc::new(crate::decimal::Decimal::parse(s).unwrap());
+        assert_eq!(p.stack[..5], [Decimal(dec("0.3")), Int(BigInt::from(3)),
+            Decimal(dec("2.5")), Decimal(dec("-0.125")),
+            Decimal(dec("0.6666666666666666666666666667"))]);
+        assert_eq!(p.stack[5..], [Bool(true), Bool(true)]);
+        assert_eq!(unparse(&p.stack[3..4]), "_0.125");
+
+        p.stack.clear();
+        p.opts.precision = Precision::Exact;
+        p.run("1 3/ 1 6/+ (-2/3) 2 _2^ (1/2) 0.25 [to-frac] [eq?] 0.5 [to-frac] 2 [den]").unwrap();
+        assert_eq!(p.stack[3..], [Bool(false), Frac(Rc::new(Ratio::from_f64(0.5).unwrap())),
+            Int(BigInt::from(1))]);
+        assert_eq!(unparse(&p.stack[..3]), "(1/2) (-2/3) (1/4)");
+        assert_eq!(Pesc::parse_with("(1/2)", &p.opts).unwrap().1, p.stack[..1]);
+        assert_eq!(Pesc::parse("(1/4)").unwrap().1, [Number(0.25)]);
+        assert!(Pesc::parse("(1/0)").is_err());
+    }
+
+    #[test]
+    fn test_radix() {
+        let s = |s: &str| PescToken::Str(String::from(s));
+
+        let mut p = pesc();
+        p.run("255 [hex] _5 [bin] 8 [oct]").unwrap();
+        assert_eq!(p.stack, vec![s("0xff"), s("-0b101"), s("0o10")]);
+        assert!(matches!(eval_err(&mut p, "0.5 [hex]"), PescErrorType::InvalidArgumentType(_, _)));
+
+        p.stack.clear();
+        p.opts.precision = Precision::Exact;
+        p.run("0xffff_ffff_ffff_ffff_ff [hex]").unwrap();
+        assert_eq!(p.stack, vec![s("0xffffffffffffffffff")]);
+    }
+
+    #[test]
+    fn test_sci() {
+        let s = |s: &str| PescToken::Str(String::from(s));
+
+        let mut p = pesc();
+        p.run("1500 [sci] _0.00025 [sci]").unwrap();
+        assert_eq!(p.stack, vec![s("1.5e3"), s("-2.5e-4")]);
+
+        p.stack.clear();
+        p.opts.precision = Precision::Decimal;
+        p.run("1.5e-3 0.1 [add] 12345678901234567890 [sci]").unwrap();
+        let n = |n: &str| crate::decimal::Decimal::parse(n).unwrap().token();
+        assert_eq!(p.stack, vec![n("0.1015"), s("1.234567890123456789e19")]);
+        assert!(matches!(eval_err(&mut p, "\"x\" [sci]"), PescErrorType::InvalidArgumentType(_, _)));
+    }
+
+    #[test]
+    fn test_multi_ops() {
+        let mut p = pesc();
+        p.load_op("**", "pow");
+        p.load_op("<=", "lte");
+        p.load_op("<=>", "cmp");
+        p.register("cmp", |p| {
+            let (a, b) = (p.pop_number()?, p.pop_number()?);
+            p.push(PescToken::Number(b.partial_cmp(&a).map_or(0.0, |o| o as i32 as f64)));
+            Ok(())
+        });
+
+        let toks = p.parse_code("2 3** 4* 1 2<= 1 2<=> 3 4<").unwrap();
+        assert_eq!(unparse(&toks), "2 3 ** 4 * 1 2 <= 1 2 <=> 3 4 <");
+        assert_eq!(toks[2], PescToken::Operator(String::from("**")));
+
+        p.eval(&toks).unwrap();
+        assert_eq!(p.stack, vec![
+            PescToken::Number(32.0), PescToken::Bool(false),
+            PescToken::Number(-1.0), PescToken::Number(48.0),
+        ]);
+
+        // without an interpreter to ask, they're just characters
+        assert_eq!(Pesc::parse("**").unwrap().1, vec![PescToken::Symbol('*'); 2]);
+        assert_eq!(p.operators("pow"), vec!["**", "^"]);
+    }
+
+    #[test]
+    fn test_bind_op() {
+        let mut p = pesc();
+        p.run("\"%\" {2*} [bind-op] \"**\" \"pow\" [bind-op] \"Q\" \"sqrt\" [bind-op]").unwrap();
+        p.run("5% 2 3** 16Q 1 [op %]").unwrap();
+        assert_eq!(p.stack, vec![
+            PescToken::Number(10.0), PescToken::Number(8.0),
+            PescToken::Number(4.0), PescToken::Number(2.0),
+        ]);
+        assert_eq!(p.operators("pow"), vec!["**", "^"]);
+
+        for code in ["\"T\" \"dup\"", "\"7\" \"dup\"", "\"a+\" \"dup\"", "\"\" \"dup\"", "\"+\" 1"] {
+            let e = eval_err(&mut p, &format!("{} [bind-op]", code));
+            assert!(matches!(e, PescErrorType::InvalidArgumentType(_, _)), "{}", code);
+        }
+        assert!(matches!(eval_err(&mut p, "\"~\" \"nope\" [bind-op]"), PescErrorType::UnknownFunction(_)));
+    }
+
+    #[test]
+    fn test_eval_spans() {
+        let mut p = pesc();
+        let span = |p: &mut Pesc, code: &str| p.run(code).unwrap_err().span;
+
+        assert_eq!(span(&mut p, "\"a\" 1+"), Some(Span::new(5, 6)));
+        assert_eq!(span(&mut p, "1 {2 {[nope]};};"), Some(Span::new(6, 12)));
+
+        // a function defined earlier fails where it's called
+        p.run("{[nope]} \"f\" [def]").unwrap();
+        assert_eq!(span(&mut p, "1 [f]"), Some(Span::new(2, 5)));
+
+        // eval() without spans doesn't know
+        let toks = Pesc::parse("[nope]").unwrap().1;
+        assert_eq!(p.eval(&toks).unwrap_err().1.span, None);
+
+        assert_eq!(Span::new(6, 12).caret("1 {2 {[nope]};};"), "      ^^^^^^");
+        assert_eq!(Span::new(2, 9).caret("1 \"a\nb\""), "  ^^");
+    }
+
+    #[test]
+    fn test_try() {
+        let s = |s: &str| PescToken::Str(String::from(s));
+
+        let mut p = pesc();
+        p.run("1 {2 [nope]} {} [try] {\"oops\" [throw]} {} [try] {42 [throw]} {} [try]").unwrap();
+        assert_eq!(p.stack, vec![
+            PescToken::Number(1.0), s("I have no idea what nope means."),
+            s("oops"), s("42"),
+        ]);
+
+        p.stack.clear();
+        p.run("{5} {6} [try] {{\"a\" [throw]} {[throw]} [try]} {\"again\"} [try]").unwrap();
+        assert_eq!(p.stack, vec![PescToken::Number(5.0), s("a"), s("again")]);
+
+        assert!(matches!(eval_err(&mut p, "\"no\" [throw]"), PescErrorType::Thrown(m) if m == "no"));
+
+        // an error that was caught isn't where a later one happened
+        assert_eq!(p.run("{[nope]}{}[try] \"a\" 1+").unwrap_err().span, Some(Span::new(21, 22)));
+    }
+
+    #[test]
+    fn test_namespaces() {
+        let mut p = pesc();
+        p.load_module("m", vec![(None, "twice", Rc::new(Box::new(|p: &mut Pesc| {
+            let n = p.pop_number()?;
+            p.push(PescToken::Number(n * 2.0));
+            Ok(())
+        })), Some((1, 1)))]);
+
+        p.run("3 [m.twice]").unwrap();
+        assert!(matches!(eval_err(&mut p, "[twice]"), PescErrorType::UnknownFunction(_)));
+        p.run("\"m\" [import] [twice]").unwrap();
+        assert_eq!(p.stack, vec![PescToken::Number(12.0)]);
+
+        // the last namespace imported wins, but not over a function
+        // with just that name
+        p.run("{3*} \"n.twice\" [def] {} \"n.dup\" [def] \"n\" [import] [twice] {} \"twice\" [def]").unwrap();
+        assert_eq!(p.stack, vec![PescToken::Number(36.0)]);
+        let w = p.warnings.iter().map(|w| format!("{:?}", w.kind)).collect::<Vec<String>>();
+        assert_eq!(w, vec![
+            "NameCollision(\"dup\", \"n.dup\")",
+            "NameCollision(\"twice\", \"m.twice\")",
+            "NameCollision(\"twice\", \"n.twice\")",
+        ]);
+
+        assert!(matches!(eval_err(&mut p, "\"nope\" [import]"), PescErrorType::UnknownNamespace(_)));
+    }
+
+    #[test]
+    fn test_include() {
+        let mut p = pesc();
+        let files = [
+            ("a", "{2*} \"double\" [def]\n\"b\" [inc]"),
+            ("b", "1\n  2 [nope]"),
+            ("c", "\"d\" [inc]"),
+            ("d", "\n\"c\" [inc]"),
+            ("e", "{ 1"),
+        ].iter().map(|(f, c)| (f.to_string(), c.to_string())).collect::<HashMap<_, _>>();
+
+        p.register("inc", move |p| {
+            let f = p.pop_string()?;
+            p.include(&f, &files[&f])
+        });
+
+        // definitions stay, and errors say where in which file
+        let e = eval_err(&mut p, "\"a\" [inc]");
+        assert_eq!(e.to_string(), "a:2:5: b:2:5: I have no idea what nope means.");
+        assert!(p.including.is_empty());
+        p.stack.clear();
+        p.run("3 [double]").unwrap();
+        assert_eq!(p.stack, vec![PescToken::Number(6.0)]);
+
+        let e = eval_err(&mut p, "\"c\" [inc]");
+        assert_eq!(e.to_string(), "c:1:5: d:2:5: c is already being included, so I'd go round in circles.");
+        let e = eval_err(&mut p, "\"e\" [inc]");
+        assert_eq!(e.to_string(), "e:1:1: Where's the matching '{'?");
+
+        // (from inside a macro too)
+        let e = eval_err(&mut p, "{\"b\" [inc]} \"f\" [def] [f]");
+        assert_eq!(e.to_string(), "b:2:5: I have no idea what nope means.");
+    }
+
+    #[test]
+    fn test_hook() {
+        let mut p = pesc();
+        let seen = Rc::new(std::cell::RefCell::new(Vec::new()));
+        let log = seen.clone();
+
+        p.hooks.push(Box::new(move |p, ev| {
+            let t = match ev {
+                PescEvent::Token(t) => t,
+                PescEvent::Break => {
+                    p.push(PescToken::Str("hit".to_string()));
+                    return Ok(());
+                },
+                _ => return Ok(()),
+            };
+            log.borrow_mut().push((p.depth(), t.clone()));
+
+            match t {
+                PescToken::Number(n) if *n == 4.0 => Err(PescErrorType::Aborted),
+                _ => Ok(()),
+            }
+        }));
+
+        let e = eval_err(&mut p, "1 {2 3}; 4 5");
+        assert!(matches!(e, PescErrorType::Aborted));
+        assert_eq!(seen.borrow().iter().map(|s| s.0).collect::<Vec<_>>(),
+            vec![0, 0, 0, 1, 1, 0]);
+        assert_eq!(p.stack.len(), 3);
+
+        p.stack.clear();
+        let parsed = p.parse_code("[break]").unwrap();
+        p.eval(&parsed).unwrap();
+        assert_eq!(p.stack, vec![PescToken::Str("hit".to_string())]);
+
+        let calls = Rc::new(std::cell::RefCell::new(Vec::new()));
+        let log = calls.clone();
+        p.hooks.clear();
+        p.hooks.push(Box::new(move |_, ev| {
+            match ev {
+                PescEvent::Call(f) => log.borrow_mut().push(format!("+{}", f)),
+                PescEvent::Return(f) => log.borrow_mut().push(format!("-{}", f)),
+                _ => (),
+            }
+            Ok(())
+        }));
+
+        let parsed = p.parse_code("1 {2+}; 0/").unwrap();
+        assert!(p.eval(&parsed).is_err());
+        assert_eq!(*calls.borrow(),
+            vec!["+run", "+add", "-add", "-run", "+div", "-div"]);
+    }
+
+    #[test]
+    fn test_assertions() {
+        let mut p = pesc();
+        for func in stdlib::testing() {
+            p.load_def(func);
+        }
+
+        let code = p.parse_code("1 2+ 3 [assert-eq] true [assert] \"a\" \"a\" [assert-eq]").unwrap();
+        p.eval(&code).unwrap();
+        assert!(p.stack.is_empty());
+
+        let e = eval_err(&mut p, "2 2+ 5 [assert-eq]");
+        assert!(matches!(e, PescErrorType::AssertionFailed(_)));
+        let e = eval_err(&mut p, "false [assert]");
+        assert!(matches!(e, PescErrorType::AssertionFailed(_)));
+    }
+
+    #[test]
+    fn test_span_map() {
+        let mut p = pesc();
+        let input = "{2 3} \"f\" [def] {4} \"g\" [def] 1 [f] [g] [f]";
+        let (toks, spans) = p.parse_code_spans(input).unwrap();
+        let map = SpanMap::new(&toks, &spans);
+
+        let seen = Rc::new(std::cell::RefCell::new(Vec::new()));
+        let log = seen.clone();
+        p.hooks.push(Box::new(move |_, ev| {
+            if let PescEvent::Token(t) = ev {
+                let s = map.find(t).unwrap();
+                log.borrow_mut().push((s.start, s.end));
+            }
+            Ok(())
+        }));
+
+        p.eval(&toks).unwrap();
+        let text = seen.borrow().iter()
+            .map(|(s, e)| &input[*s..*e])
+            .collect::<Vec<_>>();
+
+        assert_eq!(text, vec!["{2 3}", "\"f\"", "[def]", "{4}", "\"g\"", "[def]",
+            "1", "[f]", "2", "3", "[g]", "4", "[f]", "2", "3"]);
+    }
+
+    #[test]
+    fn test_definitions() {
+        let mut p = pesc();
+        let code = p.parse_code("{2*} \"twice\" [def] {\"a b\"} \"Ab\" [def]").unwrap();
+        p.eval(&code).unwrap();
+
+        assert_eq!(p.definitions(), vec!["ab", "twice"]);
+        assert_eq!(p.definition("twice").unwrap(), "{2 *} \"twice\" [def]");
+        assert_eq!(p.definition("add"), None);
+    }
+
+    #[test]
+    fn test_docs() {
+        let mut p = Pesc::with_stdlib();
+        let builtins = stdlib::standard().into_iter()
+            .chain(stdlib::extended())
+            .chain(stdlib::testing());
+        for (_, name, _, _) in builtins {
+            assert!(p.docs.contains_key(name), "[{}] isn't documented", name);
+        }
+        assert_eq!(p.arity.get("nth"), Some(&(2, 1)));
+        assert_eq!(p.arity.get("if"), None);
+
+        for (name, code) in stdlib::examples() {
+            assert!(p.docs.contains_key(name), "[{}] isn't documented", name);
+            Pesc::with_stdlib().run(code).unwrap_or_else(|e| panic!("{}: {}", code, e));
+        }
+
+        let e = p.run("\"twice\" [help]").unwrap_err();
+        assert!(matches!(e.kind, PescErrorType::UnknownFunction(_)));
+
+        p.stack.clear();
+        p.run("{2*} \"twice\" [def] \"+\" [help] \"twice\" [help]").unwrap();
+        assert_eq!(p.stack, vec![
+            PescToken::Str(String::from("( a b -- a+b ) adds two numbers.")),
+            PescToken::Nil,
+        ]);
+    }
+
+    #[test]
+    fn test_conditionals() {
+        let mut p = pesc();
+        p.run("1 {2*} T [if] {3*} F [if] {\"no\"} {\"yes\"} 3 2 [lt?] [ifelse]").unwrap();
+        assert_eq!(p.stack, vec![PescToken::Number(2.0), PescToken::Str(String::from("yes"))]);
+    }
+
+    #[test]
+    fn test_loops() {
+        let mut p = pesc();
+        p.run("1 {2*} 10 [times] 0 {\\ 5 [gt?]} {1+} [while] {\\ 8 [lte]} {1+} [until]").unwrap();
+        assert_eq!(p.stack, vec![PescToken::Number(1024.0), PescToken::Number(8.0)]);
+
+        p.opts.max_loops = 100;
+        let e = p.run("{T} {} [while]").unwrap_err();
+        assert!(matches!(e.kind, PescErrorType::LoopLimitExceeded(100)));
+        assert!(p.run("{} 101 [times]").is_err());
+    }
+
+    #[test]
+    fn test_lists() {
+        let mut p = pesc();
+        let code = Pesc::parse("'(1 \"a\" '(2) {3}) '()").unwrap().1;
+        assert_eq!(unparse(&code), "'(1 \"a\" '(2) {3}) '()");
+
+        p.run("'(1 2 3) \\ [len] , 4 [append] \\ 3 [nth] , [tail] [head]").unwrap();
+        assert_eq!(p.stack, vec![PescToken::Number(3.0), PescToken::Number(4.0),
+            PescToken::Number(2.0)]);
+
+        assert!(matches!(p.run("'(1) 1 [nth]").unwrap_err().kind,
+            PescErrorType::NoSuchIndex(1, 1)));
+        assert!(matches!(p.run("'(1 2 3) _1 [nth]").unwrap_err().kind,
+            PescErrorType::NotAnInteger(_)));
+        assert!(matches!(p.run("'(1 2 3) 1.5 [nth]").unwrap_err().kind,
+            PescErrorType::NotAnInteger(_)));
+        assert!(matches!(parse_err("'(1 2").kind, PescErrorType::UnmatchedToken('(')));
+        assert!(matches!(parse_err("'(1 })").kind, PescErrorType::UnexpectedToken('}')));
+    }
+
+    #[test]
+    fn test_maps() {
+        let mut p = pesc();
+        let code = Pesc::parse("'{\"b\" '(1) \"a\" {2}} '{}").unwrap().1;
+        assert_eq!(unparse(&code), "'{\"a\" {2} \"b\" '(1)} '{}");
+
+        p.run("'{\"x\" 1} \"y\" 2 [set] \\ \"y\" [get] , \\ \"z\" [get] , \\ \"x\" [has] ,").unwrap();
+        p.run("\\ [len] , [keys]").unwrap();
+        assert_eq!(p.stack[..4], [PescToken::Number(2.0), PescToken::Nil,
+            PescToken::Bool(true), PescToken::Number(2.0)]);
+        assert_eq!(unparse(&p.stack[4..]), "'(\"x\" \"y\")");
+
+        assert!(matches!(parse_err("'{\"a\"}").kind, PescErrorType::BadMapLiteral(_)));
+        assert!(matches!(parse_err("'{1 2}").kind, PescErrorType::BadMapLiteral(_)));
+        assert!(matches!(parse_err("'{\"a\" 1").kind, PescErrorType::UnmatchedToken('{')));
+    }
+
+    #[test]
+    fn test_variables() {
+        let mut p = pesc();
+        p.run("5 \"Rate\" [store] 2 \"rate\" [load] * \"rate\" [load]").unwrap();
+        assert_eq!(p.stack, vec![PescToken::Number(10.0), PescToken::Number(5.0)]);
+
+        let e = p.run("\"nope\" [load]").unwrap_err();
+        assert!(matches!(e.kind, PescErrorType::UnknownVariable(ref v) if v == "nope"));
+    }
+
+    #[test]
+    fn test_embedding() {
+        let mut p = Pesc::with_stdlib();
+        p.register("answer", |p| {
+            p.push(PescToken::Number(42.0));
+            Ok(())
+        });
+
+        p.run("[answer] 2 3+ *").unwrap();
+        assert_eq!(p.stack, vec![PescToken::Number(210.0)]);
+
+        assert!(p.run("[question]").is_err());
+        assert!(p.run("\"unfinished").is_err());
+    }
+}
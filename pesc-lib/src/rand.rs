@@ -1,5 +1,22 @@
 use std::os::raw::c_int;
 
+#[cfg(not(windows))]
 extern "C" {
     pub fn lrand48() -> c_int;
 }
+
+#[cfg(windows)]
+extern "C" {
+    fn rand() -> c_int;
+}
+
+// windows' C library has no lrand48, and its rand() only has 15
+// bits, so this puts three together to make the same 31
+///
+/// # Safety
+///
+/// as safe as rand() is, like lrand48() elsewhere.
+#[cfg(windows)]
+pub unsafe fn lrand48() -> c_int {
+    ((rand() << 16) | (rand() << 1) | (rand() & 1)) & 0x7fff_ffff
+}
@@ -0,0 +1,240 @@
+// exact fractions of big integers, e.g. what 1 3/ is with
+// Precision::Exact. they're written as (1/3), and are always in
+// their lowest terms, with the sign on the numerator; those that
+// turn out to be whole become big integers again.
+
+use std::cmp::Ordering;
+use std::fmt::{self, Display};
+use std::ops::{Add, Mul, Neg, Sub};
+use std::rc::Rc;
+
+use crate::bigint::BigInt;
+use crate::pesc::PescToken;
+
+// how many terms of a float's continued fraction from_f64() tries
+const MAX_TERMS: usize = 64;
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Ratio {
+    num: BigInt,
+
+    // always positive
+    den: BigInt,
+}
+
+impl Ratio {
+    // num / den in its lowest terms, or None if den is zero
+    pub fn new(num: BigInt, den: BigInt) -> Option<Self> {
+        if den.is_zero() {
+            return None;
+        }
+
+        let g = gcd(&num, &den);
+        let (num, den) = (num.div_rem(&g)?.0, den.div_rem(&g)?.0);
+
+        Some(match den.is_negative() {
+            true => Self { num: -&num, den: -&den },
+            false => Self { num, den },
+        })
+    }
+
+    // the simplest fraction that's the same float as n (so 0.1 is 1/10,
+    // not 3602879701896397/36028797018963968), going by the convergents
+    // of its continued fraction
+    pub fn from_f64(n: f64) -> Option<Self> {
+        if !n.is_finite() {
+            return None;
+        }
+
+        let (mut h, mut h_prev) = (BigInt::from(1), BigInt::zero());
+        let (mut k, mut k_prev) = (BigInt::zero(), BigInt::from(1));
+        let mut x = n;
+
+        for _ in 0..MAX_TERMS {
+            let a = x.floor();
+            let term = BigInt::from_f64(a)?;
+
+            let next_h = &(&term * &h) + &h_prev;
+            let next_k = &(&term * &k) + &k_prev;
+            h_prev = std::mem::replace(&mut h, next_h);
+            k_prev = std::mem::replace(&mut k, next_k);
+
+            if x == a || h.to_f64() / k.to_f64() == n {
+                break;
+            }
+
+            x = 1.0 / (x - a);
+        }
+
+        Self::new(h, k)
+    }
+
+    // the exact value of a number token, if it has one
+    pub fn from_token(t: &PescToken) -> Option<Self> {
+        match t {
+            PescToken::Int(n) => Some(Self::from(n.clone())),
+            PescToken::Decimal(d) => Some(d.to_ratio()),
+            PescToken::Frac(r) => Some((**r).clone()),
+            _ => None,
+        }
+    }
+
+    // the token for it: a big integer if it's whole
+    pub fn token(self) -> PescToken {
+        match self.den == BigInt::from(1) {
+            true => PescToken::Int(self.num),
+            false => PescToken::Frac(Rc::new(self)),
+        }
+    }
+
+    // the integer it is, if it's whole
+    pub fn to_integer(&self) -> Option<&BigInt> {
+        match self.den == BigInt::from(1) {
+            true => Some(&self.num),
+            false => None,
+        }
+    }
+
+    pub fn numerator(&self) -> &BigInt {
+        &self.num
+    }
+
+    pub fn denominator(&self) -> &BigInt {
+        &self.den
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        self.num.to_f64() / self.den.to_f64()
+    }
+
+    pub fn is_negative(&self) -> bool {
+        self.num.is_negative()
+    }
+
+    pub fn abs(&self) -> Self {
+        Self { num: self.num.abs(), den: self.den.clone() }
+    }
+
+    // self / other, or None when dividing by zero
+    pub fn div(&self, other: &Self) -> Option<Self> {
+        Self::new(&self.num * &other.den, &self.den * &other.num)
+    }
+
+    // the remainder of self / other, with the sign of self (like %
+    // for floats), or None when dividing by zero
+    pub fn rem(&self, other: &Self) -> Option<Self> {
+        let (q, _) = (&self.num * &other.den).div_rem(&(&self.den * &other.num))?;
+        Some(self - &(other * &Self::from(q)))
+    }
+
+    // self to the nth power, which may be negative, unless that's
+    // unreasonably big (or a division by zero)
+    pub fn pow(&self, n: &BigInt) -> Option<Self> {
+        let e = n.abs().to_u64()?;
+        let (num, den) = (self.num.pow(e)?, self.den.pow(e)?);
+
+        match n.is_negative() {
+            true => Self::new(den, num),
+            false => Self::new(num, den),
+        }
+    }
+}
+
+fn gcd(a: &BigInt, b: &BigInt) -> BigInt {
+    let (mut a, mut b) = (a.abs(), b.abs());
+    while let Some((_, r)) = a.div_rem(&b) {
+        a = std::mem::replace(&mut b, r);
+    }
+
+    a
+}
+
+impl From<BigInt> for Ratio {
+    fn from(n: BigInt) -> Self {
+        Self { num: n, den: BigInt::from(1) }
+    }
+}
+
+impl Ord for Ratio {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (&self.num * &other.den).cmp(&(&other.num * &self.den))
+    }
+}
+
+impl PartialOrd for Ratio {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Display for Ratio {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> Result<(), fmt::Error> {
+        write!(f, "{}/{}", self.num, self.den)
+    }
+}
+
+impl Neg for &Ratio {
+    type Output = Ratio;
+
+    fn neg(self) -> Ratio {
+        Ratio { num: -&self.num, den: self.den.clone() }
+    }
+}
+
+impl Add for &Ratio {
+    type Output = Ratio;
+
+    fn add(self, other: &Ratio) -> Ratio {
+        let num = &(&self.num * &other.den) + &(&other.num * &self.den);
+        Ratio::new(num, &self.den * &other.den).unwrap_or_else(|| self.clone())
+    }
+}
+
+impl Sub for &Ratio {
+    type Output = Ratio;
+
+    fn sub(self, other: &Ratio) -> Ratio {
+        self + &-other
+    }
+}
+
+impl Mul for &Ratio {
+    type Output = Ratio;
+
+    fn mul(self, other: &Ratio) -> Ratio {
+        Ratio::new(&self.num * &other.num, &self.den * &other.den)
+            .unwrap_or_else(|| self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frac(n: i64, d: i64) -> Ratio {
+        let big = |n: i64| BigInt::parse(&n.to_string()).unwrap();
+        Ratio::new(big(n), big(d)).unwrap()
+    }
+
+    #[test]
+    fn test_ratio() {
+        assert_eq!(frac(2, -4).to_string(), "-1/2");
+        assert_eq!(frac(0, 5).to_string(), "0/1");
+        assert_eq!(Ratio::new(BigInt::from(1), BigInt::zero()), None);
+
+        assert_eq!(&frac(1, 3) + &frac(1, 6), frac(1, 2));
+        assert_eq!(&frac(1, 3) - &frac(1, 2), frac(-1, 6));
+        assert_eq!(&frac(2, 3) * &frac(9, 4), frac(3, 2));
+        assert_eq!(frac(1, 3).div(&frac(-2, 3)), Some(frac(-1, 2)));
+        assert_eq!(frac(7, 2).rem(&frac(1, 1)), Some(frac(1, 2)));
+        assert_eq!(frac(-7, 2).rem(&frac(1, 1)), Some(frac(-1, 2)));
+        assert_eq!(frac(2, 3).pow(&BigInt::parse("-2").unwrap()), Some(frac(9, 4)));
+        assert!(frac(1, 3) > frac(-1, 2) && frac(1, 3) < frac(1, 2));
+
+        assert_eq!(Ratio::from_f64(0.1), Some(frac(1, 10)));
+        assert_eq!(Ratio::from_f64(-2.75), Some(frac(-11, 4)));
+        assert_eq!(Ratio::from_f64(1.0 / 3.0), Some(frac(1, 3)));
+        assert_eq!(Ratio::from_f64(f64::NAN), None);
+        assert_eq!(frac(6, 3).token(), PescToken::Int(BigInt::from(2)));
+    }
+}
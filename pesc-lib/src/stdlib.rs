@@ -1,5 +1,9 @@
 use std::rc::Rc;
+use std::cmp::Ordering;
 use std::vec::Vec;
+use crate::bigint::BigInt;
+use crate::decimal::Decimal;
+use crate::ratio::Ratio;
 use crate::errors::*;
 use crate::pesc::*;
 use crate::utils::*;
@@ -15,144 +19,547 @@ macro_rules! rc_box {
 
 // --- declaration ---
 
-pub fn standard<'a>() -> Vec<(Option<char>, &'a str, Rc<Box<PescFunc>>)> {
+// a builtin: its operator, its name, the function, and how many
+// values it takes from the stack and leaves there, when that doesn't
+// depend on the values themselves (see Pesc::declare())
+pub type PescFuncDef<'a> = (Option<char>, &'a str, Rc<Box<PescFunc>>, Option<(usize, usize)>);
+
+pub fn standard<'a>() -> Vec<PescFuncDef<'a>> {
+    vec![
+        (Some('+'),  "add",  rc_box!(pesc_add), Some((2, 1))),
+        (Some('-'),  "sub",  rc_box!(pesc_sub), Some((2, 1))),
+        (Some('*'),  "mul",  rc_box!(pesc_mul), Some((2, 1))),
+        (Some('/'),  "div",  rc_box!(pesc_div), Some((2, 1))),
+        (Some('÷'),  "div",  rc_box!(pesc_div), Some((2, 1))),
+        (Some('^'),  "pow",  rc_box!(pesc_pow), Some((2, 1))),
+        (Some('%'),  "mod",  rc_box!(pesc_mod), Some((2, 1))),
+
+        (Some('\\'), "dup",  rc_box!(pesc_dup), Some((1, 2))),
+        (Some('$'),  "pop",  rc_box!(pesc_pop), Some((1, 0))),
+        (Some(','),  "swp",  rc_box!(pesc_swp), Some((2, 2))),
+        (Some('ø'),  "get",  rc_box!(pesc_get), None),
+        (Some('@'),  "rot",  rc_box!(pesc_rot), None),
+
+        (Some('&'),  "band", rc_box!(pesc_band), Some((2, 1))),
+        (Some('~'),  "bnot", rc_box!(pesc_bnot), Some((1, 1))),
+        (Some('|'),  "bor",  rc_box!(pesc_bor), Some((2, 1))),
+        (Some('X'),  "bxor", rc_box!(pesc_bxor), Some((2, 1))),
+        (Some('<'),  "shl",  rc_box!(pesc_bshiftl), Some((2, 1))),
+        (Some('>'),  "shr",  rc_box!(pesc_bshiftr), Some((2, 1))),
+
+        (Some(';'),  "run",  rc_box!(pesc_run), None),
+        (None,       "break", rc_box!(pesc_break), Some((0, 0))),
+    ]
+}
+
+pub fn extended<'a>() -> Vec<PescFuncDef<'a>> {
+    vec![
+        (Some('!'), "neg",  rc_box!(pesc_b_neg), Some((1, 1))),
+        (None,      "and",     rc_box!(pesc_b_and), Some((2, 1))),
+        (None,      "or",      rc_box!(pesc_b_or), Some((2, 1))),
+        (None,      "eq?",     rc_box!(pesc_b_eq), Some((2, 1))),
+        (None,      "gt?",     rc_box!(pesc_b_gt), Some((2, 1))),
+        (None,      "lt?",     rc_box!(pesc_b_lt), Some((2, 1))),
+        (Some('?'), "if?",     rc_box!(pesc_b_cond), None),
+        (None,      "ifelse",  rc_box!(pesc_b_cond), None),
+        (None,      "if",      rc_box!(pesc_b_if), None),
+        (None,      "times",   rc_box!(pesc_b_times), None),
+        (None,      "while",   rc_box!(pesc_b_while), None),
+        (None,      "until",   rc_box!(pesc_b_until), None),
+        (None,      "try",     rc_box!(pesc_b_try), None),
+        (None,      "throw",   rc_box!(pesc_b_throw), Some((1, 0))),
+
+        (None,      "lte",     rc_box!(pesc_ex_lte), Some((2, 1))),
+        (None,      "gte",     rc_box!(pesc_ex_gte), Some((2, 1))),
+        (None,      "def",     rc_box!(pesc_ex_def), Some((2, 0))),
+        (None,      "bind-op", rc_box!(pesc_ex_bind_op), Some((2, 0))),
+        (None,      "import",  rc_box!(pesc_ex_import), Some((1, 0))),
+        (None,      "store",   rc_box!(pesc_ex_store), Some((2, 0))),
+        (None,      "load",    rc_box!(pesc_ex_load), Some((1, 1))),
+        (Some('s'), "size",    rc_box!(pesc_ex_size), Some((0, 1))),
+        (None,      "stack-limit", rc_box!(pesc_ex_stack_limit), Some((0, 1))),
+        (None,      "help",    rc_box!(pesc_ex_help), Some((1, 1))),
+        (Some('r'), "rand",    rc_box!(pesc_ex_rand), Some((0, 1))),
+
+        (None,      "sin",     rc_box!(pesc_ex_sin), Some((1, 1))),
+        (None,      "cos",     rc_box!(pesc_ex_cos), Some((1, 1))),
+        (None,      "tan",     rc_box!(pesc_ex_tan), Some((1, 1))),
+        (None,      "csc",     rc_box!(pesc_ex_csc), Some((1, 1))),
+        (None,      "sec",     rc_box!(pesc_ex_sec), Some((1, 1))),
+        (None,      "cot",     rc_box!(pesc_ex_cot), Some((1, 1))),
+        (None,      "atan",    rc_box!(pesc_ex_atan), Some((1, 1))),
+
+        (Some('l'), "log",     rc_box!(pesc_ex_log), Some((2, 1))),
+        (None,      "sqrt",    rc_box!(pesc_ex_sqrt), Some((1, 1))),
+        (None,      "cbrt",    rc_box!(pesc_ex_cbrt), Some((1, 1))),
+        (None,      "fact",    rc_box!(pesc_ex_fact), Some((1, 1))),
+        (Some('A'), "ack",     rc_box!(pesc_ex_ack), Some((2, 1))),
+        (Some('a'), "abs",     rc_box!(pesc_ex_abs), Some((1, 1))),
+        (None,      "lcm",     rc_box!(pesc_ex_lcm), Some((2, 1))),
+        (None,      "gcd",     rc_box!(pesc_ex_gcd), Some((2, 1))),
+
+        (Some('p'), "pi",      rc_box!(pesc_ex_pi), Some((0, 1))),
+        (Some('e'), "e",       rc_box!(pesc_ex_e), Some((0, 1))),
+
+        (Some('m'), "min",     rc_box!(pesc_ex_min), Some((2, 1))),
+        (Some('M'), "max",     rc_box!(pesc_ex_max), Some((2, 1))),
+        (Some('c'), "clamp",   rc_box!(pesc_ex_clamp), Some((3, 1))),
+        (None,      "sort",    rc_box!(pesc_ex_sort), None),
+
+        (None,      "to-frac", rc_box!(pesc_ex_to_frac), Some((1, 1))),
+        (None,      "to-float", rc_box!(pesc_ex_to_float), Some((1, 1))),
+        (None,      "num",     rc_box!(pesc_ex_num), Some((1, 1))),
+        (None,      "den",     rc_box!(pesc_ex_den), Some((1, 1))),
+
+        (None,      "hex",     rc_box!(pesc_ex_hex), Some((1, 1))),
+        (None,      "bin",     rc_box!(pesc_ex_bin), Some((1, 1))),
+        (None,      "oct",     rc_box!(pesc_ex_oct), Some((1, 1))),
+        (None,      "sci",     rc_box!(pesc_ex_sci), Some((1, 1))),
+
+        (None,      "floor",   rc_box!(pesc_ex_floor), Some((1, 1))),
+        (None,      "ceil",    rc_box!(pesc_ex_ceil), Some((1, 1))),
+        (None,      "round",   rc_box!(pesc_ex_round), Some((1, 1))),
+
+        (None,      "frrn",    rc_box!(pesc_ex_frrn), Some((1, 1))),
+        (None,      "torn",    rc_box!(pesc_ex_torn), Some((1, 1))),
+
+        (None,      "odd",     rc_box!(pesc_ex_odd), Some((1, 1))),
+        (None,      "even",    rc_box!(pesc_ex_even), Some((1, 1))),
+
+        (None,      "coprime", rc_box!(pesc_ex_coprime), Some((2, 1))),
+        (None,      "prime",   rc_box!(pesc_ex_prime), Some((1, 1))),
+
+        (None,      "len",     rc_box!(pesc_list_len), Some((1, 1))),
+        (None,      "nth",     rc_box!(pesc_list_nth), Some((2, 1))),
+        (None,      "append",  rc_box!(pesc_list_append), Some((2, 1))),
+        (None,      "head",    rc_box!(pesc_list_head), Some((1, 1))),
+        (None,      "tail",    rc_box!(pesc_list_tail), Some((1, 1))),
+
+        (None,      "set",     rc_box!(pesc_map_set), Some((3, 1))),
+        (None,      "keys",    rc_box!(pesc_map_keys), Some((1, 1))),
+        (None,      "has",     rc_box!(pesc_map_has), Some((2, 1))),
+    ]
+}
+
+// words for writing tests, which fail unless things
+// are as expected
+pub fn testing<'a>() -> Vec<PescFuncDef<'a>> {
     vec![
-        (Some('+'),  "add",  rc_box!(pesc_add)),
-        (Some('-'),  "sub",  rc_box!(pesc_sub)),
-        (Some('*'),  "mul",  rc_box!(pesc_mul)),
-        (Some('/'),  "div",  rc_box!(pesc_div)),
-        (Some('÷'),  "div",  rc_box!(pesc_div)),
-        (Some('^'),  "pow",  rc_box!(pesc_pow)),
-        (Some('%'),  "mod",  rc_box!(pesc_mod)),
-
-        (Some('\\'), "dup",  rc_box!(pesc_dup)),
-        (Some('$'),  "pop",  rc_box!(pesc_pop)),
-        (Some(','),  "swp",  rc_box!(pesc_swp)),
-        (Some('ø'),  "get",  rc_box!(pesc_get)),
-        (Some('@'),  "rot",  rc_box!(pesc_rot)),
-
-        (Some('&'),  "band", rc_box!(pesc_band)),
-        (Some('~'),  "bnot", rc_box!(pesc_bnot)),
-        (Some('|'),  "bor",  rc_box!(pesc_bor)),
-        (Some('X'),  "bxor", rc_box!(pesc_bxor)),
-        (Some('<'),  "shl",  rc_box!(pesc_bshiftl)),
-        (Some('>'),  "shr",  rc_box!(pesc_bshiftr)),
-
-        (Some(';'),  "run",  rc_box!(pesc_run)),
+        (None, "assert",    rc_box!(pesc_test_assert), Some((1, 0))),
+        (None, "assert-eq", rc_box!(pesc_test_assert_eq), Some((2, 0))),
     ]
 }
 
-pub fn extended<'a>() -> Vec<(Option<char>, &'a str, Rc<Box<PescFunc>>)> {
+// what each builtin does: (name, signature, description). the
+// signature shows what's taken from the stack and what's left there,
+// the top last.
+pub fn docs<'a>() -> Vec<(&'a str, &'a str, &'a str)> {
     vec![
-        (Some('!'), "neg",  rc_box!(pesc_b_neg)),
-        (None,      "and",     rc_box!(pesc_b_and)),
-        (None,      "or",      rc_box!(pesc_b_or)),
-        (None,      "eq?",     rc_box!(pesc_b_eq)),
-        (None,      "gt?",     rc_box!(pesc_b_gt)),
-        (None,      "lt?",     rc_box!(pesc_b_lt)),
-        (Some('?'), "if?",     rc_box!(pesc_b_cond)),
-
-        (None,      "lte",     rc_box!(pesc_ex_lte)),
-        (None,      "gte",     rc_box!(pesc_ex_gte)),
-        (None,      "def",     rc_box!(pesc_ex_def)),
-        (Some('s'), "size",    rc_box!(pesc_ex_size)),
-        (Some('r'), "rand",    rc_box!(pesc_ex_rand)),
-
-        (None,      "sin",     rc_box!(pesc_ex_sin)),
-        (None,      "cos",     rc_box!(pesc_ex_cos)),
-        (None,      "tan",     rc_box!(pesc_ex_tan)),
-        (None,      "csc",     rc_box!(pesc_ex_csc)),
-        (None,      "sec",     rc_box!(pesc_ex_sec)),
-        (None,      "cot",     rc_box!(pesc_ex_cot)),
-        (None,      "atan",    rc_box!(pesc_ex_atan)),
-
-        (Some('l'), "log",     rc_box!(pesc_ex_log)),
-        (None,      "sqrt",    rc_box!(pesc_ex_sqrt)),
-        (None,      "cbrt",    rc_box!(pesc_ex_cbrt)),
-        (None,      "fact",    rc_box!(pesc_ex_fact)),
-        (Some('A'), "ack",     rc_box!(pesc_ex_ack)),
-        (Some('a'), "abs",     rc_box!(pesc_ex_abs)),
-        (None,      "lcm",     rc_box!(pesc_ex_lcm)),
-        (None,      "gcd",     rc_box!(pesc_ex_gcd)),
-
-        (Some('p'), "pi",      rc_box!(pesc_ex_pi)),
-        (Some('e'), "e",       rc_box!(pesc_ex_e)),
-
-        (Some('m'), "min",     rc_box!(pesc_ex_min)),
-        (Some('M'), "max",     rc_box!(pesc_ex_max)),
-        (Some('c'), "clamp",   rc_box!(pesc_ex_clamp)),
-
-        (None,      "floor",   rc_box!(pesc_ex_floor)),
-        (None,      "ceil",    rc_box!(pesc_ex_ceil)),
-        (None,      "round",   rc_box!(pesc_ex_round)),
-
-        (None,      "frrn",    rc_box!(pesc_ex_frrn)),
-        (None,      "torn",    rc_box!(pesc_ex_torn)),
-
-        (None,      "odd",     rc_box!(pesc_ex_odd)),
-        (None,      "even",    rc_box!(pesc_ex_even)),
-
-        (None,      "coprime", rc_box!(pesc_ex_coprime)),
-        (None,      "prime",   rc_box!(pesc_ex_prime)),
+        ("add",  "a b -- a+b", "adds two numbers."),
+        ("sub",  "a b -- a-b", "subtracts the top number from the one under it."),
+        ("mul",  "a b -- a*b", "multiplies two numbers."),
+        ("div",  "a b -- a/b", "divides the number under the top by the top one."),
+        ("pow",  "a b -- a^b", "raises a to the power of b."),
+        ("mod",  "a b -- a%b", "the remainder of a / b, with the sign of a."),
+
+        ("dup",  "a -- a a", "copies the top value."),
+        ("pop",  "a --", "throws the top value away."),
+        ("swp",  "a b -- b a", "swaps the top two values."),
+        ("get",  "n -- x", "copies the value n from the top (0 being the top), \
+            or with a map and a key, what's under the key (or nil)."),
+        ("rot",  "n --", "swaps the top value with the one n from the top."),
+
+        ("band", "a b -- a&b", "the bitwise and of two integers."),
+        ("bnot", "a -- ~a", "the bitwise not of an integer."),
+        ("bor",  "a b -- a|b", "the bitwise or of two integers."),
+        ("bxor", "a b -- aXb", "the bitwise exclusive or of two integers."),
+        ("shl",  "a n -- a<<n", "shifts a to the left by n bits."),
+        ("shr",  "a n -- a>>n", "shifts a to the right by n bits."),
+
+        ("run",  "f --", "runs a macro, or a function given as [f]."),
+        ("break", "--", "stops and lets you look around, in the REPL."),
+
+        ("neg",  "b -- !b", "true for false, and false for true."),
+        ("and",  "a b -- a&&b", "whether both are true."),
+        ("or",   "a b -- a||b", "whether either is true."),
+        ("eq?",  "a b -- a=b", "whether the top two values are the same."),
+        ("gt?",  "a b -- b>a", "whether the top number is greater than the one under it."),
+        ("lt?",  "a b -- b<a", "whether the top number is less than the one under it."),
+        ("lte",  "a b -- b<=a", "whether the top number is at most the one under it."),
+        ("gte",  "a b -- b>=a", "whether the top number is at least the one under it."),
+        ("if?",  "{else} {then} c --", "runs then if c is true, and else if it isn't."),
+        ("ifelse", "{else} {then} c --", "runs then if c is true, and else if it isn't."),
+        ("if",   "{then} c --", "runs then if c is true."),
+        ("times", "{body} n --", "runs body n times."),
+        ("while", "{cond} {body} --", "runs body for as long as cond leaves something true."),
+        ("until", "{cond} {body} --", "runs body until cond leaves something true."),
+        ("try",  "{body} {handler} --", "runs body, or if that fails, puts the stack \
+            back, pushes what went wrong and runs handler."),
+        ("throw", "msg --", "fails with msg."),
+
+        ("def",  "{body} name --", "defines a function called name that runs body."),
+        ("bind-op", "op f --", "makes an operator run a macro or the function called f."),
+        ("import", "ns --", "lets the functions in ns be called without \"ns.\"."),
+        ("store", "x name --", "keeps x under name, for [load]."),
+        ("load", "name -- x", "what was kept under name with [store]."),
+        ("size", "-- n", "how many values are on the stack."),
+        ("stack-limit", "-- n", "how many values the stack may hold, or nil if there's no limit."),
+        ("help", "name -- doc", "what the function called name does (or nil, if it doesn't say)."),
+        ("rand", "-- n", "a random whole number."),
+
+        ("sin",  "a -- sin(a)", "the sine of a, in radians."),
+        ("cos",  "a -- cos(a)", "the cosine of a, in radians."),
+        ("tan",  "a -- tan(a)", "the tangent of a, in radians."),
+        ("csc",  "a -- csc(a)", "the cosecant of a, in radians."),
+        ("sec",  "a -- sec(a)", "the secant of a, in radians."),
+        ("cot",  "a -- cot(a)", "the cotangent of a, in radians."),
+        ("atan", "a -- atan(a)", "the arctangent of a, in radians."),
+
+        ("log",  "a b -- log_b(a)", "the logarithm of a in base b."),
+        ("sqrt", "a -- sqrt(a)", "the square root of a."),
+        ("cbrt", "a -- cbrt(a)", "the cube root of a."),
+        ("fact", "n -- n!", "the factorial of n."),
+        ("ack",  "m n -- A(m,n)", "the Ackermann function of m and n."),
+        ("abs",  "a -- |a|", "a without its sign."),
+        ("lcm",  "a b -- lcm", "the least common multiple of a and b."),
+        ("gcd",  "a b -- gcd", "the greatest common divisor of a and b."),
+        ("pi",   "-- pi", "pi."),
+        ("e",    "-- e", "Euler's number."),
+
+        ("min",  "a b -- min", "the smaller of two numbers."),
+        ("max",  "a b -- max", "the larger of two numbers."),
+        ("clamp", "max min a -- a'", "a, or min if it's less, or max if it's more."),
+        ("sort", "... n -- ...", "sorts the n numbers under n, the largest on top."),
+
+        ("to-frac", "a -- a", "a as an exact fraction."),
+        ("to-float", "a -- a", "a as a float."),
+        ("num",  "a -- n", "the numerator of a as a fraction."),
+        ("den",  "a -- d", "the denominator of a as a fraction."),
+        ("hex",  "n -- s", "a whole number in hexadecimal, e.g. \"0xff\"."),
+        ("bin",  "n -- s", "a whole number in binary, e.g. \"0b101\"."),
+        ("oct",  "n -- s", "a whole number in octal, e.g. \"0o17\"."),
+        ("sci",  "a -- s", "a in scientific notation, e.g. \"1.5e-3\"."),
+
+        ("floor", "a -- n", "a rounded down."),
+        ("ceil", "a -- n", "a rounded up."),
+        ("round", "a -- n", "a rounded to the nearest whole number."),
+        ("frrn", "s -- n", "the number that a roman numeral is."),
+        ("torn", "n -- s", "n as a roman numeral."),
+        ("odd",  "n -- b", "whether n is odd."),
+        ("even", "n -- b", "whether n is even."),
+        ("coprime", "a b -- c", "whether a and b have no common divisor but 1."),
+        ("prime", "n -- b", "whether n is prime."),
+
+        ("len",  "l -- n", "how many values there are in a list or a map."),
+        ("nth",  "l i -- x", "the value at i in a list, from 0."),
+        ("append", "l x -- l'", "the list with x added at the end."),
+        ("head", "l -- x", "the first value in a list."),
+        ("tail", "l -- l'", "everything in a list but the first value."),
+        ("set",  "m k v -- m'", "the map with v under the key k."),
+        ("keys", "m -- l", "the keys of a map, as a list."),
+        ("has",  "m k -- b", "whether a map has something under the key k."),
+
+        ("assert", "b --", "fails unless b is true."),
+        ("assert-eq", "a b --", "fails unless a and b are the same."),
     ]
 }
 
+// code that shows off some of the builtins: (name, code). `pesc doc`
+// runs each one to show what it leaves on the stack.
+pub fn examples<'a>() -> Vec<(&'a str, &'a str)> {
+    vec![
+        ("add",  "1 2 +"),
+        ("sub",  "5 3 -"),
+        ("div",  "7 2 /"),
+        ("pow",  "2 10 [pow]"),
+        ("mod",  "_7 3 [mod]"),
+
+        ("dup",  "3 [dup]"),
+        ("pop",  "1 2 [pop]"),
+        ("swp",  "1 2 [swp]"),
+        ("get",  "10 20 30 2 [get]"),
+        ("rot",  "1 2 3 2 [rot]"),
+
+        ("band", "12 10 [band]"),
+        ("shl",  "1 4 [shl]"),
+        ("run",  "{2 3 +} [run]"),
+
+        ("eq?",  "2 2 [eq?]"),
+        ("gt?",  "10 12 [gt?]"),
+        ("if?",  "{\"small\"} {\"big\"} 3 4 [gt?] [if?]"),
+        ("times", "1 {2 *} 10 [times]"),
+        ("while", "1 {[dup] 100 [gt?]} {2 *} [while]"),
+        ("try",  "{\"oops\" [throw]} {} [try]"),
+
+        ("def",  "{[dup] *} \"sq\" [def] 7 [sq]"),
+        ("store", "42 \"x\" [store] \"x\" [load]"),
+        ("size", "1 2 3 [size]"),
+
+        ("log",  "8 2 [log]"),
+        ("sqrt", "16 [sqrt]"),
+        ("fact", "5 [fact]"),
+        ("gcd",  "12 18 [gcd]"),
+        ("clamp", "10 0 15 [clamp]"),
+        ("sort", "3 1 2 3 [sort]"),
+
+        ("to-frac", "0.75 [to-frac]"),
+        ("hex",  "255 [hex]"),
+        ("torn", "1999 [torn]"),
+        ("frrn", "\"MCMXCIX\" [frrn]"),
+        ("prime", "97 [prime]"),
+
+        ("len",  "'(1 2 3) [len]"),
+        ("nth",  "'(1 2 3) 1 [nth]"),
+        ("append", "'(1 2) 3 [append]"),
+    ]
+}
+
+// --- helper functions (cont.) ---
+
+fn pesc_bool_or_nil(b: Option<bool>) -> PescToken {
+    match b {
+        Some(v) => PescToken::Bool(v),
+        None => PescToken::Nil,
+    }
+}
+
 // --- math functions ---
 
+// pop the top two values if they're both big integers (see
+// Precision::Exact), leaving the stack alone otherwise
+fn pop_ints(p: &mut Pesc) -> Option<(BigInt, BigInt)> {
+    if !matches!(p.stack.as_slice(), [.., PescToken::Int(_), PescToken::Int(_)]) {
+        return None;
+    }
+
+    match (p.pop(), p.pop()) {
+        (Ok(PescToken::Int(b)), Ok(PescToken::Int(a))) => Some((a, b)),
+        _ => None,
+    }
+}
+
+// pop the top two values if they're both decimals or big integers (see
+// Precision::Decimal), leaving the stack alone otherwise
+fn pop_decimals(p: &mut Pesc) -> Option<(Decimal, Decimal)> {
+    let decimal = |t: PescToken| match t {
+        PescToken::Int(n) => Decimal::from(n),
+        PescToken::Decimal(d) => (*d).clone(),
+        _ => unreachable!(),
+    };
+
+    match p.stack.as_slice() {
+        [.., PescToken::Int(_) | PescToken::Decimal(_),
+            PescToken::Int(_) | PescToken::Decimal(_)] => (),
+        _ => return None,
+    }
+
+    let b = decimal(p.pop().ok()?);
+    let a = decimal(p.pop().ok()?);
+    Some((a, b))
+}
+
+// pop the top two values if they're both exact (fractions, decimals
+// or big integers), leaving the stack alone otherwise
+fn pop_fracs(p: &mut Pesc) -> Option<(Ratio, Ratio)> {
+    match p.stack.as_slice() {
+        [.., a, b] if Ratio::from_token(a).is_some() && Ratio::from_token(b).is_some() => (),
+        _ => return None,
+    }
+
+    let b = Ratio::from_token(&p.pop().ok()?)?;
+    let a = Ratio::from_token(&p.pop().ok()?)?;
+    Some((a, b))
+}
+
 pub fn pesc_add(p: &mut Pesc) -> Result<(), PescErrorType> {
+    if let Some((a, b)) = pop_ints(p) {
+        p.push(PescToken::Int(&a + &b));
+        return Ok(());
+    }
+
+    if let Some((a, b)) = pop_decimals(p) {
+        p.push((&a + &b).token());
+        return Ok(());
+    }
+
+    if let Some((a, b)) = pop_fracs(p) {
+        p.push((&a + &b).token());
+        return Ok(());
+    }
+
     let b = p.pop_number()?;
     let a = p.pop_number()?;
 
-    p.push(PescToken::Number(a + b));
-    Ok(())
+    p.push_exact("add", a, b, a + b)
 }
 
 pub fn pesc_sub(p: &mut Pesc) -> Result<(), PescErrorType> {
+    if let Some((a, b)) = pop_ints(p) {
+        p.push(PescToken::Int(&a - &b));
+        return Ok(());
+    }
+
+    if let Some((a, b)) = pop_decimals(p) {
+        p.push((&a - &b).token());
+        return Ok(());
+    }
+
+    if let Some((a, b)) = pop_fracs(p) {
+        p.push((&a - &b).token());
+        return Ok(());
+    }
+
     let b = p.pop_number()?;
     let a = p.pop_number()?;
 
-    p.push(PescToken::Number(a - b));
-    Ok(())
+    p.push_exact("sub", a, b, a - b)
 }
 
 pub fn pesc_mul(p: &mut Pesc) -> Result<(), PescErrorType> {
+    if let Some((a, b)) = pop_ints(p) {
+        p.push(PescToken::Int(&a * &b));
+        return Ok(());
+    }
+
+    if let Some((a, b)) = pop_decimals(p) {
+        p.push((&a * &b).token());
+        return Ok(());
+    }
+
+    if let Some((a, b)) = pop_fracs(p) {
+        p.push((&a * &b).token());
+        return Ok(());
+    }
+
     let b = p.pop_number()?;
     let a = p.pop_number()?;
 
-    p.push(PescToken::Number(a * b));
+    p.push_exact("mul", a, b, a * b)
+}
+
+// push op(a, b), unless b is zero and p.opts.div_zero
+// says to do something else
+fn pesc_divide<F>(p: &mut Pesc, a: f64, b: f64, op: F)
+    -> Result<(), PescErrorType>
+where
+    F: Fn(f64, f64) -> f64
+{
+    if b == 0_f64 {
+        match p.opts.div_zero {
+            DivZeroMode::Error => return Err(PescErrorType::DivideByZero(a, b)),
+            DivZeroMode::Nil => { p.push(PescToken::Nil); return Ok(()) },
+            DivZeroMode::Ieee => (),
+        }
+    }
+
+    p.push(PescToken::Number(op(a, b)));
     Ok(())
 }
 
 pub fn pesc_div(p: &mut Pesc) -> Result<(), PescErrorType> {
+    // (integers that don't divide evenly make decimals with
+    // Precision::Decimal, and fractions otherwise)
+    if p.opts.precision == Precision::Decimal {
+        if let Some((a, b)) = pop_decimals(p) {
+            return match a.div(&b) {
+                Some(q) => { p.push(q.token()); Ok(()) },
+                None => pesc_divide(p, a.to_f64(), 0.0, |a, b| a / b),
+            };
+        }
+    }
+
+    if let Some((a, b)) = pop_fracs(p) {
+        return match a.div(&b) {
+            Some(q) => { p.push(q.token()); Ok(()) },
+            None => pesc_divide(p, a.to_f64(), 0.0, |a, b| a / b),
+        };
+    }
+
     let b = p.pop_number()?;
     let a = p.pop_number()?;
 
-    if b == 0_f64 {
-        Err(PescErrorType::DivideByZero(a, b))
-    } else {
-        p.push(PescToken::Number(a / b));
-        Ok(())
-    }
+    pesc_divide(p, a, b, |a, b| a / b)
 }
 
 pub fn pesc_pow(p: &mut Pesc) -> Result<(), PescErrorType> {
+    if let Some((a, b)) = pop_ints(p) {
+        // (negative powers are fractions, and enormous ones are
+        // left to floats)
+        let r = match b.to_u64() {
+            Some(n) => a.pow(n).map(PescToken::Int),
+            None => Ratio::from(a.clone()).pow(&b).map(Ratio::token),
+        };
+
+        if let Some(r) = r {
+            p.push(r);
+            return Ok(());
+        }
+
+        let (a, b) = (a.to_f64(), b.to_f64());
+        return pesc_powf(p, a, b);
+    }
+
+    if let Some((a, b)) = pop_decimals(p) {
+        if let Some(r) = b.to_integer().and_then(BigInt::to_u64).and_then(|n| a.pow(n)) {
+            p.push(r.token());
+            return Ok(());
+        }
+
+        let (a, b) = (a.to_f64(), b.to_f64());
+        return pesc_powf(p, a, b);
+    }
+
+    if let Some((a, b)) = pop_fracs(p) {
+        if let Some(r) = b.to_integer().and_then(|n| a.pow(n)) {
+            p.push(r.token());
+            return Ok(());
+        }
+
+        let (a, b) = (a.to_f64(), b.to_f64());
+        return pesc_powf(p, a, b);
+    }
+
     let b = p.pop_number()?;
     let a = p.pop_number()?;
 
-    p.push(PescToken::Number(a.powf(b)));
-    Ok(())
+    pesc_powf(p, a, b)
+}
+
+// push a^b, with zero to a negative power (1 divided by zero to the
+// opposite one) being a division by zero
+fn pesc_powf(p: &mut Pesc, a: f64, b: f64) -> Result<(), PescErrorType> {
+    match a == 0_f64 && b < 0_f64 {
+        true => pesc_divide(p, 1_f64, 0_f64, |_, _| a.powf(b)),
+        false => p.push_exact("pow", a, b, a.powf(b)),
+    }
 }
 
 pub fn pesc_mod(p: &mut Pesc) -> Result<(), PescErrorType> {
+    if let Some((a, b)) = pop_ints(p) {
+        return match a.div_rem(&b) {
+            Some((_, r)) => { p.push(PescToken::Int(r)); Ok(()) },
+            None => pesc_divide(p, a.to_f64(), 0.0, |a, b| a % b),
+        };
+    }
+
+    if let Some((a, b)) = pop_decimals(p) {
+        return match a.rem(&b) {
+            Some(r) => { p.push(r.token()); Ok(()) },
+            None => pesc_divide(p, a.to_f64(), 0.0, |a, b| a % b),
+        };
+    }
+
+    if let Some((a, b)) = pop_fracs(p) {
+        return match a.rem(&b) {
+            Some(r) => { p.push(r.token()); Ok(()) },
+            None => pesc_divide(p, a.to_f64(), 0.0, |a, b| a % b),
+        };
+    }
+
     let b = p.pop_number()?;
     let a = p.pop_number()?;
 
-    if b == 0_f64 {
-        Err(PescErrorType::DivideByZero(a, b))
-    } else {
-        p.push(PescToken::Number(a % b));
-        Ok(())
-    }
+    pesc_divide(p, a, b, |a, b| a % b)
 }
 
 // --- stack functions ---
@@ -177,6 +584,17 @@ pub fn pesc_swp(p: &mut Pesc) -> Result<(), PescErrorType> {
 }
 
 pub fn pesc_get(p: &mut Pesc) -> Result<(), PescErrorType> {
+    // a map and a key: what's under the key, or nil
+    let len = p.stack.len();
+    if let (Some(PescToken::Map(_)), Some(PescToken::Str(_))) =
+        (len.checked_sub(2).map(|i| &p.stack[i]), p.stack.last())
+    {
+        let key = p.pop_string()?;
+        let map = p.pop_map()?;
+        p.push(map.get(&key).cloned().unwrap_or(PescToken::Nil));
+        return Ok(());
+    }
+
     // copy the nth item on the stack and dup
     let nth = p.pop_number()?;
     let x   = p.nth_ref(nth)?.clone();
@@ -224,23 +642,51 @@ pub fn pesc_b_eq(p: &mut Pesc) -> Result<(), PescErrorType> {
     let b = p.pop()?;
     let a = p.pop()?;
 
-    p.push(PescToken::Bool(a == b));
+    let res = match (&a, &b) {
+        (PescToken::Number(x), PescToken::Number(y)) =>
+            p.compare(*x, *y)?.map(|o| o == Ordering::Equal),
+        (PescToken::Number(_), _) | (_, PescToken::Number(_)) =>
+            match (a.as_number(), b.as_number()) {
+                (Some(x), Some(y)) => p.compare(x, y)?.map(|o| o == Ordering::Equal),
+                _ => Some(false),
+            },
+        _ => match (Ratio::from_token(&a), Ratio::from_token(&b)) {
+            (Some(x), Some(y)) => Some(x == y),
+            _ => Some(a == b),
+        },
+    };
+
+    p.push(pesc_bool_or_nil(res));
     Ok(())
 }
 
 pub fn pesc_b_gt(p: &mut Pesc) -> Result<(), PescErrorType> {
+    if let Some((a, b)) = pop_fracs(p) {
+        let o = a.cmp(&b);
+        p.push(PescToken::Bool(o == Ordering::Less));
+        return Ok(());
+    }
+
     let b = p.pop_number()?;
     let a = p.pop_number()?;
 
-    p.push(PescToken::Bool(a < b));
+    let res = p.compare(a, b)?.map(|o| o == Ordering::Less);
+    p.push(pesc_bool_or_nil(res));
     Ok(())
 }
 
 pub fn pesc_b_lt(p: &mut Pesc) -> Result<(), PescErrorType> {
+    if let Some((a, b)) = pop_fracs(p) {
+        let o = a.cmp(&b);
+        p.push(PescToken::Bool(o == Ordering::Greater));
+        return Ok(());
+    }
+
     let b = p.pop_number()?;
     let a = p.pop_number()?;
 
-    p.push(PescToken::Bool(a > b));
+    let res = p.compare(a, b)?.map(|o| o == Ordering::Greater);
+    p.push(pesc_bool_or_nil(res));
     Ok(())
 }
 
@@ -257,36 +703,236 @@ pub fn pesc_b_cond(p: &mut Pesc) -> Result<(), PescErrorType> {
     Ok(())
 }
 
+// like if?, without an else
+pub fn pesc_b_if(p: &mut Pesc) -> Result<(), PescErrorType> {
+    let cond = p.pop_boolean()?;
+    let branch = p.pop()?;
+
+    match cond {
+        true => p.try_exec(branch),
+        false => Ok(()),
+    }
+}
+
+// {body} n [times]
+pub fn pesc_b_times(p: &mut Pesc) -> Result<(), PescErrorType> {
+    let n = p.pop_integer()?;
+    let body = p.pop()?;
+
+    if n > p.opts.max_loops {
+        return Err(PescErrorType::LoopLimitExceeded(p.opts.max_loops));
+    }
+
+    for _ in 0..n {
+        p.try_exec(body.clone())?;
+    }
+
+    Ok(())
+}
+
+// {cond} {body} [while], which runs body for as long as cond
+// leaves something true on the stack
+pub fn pesc_b_while(p: &mut Pesc) -> Result<(), PescErrorType> {
+    let body = p.pop()?;
+    let cond = p.pop()?;
+    pesc_loop(p, cond, body, true)
+}
+
+// {cond} {body} [until], the opposite of while
+pub fn pesc_b_until(p: &mut Pesc) -> Result<(), PescErrorType> {
+    let body = p.pop()?;
+    let cond = p.pop()?;
+    pesc_loop(p, cond, body, false)
+}
+
+// run body for as long as cond leaves `go` on the stack
+fn pesc_loop(p: &mut Pesc, cond: PescToken, body: PescToken, go: bool)
+    -> Result<(), PescErrorType>
+{
+    for _ in 0..=p.opts.max_loops {
+        p.try_exec(cond.clone())?;
+        if p.pop_boolean()? != go {
+            return Ok(());
+        }
+
+        p.try_exec(body.clone())?;
+    }
+
+    Err(PescErrorType::LoopLimitExceeded(p.opts.max_loops))
+}
+
+// {body} {handler} [try], which runs body, and if that fails, puts
+// the stack back the way it was before, pushes what went wrong and
+// runs handler instead. (stopping isn't something that went wrong.)
+pub fn pesc_b_try(p: &mut Pesc) -> Result<(), PescErrorType> {
+    let handler = p.pop()?;
+    let body = p.pop()?;
+    let before = p.stack.clone();
+
+    match p.try_exec(body) {
+        Err(e) if !matches!(e, PescErrorType::Aborted) => {
+            p.stack = before;
+            p.push(PescToken::Str(e.to_string()));
+            p.try_exec(handler)
+        },
+        res => res,
+    }
+}
+
+// "message" [throw], which fails with that message (or with any
+// other value, as it's written)
+pub fn pesc_b_throw(p: &mut Pesc) -> Result<(), PescErrorType> {
+    match p.pop()? {
+        PescToken::Str(s) => Err(PescErrorType::Thrown(s)),
+        v => Err(PescErrorType::Thrown(unparse(&[v]))),
+    }
+}
+
 pub fn pesc_ex_lte(p: &mut Pesc) -> Result<(), PescErrorType> {
+    if let Some((a, b)) = pop_fracs(p) {
+        let o = a.cmp(&b);
+        p.push(PescToken::Bool(o != Ordering::Less));
+        return Ok(());
+    }
+
     let b = p.pop_number()?;
     let a = p.pop_number()?;
 
-    p.push(PescToken::Bool(a >= b));
+    let res = p.compare(a, b)?.map(|o| o != Ordering::Less);
+    p.push(pesc_bool_or_nil(res));
     Ok(())
 }
 
 pub fn pesc_ex_gte(p: &mut Pesc) -> Result<(), PescErrorType> {
+    if let Some((a, b)) = pop_fracs(p) {
+        let o = a.cmp(&b);
+        p.push(PescToken::Bool(o != Ordering::Greater));
+        return Ok(());
+    }
+
     let b = p.pop_number()?;
     let a = p.pop_number()?;
 
-    p.push(PescToken::Bool(a <= b));
+    let res = p.compare(a, b)?.map(|o| o != Ordering::Greater);
+    p.push(pesc_bool_or_nil(res));
     Ok(())
 }
 
 pub fn pesc_ex_def(p: &mut Pesc) -> Result<(), PescErrorType> {
     let name = p.pop_string()?;
+    let name = p.opts.func_name(&name);
     let body = p.pop_macro()?;
 
+    if p.funcs.contains_key(&name) {
+        p.warn(PescWarningType::Redefinition(name.clone()));
+    }
+
+    let hidden = p.resolve(&name);
+    if hidden != name {
+        p.warn(PescWarningType::NameCollision(name.clone(), hidden));
+    }
+
+    for op in p.operators(&name) {
+        p.warn(PescWarningType::ShadowedOperator(op, name.clone()));
+    }
+
+    p.defs.insert(name.clone(), body.clone());
     p.funcs.insert(name, Rc::new(Box::new(move |p|
                 p.try_exec(PescToken::Macro(body.clone())))));
     Ok(())
 }
 
+// "math" [import], after which [math.sin] can be [sin]
+pub fn pesc_ex_import(p: &mut Pesc) -> Result<(), PescErrorType> {
+    let ns = p.pop_string()?;
+    let ns = p.opts.func_name(&ns);
+    p.import(&ns)
+}
+
+// make an operator run a macro or a function, e.g. "%" {2*} [bind-op]
+// or "<=" "lte" [bind-op]. a macro becomes a function named after the
+// operator, so that [op %] runs it too. (an operator of more than one
+// character is only noticed in code that's parsed afterwards.)
+pub fn pesc_ex_bind_op(p: &mut Pesc) -> Result<(), PescErrorType> {
+    let what = p.pop()?;
+    let op = p.pop_string()?;
+
+    if !bindable_operator(&op) {
+        return Err(PescErrorType::InvalidArgumentType(
+            String::from("operator"), format!("{:?}", op)));
+    }
+
+    let name = match what {
+        PescToken::Macro(body) => {
+            let name = p.opts.func_name(&format!("op {}", op));
+            p.defs.insert(name.clone(), body.clone());
+            p.funcs.insert(name.clone(), Rc::new(Box::new(move |p|
+                        p.try_exec(PescToken::Macro(body.clone())))));
+            name
+        },
+        PescToken::Str(f) => match p.opts.func_name(&f) {
+            f if p.funcs.contains_key(&f) => f,
+            f => return Err(PescErrorType::UnknownFunction(f)),
+        },
+        v => return Err(PescErrorType::InvalidArgumentType(
+            String::from("macro or function name"), v.to_string())),
+    };
+
+    let mut chars = op.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => { p.ops.insert(c, name); },
+        _ => { p.multi_ops.insert(op, name); },
+    }
+
+    Ok(())
+}
+
+// keep a value under a name (names are matched like functions')
+pub fn pesc_ex_store(p: &mut Pesc) -> Result<(), PescErrorType> {
+    let name = p.pop_string()?;
+    let value = p.pop()?;
+    p.vars.insert(p.opts.func_name(&name), value);
+    Ok(())
+}
+
+pub fn pesc_ex_load(p: &mut Pesc) -> Result<(), PescErrorType> {
+    let name = p.pop_string()?;
+    let value = p.vars.get(&p.opts.func_name(&name)).cloned()
+        .ok_or(PescErrorType::UnknownVariable(name))?;
+    p.push(value);
+    Ok(())
+}
+
 pub fn pesc_ex_size(p: &mut Pesc) -> Result<(), PescErrorType> {
     p.push(PescToken::Number(p.stack.len() as f64));
     Ok(())
 }
 
+// "add" [help], which pushes what [add] does (see docs()), or nil
+// for a function that doesn't say
+pub fn pesc_ex_help(p: &mut Pesc) -> Result<(), PescErrorType> {
+    let name = p.pop_string()?;
+    let func = p.named(&name);
+    if !p.funcs.contains_key(&func) {
+        return Err(PescErrorType::UnknownFunction(func));
+    }
+
+    p.push(match p.docs.get(&func) {
+        Some(d) => PescToken::Str(d.to_string()),
+        None => PescToken::Nil,
+    });
+    Ok(())
+}
+
+// how many values the stack may hold, or nil if there's no limit
+pub fn pesc_ex_stack_limit(p: &mut Pesc) -> Result<(), PescErrorType> {
+    p.push(match p.opts.max_stack {
+        Some(n) => PescToken::Number(n as f64),
+        None => PescToken::Nil,
+    });
+    Ok(())
+}
+
 pub fn pesc_ex_rand(p: &mut Pesc) -> Result<(), PescErrorType> {
     // TODO: random decimal, no first zero
     let r = unsafe { rand::lrand48() } as f64;
@@ -295,50 +941,48 @@ pub fn pesc_ex_rand(p: &mut Pesc) -> Result<(), PescErrorType> {
 }
 
 pub fn pesc_band(p: &mut Pesc) -> Result<(), PescErrorType> {
-    let b = p.pop_number()? as usize;
-    let a = p.pop_number()? as usize;
+    let b = p.pop_integer()?;
+    let a = p.pop_integer()?;
 
-    p.push(PescToken::Number((a & b) as f64));
-    Ok(())
+    p.push_integer("band", Some(a & b), a & b)
 }
 
 pub fn pesc_bnot(p: &mut Pesc) -> Result<(), PescErrorType> {
-    let x = p.pop_number()? as usize;
+    let x = p.pop_integer()?;
 
-    p.push(PescToken::Number(!x as f64));
-    Ok(())
+    // (only the bits of the integers that are exact)
+    p.push_integer("bnot", Some(!x & MAX_EXACT_INT), !x & MAX_EXACT_INT)
 }
 
 pub fn pesc_bor(p: &mut Pesc) -> Result<(), PescErrorType> {
-    let b = p.pop_number()? as usize;
-    let a = p.pop_number()? as usize;
+    let b = p.pop_integer()?;
+    let a = p.pop_integer()?;
 
-    p.push(PescToken::Number((a | b) as f64));
-    Ok(())
+    p.push_integer("bor", Some(a | b), a | b)
 }
 
 pub fn pesc_bxor(p: &mut Pesc) -> Result<(), PescErrorType> {
-    let b = p.pop_number()? as usize;
-    let a = p.pop_number()? as usize;
+    let b = p.pop_integer()?;
+    let a = p.pop_integer()?;
 
-    p.push(PescToken::Number((a ^ b) as f64));
-    Ok(())
+    p.push_integer("bxor", Some(a ^ b), a ^ b)
 }
 
 pub fn pesc_bshiftr(p: &mut Pesc) -> Result<(), PescErrorType> {
-    let b = p.pop_number()? as usize;
-    let a = p.pop_number()? as usize;
+    let b = p.pop_integer()?;
+    let a = p.pop_integer()?;
 
-    p.push(PescToken::Number((a >> b) as f64));
-    Ok(())
+    let r = if b >= usize::BITS as usize { 0 } else { a >> b };
+    p.push_integer("shr", Some(r), r)
 }
 
 pub fn pesc_bshiftl(p: &mut Pesc) -> Result<(), PescErrorType> {
-    let b = p.pop_number()? as usize;
-    let a = p.pop_number()? as usize;
+    let b = p.pop_integer()?;
+    let a = p.pop_integer()?;
 
-    p.push(PescToken::Number((a << b) as f64));
-    Ok(())
+    let r = if b >= usize::BITS as usize { 0 } else { a << b };
+    let lost = b >= usize::BITS as usize || r >> b != a;
+    p.push_integer("shl", if lost { None } else { Some(r) }, r)
 }
 
 pub fn pesc_ex_sin(p: &mut Pesc) -> Result<(), PescErrorType> {
@@ -412,7 +1056,13 @@ pub fn pesc_ex_min(p: &mut Pesc) -> Result<(), PescErrorType> {
     let b = p.pop_number()?;
     let a = p.pop_number()?;
 
-    p.push(PescToken::Number(if a < b { a } else { b }));
+    let res = match p.compare(a, b)? {
+        Some(Ordering::Greater) => b,
+        Some(_) => a,
+        None => f64::NAN,
+    };
+
+    p.push(PescToken::Number(res));
     Ok(())
 }
 
@@ -420,7 +1070,13 @@ pub fn pesc_ex_max(p: &mut Pesc) -> Result<(), PescErrorType> {
     let b = p.pop_number()?;
     let a = p.pop_number()?;
 
-    p.push(PescToken::Number(if a > b { a } else { b }));
+    let res = match p.compare(a, b)? {
+        Some(Ordering::Less) => b,
+        Some(_) => a,
+        None => f64::NAN,
+    };
+
+    p.push(PescToken::Number(res));
     Ok(())
 }
 
@@ -429,9 +1085,10 @@ pub fn pesc_ex_clamp(p: &mut Pesc) -> Result<(), PescErrorType> {
     let min = p.pop_number()?;
     let max = p.pop_number()?;
 
-    let res = match () {
-        _ if val < min => min,
-        _ if val > max => max,
+    let res = match (p.compare(val, min)?, p.compare(val, max)?) {
+        (None, _) | (_, None) => f64::NAN,
+        (Some(Ordering::Less), _) => min,
+        (_, Some(Ordering::Greater)) => max,
         _ => val,
     };
 
@@ -439,6 +1096,36 @@ pub fn pesc_ex_clamp(p: &mut Pesc) -> Result<(), PescErrorType> {
     Ok(())
 }
 
+pub fn pesc_ex_sort(p: &mut Pesc) -> Result<(), PescErrorType> {
+    // sort the top n numbers on the stack, largest on top
+    let n = p.pop_number()? as usize;
+    if n > p.stack.len() {
+        return Err(PescErrorType::NotEnoughArguments);
+    }
+
+    let mut nums = Vec::with_capacity(n);
+    for _ in 0..n {
+        nums.push(p.pop_number()?);
+    }
+
+    // check for NaN first, so that sort_by() doesn't
+    // have to deal with errors
+    for pair in nums.windows(2) {
+        p.compare(pair[0], pair[1])?;
+    }
+
+    // with NanMode::Propagate there's no telling where NaN
+    // should go, so just put them all on top
+    nums.sort_by(|a, b| match p.opts.nan_mode {
+        NanMode::Total => a.total_cmp(b),
+        _ => a.is_nan().cmp(&b.is_nan())
+            .then(a.partial_cmp(b).unwrap_or(Ordering::Equal)),
+    });
+
+    nums.into_iter().for_each(|n| p.push(PescToken::Number(n)));
+    Ok(())
+}
+
 pub fn pesc_ex_sqrt(p: &mut Pesc) -> Result<(), PescErrorType> {
     let v = p.pop_number()?;
 
@@ -454,9 +1141,102 @@ pub fn pesc_ex_cbrt(p: &mut Pesc) -> Result<(), PescErrorType> {
 }
 
 pub fn pesc_ex_fact(p: &mut Pesc) -> Result<(), PescErrorType> {
-    let v = p.pop_number()? as usize;
+    if let Some(PescToken::Int(n)) = p.stack.last() {
+        if let Some(f) = n.to_u64().and_then(BigInt::factorial) {
+            p.pop()?;
+            p.push(PescToken::Int(f));
+            return Ok(());
+        }
+    }
+
+    let v = p.pop_integer()?;
+
+    match checked_factorial(v) {
+        // (as a float, which is inf from 171! on)
+        None if !p.opts.checked_ints => {
+            p.push(PescToken::Number((1..=v.min(171)).map(|k| k as PescNumber).product()));
+            Ok(())
+        },
+        f => p.push_integer("fact", f, f.unwrap_or_default()),
+    }
+}
+
+// the exact value of a number, as a fraction (or an integer, if it's
+// whole). floats become the simplest fraction that's the same float.
+fn pop_ratio(p: &mut Pesc) -> Result<Ratio, PescErrorType> {
+    let v = p.pop()?;
+
+    match &v {
+        PescToken::Number(n) => Ratio::from_f64(*n),
+        t => Ratio::from_token(t),
+    }.ok_or_else(|| PescErrorType::InvalidArgumentType(
+        String::from("finite number"), v.to_string()))
+}
+
+pub fn pesc_ex_to_frac(p: &mut Pesc) -> Result<(), PescErrorType> {
+    let r = pop_ratio(p)?;
+    p.push(r.token());
+    Ok(())
+}
+
+pub fn pesc_ex_to_float(p: &mut Pesc) -> Result<(), PescErrorType> {
+    let v = p.pop_number()?;
+    p.push(PescToken::Number(v));
+    Ok(())
+}
 
-    p.push(PescToken::Number(factorial(v) as f64));
+pub fn pesc_ex_num(p: &mut Pesc) -> Result<(), PescErrorType> {
+    let r = pop_ratio(p)?;
+    p.push(PescToken::Int(r.numerator().clone()));
+    Ok(())
+}
+
+pub fn pesc_ex_den(p: &mut Pesc) -> Result<(), PescErrorType> {
+    let r = pop_ratio(p)?;
+    p.push(PescToken::Int(r.denominator().clone()));
+    Ok(())
+}
+
+// push a whole number written in another base, e.g. 255 as "0xff"
+fn pesc_radix(p: &mut Pesc, radix: u32, prefix: &str) -> Result<(), PescErrorType> {
+    let v = p.pop()?;
+    let n = match &v {
+        PescToken::Int(n) => Some(n.clone()),
+        t => t.as_number().and_then(BigInt::from_f64),
+    }.ok_or_else(|| PescErrorType::InvalidArgumentType(
+        String::from("whole number"), v.to_string()))?;
+
+    let sign = if n.is_negative() { "-" } else { "" };
+    p.push(PescToken::Str(format!("{}{}{}", sign, prefix, n.abs().to_radix(radix))));
+    Ok(())
+}
+
+pub fn pesc_ex_hex(p: &mut Pesc) -> Result<(), PescErrorType> {
+    pesc_radix(p, 16, "0x")
+}
+
+pub fn pesc_ex_bin(p: &mut Pesc) -> Result<(), PescErrorType> {
+    pesc_radix(p, 2, "0b")
+}
+
+pub fn pesc_ex_oct(p: &mut Pesc) -> Result<(), PescErrorType> {
+    pesc_radix(p, 8, "0o")
+}
+
+// e.g. "1.5e-3", exactly for integers and decimals
+pub fn pesc_ex_sci(p: &mut Pesc) -> Result<(), PescErrorType> {
+    let v = p.pop()?;
+    let s = match &v {
+        PescToken::Int(n) => Decimal::from(n.clone()).to_sci(),
+        PescToken::Decimal(d) => d.to_sci(),
+        t => match t.as_number() {
+            Some(n) => format!("{:e}", n),
+            None => return Err(PescErrorType::InvalidArgumentType(
+                String::from("number"), v.to_string())),
+        },
+    };
+
+    p.push(PescToken::Str(s));
     Ok(())
 }
 
@@ -519,44 +1299,64 @@ pub fn pesc_ex_frrn(p: &mut Pesc) -> Result<(), PescErrorType> {
 }
 
 pub fn pesc_ex_gcd(p: &mut Pesc) -> Result<(), PescErrorType> {
-    let v = p.pop_number()? as usize;
-    let u = p.pop_number()? as usize;
+    let v = p.pop_integer()?;
+    let u = p.pop_integer()?;
 
     p.push(PescToken::Number(gcd(u, v) as f64));
     Ok(())
 }
 
 pub fn pesc_ex_lcm(p: &mut Pesc) -> Result<(), PescErrorType> {
-    let b = p.pop_number()? as usize;
-    let a = p.pop_number()? as usize;
+    let b = p.pop_integer()?;
+    let a = p.pop_integer()?;
 
-    p.push(PescToken::Number(lcm(a, b) as f64));
-    Ok(())
+    p.push_integer("lcm", checked_lcm(a, b), lcm(a, b))
 }
 
 pub fn pesc_ex_ack(p: &mut Pesc) -> Result<(), PescErrorType> {
-    let n = p.pop_number()? as usize;
-    let m = p.pop_number()? as usize;
+    let n = p.pop_integer()?;
+    let m = p.pop_integer()?;
 
     p.push(PescToken::Number(ackermann(m, n) as f64));
     Ok(())
 }
 
 pub fn pesc_ex_odd(p: &mut Pesc) -> Result<(), PescErrorType> {
-    let v = p.pop_number()? as usize;
+    let v = p.pop_integer()?;
 
     p.push(PescToken::Bool(v & 1 == 1));
     Ok(())
 }
 
 pub fn pesc_ex_even(p: &mut Pesc) -> Result<(), PescErrorType> {
-    let v = p.pop_number()? as usize;
+    let v = p.pop_integer()?;
 
     p.push(PescToken::Bool(v & 1 == 0));
     Ok(())
 }
 
 pub fn pesc_ex_abs(p: &mut Pesc) -> Result<(), PescErrorType> {
+    if let Some(PescToken::Int(n)) = p.stack.last() {
+        let n = n.abs();
+        p.pop()?;
+        p.push(PescToken::Int(n));
+        return Ok(());
+    }
+
+    if let Some(PescToken::Decimal(d)) = p.stack.last() {
+        let d = d.abs();
+        p.pop()?;
+        p.push(PescToken::Decimal(Rc::new(d)));
+        return Ok(());
+    }
+
+    if let Some(PescToken::Frac(r)) = p.stack.last() {
+        let r = r.abs();
+        p.pop()?;
+        p.push(PescToken::Frac(Rc::new(r)));
+        return Ok(());
+    }
+
     let v = p.pop_number()?;
 
     p.push(PescToken::Number(v.abs()));
@@ -564,15 +1364,15 @@ pub fn pesc_ex_abs(p: &mut Pesc) -> Result<(), PescErrorType> {
 }
 
 pub fn pesc_ex_coprime(p: &mut Pesc) -> Result<(), PescErrorType> {
-    let v = p.pop_number()? as usize;
-    let u = p.pop_number()? as usize;
+    let v = p.pop_integer()?;
+    let u = p.pop_integer()?;
 
     p.push(PescToken::Bool(gcd(u, v) == 1));
     Ok(())
 }
 
 pub fn pesc_ex_prime(p: &mut Pesc) -> Result<(), PescErrorType> {
-    let x = p.pop_number()? as usize;
+    let x = p.pop_integer()?;
 
     p.push(PescToken::Bool(is_prime(x)));
     Ok(())
@@ -585,3 +1385,113 @@ pub fn pesc_run(p: &mut Pesc) -> Result<(), PescErrorType> {
     p.try_exec(f)
 }
 
+// --- list functions ---
+
+// (of a list or a map)
+pub fn pesc_list_len(p: &mut Pesc) -> Result<(), PescErrorType> {
+    let len = match p.pop()? {
+        PescToken::List(l) => l.len(),
+        PescToken::Map(m) => m.len(),
+        v => return Err(PescErrorType::InvalidArgumentType(
+            String::from("list or map"), v.to_string())),
+    };
+
+    p.push(PescToken::Number(len as f64));
+    Ok(())
+}
+
+// '(a b c) 1 [nth] -> b
+pub fn pesc_list_nth(p: &mut Pesc) -> Result<(), PescErrorType> {
+    // (pop_integer() only minds fractions with checked_ints, but an
+    // index that isn't a whole number is never what was meant)
+    let n = p.pop_number()?;
+    if n.fract() != 0.0 || n < 0.0 {
+        return Err(PescErrorType::NotAnInteger(n));
+    }
+
+    let i = n as usize;
+    let l = p.pop_list()?;
+    let v = l.get(i).cloned().ok_or(PescErrorType::NoSuchIndex(i, l.len()))?;
+    p.push(v);
+    Ok(())
+}
+
+// '(a b) c [append] -> '(a b c)
+pub fn pesc_list_append(p: &mut Pesc) -> Result<(), PescErrorType> {
+    let v = p.pop()?;
+    let mut l = p.pop_list()?;
+    Rc::make_mut(&mut l).push(v);
+    p.push(PescToken::List(l));
+    Ok(())
+}
+
+pub fn pesc_list_head(p: &mut Pesc) -> Result<(), PescErrorType> {
+    let l = p.pop_list()?;
+    let v = l.first().cloned().ok_or(PescErrorType::NoSuchIndex(0, 0))?;
+    p.push(v);
+    Ok(())
+}
+
+// everything but the head (and nothing, for an empty list)
+pub fn pesc_list_tail(p: &mut Pesc) -> Result<(), PescErrorType> {
+    let l = p.pop_list()?;
+    let rest = l.iter().skip(1).cloned().collect::<Vec<PescToken>>();
+    p.push(PescToken::List(Rc::new(rest)));
+    Ok(())
+}
+
+// --- map functions ---
+// (get is pesc_get)
+
+// '{"a" 1} "b" 2 [set] -> '{"a" 1 "b" 2}
+pub fn pesc_map_set(p: &mut Pesc) -> Result<(), PescErrorType> {
+    let v = p.pop()?;
+    let k = p.pop_string()?;
+    let mut m = p.pop_map()?;
+    Rc::make_mut(&mut m).insert(k, v);
+    p.push(PescToken::Map(m));
+    Ok(())
+}
+
+// the keys of a map, as a list (in order)
+pub fn pesc_map_keys(p: &mut Pesc) -> Result<(), PescErrorType> {
+    let m = p.pop_map()?;
+    let keys = m.keys().map(|k| PescToken::Str(k.clone())).collect::<Vec<PescToken>>();
+    p.push(PescToken::List(Rc::new(keys)));
+    Ok(())
+}
+
+pub fn pesc_map_has(p: &mut Pesc) -> Result<(), PescErrorType> {
+    let k = p.pop_string()?;
+    let m = p.pop_map()?;
+    p.push(PescToken::Bool(m.contains_key(&k)));
+    Ok(())
+}
+
+// --- testing functions ---
+
+pub fn pesc_test_assert(p: &mut Pesc) -> Result<(), PescErrorType> {
+    match p.pop_boolean()? {
+        true => Ok(()),
+        false => Err(PescErrorType::AssertionFailed(
+            String::from("I expected something true"))),
+    }
+}
+
+pub fn pesc_test_assert_eq(p: &mut Pesc) -> Result<(), PescErrorType> {
+    let b = p.pop()?;
+    let a = p.pop()?;
+
+    match a == b {
+        true => Ok(()),
+        false => Err(PescErrorType::AssertionFailed(
+            format!("I expected {} but got {}", b, a))),
+    }
+}
+
+// does nothing unless something (like the REPL) is
+// listening for breakpoints
+pub fn pesc_break(p: &mut Pesc) -> Result<(), PescErrorType> {
+    p.emit(PescEvent::Break)
+}
+
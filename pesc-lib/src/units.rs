@@ -0,0 +1,401 @@
+// units, as defined in a units(1) definitions file (such as GNU
+// units' definitions.units), and a `convert` word that uses them:
+//
+//     10 "ft" "m" [convert]          => 3.048
+//     60 "mile/hr" "km/hr" [convert] => 96.56064
+//
+// a definitions file is a list of `name definition` lines, where the
+// definition is either `!` (a primitive unit, like m or s) or an
+// expression in terms of other units, e.g. `N kg m / s^2`. names that
+// end with '-' are prefixes (`kilo- 1000`). definitions are only
+// worked out when they're used, so the parts of the file this
+// doesn't understand (nonlinear units, tables, and most commands)
+// are simply left alone.
+
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+
+use crate::errors::*;
+use crate::pesc::*;
+
+// how deeply definitions may refer to other definitions
+const MAX_DEPTH: usize = 64;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Quantity {
+    pub factor: f64,
+
+    // the power of each primitive unit
+    pub dims: BTreeMap<String, i32>,
+}
+
+#[derive(Debug, Default)]
+pub struct Units {
+    defs: HashMap<String, String>,
+    prefixes: HashMap<String, String>,
+    cache: RefCell<HashMap<String, Quantity>>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Tok {
+    Number(f64),
+    Name(String),
+    Op(char),
+}
+
+impl Quantity {
+    fn number(n: f64) -> Self {
+        Self { factor: n, dims: BTreeMap::new() }
+    }
+
+    fn mul(mut self, other: &Quantity, sign: i32) -> Self {
+        self.factor *= other.factor.powi(sign);
+        for (d, p) in &other.dims {
+            *self.dims.entry(d.clone()).or_insert(0) += p * sign;
+        }
+
+        self.dims.retain(|_, p| *p != 0);
+        self
+    }
+
+    fn pow(mut self, e: i32) -> Self {
+        self.factor = self.factor.powi(e);
+        self.dims.values_mut().for_each(|p| *p *= e);
+        self.dims.retain(|_, p| *p != 0);
+        self
+    }
+}
+
+impl Units {
+    pub fn parse(data: &str) -> Self {
+        let mut units = Units::default();
+        let mut line = String::new();
+
+        for l in data.lines() {
+            let l = l.split('#').next().unwrap_or("");
+
+            // a trailing '\' continues the line
+            if let Some(l) = l.strip_suffix('\\') {
+                line.push_str(l);
+                line.push(' ');
+                continue;
+            }
+
+            line.push_str(l);
+            units.define(&line);
+            line.clear();
+        }
+
+        units.define(&line);
+        units
+    }
+
+    fn define(&mut self, line: &str) {
+        let line = line.trim();
+        if line.starts_with('!') {
+            return;
+        }
+
+        let (name, def) = match line.split_once(char::is_whitespace) {
+            Some((n, d)) => (n, d.trim()),
+            None => return,
+        };
+
+        // nonlinear units and tables
+        if name.contains(['(', '[']) {
+            return;
+        }
+
+        match name.strip_suffix('-') {
+            Some(p) => self.prefixes.insert(p.to_string(), def.to_string()),
+            None => self.defs.insert(name.to_string(), def.to_string()),
+        };
+    }
+
+    pub fn eval(&self, expr: &str) -> Result<Quantity, PescErrorType> {
+        self.expr(expr, 0)
+    }
+
+    // convert `n` of `from` to `to`
+    pub fn convert(&self, n: f64, from: &str, to: &str) -> Result<f64, PescErrorType> {
+        let (a, b) = (self.eval(from)?, self.eval(to)?);
+
+        if a.dims != b.dims {
+            return Err(PescErrorType::IncompatibleUnits(from.to_string(), to.to_string()));
+        }
+
+        Ok(n * a.factor / b.factor)
+    }
+
+    fn expr(&self, expr: &str, depth: usize) -> Result<Quantity, PescErrorType> {
+        if depth > MAX_DEPTH {
+            return Err(PescErrorType::Other(
+                format!("The definition of {} goes around in circles.", expr)));
+        }
+
+        let toks = lex(expr)
+            .ok_or_else(|| PescErrorType::Other(format!("I can't read the unit '{}'.", expr)))?;
+        let mut i = 0;
+
+        let q = self.quotient(&toks, &mut i, depth)?;
+        match i == toks.len() {
+            true => Ok(q),
+            false => Err(PescErrorType::Other(format!("I can't read the unit '{}'.", expr))),
+        }
+    }
+
+    // terms joined by '*' and '/', which bind less tightly than
+    // juxtaposition, so that `kg m / s^2` is (kg m)/(s^2)
+    fn quotient(&self, toks: &[Tok], i: &mut usize, depth: usize) -> Result<Quantity, PescErrorType> {
+        let mut q = self.term(toks, i, depth)?;
+
+        while let Some(Tok::Op(o @ '*')) | Some(Tok::Op(o @ '/')) = toks.get(*i) {
+            *i += 1;
+            let t = self.term(toks, i, depth)?;
+            q = q.mul(&t, if *o == '*' { 1 } else { -1 });
+        }
+
+        Ok(q)
+    }
+
+    fn term(&self, toks: &[Tok], i: &mut usize, depth: usize) -> Result<Quantity, PescErrorType> {
+        let mut q = self.power(toks, i, depth)?;
+
+        while let Some(Tok::Number(_)) | Some(Tok::Name(_)) | Some(Tok::Op('(')) = toks.get(*i) {
+            q = q.mul(&self.power(toks, i, depth)?, 1);
+        }
+
+        Ok(q)
+    }
+
+    fn power(&self, toks: &[Tok], i: &mut usize, depth: usize) -> Result<Quantity, PescErrorType> {
+        let bad = || PescErrorType::Other(String::from("I can't read that unit."));
+
+        let q = match toks.get(*i).ok_or_else(bad)? {
+            Tok::Number(n) => {
+                *i += 1;
+
+                // 1|3 is a third
+                match (toks.get(*i), toks.get(*i + 1)) {
+                    (Some(Tok::Op('|')), Some(Tok::Number(d))) => {
+                        *i += 2;
+                        Quantity::number(n / d)
+                    },
+                    _ => Quantity::number(*n),
+                }
+            },
+            Tok::Name(n) => {
+                *i += 1;
+                self.resolve(n, depth)?
+            },
+            Tok::Op('(') => {
+                *i += 1;
+                let q = self.quotient(toks, i, depth)?;
+                match toks.get(*i) {
+                    Some(Tok::Op(')')) => *i += 1,
+                    _ => return Err(PescErrorType::UnmatchedToken(')')),
+                }
+                q
+            },
+            Tok::Op(_) => return Err(bad()),
+        };
+
+        match (toks.get(*i), toks.get(*i + 1), toks.get(*i + 2)) {
+            (Some(Tok::Op('^')), Some(Tok::Op('-')), Some(Tok::Number(e))) if e.fract() == 0.0 => {
+                *i += 3;
+                Ok(q.pow(-(*e as i32)))
+            },
+            (Some(Tok::Op('^')), Some(Tok::Number(e)), _) if e.fract() == 0.0 => {
+                *i += 2;
+                Ok(q.pow(*e as i32))
+            },
+            (Some(Tok::Op('^')), ..) => Err(bad()),
+            _ => Ok(q),
+        }
+    }
+
+    fn resolve(&self, name: &str, depth: usize) -> Result<Quantity, PescErrorType> {
+        if let Some(q) = self.cache.borrow().get(name) {
+            return Ok(q.clone());
+        }
+
+        let q = self.lookup(name, depth)
+            .ok_or_else(|| PescErrorType::UnknownUnit(name.to_string()))??;
+        self.cache.borrow_mut().insert(name.to_string(), q.clone());
+        Ok(q)
+    }
+
+    // None if there's no such unit, an error if there is but
+    // its definition is broken
+    fn lookup(&self, name: &str, depth: usize) -> Option<Result<Quantity, PescErrorType>> {
+        if let Some(def) = self.defs.get(name) {
+            return Some(match def.as_str() {
+                "!" => Ok(Quantity { factor: 1.0, dims: std::iter::once((name.to_string(), 1)).collect() }),
+                "!dimensionless" => Ok(Quantity::number(1.0)),
+                d => self.expr(d, depth + 1),
+            });
+        }
+
+        // plurals
+        for (end, with) in [("ies", "y"), ("es", ""), ("s", "")] {
+            if let Some(stem) = name.strip_suffix(end) {
+                let single = format!("{}{}", stem, with);
+                if !stem.is_empty() && self.defs.contains_key(&single) {
+                    return self.lookup(&single, depth);
+                }
+            }
+        }
+
+        // prefixes, longest first
+        let mut prefixes = self.prefixes.keys()
+            .filter(|p| name.starts_with(p.as_str()))
+            .collect::<Vec<&String>>();
+        prefixes.sort_by_key(|p| std::cmp::Reverse(p.len()));
+
+        for p in prefixes {
+            let rest = &name[p.len()..];
+            let unit = match rest.is_empty() {
+                true => Ok(Quantity::number(1.0)),
+                false => match self.lookup(rest, depth) {
+                    Some(u) => u,
+                    None => continue,
+                },
+            };
+
+            return Some(unit.and_then(|u| Ok(u.mul(&self.expr(&self.prefixes[p], depth + 1)?, 1))));
+        }
+
+        // m2 is m^2
+        let last = name.chars().last()?;
+        if name.len() > 1 && ('2'..='9').contains(&last) {
+            let base = &name[..name.len() - 1];
+            return self.lookup(base, depth)
+                .map(|u| u.map(|u| u.pow(last.to_digit(10).unwrap_or(1) as i32)));
+        }
+
+        None
+    }
+}
+
+fn lex(expr: &str) -> Option<Vec<Tok>> {
+    let chs = expr.chars().collect::<Vec<char>>();
+    let mut toks = Vec::new();
+    let mut i = 0;
+
+    while i < chs.len() {
+        let c = chs[i];
+        let start = i;
+
+        match c {
+            _ if c.is_whitespace() => i += 1,
+            '*' | '/' | '^' | '|' | '(' | ')' | '-' => {
+                toks.push(Tok::Op(c));
+                i += 1;
+            },
+            _ if c.is_ascii_digit() || c == '.' => {
+                while i < chs.len() && (chs[i].is_ascii_digit() || chs[i] == '.') {
+                    i += 1;
+                }
+
+                // an exponent, like 1e-3
+                if i + 1 < chs.len() && (chs[i] == 'e' || chs[i] == 'E')
+                    && (chs[i + 1].is_ascii_digit()
+                        || ("+-".contains(chs[i + 1]) && chs.get(i + 2).is_some_and(|c| c.is_ascii_digit())))
+                {
+                    i += 2;
+                    while i < chs.len() && chs[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+
+                let n = chs[start..i].iter().collect::<String>().parse::<f64>().ok()?;
+                toks.push(Tok::Number(n));
+            },
+            _ => {
+                while i < chs.len() && !chs[i].is_whitespace() && !"*/^|()".contains(chs[i]) {
+                    i += 1;
+                }
+
+                toks.push(Tok::Name(chs[start..i].iter().collect()));
+            },
+        }
+    }
+
+    Some(toks)
+}
+
+// define `convert`, which takes a number and the units to
+// convert it from and to
+pub fn load(pesc: &mut Pesc, units: Units) {
+    let units = Rc::new(units);
+
+    pesc.load_documented(None, "convert", Rc::new(Box::new(move |p| {
+        let to = p.pop_string()?;
+        let from = p.pop_string()?;
+        let n = p.pop_number()?;
+
+        p.push(PescToken::Number(units.convert(n, &from, &to)?));
+        Ok(())
+    })), Some((3, 1)), "n from to -- n'",
+        "n in the units from, in the units to, e.g. 1 \"mile\" \"km\" [convert].");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEFS: &str = "
+        # primitive units
+        m       !
+        kg      !
+        s       !
+        radian  !dimensionless
+
+        kilo-   1000
+        k-      kilo
+        centi-  1|100
+        c-      centi
+
+        minute  60 s
+        min     minute
+        hour    60 min
+        hr      hour
+        inch    2.54 cm
+        foot    12 inch
+        ft      foot
+        mile    5280 ft
+        N       kg m / s^2
+        J       N m
+        g       1e-3 kg
+        liter   1000 cm^3
+        L       liter
+        gravity 9.80665 m/s^2
+        loop    loop2
+        loop2   loop \\
+                m
+        tempC(x) units=[1;K] x K + stdtemp
+    ";
+
+    fn close(a: f64, b: f64) -> bool {
+        (a - b).abs() < 1e-9 * b.abs().max(1.0)
+    }
+
+    #[test]
+    fn test_units() {
+        let u = Units::parse(DEFS);
+
+        assert!(close(u.convert(10.0, "ft", "m").unwrap(), 3.048));
+        assert!(close(u.convert(60.0, "mile/hr", "km/hr").unwrap(), 96.56064));
+        assert!(close(u.convert(1.0, "J", "g cm^2 / s^2").unwrap(), 1e7));
+        assert!(close(u.convert(2.0, "L", "m3").unwrap(), 0.002));
+        assert!(close(u.convert(3.0, "miles", "ft").unwrap(), 15840.0));
+        assert!(close(u.convert(1.0, "kg gravity", "N").unwrap(), 9.80665));
+        assert!(close(u.convert(1.0, "radian", "1").unwrap(), 1.0));
+
+        assert!(matches!(u.convert(1.0, "ft", "s"), Err(PescErrorType::IncompatibleUnits(_, _))));
+        assert!(matches!(u.eval("furlong"), Err(PescErrorType::UnknownUnit(_))));
+        assert!(u.eval("loop").is_err());
+        assert!(u.eval("m^").is_err());
+    }
+}
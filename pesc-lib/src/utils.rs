@@ -29,6 +29,8 @@ pub fn e(e_iters: usize) -> f64 {
     1_f64 + calc_e(e_iters, 0_f64)
 }
 
+// (is_multiple_of would need Rust 1.87)
+#[allow(clippy::manual_is_multiple_of)]
 pub fn is_prime(x: usize) -> bool {
     // stolen from this SO answer:
     // https://stackoverflow.com/a/26760082
@@ -74,7 +76,17 @@ pub fn lcm(a: usize, b: usize) -> usize {
     // lcm(a, b) =  ⎜ ───────── ⎟ × |b|
     //              ⎜ gcd(a, b) ⎟
     //              ⎝           ⎠
-    (a / gcd(a, b)) * b
+    match gcd(a, b) {
+        0 => 0,
+        g => (a / g).wrapping_mul(b),
+    }
+}
+
+pub fn checked_lcm(a: usize, b: usize) -> Option<usize> {
+    match gcd(a, b) {
+        0 => Some(0),
+        g => (a / g).checked_mul(b),
+    }
 }
 
 // Josef Stein's binary GCD algorithm
@@ -115,10 +127,11 @@ pub fn gcd(mut u: usize, mut v: usize) -> usize {
 }
 
 pub fn factorial(n: usize) -> usize {
-    match n {
-        1 => n,
-        _ => n * factorial(n - 1),
-    }
+    (1..=n).fold(1, |acc, k| acc.wrapping_mul(k))
+}
+
+pub fn checked_factorial(n: usize) -> Option<usize> {
+    (1..=n).try_fold(1_usize, |acc, k| acc.checked_mul(k))
 }
 
 #[inline]